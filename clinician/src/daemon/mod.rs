@@ -475,11 +475,24 @@ async fn query(&self, problem: &str) -> QueryResult {
 
         let matching = self.rules.find_matching(&context);
         if let Some(rule) = matching.first() {
-            return QueryResult {
-                answer: format!("Matched rule: {}\n\nActions: {:?}", rule.name, rule.then),
-                confidence: 0.9,
-                source: "rules".to_string(),
-                applied_rule: Some(rule.id.clone()),
+            // Only offer a rule whose plan passes safety analysis; the nil
+            // envelope marks a preview that is not tied to any evidence
+            return match self.rules.procedure(&rule.id, uuid::Uuid::nil()) {
+                Ok(plan) => QueryResult {
+                    answer: format!(
+                        "Matched rule: {}\n\nActions: {:?}\n\nRisk: {:?}, reversibility: {:?}, privileges: {:?}",
+                        rule.name, rule.then, plan.overall_risk, plan.overall_reversibility, plan.requires_privileges
+                    ),
+                    confidence: 0.9,
+                    source: "rules".to_string(),
+                    applied_rule: Some(rule.id.clone()),
+                },
+                Err(e) => QueryResult {
+                    answer: format!("Matched rule {} but it cannot be offered: {}", rule.name, e),
+                    confidence: 0.5,
+                    source: "rules".to_string(),
+                    applied_rule: None,
+                },
             };
         }
 
@@ -134,11 +134,29 @@ enum Commands {
     /// Crisis mode - analyze incident bundle from emergency-room
     Crisis {
         /// Path to incident bundle from system-emergency-room
-        #[arg(long)]
-        incident: String,
+        #[arg(long, required_unless_present_any = ["envelope", "bundle"])]
+        incident: Option<String>,
+        /// Path to an EvidenceEnvelope JSON (e.g. from hardware-crash-team)
+        #[arg(long, conflicts_with = "incident", group = "contract")]
+        envelope: Option<String>,
+        /// Path to a Run Bundle directory holding an envelope (e.g. from `hardware-crash-team scan --bundle`)
+        #[arg(long, conflicts_with_all = ["incident", "envelope"], group = "contract")]
+        bundle: Option<String>,
         /// Correlation ID for cross-tool tracing
         #[arg(long)]
         correlation_id: Option<String>,
+        /// Trust store of signer public keys (default: trusted-signers.json in the config dir)
+        #[arg(long, requires = "contract")]
+        trust: Option<String>,
+        /// Reject envelopes that are unsigned or signed by an untrusted key
+        #[arg(long, requires = "contract")]
+        require_signature: bool,
+        /// Directory holding only the envelope's artifacts; other files in it are flagged (default: look them up beside the envelope)
+        #[arg(long, requires = "envelope")]
+        artifacts: Option<String>,
+        /// Where to record the analysis bundle (default: $PSA_RECORDS_DIR, else records/ in the data dir)
+        #[arg(long)]
+        records: Option<String>,
     },
 }
 
@@ -441,8 +459,21 @@ async fn main() -> anyhow::Result<()> {
         Commands::Health => {
             tools::health::show(&storage, &cache).await?;
         }
-        Commands::Crisis { incident, correlation_id } => {
-            tools::crisis::analyze(&incident, correlation_id.as_deref(), &storage, &cache).await?;
+        Commands::Crisis { incident, envelope, bundle, correlation_id, trust, require_signature, artifacts, records } => {
+            let records = tools::crisis::records_root(records.as_deref())?;
+            let intake = tools::crisis::EnvelopeIntake {
+                trust_store: trust,
+                require_signature,
+                artifacts_dir: artifacts,
+                ignore: Vec::new(),
+            };
+            if let Some(bundle) = bundle {
+                tools::crisis::analyze_bundle(&bundle, &intake, &records, &storage, &cache).await?;
+            } else if let Some(envelope) = envelope {
+                tools::crisis::analyze_envelope(&envelope, &intake, &records, &storage, &cache).await?;
+            } else if let Some(incident) = incident {
+                tools::crisis::analyze(&incident, correlation_id.as_deref(), &records, &storage, &cache).await?;
+            }
         }
     }
 
@@ -19,6 +19,9 @@
 use std::path::{Path, PathBuf};
 
 use crate::validation::{validate_pattern, validate_service_name};
+use ambientops_contracts::plan::{PlanStep, Privilege, StepAction, StepTarget};
+use ambientops_contracts::safety;
+use ambientops_contracts::ProcedurePlan;
 
 /// Confidence threshold for crystallizing a solution into a rule
 const CRYSTALLIZATION_THRESHOLD: u32 = 5;
@@ -458,6 +461,44 @@ async fn execute_action(&self, action: &Action) -> Result<String> {
         }
     }
 
+    /// Turn a rule's actions into a ProcedurePlan for approval.
+    ///
+    /// The plan's risk, reversibility, privileges and reboot need are derived
+    /// from its steps, and a plan that fails safety analysis is refused.
+    /// `Log`, `Notify` and `Escalate` actions change nothing and are left out.
+    pub fn procedure(&self, rule_id: &str, envelope_ref: uuid::Uuid) -> Result<ProcedurePlan> {
+        let rule = self
+            .get(rule_id)
+            .ok_or_else(|| anyhow::anyhow!("Rule not found: {}", rule_id))?;
+
+        let steps: Vec<PlanStep> = rule
+            .then
+            .iter()
+            .filter_map(action_step)
+            .enumerate()
+            .map(|(i, mut step)| {
+                step.step_id = format!("step-{}", i + 1);
+                step.order = (i + 1) as u32;
+                step
+            })
+            .collect();
+        if steps.is_empty() {
+            anyhow::bail!("Rule {} has no actions that change the system", rule_id);
+        }
+
+        let mut plan = ProcedurePlan::new(envelope_ref, steps);
+        plan.title = Some(rule.name.clone());
+        plan.description = Some(format!("Rule {} v{}: {}", rule.id, rule.version, rule.provenance.original_problem));
+        safety::derive(&plan.steps).apply_to(&mut plan);
+
+        let verdict = safety::analyze(&plan);
+        if !verdict.is_approvable() {
+            anyhow::bail!("Rule {} failed safety analysis: {}", rule_id, verdict);
+        }
+        plan.warnings = verdict.warnings().map(ToString::to_string).collect();
+        Ok(plan)
+    }
+
     /// Crystallize a proven solution into a rule
     pub fn crystallize(
         &mut self,
@@ -578,8 +619,139 @@ fn default() -> Self {
     }
 }
 
+/// The plan step for an action, numbered later; `None` for actions that
+/// change nothing.
+fn action_step(action: &Action) -> Option<PlanStep> {
+    let root = || Some(serde_json::json!({ "privilege": Privilege::Root }));
+    let (step_action, title, preview, undo, target, parameters) = match action {
+        Action::Shell { command, sudo } => (
+            StepAction::RunCommand,
+            format!("Run {}", command),
+            command.clone(),
+            None,
+            None,
+            if *sudo { root() } else { None },
+        ),
+        Action::RestartService { name } => (
+            StepAction::RestartService,
+            format!("Restart service {}", name),
+            format!("systemctl restart {}", name),
+            None,
+            Some(StepTarget { service: Some(name.clone()), path: None, registry_key: None, program: None }),
+            None,
+        ),
+        Action::EnableService { name } => (
+            StepAction::EnableService,
+            format!("Enable service {}", name),
+            format!("systemctl enable {}", name),
+            Some(format!("systemctl disable {}", name)),
+            Some(StepTarget { service: Some(name.clone()), path: None, registry_key: None, program: None }),
+            None,
+        ),
+        Action::WriteFile { path, .. } => (
+            StepAction::Custom,
+            format!("Write {}", path),
+            format!("write {}", path),
+            None,
+            Some(StepTarget { path: Some(path.clone()), service: None, registry_key: None, program: None }),
+            None,
+        ),
+        Action::LoadModule { name, options } => (
+            StepAction::RunCommand,
+            format!("Load kernel module {}", name),
+            format!("modprobe {} {}", name, options.as_deref().unwrap_or_default()).trim_end().to_string(),
+            Some(format!("modprobe -r {}", name)),
+            None,
+            None,
+        ),
+        Action::InstallPackage { name } => (
+            StepAction::Custom,
+            format!("Install package {}", name),
+            format!("install {}", name),
+            None,
+            Some(StepTarget { program: Some(name.clone()), path: None, service: None, registry_key: None }),
+            root(),
+        ),
+        Action::Log { .. } | Action::Notify { .. } | Action::Escalate { .. } => return None,
+    };
+    Some(PlanStep {
+        step_id: String::new(),
+        order: 0,
+        action: step_action,
+        title,
+        description: None,
+        preview: Some(preview),
+        risk: None,
+        reversibility: None,
+        undo_instruction: undo,
+        target,
+        parameters,
+        finding_refs: Vec::new(),
+        requires_confirmation: true,
+        estimated_duration_seconds: None,
+    })
+}
+
 /// Check if a solution should be crystallized
 pub fn should_crystallize(solution: &crate::storage::Solution) -> bool {
     solution.success_count >= CRYSTALLIZATION_THRESHOLD
         && solution.failure_count < solution.success_count / 2
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambientops_contracts::plan::{Reversibility, RiskLevel};
+
+    fn engine_with(actions: Vec<Action>) -> RulesEngine {
+        let mut engine = RulesEngine {
+            rules: vec![],
+            rules_dir: PathBuf::new(),
+            index: HashMap::new(),
+        };
+        engine.add_rule(Rule {
+            id: "rule-1".to_string(),
+            name: "Fix printing".to_string(),
+            version: "1.0.0".to_string(),
+            when: vec![],
+            then: actions,
+            provenance: Provenance {
+                source: RuleSource::Manual { author: "test".to_string() },
+                original_problem: "cups keeps crashing".to_string(),
+                solution_id: None,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                created_by: "test".to_string(),
+                decision_path: vec![],
+                history: vec![],
+            },
+            stats: RuleStats::default(),
+            enabled: true,
+            tags: vec![],
+        });
+        engine
+    }
+
+    #[test]
+    fn test_rule_procedure_derives_safety() {
+        let engine = engine_with(vec![
+            Action::Log { level: "info".to_string(), message: "fixing cups".to_string() },
+            Action::EnableService { name: "cups".to_string() },
+            Action::Shell { command: "rm -f /var/cache/cups/job.cache".to_string(), sudo: true },
+        ]);
+        let plan = engine.procedure("rule-1", uuid::Uuid::new_v4()).unwrap();
+
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[1].step_id, "step-2");
+        assert_eq!(plan.overall_reversibility, Some(Reversibility::Partial));
+        assert_eq!(plan.overall_risk, Some(RiskLevel::Guided));
+        assert_eq!(plan.requires_privileges, vec![Privilege::Root]);
+        ambientops_contracts::validate::validate_plan(&plan).unwrap();
+    }
+
+    #[test]
+    fn test_rule_without_changes_has_no_procedure() {
+        let engine = engine_with(vec![Action::Notify { title: "t".to_string(), body: "b".to_string() }]);
+        assert!(engine.procedure("rule-1", uuid::Uuid::new_v4()).is_err());
+        assert!(engine.procedure("missing", uuid::Uuid::new_v4()).is_err());
+    }
+}
@@ -3,19 +3,32 @@
 //!
 //! Integration with system-emergency-room for escalated diagnostics.
 //! Reads incident bundles, analyzes captured logs, and provides
-//! AI-assisted recommendations.
+//! AI-assisted recommendations. Each analysis is recorded as a Run Bundle
+//! under the records root.
 //!
 //! Security: All paths are validated before use.
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
 use crate::cache::Cache;
 use crate::correlation;
 use crate::storage::Storage;
 use crate::validation::validate_safe_path;
 
+use ambientops_contracts::bundle::{RunBundleReader, RunBundleWriter};
+use ambientops_contracts::case_file::CaseFile;
+use ambientops_contracts::envelope::{
+    self, Artifact, ArtifactType, EnvelopeSource, FindingCategory, FindingSeverity, HashAlgorithm, HostInfo, Provenance,
+    SourceTool,
+};
+use ambientops_contracts::integrity::{self, verify_artifacts, verify_listed_artifacts, IntegrityReport};
+use ambientops_contracts::run_bundle::{BundleSourceTool, BundleType};
+use ambientops_contracts::signing::{self, TrustStore, Verdict, Verification};
+use ambientops_contracts::validate::validate_json;
 use ambientops_contracts::EvidenceEnvelope;
 
 /// Incident envelope from system-emergency-room
@@ -62,9 +75,14 @@ struct CommandLog {
     output_len: i32,
 }
 
-/// Crisis analysis result (used for future API responses)
+/// Name of the crisis report snapshot in an analysis bundle.
+pub const CRISIS_REPORT_FILE: &str = "crisis-report.json";
+
+/// Name of the console log in an analysis bundle.
+pub const CRISIS_LOG_FILE: &str = "crisis.log";
+
+/// Crisis analysis result, recorded as the report of an analysis bundle
 #[derive(Debug, Serialize)]
-#[allow(dead_code)]
 pub struct CrisisAnalysis {
     pub incident_id: String,
     pub correlation_id: String,
@@ -91,10 +109,12 @@ pub struct Finding {
     pub severity: CrisisSeverity,
 }
 
-/// Analyze an incident bundle from system-emergency-room
+/// Analyze an incident bundle from system-emergency-room and record the
+/// analysis as a Run Bundle under `records`.
 pub async fn analyze(
     incident_path: &str,
     correlation_id: Option<&str>,
+    records: &Path,
     _storage: &Storage,
     _cache: &Cache,
 ) -> Result<()> {
@@ -102,9 +122,10 @@ pub async fn analyze(
     let safe_path = validate_safe_path(incident_path)
         .map_err(|e| anyhow::anyhow!("Invalid incident path: {}", e))?;
 
-    println!("Crisis Mode Analysis");
-    println!("{}", "=".repeat(50));
-    println!();
+    let mut out = String::new();
+    writeln!(out, "Crisis Mode Analysis")?;
+    writeln!(out, "{}", "=".repeat(50))?;
+    writeln!(out)?;
 
     // Check if incident directory exists
     let incident_dir = Path::new(safe_path);
@@ -133,80 +154,91 @@ pub async fn analyze(
         None => correlation::get().unwrap_or(&envelope.correlation_id),
     };
 
-    println!("[Incident Info]");
-    println!("  ID:             {}", envelope.id);
-    println!("  Correlation ID: {}", corr_id);
-    println!("  Created:        {}", envelope.created_at);
-    println!("  Hostname:       {}", envelope.hostname);
-    println!("  Platform:       {} ({})", envelope.platform.os, envelope.platform.arch);
-    println!("  Kernel:         {}", envelope.platform.kernel);
-    println!();
+    writeln!(out, "[Incident Info]")?;
+    writeln!(out, "  ID:             {}", envelope.id)?;
+    writeln!(out, "  Correlation ID: {}", corr_id)?;
+    writeln!(out, "  Created:        {}", envelope.created_at)?;
+    writeln!(out, "  Hostname:       {}", envelope.hostname)?;
+    writeln!(out, "  Platform:       {} ({})", envelope.platform.os, envelope.platform.arch)?;
+    writeln!(out, "  Kernel:         {}", envelope.platform.kernel)?;
+    writeln!(out)?;
 
     // Analyze captured command outputs
-    println!("[Command Analysis]");
+    writeln!(out, "[Command Analysis]")?;
     let mut failed_commands = Vec::new();
 
     for cmd in &envelope.commands {
         let status = if cmd.exit_code == 0 { "OK" } else { "FAIL" };
-        println!("  {} [{}]: {}", cmd.name, status, cmd.command);
+        writeln!(out, "  {} [{}]: {}", cmd.name, status, cmd.command)?;
 
         if cmd.exit_code != 0 {
             failed_commands.push(cmd);
         }
     }
-    println!();
+    writeln!(out)?;
 
     // Read and analyze logs
     let logs_dir = incident_dir.join("logs");
     if logs_dir.exists() {
-        println!("[Log Analysis]");
-        analyze_logs(&logs_dir).await?;
-        println!();
+        writeln!(out, "[Log Analysis]")?;
+        analyze_logs(&logs_dir, &mut out).await?;
+        writeln!(out)?;
     }
 
     // Generate findings and recommendations
-    println!("[Findings]");
+    writeln!(out, "[Findings]")?;
     let findings = generate_findings(&envelope, &failed_commands);
     for finding in &findings {
-        println!("  [{:?}] {}: {}", finding.severity, finding.category, finding.description);
+        writeln!(out, "  [{:?}] {}: {}", finding.severity, finding.category, finding.description)?;
     }
-    println!();
+    writeln!(out)?;
 
-    println!("[Recommendations]");
+    writeln!(out, "[Recommendations]")?;
     let recommendations = generate_recommendations(&findings);
     for (i, rec) in recommendations.iter().enumerate() {
-        println!("  {}. {}", i + 1, rec);
+        writeln!(out, "  {}. {}", i + 1, rec)?;
     }
-    println!();
+    writeln!(out)?;
 
     // Summary
     let severity = determine_overall_severity(&findings);
-    println!("[Summary]");
-    println!("  Overall Severity: {:?}", severity);
-    println!("  Total Findings:   {}", findings.len());
-    println!("  Failed Commands:  {}", failed_commands.len());
-    println!();
+    writeln!(out, "[Summary]")?;
+    writeln!(out, "  Overall Severity: {:?}", severity)?;
+    writeln!(out, "  Total Findings:   {}", findings.len())?;
+    writeln!(out, "  Failed Commands:  {}", failed_commands.len())?;
+    writeln!(out)?;
 
     // Suggest next steps based on severity
     match severity {
         CrisisSeverity::Critical => {
-            println!("[!] CRITICAL: Immediate action required!");
-            println!("    Consider escalating to system-operating-theatre for surgical intervention.");
+            writeln!(out, "[!] CRITICAL: Immediate action required!")?;
+            writeln!(out, "    Consider escalating to system-operating-theatre for surgical intervention.")?;
         }
         CrisisSeverity::High => {
-            println!("[!] HIGH: Prompt attention needed.");
-            println!("    Review recommendations and apply fixes systematically.");
+            writeln!(out, "[!] HIGH: Prompt attention needed.")?;
+            writeln!(out, "    Review recommendations and apply fixes systematically.")?;
         }
         _ => {
-            println!("[*] Situation appears manageable.");
-            println!("    Follow recommendations to resolve issues.");
+            writeln!(out, "[*] Situation appears manageable.")?;
+            writeln!(out, "    Follow recommendations to resolve issues.")?;
         }
     }
 
+    print!("{}", out);
+    let analysis = CrisisAnalysis {
+        incident_id: envelope.id.clone(),
+        correlation_id: corr_id.to_string(),
+        severity,
+        findings,
+        recommendations,
+    };
+    let root = write_bundle(records, &analysis, &envelope.hostname, None, &out)?;
+    println!("\nAnalysis bundle saved to: {}", root.display());
+
     Ok(())
 }
 
-async fn analyze_logs(logs_dir: &Path) -> Result<()> {
+async fn analyze_logs(logs_dir: &Path, out: &mut String) -> Result<()> {
     let entries = std::fs::read_dir(logs_dir)?;
 
     for entry in entries {
@@ -216,7 +248,7 @@ async fn analyze_logs(logs_dir: &Path) -> Result<()> {
         if path.is_file() {
             let filename = path.file_name().unwrap_or_default().to_string_lossy();
             let size = std::fs::metadata(&path)?.len();
-            println!("  {} ({} bytes)", filename, size);
+            writeln!(out, "  {} ({} bytes)", filename, size)?;
 
             // Check for common error patterns in log content
             if let Ok(content) = std::fs::read_to_string(&path) {
@@ -228,7 +260,7 @@ async fn analyze_logs(logs_dir: &Path) -> Result<()> {
                     + content.matches("WARN").count();
 
                 if error_count > 0 || warning_count > 0 {
-                    println!("    -> {} errors, {} warnings detected", error_count, warning_count);
+                    writeln!(out, "    -> {} errors, {} warnings detected", error_count, warning_count)?;
                 }
             }
         }
@@ -292,49 +324,125 @@ fn generate_recommendations(findings: &[Finding]) -> Vec<String> {
     recommendations
 }
 
+/// Trust and integrity settings for envelope intake.
+#[derive(Debug, Default)]
+pub struct EnvelopeIntake {
+    /// Trust store path; defaults to `trusted-signers.json` in the config dir.
+    pub trust_store: Option<String>,
+    /// Reject unsigned or untrusted envelopes instead of flagging them.
+    pub require_signature: bool,
+    /// Directory holding the envelope's artifacts and nothing else; files in it
+    /// that are not artifacts are flagged. Without one, the artifacts are looked
+    /// up in the envelope's own directory and other files there are not listed.
+    pub artifacts_dir: Option<String>,
+    /// Files in `artifacts_dir` that are known not to be artifacts (e.g. the rest of a run bundle).
+    pub ignore: Vec<String>,
+}
+
 /// Analyze an EvidenceEnvelope from hardware-crash-team or other AmbientOps tools.
 ///
 /// This is the contract-based intake path, complementing the legacy incident bundle path.
+///
+/// Envelopes whose signatures do not match their content, or whose artifacts
+/// were modified, are always rejected. Unsigned envelopes, or ones from
+/// signers missing from the trust store, are flagged - or rejected when
+/// `require_signature` is set. Missing or unlisted artifacts are flagged.
 pub async fn analyze_envelope(
     envelope_path: &str,
+    options: &EnvelopeIntake,
+    records: &Path,
     _storage: &Storage,
     _cache: &Cache,
 ) -> Result<()> {
+    let mut out = String::new();
+    let (analysis, hostname, parent) = envelope_analysis(envelope_path, options, &mut out)?;
+    print!("{}", out);
+    let root = write_bundle(records, &analysis, &hostname, Some(parent), &out)?;
+    println!("\nAnalysis bundle saved to: {}", root.display());
+    Ok(())
+}
+
+/// Check an envelope and write its analysis to `out`. Returns the analysis,
+/// the envelope's host and its id.
+fn envelope_analysis(envelope_path: &str, options: &EnvelopeIntake, out: &mut String) -> Result<(CrisisAnalysis, String, Uuid)> {
     let safe_path = validate_safe_path(envelope_path)
         .map_err(|e| anyhow::anyhow!("Invalid envelope path: {}", e))?;
 
     let content = std::fs::read_to_string(safe_path)?;
-    let envelope: EvidenceEnvelope = serde_json::from_str(&content)?;
-
-    println!("Crisis Mode - Evidence Envelope Analysis");
-    println!("{}", "=".repeat(50));
-    println!();
-    println!("[Envelope Info]");
-    println!("  ID:        {}", envelope.envelope_id);
-    println!("  Created:   {}", envelope.created_at);
-    println!("  Host:      {}", envelope.source.host.hostname);
-    println!("  Artifacts: {}", envelope.artifacts.len());
-    println!("  Findings:  {}", envelope.findings.len());
-    println!();
+    let raw: serde_json::Value = serde_json::from_str(&content)?;
+
+    // Reject envelopes that drift from the contract schema before trusting any field
+    validate_json::<EvidenceEnvelope>(&raw)
+        .map_err(|e| anyhow::anyhow!("Envelope rejected: {}", e))?;
+    let trust = load_trust_store(options.trust_store.as_deref())?;
+    let verification = check_signatures(&raw, &trust, options.require_signature)?;
+    let envelope: EvidenceEnvelope = serde_json::from_value(raw)?;
+    let integrity = check_artifacts(&envelope, Path::new(safe_path), options)?;
+
+    writeln!(out, "Crisis Mode - Evidence Envelope Analysis")?;
+    writeln!(out, "{}", "=".repeat(50))?;
+    writeln!(out)?;
+    writeln!(out, "[Envelope Info]")?;
+    writeln!(out, "  ID:        {}", envelope.envelope_id)?;
+    writeln!(out, "  Created:   {}", envelope.created_at)?;
+    writeln!(out, "  Host:      {}", envelope.source.host.hostname)?;
+    writeln!(out, "  Artifacts: {}", envelope.artifacts.len())?;
+    writeln!(out, "  Findings:  {}", envelope.findings.len())?;
+    if let Some(parent) = envelope.provenance.as_ref().and_then(|p| p.parent_envelope_id) {
+        writeln!(out, "  Parent:    {}", parent)?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "[Provenance]")?;
+    match verification.verdict() {
+        Verdict::Trusted => {
+            for sig in &verification.signatures {
+                writeln!(out, "  Signed by {} at {}", sig.signer, sig.timestamp)?;
+            }
+        }
+        Verdict::Unsigned => {
+            writeln!(out, "  [!] UNSIGNED: origin and integrity cannot be proven.")?;
+        }
+        _ => {
+            writeln!(out, "  [!] UNTRUSTED: signed, but not by a key in the trust store.")?;
+            for sig in &verification.signatures {
+                writeln!(out, "      {} ({:?})", sig.signer, sig.status)?;
+            }
+        }
+    }
+    writeln!(out)?;
+
+    writeln!(out, "[Artifacts]")?;
+    writeln!(out, "  Verified:  {}", integrity.verified.len())?;
+    for path in &integrity.missing {
+        writeln!(out, "  [!] Missing:  {}", path)?;
+    }
+    for path in &integrity.unhashed {
+        writeln!(out, "  [!] No hash:  {}", path)?;
+    }
+    for path in &integrity.extra {
+        writeln!(out, "  [?] Unlisted: {}", path)?;
+    }
+    writeln!(out)?;
 
     if !envelope.findings.is_empty() {
-        println!("[Findings from Evidence]");
+        writeln!(out, "[Findings from Evidence]")?;
         for finding in &envelope.findings {
-            println!(
+            writeln!(out, 
                 "  [{:?}] {:?}: {}",
                 finding.severity, finding.category, finding.title
-            );
+            )?;
             if let Some(ref rec) = finding.recommendation {
-                println!("    -> Recommendation: {}", rec);
+                writeln!(out, "    -> Recommendation: {}", rec)?;
             }
         }
-        println!();
+        writeln!(out)?;
     }
 
     let severity = if envelope.findings.iter().any(|f| {
         matches!(
             f.severity,
-            ambientops_contracts::envelope::FindingSeverity::Critical
+            FindingSeverity::Critical
         )
     }) {
         CrisisSeverity::Critical
@@ -349,28 +457,199 @@ pub async fn analyze_envelope(
         CrisisSeverity::Medium
     };
 
-    println!("[Summary]");
-    println!("  Overall Severity: {:?}", severity);
-    println!("  Auto-fixable: {}", envelope.findings.iter().filter(|f| f.auto_fixable).count());
-    println!();
+    writeln!(out, "[Summary]")?;
+    writeln!(out, "  Overall Severity: {:?}", severity)?;
+    writeln!(out, "  Auto-fixable: {}", envelope.findings.iter().filter(|f| f.auto_fixable).count())?;
+    writeln!(out)?;
 
     match severity {
         CrisisSeverity::Critical => {
-            println!("[!] CRITICAL: Immediate action required!");
-            println!("    Consider escalating to Operating Theatre for surgical intervention.");
+            writeln!(out, "[!] CRITICAL: Immediate action required!")?;
+            writeln!(out, "    Consider escalating to Operating Theatre for surgical intervention.")?;
         }
         CrisisSeverity::High => {
-            println!("[!] HIGH: Prompt attention needed.");
-            println!("    Review findings and apply auto-fixable remediations.");
+            writeln!(out, "[!] HIGH: Prompt attention needed.")?;
+            writeln!(out, "    Review findings and apply auto-fixable remediations.")?;
         }
         _ => {
-            println!("[*] Situation appears manageable.");
+            writeln!(out, "[*] Situation appears manageable.")?;
         }
     }
 
+    let findings: Vec<Finding> = envelope
+        .findings
+        .iter()
+        .map(|f| Finding {
+            category: format!("{:?}", f.category),
+            description: f.title.clone(),
+            evidence: f.description.clone().unwrap_or_default(),
+            severity: match f.severity {
+                FindingSeverity::Critical => CrisisSeverity::Critical,
+                FindingSeverity::High => CrisisSeverity::High,
+                FindingSeverity::Medium => CrisisSeverity::Medium,
+                FindingSeverity::Low | FindingSeverity::Info => CrisisSeverity::Low,
+            },
+        })
+        .collect();
+    let analysis = CrisisAnalysis {
+        incident_id: envelope.envelope_id.to_string(),
+        correlation_id: correlation::get().unwrap_or_default().to_string(),
+        severity,
+        findings,
+        recommendations: envelope.findings.iter().filter_map(|f| f.recommendation.clone()).collect(),
+    };
+    Ok((analysis, envelope.source.host.hostname, envelope.envelope_id))
+}
+
+/// Analyze the envelope inside a Run Bundle.
+///
+/// The bundle must verify against its manifest: missing or modified files
+/// reject it, unlisted files are flagged. The envelope then goes through the
+/// same intake as [`analyze_envelope`], with the bundle root as its artifact
+/// directory.
+pub async fn analyze_bundle(
+    bundle_dir: &str,
+    options: &EnvelopeIntake,
+    records: &Path,
+    _storage: &Storage,
+    _cache: &Cache,
+) -> Result<()> {
+    let safe_path = validate_safe_path(bundle_dir)
+        .map_err(|e| anyhow::anyhow!("Invalid bundle path: {}", e))?;
+    let (bundle, report) = check_bundle(Path::new(safe_path))?;
+
+    let mut out = String::new();
+    writeln!(out, "[Run Bundle]")?;
+    writeln!(out, "  ID:        {}", bundle.manifest().bundle_id)?;
+    writeln!(out, "  Files:     {} verified", report.verified.len())?;
+    for path in &report.extra {
+        writeln!(out, "  [?] Unlisted: {}", path)?;
+    }
+    writeln!(out)?;
+
+    let envelope_path = bundle.path(&bundle.envelope_path());
+    let intake = EnvelopeIntake {
+        trust_store: options.trust_store.clone(),
+        require_signature: options.require_signature,
+        artifacts_dir: Some(safe_path.to_string()),
+        ignore: bundle_files(&bundle),
+    };
+    let (analysis, hostname, parent) = envelope_analysis(&envelope_path.to_string_lossy(), &intake, &mut out)?;
+    print!("{}", out);
+    let root = write_bundle(records, &analysis, &hostname, Some(parent), &out)?;
+    println!("\nAnalysis bundle saved to: {}", root.display());
     Ok(())
 }
 
+/// Open a bundle and reject it unless every listed file is intact.
+fn check_bundle(root: &Path) -> Result<(RunBundleReader, IntegrityReport)> {
+    let bundle = RunBundleReader::open(root).map_err(|e| anyhow::anyhow!("Bundle rejected: {}", e))?;
+    let report = bundle.verify()?;
+    if !report.rejected.is_empty() {
+        anyhow::bail!("Bundle rejected: manifest paths escape the bundle: {}", report.rejected.join(", "));
+    }
+    if !report.modified.is_empty() || !report.missing.is_empty() {
+        let mut paths: Vec<&str> = report.modified.iter().map(|m| m.path.as_str()).collect();
+        paths.extend(report.missing.iter().map(String::as_str));
+        anyhow::bail!("Bundle rejected: files modified or missing since it was written: {}", paths.join(", "));
+    }
+    if !bundle.contains(&bundle.envelope_path()) {
+        anyhow::bail!("Bundle rejected: it contains no evidence envelope");
+    }
+    let links = CaseFile::from_bundle(&bundle)
+        .map_err(|e| anyhow::anyhow!("Bundle rejected: {}", e))?
+        .check();
+    if !links.is_intact() {
+        let problems: Vec<String> = links.problems.iter().map(ToString::to_string).collect();
+        anyhow::bail!("Bundle rejected: broken envelope chain: {}", problems.join("; "));
+    }
+    Ok((bundle, report))
+}
+
+/// Everything in a bundle that is not an envelope artifact in its own right.
+fn bundle_files(bundle: &RunBundleReader) -> Vec<String> {
+    let integrity = bundle.manifest().integrity.clone().unwrap_or_default();
+    let mut files = bundle.listed_paths();
+    files.extend([bundle.manifest_path(), integrity.checksum_file, integrity.signature_file]);
+    files
+}
+
+/// Record an analysis as a Run Bundle under `records`: the report, an
+/// envelope pointing at it, the console output and a summary.
+///
+/// `parent` is the envelope the analysis was made from, if any.
+fn write_bundle(records: &Path, analysis: &CrisisAnalysis, hostname: &str, parent: Option<Uuid>, log: &str) -> Result<PathBuf> {
+    let mut writer = RunBundleWriter::create(records, BundleSourceTool::Psa, BundleType::Scan)?;
+
+    let report = serde_json::to_vec_pretty(analysis)?;
+    let report_path = writer.write_snapshot(CRISIS_REPORT_FILE, &report)?;
+    let artifact_id = Uuid::new_v4();
+    let mut envelope = EvidenceEnvelope::new(
+        EnvelopeSource {
+            tool: SourceTool::Psa,
+            tool_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            host: HostInfo {
+                hostname: hostname.to_string(),
+                os: Some(std::env::consts::OS.to_string()),
+                os_version: None,
+                arch: Some(std::env::consts::ARCH.to_string()),
+            },
+            profile: Some("crisis".to_string()),
+            pack: None,
+        },
+        vec![Artifact {
+            artifact_id,
+            artifact_type: ArtifactType::Report,
+            path: report_path,
+            hash: Some(integrity::hash_bytes(HashAlgorithm::Sha256, &report)),
+            size_bytes: Some(report.len() as u64),
+            mime_type: Some("application/json".to_string()),
+            description: Some("PSA crisis analysis report".to_string()),
+        }],
+    );
+    envelope.findings = analysis
+        .findings
+        .iter()
+        .map(|f| envelope::Finding {
+            finding_id: Uuid::new_v4().to_string(),
+            severity: match f.severity {
+                CrisisSeverity::Critical => FindingSeverity::Critical,
+                CrisisSeverity::High => FindingSeverity::High,
+                CrisisSeverity::Medium => FindingSeverity::Medium,
+                CrisisSeverity::Low => FindingSeverity::Low,
+                CrisisSeverity::Unknown => FindingSeverity::Info,
+            },
+            category: FindingCategory::Other,
+            title: f.description.clone(),
+            description: Some(f.evidence.clone()).filter(|e| !e.is_empty()),
+            evidence_refs: vec![artifact_id],
+            recommendation: None,
+            auto_fixable: false,
+        })
+        .collect();
+    envelope.provenance = parent.map(|id| Provenance { parent_envelope_id: Some(id), signatures: Vec::new() });
+    writer.write_envelope(&envelope)?;
+    writer.write_log(CRISIS_LOG_FILE, log.as_bytes())?;
+
+    let mut summary = String::new();
+    writeln!(summary, "# Crisis analysis\n")?;
+    writeln!(summary, "- Incident: {}", analysis.incident_id)?;
+    if !analysis.correlation_id.is_empty() {
+        writeln!(summary, "- Correlation ID: {}", analysis.correlation_id)?;
+    }
+    writeln!(summary, "- Host: {}", hostname)?;
+    writeln!(summary, "- Severity: {:?}", analysis.severity)?;
+    writeln!(summary, "- Findings: {}", analysis.findings.len())?;
+    if !analysis.recommendations.is_empty() {
+        writeln!(summary, "\n## Recommendations\n")?;
+        for rec in &analysis.recommendations {
+            writeln!(summary, "- {}", rec)?;
+        }
+    }
+    writer.write_summary(&summary)?;
+    Ok(writer.finish()?)
+}
+
 fn determine_overall_severity(findings: &[Finding]) -> CrisisSeverity {
     let mut max_severity = CrisisSeverity::Unknown;
 
@@ -390,3 +669,240 @@ fn determine_overall_severity(findings: &[Finding]) -> CrisisSeverity {
 
     max_severity
 }
+
+/// Where to record crisis analyses: `path` if given, else `$PSA_RECORDS_DIR`,
+/// else `records/` in the PSA data dir.
+pub fn records_root(path: Option<&str>) -> Result<PathBuf> {
+    if let Some(p) = path {
+        return validate_safe_path(p)
+            .map(PathBuf::from)
+            .map_err(|e| anyhow::anyhow!("Invalid records path: {}", e));
+    }
+    Ok(default_records_root())
+}
+
+fn default_records_root() -> PathBuf {
+    if let Some(dir) = std::env::var_os("PSA_RECORDS_DIR") {
+        return PathBuf::from(dir);
+    }
+    directories::ProjectDirs::from("com", "hyperpolymath", "personal-sysadmin")
+        .map(|d| d.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from(".local/share/psa"))
+        .join("records")
+}
+
+/// Default trust store location: `trusted-signers.json` in the PSA config dir.
+fn default_trust_store() -> std::path::PathBuf {
+    directories::ProjectDirs::from("com", "hyperpolymath", "personal-sysadmin")
+        .map(|d| d.config_dir().to_path_buf())
+        .unwrap_or_else(|| std::path::PathBuf::from(".config/psa"))
+        .join("trusted-signers.json")
+}
+
+/// Load the trust store. An explicit path must exist; a missing default
+/// store just means no signer is trusted yet.
+fn load_trust_store(path: Option<&str>) -> Result<TrustStore> {
+    let path = match path {
+        Some(p) => validate_safe_path(p)
+            .map(std::path::PathBuf::from)
+            .map_err(|e| anyhow::anyhow!("Invalid trust store path: {}", e))?,
+        None => {
+            let default = default_trust_store();
+            if !default.exists() {
+                return Ok(TrustStore::default());
+            }
+            default
+        }
+    };
+    let content = std::fs::read_to_string(&path)?;
+    TrustStore::from_json(&content).map_err(|e| anyhow::anyhow!("Invalid trust store: {}", e))
+}
+
+/// Check artifacts against their directory. Modified artifacts, or artifact
+/// paths that escape the directory, reject the envelope; the rest is reported.
+///
+/// Only an explicit artifacts directory is searched for unlisted files: the
+/// envelope's own directory may be shared with anything.
+fn check_artifacts(envelope: &EvidenceEnvelope, envelope_path: &Path, options: &EnvelopeIntake) -> Result<IntegrityReport> {
+    let report = match options.artifacts_dir.as_deref() {
+        Some(d) => {
+            let d = validate_safe_path(d).map_err(|e| anyhow::anyhow!("Invalid artifacts path: {}", e))?;
+            // The envelope may sit among its artifacts
+            let own = envelope_path.strip_prefix(d).ok().map(|p| p.to_string_lossy().into_owned());
+            let ignore: Vec<&str> = options.ignore.iter().chain(&own).map(String::as_str).collect();
+            verify_artifacts(envelope, Path::new(d), &ignore)?
+        }
+        None => {
+            let envelope_dir = match envelope_path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            verify_listed_artifacts(envelope, envelope_dir)?
+        }
+    };
+    if !report.rejected.is_empty() {
+        anyhow::bail!("Envelope rejected: artifact paths escape the bundle: {}", report.rejected.join(", "));
+    }
+    if !report.modified.is_empty() {
+        let paths: Vec<&str> = report.modified.iter().map(|m| m.path.as_str()).collect();
+        anyhow::bail!("Envelope rejected: artifacts modified after capture: {}", paths.join(", "));
+    }
+    Ok(report)
+}
+
+/// Apply the intake signature policy to a raw envelope.
+fn check_signatures(raw: &serde_json::Value, trust: &TrustStore, require_signature: bool) -> Result<Verification> {
+    let verification = signing::verify_json::<EvidenceEnvelope>(raw, trust)
+        .map_err(|e| anyhow::anyhow!("Envelope rejected: {}", e))?;
+
+    match verification.verdict() {
+        Verdict::Tampered => {
+            anyhow::bail!("Envelope rejected: signature does not match content (modified after signing)")
+        }
+        Verdict::Unsigned | Verdict::Untrusted if require_signature => {
+            anyhow::bail!("Envelope rejected: {} and --require-signature is set", verification.verdict())
+        }
+        _ => Ok(verification),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambientops_contracts::signing::SigningKey;
+
+    fn signed_envelope() -> (serde_json::Value, TrustStore) {
+        let (key, _) = SigningKey::generate("helper").unwrap();
+        let mut trust = TrustStore::default();
+        trust.trust("helper", &key.public_key_base64());
+
+        let env = ambientops_contracts::conversions::system_report_to_envelope(
+            &serde_json::json!({ "devices": [] }),
+            "test-host",
+        );
+        let mut raw = serde_json::to_value(env).unwrap();
+        signing::sign_json::<EvidenceEnvelope>(&mut raw, &key).unwrap();
+        (raw, trust)
+    }
+
+    #[test]
+    fn test_tampered_envelope_rejected() {
+        let (mut raw, trust) = signed_envelope();
+        assert_eq!(check_signatures(&raw, &trust, true).unwrap().verdict(), Verdict::Trusted);
+
+        raw["source"]["host"]["hostname"] = serde_json::json!("elsewhere");
+        assert!(check_signatures(&raw, &trust, false).is_err());
+    }
+
+    #[test]
+    fn test_modified_artifact_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let envelope = ambientops_contracts::conversions::system_report_to_envelope(&report, "test-host");
+        let report_path = dir.path().join(ambientops_contracts::conversions::SCAN_REPORT_FILE);
+        std::fs::write(&report_path, serde_json::to_vec_pretty(&report).unwrap()).unwrap();
+        let envelope_path = dir.path().join("envelope.json");
+        std::fs::write(&envelope_path, "{}").unwrap();
+
+        let checked = check_artifacts(&envelope, &envelope_path, &EnvelopeIntake::default()).unwrap();
+        assert!(checked.is_intact());
+
+        std::fs::write(&report_path, b"{\"devices\": [\"forged\"]}").unwrap();
+        assert!(check_artifacts(&envelope, &envelope_path, &EnvelopeIntake::default()).is_err());
+    }
+
+    #[test]
+    fn test_unlisted_files_only_flagged_in_explicit_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let envelope = ambientops_contracts::conversions::system_report_to_envelope(&report, "test-host");
+        std::fs::write(dir.path().join(ambientops_contracts::conversions::SCAN_REPORT_FILE), serde_json::to_vec_pretty(&report).unwrap()).unwrap();
+        let envelope_path = dir.path().join("envelope.json");
+        std::fs::write(&envelope_path, "{}").unwrap();
+        std::fs::create_dir(dir.path().join("notes")).unwrap();
+        std::fs::write(dir.path().join("notes/todo.txt"), "unrelated").unwrap();
+
+        let checked = check_artifacts(&envelope, &envelope_path, &EnvelopeIntake::default()).unwrap();
+        assert!(checked.is_intact(), "{:?}", checked);
+
+        let intake = EnvelopeIntake {
+            artifacts_dir: Some(dir.path().to_string_lossy().into_owned()),
+            ..EnvelopeIntake::default()
+        };
+        let checked = check_artifacts(&envelope, &envelope_path, &intake).unwrap();
+        assert_eq!(checked.extra, vec!["notes/todo.txt"]);
+    }
+
+    #[test]
+    fn test_unsigned_envelope_flagged_or_rejected() {
+        let (mut raw, trust) = signed_envelope();
+        raw.as_object_mut().unwrap().remove("provenance");
+
+        assert_eq!(check_signatures(&raw, &trust, false).unwrap().verdict(), Verdict::Unsigned);
+        assert!(check_signatures(&raw, &trust, true).is_err());
+        assert!(check_signatures(&raw, &TrustStore::default(), true).is_err());
+    }
+
+    #[test]
+    fn test_bundle_checked_before_intake() {
+        use ambientops_contracts::bundle::RunBundleWriter;
+        use ambientops_contracts::run_bundle::{BundleSourceTool, BundleType};
+
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let mut envelope = ambientops_contracts::conversions::system_report_to_envelope(&report, "test-host");
+        let mut writer = RunBundleWriter::create(dir.path(), BundleSourceTool::HardwareCrashTeam, BundleType::Scan).unwrap();
+        envelope.artifacts[0].path = writer.write_snapshot("scan-report.json", &serde_json::to_vec_pretty(&report).unwrap()).unwrap();
+        writer.write_envelope(&envelope).unwrap();
+        writer.write_summary("# Scan\n").unwrap();
+        let root = writer.finish().unwrap();
+
+        let (bundle, _) = check_bundle(&root).unwrap();
+        let intake = EnvelopeIntake {
+            artifacts_dir: Some(root.to_string_lossy().into_owned()),
+            ignore: bundle_files(&bundle),
+            ..EnvelopeIntake::default()
+        };
+        let checked = check_artifacts(&envelope, &bundle.path("envelope.json"), &intake).unwrap();
+        assert!(checked.is_intact(), "{:?}", checked);
+
+        std::fs::write(root.join("SUMMARY.md"), "# Nothing to see\n").unwrap();
+        assert!(check_bundle(&root).is_err());
+    }
+
+    #[test]
+    fn test_analysis_recorded_as_bundle() {
+        let records = tempfile::tempdir().unwrap();
+        let analysis = CrisisAnalysis {
+            incident_id: "incident-1".to_string(),
+            correlation_id: "corr-1".to_string(),
+            severity: CrisisSeverity::Medium,
+            findings: vec![Finding {
+                category: "Command Failure".to_string(),
+                description: "dmesg exited with code 1".to_string(),
+                evidence: "dmesg".to_string(),
+                severity: CrisisSeverity::Medium,
+            }],
+            recommendations: vec!["Investigate why 'dmesg' failed - check logs for details".to_string()],
+        };
+        let parent = Uuid::new_v4();
+        let root = write_bundle(records.path(), &analysis, "test-host", Some(parent), "Crisis Mode Analysis\n").unwrap();
+        assert!(root.starts_with(records.path()));
+
+        let (bundle, _) = check_bundle(&root).unwrap();
+        assert!(bundle.contains(&format!("logs/{}", CRISIS_LOG_FILE)));
+        let envelope = bundle.envelope().unwrap().unwrap();
+        assert!(matches!(envelope.source.tool, SourceTool::Psa));
+        assert_eq!(envelope.findings.len(), 1);
+        assert_eq!(envelope.provenance.as_ref().and_then(|p| p.parent_envelope_id), Some(parent));
+
+        // The recorded bundle passes the same intake as any other
+        let intake = EnvelopeIntake {
+            artifacts_dir: Some(root.to_string_lossy().into_owned()),
+            ignore: bundle_files(&bundle),
+            ..EnvelopeIntake::default()
+        };
+        let checked = check_artifacts(&envelope, &bundle.path(&bundle.envelope_path()), &intake).unwrap();
+        assert!(checked.is_intact(), "{:?}", checked);
+    }
+}
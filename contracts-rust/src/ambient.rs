@@ -0,0 +1,517 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Ambient - turn System Weather into the Ward tray payload, and decide
+//! whether the user hears about it.
+//!
+//! [`PayloadBuilder`] maps weather to the indicator, popover and schedule:
+//!
+//! | State | Icon | Animation | Colour | Badge |
+//! |-------|------|-----------|--------|-------|
+//! | calm | sun | none | `#4CAF50` | none |
+//! | watch | cloud | none | `#FFC107` | dot |
+//! | act | storm | glow | `#F44336` | number of categories at Act |
+//!
+//! Badges only count things that need doing, never findings that are merely
+//! informational, and nothing bounces or pulses.
+//!
+//! [`NotificationBudget`] is the only way a payload gets a pending
+//! notification ("no fearware"). In order, it suppresses:
+//!
+//! 1. calm weather, and weather whose own hints ask for no notification;
+//! 2. anything while the user has snoozed notifications;
+//! 3. anything during the cooldown after the last notification;
+//! 4. anything once `max_per_period` notifications were sent in the period;
+//! 5. watch weather unless it is worse than what was last notified, and
+//!    act weather unless it escalated or `act_repeat` has passed.
+//!
+//! The budget is serializable so a UI can persist it between runs.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ambient_payload::*;
+use crate::weather::{ActionHandler, SystemWeather, TrendDirection, Trends, WeatherState};
+
+/// Builds an [`AmbientPayload`] from a [`SystemWeather`].
+pub struct PayloadBuilder<'a> {
+    weather: &'a SystemWeather,
+    now: DateTime<Utc>,
+    schedule: Option<AmbientSchedule>,
+    budget: Option<&'a mut NotificationBudget>,
+}
+
+impl<'a> PayloadBuilder<'a> {
+    pub fn new(weather: &'a SystemWeather) -> Self {
+        Self {
+            weather,
+            now: Utc::now(),
+            schedule: None,
+            budget: None,
+        }
+    }
+
+    /// Build as of `now` (defaults to the current time).
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// Scan schedule to show; defaults to the weather's `next_scan`.
+    pub fn schedule(mut self, schedule: AmbientSchedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Let the budget decide whether this payload carries a notification.
+    /// Without a budget the payload never notifies.
+    pub fn notify_with(mut self, budget: &'a mut NotificationBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn build(self) -> AmbientPayload {
+        let weather = self.weather;
+        let mut payload = AmbientPayload::calm(&weather.summary);
+        payload.timestamp = self.now;
+        payload.indicator = indicator(weather);
+        payload.popover = Some(popover(weather, self.now));
+        payload.schedule = self.schedule.or_else(|| {
+            let next_scan = weather.source.as_ref()?.next_scan?;
+            Some(AmbientSchedule {
+                next_scan: Some(next_scan),
+                scan_type: None,
+                auto_enabled: None,
+            })
+        });
+        if let Some(budget) = self.budget {
+            payload.notifications = Some(budget.review(weather, self.now));
+        }
+        payload
+    }
+}
+
+fn state_of(state: WeatherState) -> AmbientState {
+    match state {
+        WeatherState::Calm => AmbientState::Calm,
+        WeatherState::Watch => AmbientState::Watch,
+        WeatherState::Act => AmbientState::Act,
+    }
+}
+
+/// Category name and parsed state, for each category in the weather.
+fn categories(weather: &SystemWeather) -> Vec<(String, WeatherState, &serde_json::Value)> {
+    let Some(map) = weather.categories.as_ref().and_then(|c| c.as_object()) else {
+        return Vec::new();
+    };
+    map.iter()
+        .filter_map(|(name, status)| {
+            let state = serde_json::from_value(status.get("state")?.clone()).ok()?;
+            Some((name.clone(), state, status))
+        })
+        .collect()
+}
+
+fn indicator(weather: &SystemWeather) -> Indicator {
+    let (icon, color, animation) = match weather.state {
+        WeatherState::Calm => (IndicatorIcon::Sun, "#4CAF50", IndicatorAnimation::None),
+        WeatherState::Watch => (IndicatorIcon::Cloud, "#FFC107", IndicatorAnimation::None),
+        WeatherState::Act => (IndicatorIcon::Storm, "#F44336", IndicatorAnimation::Glow),
+    };
+
+    let badge = match weather.state {
+        WeatherState::Calm => None,
+        WeatherState::Watch => Some(Badge {
+            show: true,
+            count: None,
+            badge_type: Some(BadgeType::Dot),
+        }),
+        WeatherState::Act => {
+            let count = categories(weather).iter().filter(|(_, s, _)| *s == WeatherState::Act).count() as u32;
+            Some(if count > 0 {
+                Badge {
+                    show: true,
+                    count: Some(count),
+                    badge_type: Some(BadgeType::Number),
+                }
+            } else {
+                Badge {
+                    show: true,
+                    count: None,
+                    badge_type: Some(BadgeType::Exclamation),
+                }
+            })
+        }
+    };
+
+    Indicator {
+        state: state_of(weather.state),
+        icon: Some(icon),
+        color: Some(color.to_string()),
+        badge,
+        tooltip: Some(weather.summary.clone()),
+        animation,
+    }
+}
+
+fn popover(weather: &SystemWeather, now: DateTime<Utc>) -> Popover {
+    let mut metrics: Vec<PopoverMetric> = Vec::new();
+    let cats = categories(weather);
+
+    // Measured metrics first, then categories that need attention
+    for (name, state, status) in cats.iter().filter(|(_, _, s)| s.get("metric_value").is_some()) {
+        let value = status.get("metric_value").and_then(|v| v.as_f64()).unwrap_or_default();
+        let unit = status.get("metric_unit").and_then(|u| u.as_str()).unwrap_or_default();
+        metrics.push(PopoverMetric {
+            label: Some(title_case(name)),
+            value: Some(format!("{:.0}{}", value, unit)),
+            state: Some(metric_state(*state)),
+            trend: metric_trend(weather.trends.as_ref(), name),
+        });
+    }
+    for (name, state, status) in cats.iter().filter(|(_, s, st)| *s != WeatherState::Calm && st.get("metric_value").is_none()) {
+        metrics.push(PopoverMetric {
+            label: Some(title_case(name)),
+            value: status.get("summary").and_then(|s| s.as_str()).map(str::to_string),
+            state: Some(metric_state(*state)),
+            trend: None,
+        });
+    }
+    metrics.truncate(4);
+
+    let last_scan = weather.source.as_ref().and_then(|s| s.last_scan).map(|at| LastScan {
+        timestamp: Some(at),
+        relative: Some(relative(now - at)),
+        result: Some(
+            match weather.state {
+                WeatherState::Calm => "all clear",
+                WeatherState::Watch => "worth watching",
+                WeatherState::Act => "action recommended",
+            }
+            .to_string(),
+        ),
+    });
+
+    Popover {
+        headline: Some(weather.summary.clone()),
+        subtext: weather.details.clone(),
+        metrics,
+        quick_actions: quick_actions(weather),
+        last_scan,
+    }
+}
+
+fn metric_state(state: WeatherState) -> MetricState {
+    match state {
+        WeatherState::Calm => MetricState::Good,
+        WeatherState::Watch => MetricState::Warning,
+        WeatherState::Act => MetricState::Critical,
+    }
+}
+
+/// Weather trends describe health; tray metrics show which way the value moves.
+fn metric_trend(trends: Option<&Trends>, category: &str) -> Option<MetricTrend> {
+    let trends = trends?;
+    let trend = match category {
+        "disk" => trends.disk_usage.as_ref(),
+        "memory" => trends.memory_pressure.as_ref(),
+        "cpu" => trends.cpu_load.as_ref(),
+        _ => None,
+    }?;
+    Some(match trend.direction {
+        TrendDirection::Degrading => MetricTrend::Up,
+        TrendDirection::Improving => MetricTrend::Down,
+        TrendDirection::Stable => MetricTrend::Stable,
+    })
+}
+
+fn quick_actions(weather: &SystemWeather) -> Vec<QuickAction> {
+    let mut actions: Vec<QuickAction> = Vec::new();
+    for suggested in &weather.actions {
+        let action = match suggested.handler {
+            Some(ActionHandler::OpenTheatre | ActionHandler::OpenAAndE | ActionHandler::OpenPsa) => QuickActionType::OpenDashboard,
+            Some(ActionHandler::Snooze) => QuickActionType::Snooze,
+            Some(ActionHandler::Dismiss) => QuickActionType::Dismiss,
+            Some(ActionHandler::OpenSettings) => QuickActionType::Settings,
+            Some(ActionHandler::Custom) | None => continue,
+        };
+        if actions.iter().any(|a| a.action.as_ref().is_some_and(|t| same_type(t, &action))) {
+            continue;
+        }
+        actions.push(QuickAction {
+            label: Some(suggested.label.clone()),
+            action: Some(action),
+            primary: actions.is_empty(),
+        });
+    }
+    if actions.is_empty() {
+        actions.push(QuickAction {
+            label: Some("Quick scan".to_string()),
+            action: Some(QuickActionType::QuickScan),
+            primary: true,
+        });
+    }
+    actions.truncate(3);
+    actions
+}
+
+fn same_type(a: &QuickActionType, b: &QuickActionType) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+fn title_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn relative(elapsed: Duration) -> String {
+    let minutes = elapsed.num_minutes();
+    if minutes < 1 {
+        "just now".to_string()
+    } else if minutes < 60 {
+        format!("{} minute(s) ago", minutes)
+    } else if minutes < 48 * 60 {
+        format!("{} hour(s) ago", elapsed.num_hours())
+    } else {
+        format!("{} day(s) ago", elapsed.num_days())
+    }
+}
+
+/// Limits on how often the Ward may notify.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetPolicy {
+    /// Most notifications in any rolling `period_seconds`.
+    pub max_per_period: u32,
+    pub period_seconds: i64,
+    /// Quiet time after each notification.
+    pub cooldown_seconds: i64,
+    /// How long a persisting Act state waits before notifying again.
+    pub act_repeat_seconds: i64,
+}
+
+impl Default for BudgetPolicy {
+    fn default() -> Self {
+        Self {
+            max_per_period: 3,
+            period_seconds: 24 * 3600,
+            cooldown_seconds: 4 * 3600,
+            act_repeat_seconds: 24 * 3600,
+        }
+    }
+}
+
+/// Record of notifications sent, cooldowns and snoozes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationBudget {
+    pub policy: BudgetPolicy,
+    #[serde(default)]
+    sent: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    last_state: Option<WeatherState>,
+    #[serde(default)]
+    snoozed_until: Option<DateTime<Utc>>,
+}
+
+impl NotificationBudget {
+    pub fn new(policy: BudgetPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Silence notifications until `now + duration`.
+    pub fn snooze(&mut self, now: DateTime<Utc>, duration: Duration) {
+        self.snoozed_until = Some(now + duration);
+    }
+
+    pub fn unsnooze(&mut self) {
+        self.snoozed_until = None;
+    }
+
+    /// Notifications sent within the current period.
+    pub fn sent_in_period(&self, now: DateTime<Utc>) -> usize {
+        let since = now - Duration::seconds(self.policy.period_seconds);
+        self.sent.iter().filter(|t| **t > since).count()
+    }
+
+    /// Decide whether `weather` earns a notification now, and record it if so.
+    pub fn review(&mut self, weather: &SystemWeather, now: DateTime<Utc>) -> AmbientNotifications {
+        self.sent.retain(|t| now - *t < Duration::seconds(self.policy.period_seconds));
+
+        if weather.state == WeatherState::Calm {
+            // Calm resets escalation, so the next problem is news again
+            self.last_state = Some(WeatherState::Calm);
+            return self.quiet(None);
+        }
+        if let Some(hint) = &weather.notifications {
+            if !hint.should_notify || hint.cooldown_until.is_some_and(|until| until > now) {
+                return self.quiet(hint.cooldown_until.filter(|u| *u > now).map(|u| (u, "weather source asked for quiet")));
+            }
+        }
+        if let Some(until) = self.snoozed_until.filter(|u| *u > now) {
+            return self.quiet(Some((until, "snoozed")));
+        }
+        if let Some(last) = self.sent.last() {
+            let until = *last + Duration::seconds(self.policy.cooldown_seconds);
+            if until > now {
+                return self.quiet(Some((until, "cooling down after the last notification")));
+            }
+        }
+        if self.sent.len() >= self.policy.max_per_period as usize {
+            let until = self.sent[0] + Duration::seconds(self.policy.period_seconds);
+            return self.quiet(Some((until, "notification budget used up for this period")));
+        }
+
+        let escalated = self.last_state.map_or(true, |last| weather.state > last);
+        let repeat_due = weather.state == WeatherState::Act
+            && self.sent.last().map_or(true, |t| now - *t >= Duration::seconds(self.policy.act_repeat_seconds));
+        if !escalated && !repeat_due {
+            return self.quiet(None);
+        }
+
+        self.sent.push(now);
+        self.last_state = Some(weather.state);
+        let notification_type = if weather.state == WeatherState::Act {
+            PendingNotificationType::ActionRequired
+        } else {
+            PendingNotificationType::Warning
+        };
+        AmbientNotifications {
+            pending: vec![PendingNotification {
+                id: Some(format!("weather-{}", now.timestamp())),
+                notification_type: Some(notification_type),
+                title: Some(
+                    match weather.state {
+                        WeatherState::Act => "Action recommended",
+                        _ => "Worth watching",
+                    }
+                    .to_string(),
+                ),
+                body: Some(weather.summary.clone()),
+                action_url: None,
+                dismissible: true,
+                expires_at: Some(now + Duration::seconds(self.policy.cooldown_seconds)),
+            }],
+            cooldown: Some(NotificationCooldown {
+                active: true,
+                until: Some(now + Duration::seconds(self.policy.cooldown_seconds)),
+                reason: Some("just notified".to_string()),
+            }),
+        }
+    }
+
+    fn quiet(&self, until: Option<(DateTime<Utc>, &str)>) -> AmbientNotifications {
+        AmbientNotifications {
+            pending: Vec::new(),
+            cooldown: until.map(|(until, reason)| NotificationCooldown {
+                active: true,
+                until: Some(until),
+                reason: Some(reason.to_string()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::{Trend, WeatherSource};
+
+    fn weather(state: WeatherState) -> SystemWeather {
+        let mut w = SystemWeather::calm("summary");
+        w.state = state;
+        w.categories = Some(serde_json::json!({
+            "disk": { "state": state, "summary": "disk usage at 96.0%", "metric_value": 96.0, "metric_unit": "%" },
+            "performance": { "state": "watch", "summary": "1 finding(s)" },
+            "security": { "state": "calm", "summary": "1 finding(s)" }
+        }));
+        w.trends = Some(Trends {
+            disk_usage: Some(Trend {
+                direction: TrendDirection::Degrading,
+                rate: None,
+                forecast: None,
+            }),
+            memory_pressure: None,
+            cpu_load: None,
+            overall: None,
+        });
+        w.source = Some(WeatherSource {
+            tool: None,
+            last_scan: Some(Utc::now() - Duration::minutes(5)),
+            next_scan: None,
+            scan_profile: None,
+        });
+        w
+    }
+
+    #[test]
+    fn test_payload_from_weather() {
+        let calm = PayloadBuilder::new(&SystemWeather::calm("Fine")).build();
+        assert!(calm.indicator.badge.is_none());
+        assert!(calm.notifications.is_none());
+        crate::validate::validate(&calm).unwrap();
+
+        let act = PayloadBuilder::new(&weather(WeatherState::Act)).build();
+        assert!(matches!(act.indicator.icon, Some(IndicatorIcon::Storm)));
+        let badge = act.indicator.badge.as_ref().unwrap();
+        assert_eq!(badge.count, Some(1));
+        let popover = act.popover.as_ref().unwrap();
+        assert_eq!(popover.metrics.len(), 2);
+        assert!(matches!(popover.metrics[0].trend, Some(MetricTrend::Up)));
+        assert!(matches!(popover.metrics[0].state, Some(MetricState::Critical)));
+        assert_eq!(popover.last_scan.as_ref().unwrap().relative.as_deref(), Some("5 minute(s) ago"));
+        assert!(popover.quick_actions[0].primary);
+        crate::validate::validate(&act).unwrap();
+    }
+
+    #[test]
+    fn test_budget_notifies_on_escalation_only() {
+        let mut budget = NotificationBudget::default();
+        let t0 = Utc::now();
+        let watch = weather(WeatherState::Watch);
+        let act = weather(WeatherState::Act);
+
+        let first = PayloadBuilder::new(&watch).at(t0).notify_with(&mut budget).build();
+        assert_eq!(first.notifications.unwrap().pending.len(), 1);
+
+        // Same state later: nothing new to say
+        let again = budget.review(&watch, t0 + Duration::hours(5));
+        assert!(again.pending.is_empty());
+
+        // Escalation inside the cooldown still waits for it
+        let cooling = budget.review(&act, t0 + Duration::hours(1));
+        assert!(cooling.pending.is_empty());
+        assert!(cooling.cooldown.unwrap().active);
+        assert_eq!(budget.review(&act, t0 + Duration::hours(5)).pending.len(), 1);
+    }
+
+    #[test]
+    fn test_budget_snooze_and_period_limit() {
+        let policy = BudgetPolicy {
+            max_per_period: 2,
+            cooldown_seconds: 60,
+            act_repeat_seconds: 60,
+            ..BudgetPolicy::default()
+        };
+        let mut budget = NotificationBudget::new(policy);
+        let act = weather(WeatherState::Act);
+        let t0 = Utc::now();
+
+        budget.snooze(t0, Duration::hours(1));
+        assert_eq!(budget.review(&act, t0).cooldown.unwrap().reason.as_deref(), Some("snoozed"));
+        budget.unsnooze();
+
+        assert_eq!(budget.review(&act, t0).pending.len(), 1);
+        assert_eq!(budget.review(&act, t0 + Duration::minutes(2)).pending.len(), 1);
+        let exhausted = budget.review(&act, t0 + Duration::minutes(4));
+        assert!(exhausted.pending.is_empty());
+        assert!(exhausted.cooldown.unwrap().reason.unwrap().contains("budget"));
+        assert_eq!(budget.sent_in_period(t0 + Duration::minutes(4)), 2);
+
+        // Budget state survives a round trip through storage
+        let restored: NotificationBudget = serde_json::from_str(&serde_json::to_string(&budget).unwrap()).unwrap();
+        assert_eq!(restored.sent_in_period(t0 + Duration::minutes(4)), 2);
+    }
+}
@@ -0,0 +1,748 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Bundle I/O - materialize Run Bundles on disk and read them back.
+//!
+//! [`RunBundleWriter`] creates the root directory from the layout's
+//! `root_dir_pattern`, places contract documents and logs where the layout
+//! says, and enforces the naming rules on every path it writes. `finish`
+//! records each file in the manifest's `contents` with a [`ContentHash`] and
+//! writes the checksum file (`SHA256SUMS` format) next to `manifest.json`.
+//!
+//! [`RunBundleReader`] opens a bundle, validates its manifest, and verifies
+//! every listed file against its recorded hash.
+//!
+//! Root directory patterns accept `{tool}`, `{type}`, `{timestamp}`,
+//! `{short_id}` and `{bundle_id}`; timestamps use the naming rules'
+//! `timestamp_format` tokens (`YYYY`, `MM`, `DD`, `HH`, `mm`, `ss`).
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+
+use crate::envelope::HashAlgorithm;
+use crate::integrity::{self, ArtifactMismatch, IntegrityReport};
+use crate::run_bundle::*;
+use crate::validate::{self, Contract, ValidationErrors};
+use crate::{EvidenceEnvelope, ProcedurePlan, Receipt};
+
+/// Manifest file name of the default layout.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Errors raised while writing or reading a bundle.
+#[derive(Debug)]
+pub enum BundleError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// A document or the manifest does not match its schema.
+    Schema(ValidationErrors),
+    /// A path breaks the bundle's naming rules and cannot be fixed up.
+    Naming(String),
+    /// The bundle on disk does not match its manifest.
+    Integrity(Box<IntegrityReport>),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "bundle I/O error: {}", e),
+            Self::Json(e) => write!(f, "bundle JSON error: {}", e),
+            Self::Schema(e) => write!(f, "{}", e),
+            Self::Naming(msg) => write!(f, "bundle naming rule violated: {}", msg),
+            Self::Integrity(report) => write!(
+                f,
+                "bundle integrity check failed: {} missing, {} modified, {} unlisted",
+                report.missing.len(),
+                report.modified.len(),
+                report.extra.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl From<io::Error> for BundleError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BundleError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<ValidationErrors> for BundleError {
+    fn from(e: ValidationErrors) -> Self {
+        Self::Schema(e)
+    }
+}
+
+/// Naming rules compiled for use.
+#[derive(Debug, Clone)]
+struct Namer {
+    rules: NamingRules,
+    disallowed: Regex,
+}
+
+impl Namer {
+    fn new(rules: NamingRules) -> Result<Self, BundleError> {
+        let disallowed = Regex::new(&format!("[^{}]", rules.allowed_chars))
+            .map_err(|e| BundleError::Naming(format!("allowed_chars is not a character class: {}", e)))?;
+        Ok(Self { rules, disallowed })
+    }
+
+    /// Make one path component safe: disallowed characters become `_`,
+    /// reserved device names get a `_` prefix, and over-long names are
+    /// shortened while keeping their extension.
+    fn sanitize(&self, name: &str) -> String {
+        let mut safe = self.disallowed.replace_all(name, "_").into_owned();
+        if safe.is_empty() || safe.chars().all(|c| c == '.') {
+            safe = format!("_{}", safe);
+        }
+
+        let stem = safe.split('.').next().unwrap_or_default();
+        if self.rules.reserved_names.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            safe = format!("_{}", safe);
+        }
+
+        let max = self.rules.max_filename_length as usize;
+        if safe.len() > max {
+            let ext = safe.rfind('.').filter(|&i| i > 0 && safe.len() - i <= 16).map(|i| safe[i..].to_string());
+            let ext = ext.unwrap_or_default();
+            let keep = max.saturating_sub(ext.len());
+            safe = format!("{}{}", &safe[..keep], ext);
+        }
+        safe
+    }
+
+    /// Sanitize each component of a bundle-relative path.
+    fn relative(&self, path: &str) -> Result<String, BundleError> {
+        let mut parts = Vec::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => parts.push(self.sanitize(&part.to_string_lossy())),
+                Component::CurDir => {}
+                _ => return Err(BundleError::Naming(format!("{} leaves the bundle", path))),
+            }
+        }
+        if parts.is_empty() {
+            return Err(BundleError::Naming("empty path".to_string()));
+        }
+        Ok(parts.join("/"))
+    }
+
+    fn check_length(&self, root_name: &str, relative: &str) -> Result<(), BundleError> {
+        let length = root_name.len() + 1 + relative.len();
+        if length > self.rules.max_path_length as usize {
+            return Err(BundleError::Naming(format!(
+                "{}/{} is {} characters (limit {})",
+                root_name, relative, length, self.rules.max_path_length
+            )));
+        }
+        Ok(())
+    }
+
+    fn timestamp(&self, at: DateTime<Utc>) -> String {
+        let tokens = [("YYYY", "%Y"), ("MM", "%m"), ("DD", "%d"), ("HH", "%H"), ("mm", "%M"), ("ss", "%S")];
+        let mut format = String::new();
+        let mut rest = self.rules.timestamp_format.as_str();
+        'outer: while !rest.is_empty() {
+            for (token, spec) in tokens {
+                if let Some(tail) = rest.strip_prefix(token) {
+                    format.push_str(spec);
+                    rest = tail;
+                    continue 'outer;
+                }
+            }
+            let c = rest.chars().next().expect("non-empty");
+            if c == '%' {
+                format.push_str("%%");
+            } else {
+                format.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        at.format(&format).to_string()
+    }
+}
+
+fn layout_dirs(bundle: &RunBundle) -> BundleDirectories {
+    bundle.layout.directories.clone().unwrap_or(BundleDirectories {
+        snapshots: "snapshots/".to_string(),
+        logs: "logs/".to_string(),
+        diffs: "diffs/".to_string(),
+        exports: "exports/".to_string(),
+        undo: "undo/".to_string(),
+        temp: ".temp/".to_string(),
+    })
+}
+
+fn layout_files(bundle: &RunBundle) -> BundleFiles {
+    bundle.layout.files.clone().unwrap_or(BundleFiles {
+        manifest: MANIFEST_FILE.to_string(),
+        envelope: "envelope.json".to_string(),
+        plan: "plan.json".to_string(),
+        receipt: "receipt.json".to_string(),
+        receipt_human: "receipt.txt".to_string(),
+        summary: "SUMMARY.md".to_string(),
+    })
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Creates a bundle directory and fills it according to its layout.
+pub struct RunBundleWriter {
+    root: PathBuf,
+    root_name: String,
+    manifest: RunBundle,
+    namer: Namer,
+}
+
+impl RunBundleWriter {
+    /// Create a bundle with the default layout under `parent`.
+    pub fn create(parent: &Path, tool: BundleSourceTool, bundle_type: BundleType) -> Result<Self, BundleError> {
+        let mut manifest = RunBundle::new(bundle_type);
+        manifest.source_tool = Some(tool);
+        Self::create_with(parent, manifest)
+    }
+
+    /// Create a bundle under `parent` from a prepared manifest.
+    pub fn create_with(parent: &Path, mut manifest: RunBundle) -> Result<Self, BundleError> {
+        let rules = manifest.naming_rules.clone().unwrap_or_default();
+        let namer = Namer::new(rules.clone())?;
+        manifest.naming_rules = Some(rules);
+        manifest.integrity.get_or_insert_with(BundleIntegrity::default);
+        manifest.contents.clear();
+
+        let tool = manifest
+            .source_tool
+            .and_then(|t| serde_json::to_value(t).ok())
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_else(|| "bundle".to_string());
+        let kind = manifest
+            .bundle_type
+            .and_then(|t| serde_json::to_value(t).ok())
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_else(|| "run".to_string());
+        let id = manifest.bundle_id.simple().to_string();
+        let root_name = namer.sanitize(
+            &manifest
+                .layout
+                .root_dir_pattern
+                .replace("{tool}", &tool)
+                .replace("{type}", &kind)
+                .replace("{timestamp}", &namer.timestamp(manifest.created_at))
+                .replace("{short_id}", &id[..8])
+                .replace("{bundle_id}", &manifest.bundle_id.to_string()),
+        );
+
+        let root = parent.join(&root_name);
+        std::fs::create_dir_all(parent)?;
+        std::fs::create_dir(&root)?;
+        Ok(Self {
+            root,
+            root_name,
+            manifest,
+            namer,
+        })
+    }
+
+    /// The bundle's root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The manifest as it will be written.
+    pub fn manifest(&self) -> &RunBundle {
+        &self.manifest
+    }
+
+    /// Write raw bytes at a bundle-relative path (sanitized per the naming
+    /// rules) and return the path actually used.
+    pub fn write_file(&mut self, relative: &str, content_type: ContentType, bytes: &[u8]) -> Result<String, BundleError> {
+        let relative = self.namer.relative(relative)?;
+        self.namer.check_length(&self.root_name, &relative)?;
+        let files = layout_files(&self.manifest);
+        let checksum = self.manifest.integrity.clone().unwrap_or_default().checksum_file;
+        if relative == files.manifest || relative == checksum {
+            return Err(BundleError::Naming(format!("{} is reserved for the bundle itself", relative)));
+        }
+
+        let path = self.root.join(&relative);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, bytes)?;
+
+        self.manifest.contents.retain(|c| c.path.as_deref() != Some(relative.as_str()));
+        self.manifest.contents.push(BundleContent {
+            path: Some(relative.clone()),
+            content_type: Some(content_type),
+            hash: None,
+            size_bytes: Some(bytes.len() as u64),
+            created_at: Some(Utc::now()),
+        });
+        Ok(relative)
+    }
+
+    /// Write a contract document as pretty JSON after validating it.
+    pub fn write_contract<T: Contract>(&mut self, relative: &str, content_type: ContentType, doc: &T) -> Result<String, BundleError> {
+        validate::validate(doc)?;
+        self.write_file(relative, content_type, &serde_json::to_vec_pretty(doc)?)
+    }
+
+    pub fn write_envelope(&mut self, envelope: &EvidenceEnvelope) -> Result<String, BundleError> {
+        let name = layout_files(&self.manifest).envelope;
+        self.write_contract(&name, ContentType::Envelope, envelope)
+    }
+
+    pub fn write_plan(&mut self, plan: &ProcedurePlan) -> Result<String, BundleError> {
+        let name = layout_files(&self.manifest).plan;
+        self.write_contract(&name, ContentType::Plan, plan)
+    }
+
+    pub fn write_receipt(&mut self, receipt: &Receipt) -> Result<String, BundleError> {
+        let name = layout_files(&self.manifest).receipt;
+        self.write_contract(&name, ContentType::Receipt, receipt)
+    }
+
+    /// Human-readable receipt (`receipt.txt`).
+    pub fn write_receipt_human(&mut self, text: &str) -> Result<String, BundleError> {
+        let name = layout_files(&self.manifest).receipt_human;
+        self.write_file(&name, ContentType::Receipt, text.as_bytes())
+    }
+
+    /// Human-readable summary (`SUMMARY.md`).
+    pub fn write_summary(&mut self, markdown: &str) -> Result<String, BundleError> {
+        let name = layout_files(&self.manifest).summary;
+        self.write_file(&name, ContentType::Other, markdown.as_bytes())
+    }
+
+    pub fn write_log(&mut self, name: &str, bytes: &[u8]) -> Result<String, BundleError> {
+        let path = join(&layout_dirs(&self.manifest).logs, name);
+        self.write_file(&path, ContentType::Log, bytes)
+    }
+
+    pub fn write_snapshot(&mut self, name: &str, bytes: &[u8]) -> Result<String, BundleError> {
+        let path = join(&layout_dirs(&self.manifest).snapshots, name);
+        self.write_file(&path, ContentType::Snapshot, bytes)
+    }
+
+    pub fn write_diff(&mut self, name: &str, bytes: &[u8]) -> Result<String, BundleError> {
+        let path = join(&layout_dirs(&self.manifest).diffs, name);
+        self.write_file(&path, ContentType::Diff, bytes)
+    }
+
+    pub fn write_export(&mut self, name: &str, bytes: &[u8]) -> Result<String, BundleError> {
+        let path = join(&layout_dirs(&self.manifest).exports, name);
+        self.write_file(&path, ContentType::Export, bytes)
+    }
+
+    pub fn write_undo(&mut self, name: &str, bytes: &[u8]) -> Result<String, BundleError> {
+        let path = join(&layout_dirs(&self.manifest).undo, name);
+        self.write_file(&path, ContentType::Undo, bytes)
+    }
+
+    /// Scratch directory, removed by `finish` unless retention says otherwise.
+    pub fn temp_dir(&self) -> Result<PathBuf, BundleError> {
+        let dir = self.root.join(self.namer.relative(&layout_dirs(&self.manifest).temp)?);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Copy every listed file of another (verified) bundle into this one,
+    /// keeping paths and content types.
+    pub fn import(&mut self, other: &RunBundleReader) -> Result<(), BundleError> {
+        for content in &other.manifest().contents {
+            let Some(path) = content.path.as_deref() else { continue };
+            let bytes = other.read_bytes(path)?;
+            self.write_file(path, content.content_type.unwrap_or(ContentType::Other), &bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Hash every file, write the checksum file and manifest, clean up the
+    /// temp directory, and return the bundle root.
+    pub fn finish(mut self) -> Result<PathBuf, BundleError> {
+        let mut sums = String::new();
+        for content in &mut self.manifest.contents {
+            let path = content.path.clone().unwrap_or_default();
+            let (hash, size) = integrity::hash_file(HashAlgorithm::Sha256, &self.root.join(&path))?;
+            sums.push_str(&format!("{}  {}\n", hash.value, path));
+            content.hash = Some(ContentHash {
+                algorithm: Some("sha256".to_string()),
+                value: Some(hash.value),
+            });
+            content.size_bytes = Some(size);
+        }
+
+        let integrity = self.manifest.integrity.clone().unwrap_or_default();
+        std::fs::write(self.root.join(&integrity.checksum_file), sums)?;
+
+        validate::validate(&self.manifest)?;
+        let manifest_name = layout_files(&self.manifest).manifest;
+        std::fs::write(self.root.join(manifest_name), serde_json::to_vec_pretty(&self.manifest)?)?;
+
+        let delete_temp = self
+            .manifest
+            .retention
+            .as_ref()
+            .map_or(true, |r| r.delete_temp_after_completion);
+        let temp = self.root.join(self.namer.relative(&layout_dirs(&self.manifest).temp)?);
+        if delete_temp && temp.is_dir() {
+            std::fs::remove_dir_all(temp)?;
+        }
+
+        Ok(self.root)
+    }
+}
+
+/// An opened bundle.
+#[derive(Debug, Clone)]
+pub struct RunBundleReader {
+    root: PathBuf,
+    manifest: RunBundle,
+}
+
+impl RunBundleReader {
+    /// Open a bundle by its root directory, validating the manifest.
+    ///
+    /// The manifest is [`MANIFEST_FILE`] unless the bundle's layout names
+    /// another file, which is then looked for among the root's JSON files.
+    pub fn open(root: &Path) -> Result<Self, BundleError> {
+        if root.join(MANIFEST_FILE).is_file() {
+            return Self::open_manifest(root, MANIFEST_FILE);
+        }
+        let mut names: Vec<String> = std::fs::read_dir(root)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .filter(|n| n.ends_with(".json"))
+            .collect();
+        names.sort();
+        names
+            .iter()
+            .find_map(|name| Self::open_manifest(root, name).ok())
+            .ok_or_else(|| {
+                BundleError::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no bundle manifest in {}", root.display()),
+                ))
+            })
+    }
+
+    /// Open a bundle whose manifest is the root-relative file `manifest`.
+    /// The manifest's layout must name that same file.
+    pub fn open_manifest(root: &Path, manifest: &str) -> Result<Self, BundleError> {
+        let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(root.join(manifest))?)?;
+        validate::validate_json::<RunBundle>(&raw)?;
+        let reader = Self {
+            root: root.to_path_buf(),
+            manifest: serde_json::from_value(raw)?,
+        };
+        if reader.manifest_path() != manifest {
+            return Err(BundleError::Naming(format!(
+                "{} names {} as the bundle manifest",
+                manifest,
+                reader.manifest_path()
+            )));
+        }
+        Ok(reader)
+    }
+
+    /// Open a bundle and fail unless it verifies.
+    pub fn open_verified(root: &Path) -> Result<Self, BundleError> {
+        let reader = Self::open(root)?;
+        let report = reader.verify()?;
+        if !report.is_intact() {
+            return Err(BundleError::Integrity(Box::new(report)));
+        }
+        Ok(reader)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest(&self) -> &RunBundle {
+        &self.manifest
+    }
+
+    /// Check every listed file against its hash and size, and look for files
+    /// the manifest does not account for.
+    pub fn verify(&self) -> Result<IntegrityReport, BundleError> {
+        let mut report = IntegrityReport::default();
+        let namer = Namer::new(self.manifest.naming_rules.clone().unwrap_or_default())?;
+        let mut listed = BTreeSet::new();
+
+        for content in &self.manifest.contents {
+            let Some(path) = content.path.clone() else { continue };
+            if namer.relative(&path).ok().as_deref() != Some(path.as_str()) {
+                report.rejected.push(path);
+                continue;
+            }
+            listed.insert(path.clone());
+
+            let file = self.root.join(&path);
+            if !file.is_file() {
+                report.missing.push(path);
+                continue;
+            }
+            let expected = content.hash.as_ref().and_then(|h| {
+                let algorithm = serde_json::from_value(serde_json::Value::String(h.algorithm.clone()?)).ok()?;
+                Some((algorithm, h.value.clone()?))
+            });
+            let Some((algorithm, expected)) = expected else {
+                report.unhashed.push(path);
+                continue;
+            };
+
+            let (actual, size) = integrity::hash_file(algorithm, &file)?;
+            if !actual.value.eq_ignore_ascii_case(&expected) || content.size_bytes.is_some_and(|s| s != size) {
+                report.modified.push(ArtifactMismatch {
+                    path,
+                    expected,
+                    actual: actual.value,
+                });
+            } else {
+                report.verified.push(path);
+            }
+        }
+
+        // The checksum file must agree with the manifest
+        let integrity = self.manifest.integrity.clone().unwrap_or_default();
+        if let Ok(sums) = std::fs::read_to_string(self.root.join(&integrity.checksum_file)) {
+            for line in sums.lines() {
+                let Some((hash, path)) = line.split_once("  ") else { continue };
+                let recorded = self
+                    .manifest
+                    .contents
+                    .iter()
+                    .find(|c| c.path.as_deref() == Some(path))
+                    .and_then(|c| c.hash.as_ref()?.value.clone());
+                if recorded.as_deref() != Some(hash) && !report.modified.iter().any(|m| m.path == path) {
+                    report.modified.push(ArtifactMismatch {
+                        path: format!("{} ({})", path, integrity.checksum_file),
+                        expected: recorded.unwrap_or_default(),
+                        actual: hash.to_string(),
+                    });
+                }
+            }
+        }
+
+        let files = layout_files(&self.manifest);
+        let temp = layout_dirs(&self.manifest).temp.trim_end_matches('/').to_string();
+        let own = [files.manifest, integrity.checksum_file, integrity.signature_file];
+        let mut on_disk = Vec::new();
+        integrity::list_files(&self.root, "", &mut on_disk)?;
+        report.extra = on_disk
+            .into_iter()
+            .filter(|f| !listed.contains(f) && !own.contains(f) && !f.starts_with(&format!("{}/", temp)))
+            .collect();
+
+        Ok(report)
+    }
+
+    /// Full path of a bundle-relative file.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// True when the manifest lists `relative`.
+    pub fn contains(&self, relative: &str) -> bool {
+        self.manifest.contents.iter().any(|c| c.path.as_deref() == Some(relative))
+    }
+
+    /// Read a listed file. Unlisted paths, and listed ones that resolve
+    /// outside the bundle root (through `..` or a symlink), are refused.
+    pub fn read_bytes(&self, relative: &str) -> Result<Vec<u8>, BundleError> {
+        if !self.contains(relative) {
+            return Err(BundleError::Naming(format!("{} is not part of the bundle", relative)));
+        }
+        let path = self.root.join(relative).canonicalize()?;
+        if !path.starts_with(self.root.canonicalize()?) {
+            return Err(BundleError::Naming(format!("{} leaves the bundle", relative)));
+        }
+        Ok(std::fs::read(path)?)
+    }
+
+    /// Read and validate a contract document.
+    pub fn read_contract<T: Contract + DeserializeOwned>(&self, relative: &str) -> Result<T, BundleError> {
+        let raw: serde_json::Value = serde_json::from_slice(&self.read_bytes(relative)?)?;
+        validate::validate_json::<T>(&raw)?;
+        Ok(serde_json::from_value(raw)?)
+    }
+
+    /// The bundle's Evidence Envelope, if it has one.
+    pub fn envelope(&self) -> Result<Option<EvidenceEnvelope>, BundleError> {
+        self.optional(&layout_files(&self.manifest).envelope)
+    }
+
+    /// The bundle's Procedure Plan, if it has one.
+    pub fn plan(&self) -> Result<Option<ProcedurePlan>, BundleError> {
+        self.optional(&layout_files(&self.manifest).plan)
+    }
+
+    /// The bundle's Receipt, if it has one.
+    pub fn receipt(&self) -> Result<Option<Receipt>, BundleError> {
+        self.optional(&layout_files(&self.manifest).receipt)
+    }
+
+    /// Bundle-relative path of the manifest file.
+    pub fn manifest_path(&self) -> String {
+        layout_files(&self.manifest).manifest
+    }
+
+    /// Bundle-relative path of the envelope file.
+    pub fn envelope_path(&self) -> String {
+        layout_files(&self.manifest).envelope
+    }
+
+    /// Every bundle-relative path the manifest lists.
+    pub fn listed_paths(&self) -> Vec<String> {
+        self.manifest.contents.iter().filter_map(|c| c.path.clone()).collect()
+    }
+
+    fn optional<T: Contract + DeserializeOwned>(&self, relative: &str) -> Result<Option<T>, BundleError> {
+        if self.contains(relative) {
+            self.read_contract(relative).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::system_report_to_envelope;
+    use crate::receipt::ReceiptStatus;
+
+    fn write_scan_bundle(parent: &Path) -> PathBuf {
+        let mut writer = RunBundleWriter::create(parent, BundleSourceTool::HardwareCrashTeam, BundleType::Scan).unwrap();
+        let envelope = system_report_to_envelope(&serde_json::json!({ "devices": [] }), "h");
+        writer.write_envelope(&envelope).unwrap();
+        writer.write_log("scan.log", b"scanned\n").unwrap();
+        writer.write_summary("# Scan\n").unwrap();
+        std::fs::write(writer.temp_dir().unwrap().join("scratch"), b"x").unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = write_scan_bundle(dir.path());
+
+        let name = root.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("hardware-crash-team-"), "{}", name);
+        assert!(root.join("logs/scan.log").is_file());
+        assert!(root.join("SHA256SUMS").is_file());
+        assert!(!root.join(".temp").exists());
+
+        let reader = RunBundleReader::open_verified(&root).unwrap();
+        assert_eq!(reader.manifest().contents.len(), 3);
+        assert!(reader.envelope().unwrap().is_some());
+        assert!(reader.plan().unwrap().is_none());
+        assert!(reader.read_bytes("../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_custom_manifest_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = RunBundle::new(BundleType::Scan);
+        manifest.layout.files = serde_json::from_value(serde_json::json!({ "manifest": "bundle.json" })).unwrap();
+        let mut writer = RunBundleWriter::create_with(dir.path(), manifest).unwrap();
+        writer.write_summary("# Scan\n").unwrap();
+        let root = writer.finish().unwrap();
+
+        assert!(!root.join(MANIFEST_FILE).exists());
+        let reader = RunBundleReader::open_verified(&root).unwrap();
+        assert_eq!(reader.manifest_path(), "bundle.json");
+        assert!(RunBundleReader::open_manifest(&root, "SHA256SUMS").is_err());
+    }
+
+    #[test]
+    fn test_read_bytes_stays_in_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = write_scan_bundle(dir.path());
+        std::fs::write(dir.path().join("secret"), b"outside").unwrap();
+
+        let manifest_path = root.join(MANIFEST_FILE);
+        let edited = std::fs::read_to_string(&manifest_path).unwrap().replace("\"logs/scan.log\"", "\"logs/../../secret\"");
+        std::fs::write(&manifest_path, edited).unwrap();
+        let reader = RunBundleReader::open(&root).unwrap();
+        assert!(reader.contains("logs/../../secret"));
+        assert!(matches!(reader.read_bytes("logs/../../secret"), Err(BundleError::Naming(_))));
+        assert_eq!(reader.read_bytes("SUMMARY.md").unwrap(), b"# Scan\n");
+    }
+
+    #[test]
+    fn test_verify_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = write_scan_bundle(dir.path());
+
+        std::fs::write(root.join("logs/scan.log"), b"edited\n").unwrap();
+        std::fs::write(root.join("logs/stray.log"), b"?").unwrap();
+        std::fs::remove_file(root.join("SUMMARY.md")).unwrap();
+
+        let report = RunBundleReader::open(&root).unwrap().verify().unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].path, "logs/scan.log");
+        assert_eq!(report.missing, vec!["SUMMARY.md"]);
+        assert_eq!(report.extra, vec!["logs/stray.log"]);
+        assert!(matches!(RunBundleReader::open_verified(&root), Err(BundleError::Integrity(_))));
+    }
+
+    #[test]
+    fn test_naming_rules_enforced() {
+        let namer = Namer::new(NamingRules::default()).unwrap();
+        assert_eq!(namer.sanitize("dmesg: boot #2.log"), "dmesg__boot__2.log");
+        assert_eq!(namer.sanitize("con.txt"), "_con.txt");
+        assert_eq!(namer.sanitize(&format!("{}.log", "a".repeat(300))).len(), 200);
+        assert!(namer.sanitize(&format!("{}.log", "a".repeat(300))).ends_with(".log"));
+        assert!(namer.relative("../escape").is_err());
+        assert_eq!(namer.timestamp("2026-01-02T03:04:05Z".parse().unwrap()), "20260102-030405");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = RunBundle::new(BundleType::Execution);
+        manifest.naming_rules = Some(NamingRules {
+            max_path_length: 60,
+            ..NamingRules::default()
+        });
+        let mut writer = RunBundleWriter::create_with(dir.path(), manifest).unwrap();
+        assert!(matches!(writer.write_log(&"x".repeat(40), b""), Err(BundleError::Naming(_))));
+        assert!(writer.write_file("manifest.json", ContentType::Other, b"{}").is_err());
+    }
+
+    #[test]
+    fn test_import_carries_contents_forward() {
+        let dir = tempfile::tempdir().unwrap();
+        let scan = RunBundleReader::open_verified(&write_scan_bundle(dir.path())).unwrap();
+        let envelope = scan.envelope().unwrap().unwrap();
+
+        let mut writer = RunBundleWriter::create(dir.path(), BundleSourceTool::HardwareCrashTeam, BundleType::Execution).unwrap();
+        writer.import(&scan).unwrap();
+        let plan = crate::conversions::remediation_plan_to_procedure(
+            &serde_json::json!({ "steps": [{ "description": "Unbind", "command": "true" }] }),
+            envelope.envelope_id,
+        );
+        writer.write_plan(&plan).unwrap();
+        writer
+            .write_receipt(&Receipt::new(plan.plan_id, envelope.envelope_id, ReceiptStatus::Completed, Vec::new()))
+            .unwrap();
+        let root = writer.finish().unwrap();
+
+        let reader = RunBundleReader::open_verified(&root).unwrap();
+        assert_eq!(reader.envelope().unwrap().unwrap().envelope_id, envelope.envelope_id);
+        assert_eq!(reader.receipt().unwrap().unwrap().plan_ref, plan.plan_id);
+        assert!(reader.contains("logs/scan.log"));
+    }
+}
@@ -0,0 +1,386 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Case File - check that the references between contract documents resolve.
+//!
+//! A case file holds a set of envelopes, plans and receipts and follows
+//! their links:
+//!
+//! ```text
+//! Finding.evidence_refs ──► Artifact.artifact_id        (same envelope)
+//! ProcedurePlan.envelope_ref ──► EvidenceEnvelope.envelope_id
+//! PlanStep.finding_refs ──► Finding.finding_id          (plan's envelope)
+//! Receipt.plan_ref ──► ProcedurePlan.plan_id
+//! Receipt.envelope_ref ──► the plan's envelope_ref
+//! StepResult.step_ref / UndoStep.step_ref ──► PlanStep.step_id
+//! ```
+//!
+//! Anything that does not resolve is a [`LinkProblem`] and breaks the chain.
+//! Findings that no plan step addresses, in an envelope that does have a
+//! plan, are reported as orphans - worth a look, but not broken.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::bundle::{BundleError, RunBundleReader};
+use crate::validate::{validate_json, ValidationErrors};
+use crate::{EvidenceEnvelope, ProcedurePlan, Receipt};
+
+/// Errors raised while loading documents into a case file.
+#[derive(Debug)]
+pub enum CaseFileError {
+    Json(serde_json::Error),
+    Schema(ValidationErrors),
+    /// The JSON has none of `envelope_id`, `plan_id` or `receipt_id`.
+    UnknownDocument,
+    /// Two different documents share an identifier.
+    DuplicateId(Uuid),
+    Bundle(BundleError),
+}
+
+impl fmt::Display for CaseFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "case file JSON error: {}", e),
+            Self::Schema(e) => write!(f, "{}", e),
+            Self::UnknownDocument => write!(f, "not a contract document (expected envelope_id, plan_id or receipt_id)"),
+            Self::DuplicateId(id) => write!(f, "two different documents share the id {}", id),
+            Self::Bundle(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaseFileError {}
+
+impl From<serde_json::Error> for CaseFileError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<ValidationErrors> for CaseFileError {
+    fn from(e: ValidationErrors) -> Self {
+        Self::Schema(e)
+    }
+}
+
+impl From<BundleError> for CaseFileError {
+    fn from(e: BundleError) -> Self {
+        Self::Bundle(e)
+    }
+}
+
+/// A reference that does not resolve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkProblem {
+    /// A plan names an envelope that is not in the case file.
+    UnknownEnvelope { plan_id: Uuid, envelope_ref: Uuid },
+    /// A receipt names a plan that is not in the case file.
+    UnknownPlan { receipt_id: Uuid, plan_ref: Uuid },
+    /// A receipt's envelope is not the one its plan was made from.
+    ReceiptEnvelopeMismatch { receipt_id: Uuid, envelope_ref: Uuid, plan_envelope_ref: Uuid },
+    /// A plan step addresses a finding its envelope does not contain.
+    UnknownFinding { plan_id: Uuid, step_id: String, finding_ref: String },
+    /// A finding cites an artifact its envelope does not contain.
+    UnknownArtifact { envelope_id: Uuid, finding_id: String, artifact_ref: Uuid },
+    /// A receipt reports on, or undoes, a step its plan does not contain.
+    UnknownStep { receipt_id: Uuid, step_ref: String },
+}
+
+impl fmt::Display for LinkProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownEnvelope { plan_id, envelope_ref } => {
+                write!(f, "plan {} refers to unknown envelope {}", plan_id, envelope_ref)
+            }
+            Self::UnknownPlan { receipt_id, plan_ref } => {
+                write!(f, "receipt {} is for unknown plan {}", receipt_id, plan_ref)
+            }
+            Self::ReceiptEnvelopeMismatch { receipt_id, envelope_ref, plan_envelope_ref } => write!(
+                f,
+                "receipt {} refers to envelope {} but its plan was made from {}",
+                receipt_id, envelope_ref, plan_envelope_ref
+            ),
+            Self::UnknownFinding { plan_id, step_id, finding_ref } => {
+                write!(f, "plan {} step {} addresses unknown finding {}", plan_id, step_id, finding_ref)
+            }
+            Self::UnknownArtifact { envelope_id, finding_id, artifact_ref } => write!(
+                f,
+                "envelope {} finding {} cites unknown artifact {}",
+                envelope_id, finding_id, artifact_ref
+            ),
+            Self::UnknownStep { receipt_id, step_ref } => {
+                write!(f, "receipt {} refers to unknown plan step {}", receipt_id, step_ref)
+            }
+        }
+    }
+}
+
+/// A finding that no plan step addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanedFinding {
+    pub envelope_id: Uuid,
+    pub finding_id: String,
+    pub title: String,
+}
+
+/// Result of checking a case file.
+#[derive(Debug, Clone, Default)]
+pub struct CaseReport {
+    pub problems: Vec<LinkProblem>,
+    pub orphaned_findings: Vec<OrphanedFinding>,
+}
+
+impl CaseReport {
+    /// True when every reference resolves. Orphans do not break the chain.
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// A set of contract documents to check together.
+#[derive(Debug, Clone, Default)]
+pub struct CaseFile {
+    envelopes: BTreeMap<Uuid, EvidenceEnvelope>,
+    plans: BTreeMap<Uuid, ProcedurePlan>,
+    receipts: BTreeMap<Uuid, Receipt>,
+}
+
+impl CaseFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect the envelope, plan and receipt of a Run Bundle.
+    pub fn from_bundle(bundle: &RunBundleReader) -> Result<Self, CaseFileError> {
+        let mut case = Self::new();
+        if let Some(envelope) = bundle.envelope()? {
+            case.add_envelope(envelope)?;
+        }
+        if let Some(plan) = bundle.plan()? {
+            case.add_plan(plan)?;
+        }
+        if let Some(receipt) = bundle.receipt()? {
+            case.add_receipt(receipt)?;
+        }
+        Ok(case)
+    }
+
+    pub fn add_envelope(&mut self, envelope: EvidenceEnvelope) -> Result<(), CaseFileError> {
+        insert(&mut self.envelopes, envelope.envelope_id, envelope)
+    }
+
+    pub fn add_plan(&mut self, plan: ProcedurePlan) -> Result<(), CaseFileError> {
+        insert(&mut self.plans, plan.plan_id, plan)
+    }
+
+    pub fn add_receipt(&mut self, receipt: Receipt) -> Result<(), CaseFileError> {
+        insert(&mut self.receipts, receipt.receipt_id, receipt)
+    }
+
+    /// Add any contract document, recognised by its identifier field and
+    /// validated against its schema.
+    pub fn add_json(&mut self, json: &serde_json::Value) -> Result<(), CaseFileError> {
+        if json.get("envelope_id").is_some() {
+            validate_json::<EvidenceEnvelope>(json)?;
+            self.add_envelope(serde_json::from_value(json.clone())?)
+        } else if json.get("receipt_id").is_some() {
+            validate_json::<Receipt>(json)?;
+            self.add_receipt(serde_json::from_value(json.clone())?)
+        } else if json.get("plan_id").is_some() {
+            validate_json::<ProcedurePlan>(json)?;
+            self.add_plan(serde_json::from_value(json.clone())?)
+        } else {
+            Err(CaseFileError::UnknownDocument)
+        }
+    }
+
+    pub fn envelope(&self, id: &Uuid) -> Option<&EvidenceEnvelope> {
+        self.envelopes.get(id)
+    }
+
+    pub fn plan(&self, id: &Uuid) -> Option<&ProcedurePlan> {
+        self.plans.get(id)
+    }
+
+    pub fn receipt(&self, id: &Uuid) -> Option<&Receipt> {
+        self.receipts.get(id)
+    }
+
+    /// Follow every link and report what does not resolve.
+    pub fn check(&self) -> CaseReport {
+        let mut report = CaseReport::default();
+
+        for envelope in self.envelopes.values() {
+            let artifacts: BTreeSet<Uuid> = envelope.artifacts.iter().map(|a| a.artifact_id).collect();
+            for finding in &envelope.findings {
+                for artifact_ref in finding.evidence_refs.iter().filter(|r| !artifacts.contains(r)) {
+                    report.problems.push(LinkProblem::UnknownArtifact {
+                        envelope_id: envelope.envelope_id,
+                        finding_id: finding.finding_id.clone(),
+                        artifact_ref: *artifact_ref,
+                    });
+                }
+            }
+        }
+
+        let mut addressed: BTreeSet<(Uuid, &str)> = BTreeSet::new();
+        for plan in self.plans.values() {
+            let Some(envelope) = self.envelopes.get(&plan.envelope_ref) else {
+                report.problems.push(LinkProblem::UnknownEnvelope {
+                    plan_id: plan.plan_id,
+                    envelope_ref: plan.envelope_ref,
+                });
+                continue;
+            };
+            for step in &plan.steps {
+                for finding_ref in &step.finding_refs {
+                    if envelope.findings.iter().any(|f| &f.finding_id == finding_ref) {
+                        addressed.insert((envelope.envelope_id, finding_ref));
+                    } else {
+                        report.problems.push(LinkProblem::UnknownFinding {
+                            plan_id: plan.plan_id,
+                            step_id: step.step_id.clone(),
+                            finding_ref: finding_ref.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for receipt in self.receipts.values() {
+            let Some(plan) = self.plans.get(&receipt.plan_ref) else {
+                report.problems.push(LinkProblem::UnknownPlan {
+                    receipt_id: receipt.receipt_id,
+                    plan_ref: receipt.plan_ref,
+                });
+                continue;
+            };
+            if receipt.envelope_ref != plan.envelope_ref {
+                report.problems.push(LinkProblem::ReceiptEnvelopeMismatch {
+                    receipt_id: receipt.receipt_id,
+                    envelope_ref: receipt.envelope_ref,
+                    plan_envelope_ref: plan.envelope_ref,
+                });
+            }
+
+            let undo_refs = receipt.undo_bundle.iter().flat_map(|u| u.steps.iter()).filter_map(|s| s.step_ref.as_ref());
+            let step_refs = receipt.steps_executed.iter().filter_map(|s| s.step_ref.as_ref()).chain(undo_refs);
+            let mut seen = BTreeSet::new();
+            for step_ref in step_refs {
+                if !plan.steps.iter().any(|s| &s.step_id == step_ref) && seen.insert(step_ref) {
+                    report.problems.push(LinkProblem::UnknownStep {
+                        receipt_id: receipt.receipt_id,
+                        step_ref: step_ref.clone(),
+                    });
+                }
+            }
+        }
+
+        let planned: BTreeSet<Uuid> = self.plans.values().map(|p| p.envelope_ref).collect();
+        for envelope in self.envelopes.values().filter(|e| planned.contains(&e.envelope_id)) {
+            for finding in &envelope.findings {
+                if !addressed.contains(&(envelope.envelope_id, finding.finding_id.as_str())) {
+                    report.orphaned_findings.push(OrphanedFinding {
+                        envelope_id: envelope.envelope_id,
+                        finding_id: finding.finding_id.clone(),
+                        title: finding.title.clone(),
+                    });
+                }
+            }
+        }
+
+        report
+    }
+}
+
+fn insert<T: serde::Serialize>(map: &mut BTreeMap<Uuid, T>, id: Uuid, doc: T) -> Result<(), CaseFileError> {
+    if let Some(existing) = map.get(&id) {
+        // The same document added twice is harmless; a different one is not
+        if serde_json::to_value(existing)? != serde_json::to_value(&doc)? {
+            return Err(CaseFileError::DuplicateId(id));
+        }
+        return Ok(());
+    }
+    map.insert(id, doc);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions;
+
+    fn envelope() -> EvidenceEnvelope {
+        let report = serde_json::json!({
+            "devices": [{ "issues": [
+                { "severity": "High", "description": "Device 01:00.0 is a zombie" },
+                { "severity": "Warning", "description": "Device 02:00.0 is noisy" }
+            ]}]
+        });
+        conversions::system_report_to_envelope(&report, "host")
+    }
+
+    fn chain() -> (EvidenceEnvelope, ProcedurePlan, Receipt) {
+        let envelope = envelope();
+        let native = serde_json::json!({
+            "steps": [{ "description": "Claim 01:00.0", "command": "true" }],
+            "undo_steps": [{ "description": "Release 01:00.0", "command": "true" }]
+        });
+        let mut plan = conversions::remediation_plan_to_procedure(&native, envelope.envelope_id);
+        conversions::link_device_findings(&mut plan, &envelope, "01:00.0");
+        let receipt = conversions::remediation_receipt_to_contract(
+            &serde_json::json!({ "plan": native }),
+            plan.plan_id,
+            envelope.envelope_id,
+        );
+        (envelope, plan, receipt)
+    }
+
+    #[test]
+    fn test_intact_chain() {
+        let (envelope, plan, receipt) = chain();
+        let mut case = CaseFile::new();
+        case.add_envelope(envelope.clone()).unwrap();
+        case.add_plan(plan.clone()).unwrap();
+        case.add_json(&serde_json::to_value(&receipt).unwrap()).unwrap();
+        case.add_plan(plan).unwrap();
+
+        let report = case.check();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!(report.orphaned_findings.len(), 1);
+        assert!(report.orphaned_findings[0].title.contains("02:00.0"));
+    }
+
+    #[test]
+    fn test_dangling_references_reported() {
+        let (mut envelope, mut plan, mut receipt) = chain();
+        envelope.findings[1].evidence_refs = vec![Uuid::new_v4()];
+        plan.steps[0].finding_refs.push("no-such-finding".to_string());
+        receipt.envelope_ref = Uuid::new_v4();
+        receipt.steps_executed[0].step_ref = Some("step-9".to_string());
+
+        let mut case = CaseFile::new();
+        case.add_envelope(envelope).unwrap();
+        case.add_plan(plan.clone()).unwrap();
+        case.add_receipt(receipt).unwrap();
+        let problems = case.check().problems;
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().any(|p| matches!(p, LinkProblem::UnknownArtifact { .. })));
+        assert!(problems.iter().any(|p| matches!(p, LinkProblem::UnknownFinding { .. })));
+        assert!(problems.iter().any(|p| matches!(p, LinkProblem::ReceiptEnvelopeMismatch { .. })));
+        assert!(problems.iter().any(|p| matches!(p, LinkProblem::UnknownStep { .. })));
+
+        // Without its envelope, or with a receipt for another plan
+        let mut case = CaseFile::new();
+        case.add_plan(plan.clone()).unwrap();
+        let (_, other_plan, other_receipt) = chain();
+        case.add_receipt(other_receipt).unwrap();
+        let problems = case.check().problems;
+        assert!(problems.contains(&LinkProblem::UnknownEnvelope { plan_id: plan.plan_id, envelope_ref: plan.envelope_ref }));
+        assert!(problems.iter().any(|p| matches!(p, LinkProblem::UnknownPlan { plan_ref, .. } if *plan_ref == other_plan.plan_id)));
+
+        plan.title = Some("changed".to_string());
+        assert!(matches!(case.add_plan(plan), Err(CaseFileError::DuplicateId(_))));
+    }
+}
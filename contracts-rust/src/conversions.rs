@@ -11,9 +11,14 @@
 use chrono::Utc;
 use uuid::Uuid;
 
+/// File name of the scan report artifact referenced by [`system_report_to_envelope`].
+pub const SCAN_REPORT_FILE: &str = "scan-report.json";
+
 /// Convert a hardware-crash-team SystemReport into an EvidenceEnvelope.
 ///
 /// This produces a fully schema-conformant envelope from raw scan output.
+/// The report artifact is hashed over `serde_json::to_vec_pretty(report_json)`,
+/// so writers should store exactly those bytes as [`SCAN_REPORT_FILE`].
 pub fn system_report_to_envelope(
     report_json: &serde_json::Value,
     hostname: &str,
@@ -80,8 +85,8 @@ pub fn system_report_to_envelope(
         artifacts: vec![Artifact {
             artifact_id,
             artifact_type: ArtifactType::Report,
-            path: "scan-report.json".to_string(),
-            hash: None,
+            path: SCAN_REPORT_FILE.to_string(),
+            hash: Some(crate::integrity::hash_bytes(HashAlgorithm::Sha256, &report_bytes)),
             size_bytes: Some(report_bytes.len() as u64),
             mime_type: Some("application/json".to_string()),
             description: Some("Hardware crash team PCI scan report".to_string()),
@@ -94,11 +99,16 @@ pub fn system_report_to_envelope(
 }
 
 /// Convert a hardware-crash-team RemediationPlan into a ProcedurePlan.
+///
+/// Each step takes the undo step at the same position as its
+/// `undo_instruction`. Risk, reversibility, privileges and reboot are derived
+/// by [`crate::safety`] rather than asserted.
 pub fn remediation_plan_to_procedure(
     plan_json: &serde_json::Value,
     envelope_ref: Uuid,
 ) -> ProcedurePlan {
     let mut steps = Vec::new();
+    let undo_steps = plan_json.get("undo_steps").and_then(|s| s.as_array());
 
     if let Some(plan_steps) = plan_json.get("steps").and_then(|s| s.as_array()) {
         for (i, step) in plan_steps.iter().enumerate() {
@@ -116,6 +126,15 @@ pub fn remediation_plan_to_procedure(
                 .get("needs_sudo")
                 .and_then(|n| n.as_bool())
                 .unwrap_or(false);
+            let needs_reboot = step
+                .get("needs_reboot")
+                .and_then(|n| n.as_bool())
+                .unwrap_or(false);
+            let undo_instruction = undo_steps
+                .and_then(|u| u.get(i))
+                .and_then(|u| u.get("command"))
+                .and_then(|c| c.as_str())
+                .map(String::from);
 
             steps.push(PlanStep {
                 step_id: format!("step-{}", i + 1),
@@ -124,11 +143,14 @@ pub fn remediation_plan_to_procedure(
                 title: description.clone(),
                 description: Some(description),
                 preview: Some(command),
-                risk: Some(RiskLevel::Guided),
-                reversibility: Some(Reversibility::Full),
-                undo_instruction: None,
+                risk: None,
+                reversibility: None,
+                undo_instruction,
                 target: None,
-                parameters: None,
+                parameters: Some(serde_json::json!({
+                    "privilege": if needs_sudo { Privilege::Root } else { Privilege::User },
+                    "requires_reboot": needs_reboot,
+                })),
                 finding_refs: Vec::new(),
                 requires_confirmation: true,
                 estimated_duration_seconds: Some(5),
@@ -136,10 +158,11 @@ pub fn remediation_plan_to_procedure(
         }
     }
 
-    let requires_reboot = plan_json
-        .get("requires_reboot")
-        .and_then(|r| r.as_bool())
-        .unwrap_or(false);
+    let derived = crate::safety::derive(&steps);
+    for (step, profile) in steps.iter_mut().zip(&derived.steps) {
+        step.risk = Some(profile.risk);
+        step.reversibility = Some(profile.reversibility);
+    }
 
     let device = plan_json
         .get("device")
@@ -156,88 +179,200 @@ pub fn remediation_plan_to_procedure(
         "Remediate hardware issue on device {} using {} strategy",
         device, strategy
     ));
-    plan.overall_risk = Some(RiskLevel::Guided);
-    plan.overall_reversibility = Some(Reversibility::Full);
-    plan.requires_reboot = requires_reboot;
-    if requires_reboot {
-        plan.requires_privileges = vec![Privilege::Root];
+    derived.apply_to(&mut plan);
+    // The native plan may know of a reboot no single step reports
+    plan.requires_reboot |= plan_json
+        .get("requires_reboot")
+        .and_then(|r| r.as_bool())
+        .unwrap_or(false);
+    if plan.requires_reboot {
+        plan.warnings = vec![
+            "This plan modifies kernel boot parameters.".to_string(),
+            "A reboot will be required for changes to take effect.".to_string(),
+        ];
     }
-    plan.warnings = vec![
-        "This plan modifies kernel boot parameters.".to_string(),
-        "A reboot will be required for changes to take effect.".to_string(),
-    ];
 
     plan
 }
 
+/// Point every step of a device plan at the envelope findings about that
+/// device, and return how many findings were linked.
+///
+/// hardware-crash-team findings name their device slot in the title
+/// (`Device 0000:01:00.0 ...`), which is matched against `device` with or
+/// without its PCI domain.
+pub fn link_device_findings(plan: &mut ProcedurePlan, envelope: &EvidenceEnvelope, device: &str) -> usize {
+    let refs: Vec<String> = envelope
+        .findings
+        .iter()
+        .filter(|f| mentions_slot(&f.title, device))
+        .map(|f| f.finding_id.clone())
+        .collect();
+    for step in &mut plan.steps {
+        for finding_ref in &refs {
+            if !step.finding_refs.contains(finding_ref) {
+                step.finding_refs.push(finding_ref.clone());
+            }
+        }
+    }
+    refs.len()
+}
+
+fn mentions_slot(text: &str, slot: &str) -> bool {
+    text.match_indices(slot).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + slot.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric()) && !after.is_some_and(|c| c.is_ascii_digit())
+    })
+}
+
 /// Convert a hardware-crash-team RemediationReceipt into a contract Receipt.
+///
+/// Per-step status, exit codes and output come from the receipt's `results`.
+/// Receipts written before plans were executed have none; their steps are
+/// all reported as succeeded, as they always were. A verified receipt
+/// counts its checked targets and takes its status from the verification:
+/// Completed when every target ended up as planned, Partial when some did,
+/// Failed when none did.
 pub fn remediation_receipt_to_contract(
     receipt_json: &serde_json::Value,
     plan_ref: Uuid,
     envelope_ref: Uuid,
 ) -> Receipt {
+    let Some(plan) = receipt_json.get("plan") else {
+        return Receipt::new(plan_ref, envelope_ref, ReceiptStatus::Failed, Vec::new());
+    };
+    let results = receipt_json.get("results").and_then(|r| r.as_array());
+    let native_status = receipt_json.get("status").and_then(|s| s.as_str());
+    let plan_steps = plan.get("steps").and_then(|s| s.as_array()).map_or(&[][..], |s| s.as_slice());
+
     let mut step_results = Vec::new();
+    for (i, step) in plan_steps.iter().enumerate() {
+        let result = results.and_then(|r| r.iter().find(|r| r.get("step").and_then(|s| s.as_u64()) == Some(i as u64 + 1)));
+        step_results.push(step_result(i + 1, step, result, native_status == Some("DryRun")));
+    }
+    let succeeded = |r: &&StepResult| matches!(r.status, StepStatus::Success);
+    let changed = step_results.iter().filter(succeeded).count() as u32;
+    let failed = step_results.iter().filter(|r| matches!(r.status, StepStatus::Failed)).count() as u32;
 
-    // The hardware-crash-team receipt contains the original plan steps
-    if let Some(plan) = receipt_json.get("plan") {
-        if let Some(plan_steps) = plan.get("steps").and_then(|s| s.as_array()) {
-            for (i, _step) in plan_steps.iter().enumerate() {
-                step_results.push(StepResult {
-                    step_id: format!("step-{}", i + 1),
-                    step_ref: Some(format!("step-{}", i + 1)),
-                    status: StepStatus::Success,
-                    started_at: Some(Utc::now()),
-                    completed_at: Some(Utc::now()),
-                    what_changed: None,
-                    why_changed: None,
-                    before: None,
-                    after: None,
-                    error: None,
-                    skip_reason: None,
-                });
-            }
-        }
+    let verification = receipt_json.get("verification").filter(|v| !v.is_null());
+    let checks = verification.and_then(|v| v.get("checks")).and_then(|c| c.as_array());
+    let verified = verification.and_then(|v| v.get("outcome")).and_then(|o| o.as_str());
+    let status = match (verified, native_status) {
+        (Some("Verified"), _) => ReceiptStatus::Completed,
+        (Some("Partial"), _) => ReceiptStatus::Partial,
+        (Some(_), _) => ReceiptStatus::Failed,
+        (None, None | Some("Completed")) => ReceiptStatus::Completed,
+        (None, Some("RolledBack")) => ReceiptStatus::RolledBack,
+        (None, Some("DryRun")) => ReceiptStatus::Cancelled,
+        (None, Some(_)) if changed > 0 => ReceiptStatus::Partial,
+        (None, Some(_)) => ReceiptStatus::Failed,
+    };
 
-        // Build undo bundle from undo_steps
-        let undo_steps: Vec<UndoStep> = plan
-            .get("undo_steps")
-            .and_then(|s| s.as_array())
-            .map(|steps| {
-                steps
-                    .iter()
-                    .enumerate()
-                    .map(|(i, step)| UndoStep {
-                        step_ref: Some(format!("step-{}", i + 1)),
-                        reversible: true,
-                        undo_command: step.get("command").and_then(|c| c.as_str()).map(String::from),
-                        backup_path: None,
-                    })
-                    .collect()
+    // Only steps that are still applied can be undone: last first, each by
+    // the undo step at its index. Undo steps already run successfully, in a
+    // rollback or by an earlier undo, name the plan step they undid.
+    let native_undo = plan.get("undo_steps").and_then(|s| s.as_array());
+    let undone = |number: usize| {
+        ["rollback", "undone"]
+            .iter()
+            .filter_map(|field| receipt_json.get(*field).and_then(|r| r.as_array()))
+            .flatten()
+            .any(|o| {
+                o.get("step").and_then(|s| s.as_u64()) == Some(number as u64)
+                    && o.get("status").and_then(|s| s.as_str()) == Some("Success")
             })
-            .unwrap_or_default();
-
-        let mut receipt = Receipt::new(plan_ref, envelope_ref, ReceiptStatus::Completed, step_results);
-        receipt.undo_bundle = Some(UndoBundle {
-            available: !undo_steps.is_empty(),
-            path: None,
-            expires_at: None,
-            steps: undo_steps,
-        });
-        receipt.summary = Some(ReceiptSummary {
-            title: Some("Hardware remediation applied".to_string()),
-            description: Some("Kernel boot parameters modified for hardware isolation".to_string()),
-            items_checked: None,
-            items_changed: Some(receipt.steps_executed.len() as u32),
-            items_unchanged: Some(0),
-            items_failed: Some(0),
-            space_recovered_bytes: None,
-            duration_seconds: None,
-        });
+    };
+    let undo_steps: Vec<UndoStep> = step_results
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(i, r)| matches!(r.status, StepStatus::Success) && !undone(i + 1))
+        .map(|(i, _)| {
+            let undo_command = native_undo
+                .and_then(|u| u.get(i))
+                .and_then(|u| u.get("command"))
+                .and_then(|c| c.as_str())
+                .map(String::from);
+            UndoStep {
+                step_ref: Some(format!("step-{}", i + 1)),
+                reversible: undo_command.is_some(),
+                undo_command,
+                backup_path: None,
+            }
+        })
+        .collect();
 
-        return receipt;
-    }
+    let (title, verb) = match (verified, &status) {
+        (Some(_), ReceiptStatus::Completed) => ("Hardware remediation verified", "took effect on"),
+        (Some(_), ReceiptStatus::Partial) => ("Hardware remediation partially verified", "took effect on some of"),
+        (Some(_), _) => ("Hardware remediation failed verification", "did not take effect on"),
+        (None, ReceiptStatus::Completed) => ("Hardware remediation applied", "applied"),
+        (None, ReceiptStatus::Partial) => ("Hardware remediation partially applied", "failed on"),
+        (None, ReceiptStatus::RolledBack) => ("Hardware remediation rolled back", "rolled back on"),
+        (None, ReceiptStatus::Cancelled) => ("Hardware remediation not run (dry run)", "planned for"),
+        (None, ReceiptStatus::Failed) => ("Hardware remediation failed", "failed on"),
+    };
+    let strategy = plan.get("strategy").and_then(|s| s.as_str()).unwrap_or("remediation");
+    let device = plan.get("device").and_then(|d| d.as_str()).unwrap_or("device");
+
+    let mut receipt = Receipt::new(plan_ref, envelope_ref, status, step_results);
+    receipt.undo_bundle = Some(UndoBundle {
+        available: undo_steps.iter().any(|s| s.reversible),
+        path: None,
+        expires_at: None,
+        steps: undo_steps,
+    });
+    receipt.summary = Some(ReceiptSummary {
+        title: Some(title.to_string()),
+        description: Some(format!("{} {} {}", strategy, verb, device)),
+        items_checked: checks.map(|c| c.len() as u32),
+        items_changed: Some(changed),
+        items_unchanged: Some(plan_steps.len() as u32 - changed - failed),
+        items_failed: Some(failed),
+        space_recovered_bytes: None,
+        duration_seconds: None,
+    });
+    receipt
+}
 
-    Receipt::new(plan_ref, envelope_ref, ReceiptStatus::Failed, step_results)
+/// Contract result for plan step `number`, from its native outcome if any.
+fn step_result(number: usize, step: &serde_json::Value, result: Option<&serde_json::Value>, dry_run: bool) -> StepResult {
+    let field = |name: &str| result.and_then(|r| r.get(name));
+    let time = |name: &str| {
+        field(name)
+            .and_then(|t| t.as_str())
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc))
+    };
+    let status = match field("status").and_then(|s| s.as_str()) {
+        None | Some("Success") => StepStatus::Success,
+        Some("Failed") => StepStatus::Failed,
+        Some("RolledBack") => StepStatus::RolledBack,
+        Some(_) => StepStatus::Skipped,
+    };
+    let output = field("output").and_then(|o| o.as_str()).filter(|o| !o.is_empty()).map(String::from);
+    let description = step.get("description").and_then(|d| d.as_str()).map(String::from);
+
+    StepResult {
+        step_id: format!("step-{}", number),
+        step_ref: Some(format!("step-{}", number)),
+        started_at: if result.is_some() { time("started_at") } else { Some(Utc::now()) },
+        completed_at: if result.is_some() { time("completed_at") } else { Some(Utc::now()) },
+        what_changed: matches!(status, StepStatus::Success | StepStatus::RolledBack).then_some(description).flatten(),
+        why_changed: None,
+        before: None,
+        after: None,
+        error: matches!(status, StepStatus::Failed).then(|| StepError {
+            code: field("exit_code").and_then(|c| c.as_i64()).map(|c| c.to_string()),
+            message: output.clone(),
+            recoverable: true,
+        }),
+        skip_reason: matches!(status, StepStatus::Skipped).then(|| {
+            if dry_run { "Dry run" } else { "An earlier step failed" }.to_string()
+        }),
+        status,
+    }
 }
 
 #[cfg(test)]
@@ -307,6 +442,33 @@ fn test_remediation_plan_to_procedure() {
         assert!(procedure.title.unwrap().contains("01:00.0"));
     }
 
+    #[test]
+    fn test_sudo_steps_require_root() {
+        let step = |sudo: bool| serde_json::json!({ "description": "power", "command": "echo auto", "needs_sudo": sudo, "needs_reboot": false });
+        let plan = |sudo: bool| serde_json::json!({ "device": "01:00.0", "steps": [step(false), step(sudo)] });
+
+        let procedure = remediation_plan_to_procedure(&plan(true), Uuid::new_v4());
+        assert_eq!(procedure.requires_privileges, vec![Privilege::Root]);
+        let procedure = remediation_plan_to_procedure(&plan(false), Uuid::new_v4());
+        assert!(procedure.requires_privileges.is_empty());
+    }
+
+    #[test]
+    fn test_link_device_findings() {
+        let report = serde_json::json!({
+            "devices": [{ "issues": [
+                { "description": "Device 0000:01:00.0 is in D0 with no driver" },
+                { "description": "Device 0000:101:00.0 has no IOMMU group" }
+            ]}]
+        });
+        let envelope = system_report_to_envelope(&report, "test-host");
+        let plan_json = serde_json::json!({ "steps": [{ "description": "claim", "command": "true" }] });
+        let mut procedure = remediation_plan_to_procedure(&plan_json, envelope.envelope_id);
+
+        assert_eq!(link_device_findings(&mut procedure, &envelope, "01:00.0"), 1);
+        assert_eq!(procedure.steps[0].finding_refs, vec![envelope.findings[0].finding_id.clone()]);
+    }
+
     #[test]
     fn test_remediation_receipt_to_contract() {
         let receipt = serde_json::json!({
@@ -332,4 +494,92 @@ fn test_remediation_receipt_to_contract() {
         assert!(contract_receipt.undo_bundle.is_some());
         assert!(contract_receipt.undo_bundle.as_ref().unwrap().available);
     }
+
+    #[test]
+    fn test_failed_receipt_to_contract() {
+        let step = |n: u32| serde_json::json!({ "description": format!("step {}", n), "command": format!("cmd{}", n), "needs_sudo": true, "needs_reboot": false });
+        let outcome = |n: u32, status: &str, exit: Option<i32>| serde_json::json!({
+            "step": n, "command": format!("cmd{}", n), "status": status, "exit_code": exit,
+            "output": if status == "Failed" { "permission denied" } else { "" },
+            "started_at": "2026-02-12T10:00:00Z", "completed_at": "2026-02-12T10:00:01Z"
+        });
+        let mut receipt = serde_json::json!({
+            "plan": { "device": "0000:01:00.0", "strategy": "PciStub", "steps": [step(1), step(2), step(3)], "undo_steps": [step(4), step(5), step(6)] },
+            "applied_at": "2026-02-12T10:00:00Z",
+            "reboot_pending": false,
+            "status": "Failed",
+            "results": [outcome(1, "Success", Some(0)), outcome(2, "Failed", Some(1)), outcome(3, "Skipped", None)]
+        });
+
+        let contract = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(contract.status, ReceiptStatus::Partial));
+        let error = contract.steps_executed[1].error.as_ref().unwrap();
+        assert_eq!(error.code.as_deref(), Some("1"));
+        assert_eq!(error.message.as_deref(), Some("permission denied"));
+        assert_eq!(contract.steps_executed[2].skip_reason.as_deref(), Some("An earlier step failed"));
+        // Only the step that is still applied can be undone
+        let undo = contract.undo_bundle.as_ref().unwrap();
+        assert_eq!(undo.steps.len(), 1);
+        assert_eq!(undo.steps[0].undo_command.as_deref(), Some("cmd4"));
+        assert_eq!(contract.summary.as_ref().unwrap().items_failed, Some(1));
+
+        receipt["status"] = "RolledBack".into();
+        receipt["results"][0]["status"] = "RolledBack".into();
+        let contract = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(contract.status, ReceiptStatus::RolledBack));
+        assert!(matches!(contract.steps_executed[0].status, StepStatus::RolledBack));
+        assert!(!contract.undo_bundle.unwrap().available);
+
+        // Every step ran, but verification found the device unchanged
+        receipt["status"] = "Completed".into();
+        receipt["results"] = serde_json::json!([outcome(1, "Success", Some(0)), outcome(2, "Success", Some(0)), outcome(3, "Success", Some(0))]);
+        receipt["verification"] = serde_json::json!({ "checks": [{ "target": "0000:01:00.0", "outcome_met": false }], "outcome": "Failed" });
+        let contract = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(contract.status, ReceiptStatus::Failed));
+        let summary = contract.summary.as_ref().unwrap();
+        assert_eq!(summary.items_checked, Some(1));
+        assert_eq!(summary.title.as_deref(), Some("Hardware remediation failed verification"));
+
+        receipt["verification"]["outcome"] = "Partial".into();
+        let contract = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(contract.status, ReceiptStatus::Partial));
+        receipt["verification"]["outcome"] = "Verified".into();
+        let contract = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4());
+        assert!(matches!(contract.status, ReceiptStatus::Completed));
+        assert_eq!(contract.summary.unwrap().title.as_deref(), Some("Hardware remediation verified"));
+    }
+
+    #[test]
+    fn test_undo_bundle_runs_last_step_first() {
+        let step = |n: u32| serde_json::json!({ "description": format!("step {}", n), "command": format!("cmd{}", n), "needs_sudo": true, "needs_reboot": false });
+        let outcome = |n: u32, command: &str| serde_json::json!({
+            "step": n, "command": command, "status": "Success", "exit_code": 0, "output": "",
+            "started_at": "2026-02-12T10:00:00Z", "completed_at": "2026-02-12T10:00:01Z"
+        });
+        let mut receipt = serde_json::json!({
+            "plan": { "device": "0000:01:00.0", "strategy": "AcpiPowerOff", "steps": [step(1), step(2)], "undo_steps": [step(3), step(4)] },
+            "applied_at": "2026-02-12T10:00:00Z",
+            "reboot_pending": false,
+            "status": "Completed",
+            "results": [outcome(1, "cmd1"), outcome(2, "cmd2")]
+        });
+
+        let undo = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4()).undo_bundle.unwrap();
+        let steps: Vec<(Option<&str>, Option<&str>)> =
+            undo.steps.iter().map(|s| (s.step_ref.as_deref(), s.undo_command.as_deref())).collect();
+        assert_eq!(steps, vec![(Some("step-2"), Some("cmd4")), (Some("step-1"), Some("cmd3"))]);
+
+        // An earlier undo got through step 2 only
+        receipt["undone"] = serde_json::json!([outcome(2, "cmd4")]);
+        let undo = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4()).undo_bundle.unwrap();
+        assert_eq!(undo.steps.len(), 1);
+        assert_eq!(undo.steps[0].undo_command.as_deref(), Some("cmd3"));
+
+        // A plan written before undo steps were paired has none for step 2
+        receipt["undone"] = serde_json::json!([]);
+        receipt["plan"]["undo_steps"] = serde_json::json!([step(3)]);
+        let undo = remediation_receipt_to_contract(&receipt, Uuid::new_v4(), Uuid::new_v4()).undo_bundle.unwrap();
+        assert!(!undo.steps[0].reversible);
+        assert!(undo.steps[1].reversible);
+    }
 }
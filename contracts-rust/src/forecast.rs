@@ -0,0 +1,618 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Forecast - derive System Weather from Evidence Envelopes and earlier
+//! weather snapshots.
+//!
+//! This is the one place that maps findings and metrics to Calm/Watch/Act;
+//! tools hand over their envelopes instead of inventing their own mapping.
+//!
+//! Inputs: only the newest envelope per (tool, host, profile) counts, and
+//! envelopes older than [`Thresholds::max_envelope_age`] are ignored.
+//!
+//! State, per category and overall (worst category wins), with the
+//! [`Thresholds::default`] values:
+//!
+//! | Input | Watch | Act |
+//! |-------|-------|-----|
+//! | Finding severity | `medium` | `high` or `critical` |
+//! | Count of `low` findings | 3 or more | - |
+//! | `disk_usage` metric (%) | 80 | 95 |
+//! | `memory_pressure` metric (%) | 85 | 95 |
+//! | `cpu_load` metric (%) | 85 | 95 |
+//!
+//! Metrics are read from `envelope.metrics.<name>.value` (or a bare number).
+//! Trends compare each metric with the most recent earlier snapshot that
+//! recorded it: a change within `trend_tolerance` points is stable, a rise
+//! is degrading, a fall improving. The overall trend compares state, then
+//! the number of findings; the earlier count comes from that snapshot's
+//! envelopes when they are among the inputs.
+//!
+//! Actions, highest priority first (at most 10):
+//!
+//! | Condition | Handler | Priority |
+//! |-----------|---------|----------|
+//! | Any critical finding | `open_a_and_e` | high |
+//! | Auto-fixable findings at Watch or above | `open_theatre` | high at Act, else medium |
+//! | Other findings at Watch or above | `open_psa` | high at Act, else medium |
+//! | Metric at Watch or above | `open_psa` | high at Act, else medium |
+//! | Overall Watch | `snooze` | low |
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::envelope::{EvidenceEnvelope, Finding, FindingSeverity};
+use crate::weather::*;
+
+/// Most evidence pointers a snapshot carries.
+const MAX_EVIDENCE_POINTERS: usize = 50;
+
+/// Thresholds the forecast is computed under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thresholds {
+    /// Findings at or above this severity put their category at Act.
+    pub act_severity: FindingSeverity,
+    /// Findings at or above this severity put their category at Watch.
+    pub watch_severity: FindingSeverity,
+    /// This many `low` findings together put the overall state at Watch.
+    pub watch_low_count: usize,
+    pub disk_usage: MetricThreshold,
+    pub memory_pressure: MetricThreshold,
+    pub cpu_load: MetricThreshold,
+    /// Metric changes within this many points count as stable.
+    pub trend_tolerance: f64,
+    /// Envelopes older than this are ignored.
+    pub max_envelope_age: Duration,
+}
+
+/// Watch and Act levels for one percentage metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricThreshold {
+    pub watch: f64,
+    pub act: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            act_severity: FindingSeverity::High,
+            watch_severity: FindingSeverity::Medium,
+            watch_low_count: 3,
+            disk_usage: MetricThreshold { watch: 80.0, act: 95.0 },
+            memory_pressure: MetricThreshold { watch: 85.0, act: 95.0 },
+            cpu_load: MetricThreshold { watch: 85.0, act: 95.0 },
+            trend_tolerance: 2.0,
+            max_envelope_age: Duration::days(7),
+        }
+    }
+}
+
+impl Thresholds {
+    /// Metric name, weather category and thresholds for each tracked metric.
+    fn metrics(&self) -> [(&'static str, &'static str, MetricThreshold); 3] {
+        [
+            ("disk_usage", "disk", self.disk_usage),
+            ("memory_pressure", "memory", self.memory_pressure),
+            ("cpu_load", "cpu", self.cpu_load),
+        ]
+    }
+
+    fn finding_state(&self, severity: FindingSeverity) -> WeatherState {
+        if severity >= self.act_severity {
+            WeatherState::Act
+        } else if severity >= self.watch_severity {
+            WeatherState::Watch
+        } else {
+            WeatherState::Calm
+        }
+    }
+}
+
+impl MetricThreshold {
+    fn state(&self, value: f64) -> WeatherState {
+        if value >= self.act {
+            WeatherState::Act
+        } else if value >= self.watch {
+            WeatherState::Watch
+        } else {
+            WeatherState::Calm
+        }
+    }
+}
+
+/// A metric reading taken from an envelope.
+struct Reading {
+    name: &'static str,
+    category: &'static str,
+    value: f64,
+    unit: String,
+    threshold: MetricThreshold,
+    envelope_id: String,
+}
+
+/// Compute the weather at `now` from envelopes and earlier snapshots.
+pub fn forecast(
+    envelopes: &[EvidenceEnvelope],
+    history: &[SystemWeather],
+    thresholds: &Thresholds,
+    now: DateTime<Utc>,
+) -> SystemWeather {
+    let previous = history
+        .iter()
+        .filter(|w| w.timestamp < now)
+        .max_by_key(|w| w.timestamp);
+    let previous_findings = previous.and_then(|p| previous_finding_count(p, envelopes));
+    let envelopes = current_envelopes(envelopes, thresholds, now);
+
+    let mut findings: Vec<&Finding> = envelopes.iter().flat_map(|e| e.findings.iter()).collect();
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    let readings = read_metrics(&envelopes, thresholds);
+
+    // Per-category state
+    let mut categories: BTreeMap<String, serde_json::Value> = BTreeMap::new();
+    let mut category_states: BTreeMap<String, WeatherState> = BTreeMap::new();
+    for finding in &findings {
+        let name = category_name(finding);
+        let state = thresholds.finding_state(finding.severity);
+        let entry = category_states.entry(name).or_insert(WeatherState::Calm);
+        *entry = (*entry).max(state);
+    }
+    for (name, state) in &category_states {
+        let count = findings.iter().filter(|f| &category_name(f) == name).count();
+        categories.insert(
+            name.clone(),
+            serde_json::json!({ "state": state, "summary": format!("{} finding(s)", count) }),
+        );
+    }
+    for reading in &readings {
+        let state = reading.threshold.state(reading.value);
+        let merged = category_states.get(reading.category).copied().unwrap_or(WeatherState::Calm).max(state);
+        category_states.insert(reading.category.to_string(), merged);
+        categories.insert(
+            reading.category.to_string(),
+            serde_json::json!({
+                "state": merged,
+                "summary": format!("{} at {:.1}{}", reading.name.replace('_', " "), reading.value, reading.unit),
+                "metric_value": reading.value,
+                "metric_unit": reading.unit,
+                "threshold_warning": reading.threshold.watch,
+                "threshold_critical": reading.threshold.act,
+            }),
+        );
+    }
+
+    let low_count = findings.iter().filter(|f| f.severity == FindingSeverity::Low).count();
+    let mut state = category_states.values().copied().max().unwrap_or(WeatherState::Calm);
+    if state == WeatherState::Calm && low_count >= thresholds.watch_low_count {
+        state = WeatherState::Watch;
+    }
+
+    let mut weather = SystemWeather::calm("");
+    weather.timestamp = now;
+    weather.state = state;
+    weather.summary = summary(state, envelopes.len(), &findings, &readings, thresholds);
+    weather.details = details(&category_states, &categories);
+    weather.categories = (!categories.is_empty()).then(|| serde_json::to_value(&categories).unwrap_or_default());
+    weather.evidence_pointers = evidence_pointers(&envelopes, &findings, &readings);
+    weather.actions = actions(state, &findings, &readings, thresholds);
+    weather.trends = trends(state, findings.len(), &readings, previous, previous_findings, thresholds, now);
+    weather.source = source(&envelopes);
+    weather
+}
+
+/// Newest envelope per (tool, host, profile), dropping stale ones.
+fn current_envelopes<'a>(envelopes: &'a [EvidenceEnvelope], thresholds: &Thresholds, now: DateTime<Utc>) -> Vec<&'a EvidenceEnvelope> {
+    let mut newest: BTreeMap<String, &EvidenceEnvelope> = BTreeMap::new();
+    for envelope in envelopes {
+        if now - envelope.created_at > thresholds.max_envelope_age || envelope.created_at > now {
+            continue;
+        }
+        let key = format!(
+            "{}|{}|{}",
+            serde_json::to_string(&envelope.source.tool).unwrap_or_default(),
+            envelope.source.host.hostname,
+            envelope.source.profile.as_deref().unwrap_or_default()
+        );
+        match newest.get(&key) {
+            Some(existing) if existing.created_at >= envelope.created_at => {}
+            _ => {
+                newest.insert(key, envelope);
+            }
+        }
+    }
+    let mut current: Vec<_> = newest.into_values().collect();
+    current.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+    current
+}
+
+fn read_metrics(envelopes: &[&EvidenceEnvelope], thresholds: &Thresholds) -> Vec<Reading> {
+    let mut readings: Vec<Reading> = Vec::new();
+    // Envelopes are newest first, so the first reading of a metric wins
+    for envelope in envelopes {
+        let Some(metrics) = envelope.metrics.as_ref() else { continue };
+        for (name, category, threshold) in thresholds.metrics() {
+            if readings.iter().any(|r| r.name == name) {
+                continue;
+            }
+            let Some(metric) = metrics.get(name) else { continue };
+            let value = metric.get("value").unwrap_or(metric).as_f64();
+            if let Some(value) = value {
+                readings.push(Reading {
+                    name,
+                    category,
+                    value,
+                    unit: metric.get("unit").and_then(|u| u.as_str()).unwrap_or("%").to_string(),
+                    threshold,
+                    envelope_id: envelope.envelope_id.to_string(),
+                });
+            }
+        }
+    }
+    readings
+}
+
+fn category_name(finding: &Finding) -> String {
+    serde_json::to_value(finding.category)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| "other".to_string())
+}
+
+fn summary(state: WeatherState, envelopes: usize, findings: &[&Finding], readings: &[Reading], thresholds: &Thresholds) -> String {
+    let notable: Vec<&&Finding> = findings
+        .iter()
+        .filter(|f| thresholds.finding_state(f.severity) == state)
+        .collect();
+    let hot: Vec<&Reading> = readings.iter().filter(|r| r.threshold.state(r.value) == state).collect();
+
+    let headline = match (notable.first(), hot.first()) {
+        (Some(f), _) => f.title.clone(),
+        (None, Some(r)) => format!("{} is at {:.0}{}", r.name.replace('_', " "), r.value, r.unit),
+        (None, None) => format!("{} low-severity finding(s) are building up", findings.len()),
+    };
+    let more = (notable.len() + hot.len()).saturating_sub(1);
+    let tail = if more > 0 { format!(" (and {} more)", more) } else { String::new() };
+
+    match state {
+        WeatherState::Calm if envelopes == 0 => "No recent evidence to report on.".to_string(),
+        WeatherState::Calm => format!("All clear across {} recent report(s).", envelopes),
+        WeatherState::Watch => format!("Worth watching: {}{}.", headline, tail),
+        WeatherState::Act => format!("Action recommended: {}{}.", headline, tail),
+    }
+}
+
+fn details(states: &BTreeMap<String, WeatherState>, categories: &BTreeMap<String, serde_json::Value>) -> Option<String> {
+    let lines: Vec<String> = states
+        .iter()
+        .filter(|(_, state)| **state != WeatherState::Calm)
+        .map(|(name, state)| {
+            let summary = categories
+                .get(name)
+                .and_then(|c| c.get("summary"))
+                .and_then(|s| s.as_str())
+                .unwrap_or_default();
+            let state = serde_json::to_value(state).ok().and_then(|v| v.as_str().map(str::to_string));
+            format!("{}: {} ({})", name, state.unwrap_or_default(), summary)
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("; "))
+}
+
+fn evidence_pointers(envelopes: &[&EvidenceEnvelope], findings: &[&Finding], readings: &[Reading]) -> Vec<EvidencePointer> {
+    let mut pointers: Vec<EvidencePointer> = envelopes
+        .iter()
+        .map(|e| EvidencePointer {
+            pointer_type: EvidenceType::Envelope,
+            reference: e.envelope_id.to_string(),
+            label: Some(format!("{} on {}", tool_name(e), e.source.host.hostname)),
+        })
+        .collect();
+    pointers.extend(readings.iter().map(|r| EvidencePointer {
+        pointer_type: EvidenceType::Metric,
+        reference: format!("{}#/metrics/{}", r.envelope_id, r.name),
+        label: Some(r.name.to_string()),
+    }));
+    pointers.extend(findings.iter().map(|f| EvidencePointer {
+        pointer_type: EvidenceType::Finding,
+        reference: f.finding_id.clone(),
+        label: Some(f.title.clone()),
+    }));
+    pointers.truncate(MAX_EVIDENCE_POINTERS);
+    pointers
+}
+
+fn actions(state: WeatherState, findings: &[&Finding], readings: &[Reading], thresholds: &Thresholds) -> Vec<SuggestedAction> {
+    let urgency = |s: WeatherState| if s == WeatherState::Act { ActionPriority::High } else { ActionPriority::Medium };
+    let mut actions = Vec::new();
+
+    let critical: Vec<&&Finding> = findings.iter().filter(|f| f.severity == FindingSeverity::Critical).collect();
+    if !critical.is_empty() {
+        actions.push(action(
+            "triage-critical",
+            format!("Triage {} critical finding(s)", critical.len()),
+            critical.first().map(|f| f.title.clone()),
+            ActionPriority::High,
+            ActionHandler::OpenAAndE,
+            serde_json::json!({ "finding_ids": critical.iter().map(|f| &f.finding_id).collect::<Vec<_>>() }),
+        ));
+    }
+
+    let notable = |fixable: bool| -> Vec<&&Finding> {
+        findings
+            .iter()
+            .filter(|f| f.auto_fixable == fixable && thresholds.finding_state(f.severity) != WeatherState::Calm)
+            .collect()
+    };
+    for (fixable, id, verb, handler) in [
+        (true, "review-fixes", "Review fixes for", ActionHandler::OpenTheatre),
+        (false, "investigate-findings", "Investigate", ActionHandler::OpenPsa),
+    ] {
+        let selected = notable(fixable);
+        if let Some(worst) = selected.first() {
+            actions.push(action(
+                id,
+                format!("{} {} finding(s)", verb, selected.len()),
+                worst.recommendation.clone().or_else(|| Some(worst.title.clone())),
+                urgency(thresholds.finding_state(worst.severity)),
+                handler,
+                serde_json::json!({ "finding_ids": selected.iter().map(|f| &f.finding_id).collect::<Vec<_>>() }),
+            ));
+        }
+    }
+
+    for reading in readings {
+        let metric_state = reading.threshold.state(reading.value);
+        if metric_state != WeatherState::Calm {
+            actions.push(action(
+                &format!("check-{}", reading.category),
+                format!("Check {}", reading.name.replace('_', " ")),
+                Some(format!("{:.1}{} (watch at {}, act at {})", reading.value, reading.unit, reading.threshold.watch, reading.threshold.act)),
+                urgency(metric_state),
+                ActionHandler::OpenPsa,
+                serde_json::json!({ "category": reading.category }),
+            ));
+        }
+    }
+
+    if state == WeatherState::Watch {
+        actions.push(action(
+            "snooze",
+            "Remind me tomorrow".to_string(),
+            None,
+            ActionPriority::Low,
+            ActionHandler::Snooze,
+            serde_json::json!({ "duration_seconds": 86_400 }),
+        ));
+    }
+
+    actions.sort_by_key(|a| std::cmp::Reverse(a.priority));
+    actions.truncate(10);
+    actions
+}
+
+fn action(
+    id: &str,
+    label: String,
+    description: Option<String>,
+    priority: ActionPriority,
+    handler: ActionHandler,
+    parameters: serde_json::Value,
+) -> SuggestedAction {
+    SuggestedAction {
+        action_id: Some(id.to_string()),
+        label,
+        description,
+        priority: Some(priority),
+        handler: Some(handler),
+        parameters: Some(parameters),
+    }
+}
+
+fn trends(
+    state: WeatherState,
+    finding_count: usize,
+    readings: &[Reading],
+    previous: Option<&SystemWeather>,
+    previous_findings: Option<usize>,
+    thresholds: &Thresholds,
+    now: DateTime<Utc>,
+) -> Option<Trends> {
+    let previous = previous?;
+    let mut trends = Trends {
+        disk_usage: None,
+        memory_pressure: None,
+        cpu_load: None,
+        overall: None,
+    };
+
+    for reading in readings {
+        let before = previous
+            .categories
+            .as_ref()
+            .and_then(|c| c.get(reading.category))
+            .and_then(|c| c.get("metric_value"))
+            .and_then(|v| v.as_f64());
+        let Some(before) = before else { continue };
+        let trend = metric_trend(reading, before, now - previous.timestamp, thresholds.trend_tolerance);
+        match reading.name {
+            "disk_usage" => trends.disk_usage = Some(trend),
+            "memory_pressure" => trends.memory_pressure = Some(trend),
+            _ => trends.cpu_load = Some(trend),
+        }
+    }
+
+    let findings = previous_findings.map_or(std::cmp::Ordering::Equal, |before| finding_count.cmp(&before));
+    let direction = match state.cmp(&previous.state).then(findings) {
+        std::cmp::Ordering::Greater => TrendDirection::Degrading,
+        std::cmp::Ordering::Less => TrendDirection::Improving,
+        std::cmp::Ordering::Equal => TrendDirection::Stable,
+    };
+    trends.overall = Some(Trend {
+        direction,
+        rate: None,
+        forecast: None,
+    });
+    Some(trends)
+}
+
+/// How many findings an earlier snapshot was made from: summed over its
+/// envelopes if all of them are in `envelopes`, otherwise counted from its
+/// finding pointers, unless those were cut off.
+fn previous_finding_count(previous: &SystemWeather, envelopes: &[EvidenceEnvelope]) -> Option<usize> {
+    let mut count = 0;
+    for pointer in previous.evidence_pointers.iter().filter(|p| p.pointer_type == EvidenceType::Envelope) {
+        match envelopes.iter().find(|e| e.envelope_id.to_string() == pointer.reference) {
+            Some(envelope) => count += envelope.findings.len(),
+            None => {
+                let pointers = &previous.evidence_pointers;
+                return (pointers.len() < MAX_EVIDENCE_POINTERS)
+                    .then(|| pointers.iter().filter(|p| p.pointer_type == EvidenceType::Finding).count());
+            }
+        }
+    }
+    Some(count)
+}
+
+fn metric_trend(reading: &Reading, before: f64, elapsed: Duration, tolerance: f64) -> Trend {
+    let change = reading.value - before;
+    let direction = if change > tolerance {
+        TrendDirection::Degrading
+    } else if change < -tolerance {
+        TrendDirection::Improving
+    } else {
+        TrendDirection::Stable
+    };
+
+    let days = elapsed.num_seconds() as f64 / 86_400.0;
+    let per_day = (days >= 1.0 / 24.0).then(|| change / days);
+    let rate = match per_day {
+        Some(rate) => format!("{:+.1} points/day", rate),
+        None => format!("{:+.1} points", change),
+    };
+    let forecast = match per_day {
+        Some(rate) if direction == TrendDirection::Degrading && rate > 0.0 && reading.value < reading.threshold.act => {
+            let eta = (reading.threshold.act - reading.value) / rate;
+            Some(format!("reaches {}{} in about {:.0} day(s)", reading.threshold.act, reading.unit, eta.ceil()))
+        }
+        _ => None,
+    };
+
+    Trend {
+        direction,
+        rate: Some(rate),
+        forecast,
+    }
+}
+
+fn source(envelopes: &[&EvidenceEnvelope]) -> Option<WeatherSource> {
+    let latest = envelopes.first()?;
+    let tools: BTreeSet<String> = envelopes.iter().map(|e| tool_name(e)).collect();
+    Some(WeatherSource {
+        tool: Some(tools.into_iter().collect::<Vec<_>>().join(", ")),
+        last_scan: Some(latest.created_at),
+        next_scan: None,
+        scan_profile: latest.source.profile.clone(),
+    })
+}
+
+fn tool_name(envelope: &EvidenceEnvelope) -> String {
+    serde_json::to_value(&envelope.source.tool)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::system_report_to_envelope;
+
+    fn envelope(issues: &[(&str, &str)], metrics: Option<serde_json::Value>, age: Duration) -> EvidenceEnvelope {
+        let issues: Vec<_> = issues
+            .iter()
+            .map(|(severity, description)| serde_json::json!({ "severity": severity, "description": description }))
+            .collect();
+        let mut env = system_report_to_envelope(&serde_json::json!({ "devices": [{ "issues": issues }] }), "host");
+        env.created_at = Utc::now() - age;
+        env.metrics = metrics;
+        env
+    }
+
+    #[test]
+    fn test_states_follow_thresholds() {
+        let now = Utc::now();
+        let t = Thresholds::default();
+
+        let calm = forecast(&[envelope(&[], None, Duration::hours(1))], &[], &t, now);
+        assert_eq!(calm.state, WeatherState::Calm);
+        assert!(calm.actions.is_empty());
+        crate::validate::validate(&calm).unwrap();
+
+        let watch = forecast(&[envelope(&[("Warning", "Fan noisy")], None, Duration::hours(1))], &[], &t, now);
+        assert_eq!(watch.state, WeatherState::Watch);
+        assert!(watch.summary.contains("Fan noisy"));
+        assert_eq!(watch.actions.last().unwrap().handler, Some(ActionHandler::Snooze));
+
+        let lows = [("Low", "a"), ("Low", "b"), ("Low", "c")];
+        assert_eq!(forecast(&[envelope(&lows, None, Duration::hours(1))], &[], &t, now).state, WeatherState::Watch);
+
+        let act = forecast(&[envelope(&[("Critical", "GPU wedged")], None, Duration::hours(1))], &[], &t, now);
+        assert_eq!(act.state, WeatherState::Act);
+        assert_eq!(act.actions[0].handler, Some(ActionHandler::OpenAAndE));
+        assert!(act.actions.iter().any(|a| a.handler == Some(ActionHandler::OpenTheatre)));
+        crate::validate::validate(&act).unwrap();
+    }
+
+    #[test]
+    fn test_newest_envelope_per_source_wins() {
+        let now = Utc::now();
+        let old = envelope(&[("Critical", "Old problem")], None, Duration::hours(5));
+        let new = envelope(&[], None, Duration::hours(1));
+        let stale = envelope(&[("Critical", "Ancient")], None, Duration::days(30));
+        let weather = forecast(&[old, new.clone(), stale], &[], &Thresholds::default(), now);
+        assert_eq!(weather.state, WeatherState::Calm);
+        assert_eq!(weather.evidence_pointers[0].reference, new.envelope_id.to_string());
+    }
+
+    #[test]
+    fn test_metric_trends_against_history() {
+        let t = Thresholds::default();
+        let disk = |v: f64| Some(serde_json::json!({ "disk_usage": { "value": v, "unit": "%" } }));
+        let yesterday = Utc::now() - Duration::days(1);
+        let now = Utc::now();
+
+        let before = forecast(&[envelope(&[], disk(70.0), Duration::hours(25))], &[], &t, yesterday);
+        assert_eq!(before.state, WeatherState::Calm);
+        assert!(before.trends.is_none());
+
+        let after = forecast(&[envelope(&[], disk(82.0), Duration::hours(1))], &[before], &t, now);
+        assert_eq!(after.state, WeatherState::Watch);
+        let trends = after.trends.as_ref().unwrap();
+        let disk_trend = trends.disk_usage.as_ref().unwrap();
+        assert_eq!(disk_trend.direction, TrendDirection::Degrading);
+        assert!(disk_trend.forecast.as_deref().unwrap().starts_with("reaches 95%"));
+        assert_eq!(trends.overall.as_ref().unwrap().direction, TrendDirection::Degrading);
+        assert!(after.actions.iter().any(|a| a.action_id.as_deref() == Some("check-disk")));
+        crate::validate::validate(&after).unwrap();
+    }
+
+    #[test]
+    fn test_finding_trend_counts_beyond_pointer_limit() {
+        let t = Thresholds::default();
+        let lows = |n: usize| -> Vec<(&str, &str)> { vec![("Low", "worn"); n] };
+        let yesterday = Utc::now() - Duration::days(1);
+        let now = Utc::now();
+
+        let old = envelope(&lows(60), None, Duration::hours(25));
+        let before = forecast(std::slice::from_ref(&old), &[], &t, yesterday);
+        assert_eq!(before.evidence_pointers.len(), MAX_EVIDENCE_POINTERS);
+
+        let new = envelope(&lows(55), None, Duration::hours(1));
+        let mut psa = envelope(&[], None, Duration::hours(2));
+        psa.source.tool = crate::envelope::SourceTool::Psa;
+        let mut other_host = envelope(&[], None, Duration::hours(3));
+        other_host.source.host.hostname = "other".to_string();
+        let after = forecast(&[old, new, psa, other_host], &[before], &t, now);
+        assert_eq!(after.trends.unwrap().overall.unwrap().direction, TrendDirection::Improving);
+        assert_eq!(after.source.unwrap().tool.as_deref(), Some("hardware-crash-team, psa"));
+    }
+}
@@ -0,0 +1,365 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Integrity - content hashes for envelope artifacts.
+//!
+//! Producers fill `hash`, `size_bytes` and `mime_type` on each artifact from
+//! the file on disk; consumers check an envelope against the directory its
+//! artifacts were delivered in. Artifact paths are relative to that directory
+//! and may not escape it.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+use ring::digest;
+
+use crate::envelope::{Artifact, ArtifactHash, EvidenceEnvelope, HashAlgorithm};
+
+/// Hash an in-memory buffer.
+pub fn hash_bytes(algorithm: HashAlgorithm, bytes: &[u8]) -> ArtifactHash {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// Hash everything a reader yields, returning the hash and the byte count.
+pub fn hash_reader<R: Read>(algorithm: HashAlgorithm, mut reader: R) -> io::Result<(ArtifactHash, u64)> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = [0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        total += n as u64;
+    }
+    Ok((hasher.finish(), total))
+}
+
+/// Hash a file on disk, returning the hash and its size.
+pub fn hash_file(algorithm: HashAlgorithm, path: &Path) -> io::Result<(ArtifactHash, u64)> {
+    hash_reader(algorithm, File::open(path)?)
+}
+
+/// MIME type for an artifact, judged by file extension.
+pub fn mime_type_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match ext.as_str() {
+        "json" => "application/json",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "sarif" => "application/sarif+json",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "diff" | "patch" => "text/x-diff",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "zst" => "application/zstd",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Artifact {
+    /// Fill `hash`, `size_bytes` and `mime_type` from the artifact's file
+    /// under `base_dir`.
+    pub fn fill_from_file(&mut self, base_dir: &Path, algorithm: HashAlgorithm) -> io::Result<()> {
+        let relative = safe_relative_path(&self.path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("artifact path escapes the bundle: {}", self.path),
+            )
+        })?;
+        let path = base_dir.join(relative);
+        let (hash, size) = hash_file(algorithm, &path)?;
+        self.hash = Some(hash);
+        self.size_bytes = Some(size);
+        if self.mime_type.is_none() {
+            self.mime_type = Some(mime_type_for(&path).to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Fill hashes, sizes and MIME types for every artifact of an envelope.
+pub fn fill_artifacts(envelope: &mut EvidenceEnvelope, base_dir: &Path, algorithm: HashAlgorithm) -> io::Result<()> {
+    for artifact in &mut envelope.artifacts {
+        artifact.fill_from_file(base_dir, algorithm)?;
+    }
+    Ok(())
+}
+
+/// An artifact whose content no longer matches the envelope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactMismatch {
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Result of checking an envelope against its artifact directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Artifacts whose hash (and size, if recorded) matched.
+    pub verified: Vec<String>,
+    /// Artifacts listed in the envelope but absent from the directory.
+    pub missing: Vec<String>,
+    /// Artifacts whose hash or size differs from the envelope.
+    pub modified: Vec<ArtifactMismatch>,
+    /// Artifacts present but carrying no hash to check against.
+    pub unhashed: Vec<String>,
+    /// Artifacts with absolute paths or `..` components.
+    pub rejected: Vec<String>,
+    /// Files in the directory that no artifact refers to.
+    pub extra: Vec<String>,
+}
+
+impl IntegrityReport {
+    /// True when every listed artifact is present and verified, and there is
+    /// nothing unaccounted for in the directory.
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty()
+            && self.modified.is_empty()
+            && self.unhashed.is_empty()
+            && self.rejected.is_empty()
+            && self.extra.is_empty()
+    }
+}
+
+/// Check every artifact of `envelope` against the files in `base_dir`, and
+/// list the files under `base_dir` that are not artifacts as `extra`.
+///
+/// `ignore` lists relative paths expected in the directory that are not
+/// artifacts - typically the envelope file itself.
+pub fn verify_artifacts(envelope: &EvidenceEnvelope, base_dir: &Path, ignore: &[&str]) -> io::Result<IntegrityReport> {
+    let mut report = verify_listed_artifacts(envelope, base_dir)?;
+    let mut referenced: BTreeSet<String> = ignore.iter().map(|p| normalize(p)).collect();
+    referenced.extend(
+        envelope
+            .artifacts
+            .iter()
+            .filter(|a| safe_relative_path(&a.path).is_some())
+            .map(|a| normalize(&a.path)),
+    );
+
+    let mut files = Vec::new();
+    list_files(base_dir, "", &mut files)?;
+    report.extra = files.into_iter().filter(|f| !referenced.contains(f)).collect();
+
+    Ok(report)
+}
+
+/// Check every artifact of `envelope` against the files in `base_dir`,
+/// without looking at anything else there. For directories the envelope
+/// does not own, where other files are expected.
+pub fn verify_listed_artifacts(envelope: &EvidenceEnvelope, base_dir: &Path) -> io::Result<IntegrityReport> {
+    let mut report = IntegrityReport::default();
+
+    for artifact in &envelope.artifacts {
+        let Some(relative) = safe_relative_path(&artifact.path) else {
+            report.rejected.push(artifact.path.clone());
+            continue;
+        };
+        let name = normalize(&artifact.path);
+
+        let path = base_dir.join(relative);
+        if !path.is_file() {
+            report.missing.push(name);
+            continue;
+        }
+
+        let Some(expected) = &artifact.hash else {
+            report.unhashed.push(name);
+            continue;
+        };
+
+        let (actual, size) = hash_file(expected.algorithm, &path)?;
+        if !actual.value.eq_ignore_ascii_case(&expected.value) {
+            report.modified.push(ArtifactMismatch {
+                path: name,
+                expected: expected.value.clone(),
+                actual: actual.value,
+            });
+        } else if artifact.size_bytes.is_some_and(|s| s != size) {
+            report.modified.push(ArtifactMismatch {
+                path: name,
+                expected: format!("{} bytes", artifact.size_bytes.unwrap_or_default()),
+                actual: format!("{} bytes", size),
+            });
+        } else {
+            report.verified.push(name);
+        }
+    }
+
+    Ok(report)
+}
+
+struct Hasher {
+    algorithm: HashAlgorithm,
+    state: HasherState,
+}
+
+enum HasherState {
+    Ring(Box<digest::Context>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        let state = match algorithm {
+            HashAlgorithm::Sha256 => HasherState::Ring(Box::new(digest::Context::new(&digest::SHA256))),
+            HashAlgorithm::Sha384 => HasherState::Ring(Box::new(digest::Context::new(&digest::SHA384))),
+            HashAlgorithm::Sha512 => HasherState::Ring(Box::new(digest::Context::new(&digest::SHA512))),
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+        };
+        Self { algorithm, state }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match &mut self.state {
+            HasherState::Ring(ctx) => ctx.update(bytes),
+            HasherState::Blake3(h) => {
+                h.update(bytes);
+            }
+        }
+    }
+
+    fn finish(self) -> ArtifactHash {
+        let value = match self.state {
+            HasherState::Ring(ctx) => hex::encode(ctx.finish()),
+            HasherState::Blake3(h) => h.finalize().to_hex().to_string(),
+        };
+        ArtifactHash {
+            algorithm: self.algorithm,
+            value,
+        }
+    }
+}
+
+/// The artifact path as a relative path that stays inside its directory.
+fn safe_relative_path(path: &str) -> Option<&Path> {
+    let p = Path::new(path);
+    let safe = !path.is_empty()
+        && p.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    safe.then_some(p)
+}
+
+/// Forward-slash path without a leading `./`, for comparing listings.
+fn normalize(path: &str) -> String {
+    Path::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Every file under `dir` as a sorted, forward-slash relative path. Symlinks
+/// and other non-directories are listed, not followed.
+pub(crate) fn list_files(dir: &Path, prefix: &str, out: &mut Vec<String>) -> io::Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &relative, out)?;
+        } else {
+            out.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::system_report_to_envelope;
+    use crate::envelope::ArtifactType;
+    use uuid::Uuid;
+
+    fn artifact(path: &str) -> Artifact {
+        Artifact {
+            artifact_id: Uuid::new_v4(),
+            artifact_type: ArtifactType::Log,
+            path: path.to_string(),
+            hash: None,
+            size_bytes: None,
+            mime_type: None,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(
+            hash_bytes(HashAlgorithm::Sha256, b"abc").value,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash_bytes(HashAlgorithm::Sha384, b"abc").value.len(), 96);
+        assert_eq!(hash_bytes(HashAlgorithm::Sha512, b"abc").value.len(), 128);
+        assert_eq!(
+            hash_bytes(HashAlgorithm::Blake3, b"abc").value,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(hash_bytes(HashAlgorithm::Sha512, b"abc").algorithm, HashAlgorithm::Sha512);
+    }
+
+    #[test]
+    fn test_fill_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("logs")).unwrap();
+        std::fs::write(dir.path().join("logs/dmesg.log"), b"abc").unwrap();
+
+        let mut a = artifact("logs/dmesg.log");
+        a.fill_from_file(dir.path(), HashAlgorithm::Sha256).unwrap();
+        assert_eq!(a.size_bytes, Some(3));
+        assert_eq!(a.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(a.hash, Some(hash_bytes(HashAlgorithm::Sha256, b"abc")));
+
+        assert!(artifact("../etc/shadow").fill_from_file(dir.path(), HashAlgorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_verify_reports_missing_extra_and_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.log", "b.log", "c.log", "stray.txt", "envelope.json"] {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+
+        let mut env = system_report_to_envelope(&serde_json::json!({ "devices": [] }), "h");
+        env.artifacts = vec![artifact("a.log"), artifact("./b.log"), artifact("c.log")];
+        fill_artifacts(&mut env, dir.path(), HashAlgorithm::Blake3).unwrap();
+        env.artifacts.push(artifact("gone.log"));
+        env.artifacts.push(artifact("/etc/passwd"));
+
+        std::fs::write(dir.path().join("c.log"), "edited").unwrap();
+
+        let report = verify_artifacts(&env, dir.path(), &["envelope.json"]).unwrap();
+        assert_eq!(report.verified, vec!["a.log", "b.log"]);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].path, "c.log");
+        assert_eq!(report.missing, vec!["gone.log"]);
+        assert_eq!(report.rejected, vec!["/etc/passwd"]);
+        assert_eq!(report.extra, vec!["stray.txt"]);
+        assert!(!report.is_intact());
+    }
+
+    #[test]
+    fn test_report_artifact_hash_matches_serialized_report() {
+        let report = serde_json::json!({ "devices": [] });
+        let env = system_report_to_envelope(&report, "h");
+        let bytes = serde_json::to_vec_pretty(&report).unwrap();
+        assert_eq!(env.artifacts[0].hash, Some(hash_bytes(HashAlgorithm::Sha256, &bytes)));
+    }
+}
@@ -16,6 +16,16 @@
 pub mod pack_manifest;
 pub mod ambient_payload;
 pub mod run_bundle;
+pub mod validate;
+pub mod signing;
+pub mod integrity;
+pub mod redaction;
+pub mod bundle;
+pub mod retention;
+pub mod forecast;
+pub mod ambient;
+pub mod case_file;
+pub mod safety;
 
 pub use envelope::EvidenceEnvelope;
 pub use plan::ProcedurePlan;
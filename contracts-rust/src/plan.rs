@@ -5,6 +5,8 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::envelope::Signature;
+
 /// Operating Theatre plan representation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcedurePlan {
@@ -33,13 +35,15 @@ pub struct ProcedurePlan {
     pub warnings: Vec<String>,
     #[serde(default = "default_approval")]
     pub approval_required: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<Signature>,
 }
 
 fn default_approval() -> bool {
     true
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum RiskLevel {
     Safe,
@@ -47,7 +51,7 @@ pub enum RiskLevel {
     Expert,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Reversibility {
     Full,
@@ -55,7 +59,7 @@ pub enum Reversibility {
     None,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Privilege {
     User,
@@ -92,7 +96,7 @@ pub struct PlanStep {
     pub estimated_duration_seconds: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StepAction {
     DeleteFile,
@@ -159,6 +163,7 @@ pub fn new(envelope_ref: Uuid, steps: Vec<PlanStep>) -> Self {
             prerequisites: Vec::new(),
             warnings: Vec::new(),
             approval_required: true,
+            signatures: Vec::new(),
         }
     }
 }
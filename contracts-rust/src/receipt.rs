@@ -5,6 +5,8 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::envelope::Signature;
+
 /// The trust anchor: what was checked, what changed, undo guidance.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Receipt {
@@ -25,6 +27,8 @@ pub struct Receipt {
     pub undo_bundle: Option<UndoBundle>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub evidence: Option<ReceiptEvidence>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<Signature>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,6 +172,7 @@ pub fn new(plan_ref: Uuid, envelope_ref: Uuid, status: ReceiptStatus, steps: Vec
             unchanged: Vec::new(),
             undo_bundle: None,
             evidence: None,
+            signatures: Vec::new(),
         }
     }
 }
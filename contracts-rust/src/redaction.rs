@@ -0,0 +1,675 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Redaction - scrub envelopes and artifacts before they leave the machine.
+//!
+//! The presets, applied the same way to envelope fields, metrics JSON and
+//! text artifacts:
+//!
+//! | Profile  | Hosts, users | Home paths | IPv4/IPv6/MAC | Email | Custom patterns |
+//! |----------|--------------|------------|---------------|-------|-----------------|
+//! | None     |              |            |               |       |                 |
+//! | Minimal  | pseudonym    | rewritten  |               |       | yes             |
+//! | Standard | pseudonym    | rewritten  | masked        |       | yes             |
+//! | Maximum  | pseudonym    | rewritten  | masked        | masked| yes             |
+//!
+//! Pseudonyms are derived from a hash of the name (and an optional salt), so
+//! the same host or user maps to the same `host-…`/`user-…` label in every
+//! file of a bundle and across runs. Home directories (`/home/alice/…`) are
+//! rewritten to `~user-…/…`, and users found that way are pseudonymized
+//! wherever else they appear as a whole token (`by alice`, `user=alice`,
+//! `alice@host`), but not inside other words or paths, so a user named `dev`
+//! leaves `/dev/sda` alone. Loopback and unspecified addresses are left
+//! alone since they carry diagnostic value and nothing identifying.
+//!
+//! Every replacement is counted in a [`RedactionLog`] by location and
+//! category. The log never contains the original values.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::envelope::{EvidenceEnvelope, RedactionProfile};
+use crate::message_intent::{IntentRedaction, RedactionLevel};
+
+impl From<RedactionLevel> for RedactionProfile {
+    fn from(level: RedactionLevel) -> Self {
+        match level {
+            RedactionLevel::None => Self::None,
+            RedactionLevel::Minimal => Self::Minimal,
+            RedactionLevel::Standard => Self::Standard,
+            RedactionLevel::Maximum => Self::Maximum,
+        }
+    }
+}
+
+impl std::str::FromStr for RedactionProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "minimal" => Ok(Self::Minimal),
+            "standard" => Ok(Self::Standard),
+            "maximum" => Ok(Self::Maximum),
+            other => Err(format!(
+                "unknown redaction profile '{}' (use none, minimal, standard, maximum)",
+                other
+            )),
+        }
+    }
+}
+
+/// Kind of value that was redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionCategory {
+    Hostname,
+    Username,
+    HomePath,
+    Ipv4,
+    Ipv6,
+    Mac,
+    Email,
+    Custom,
+}
+
+/// Count of replacements of one category at one location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionRecord {
+    /// JSON pointer within the envelope, or `artifact:<path>` for files.
+    pub location: String,
+    pub category: RedactionCategory,
+    pub count: usize,
+}
+
+/// File name for a saved [`RedactionLog`], kept beside the output it describes.
+pub const REDACTION_LOG_FILE: &str = "redaction-log.json";
+
+/// What a [`Redactor`] changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionLog {
+    pub profile: RedactionProfile,
+    pub records: Vec<RedactionRecord>,
+}
+
+impl RedactionLog {
+    /// Total number of replacements.
+    pub fn total(&self) -> usize {
+        self.records.iter().map(|r| r.count).sum()
+    }
+
+    /// Replacements of one category across all locations.
+    pub fn count(&self, category: RedactionCategory) -> usize {
+        self.records
+            .iter()
+            .filter(|r| r.category == category)
+            .map(|r| r.count)
+            .sum()
+    }
+}
+
+/// Errors building a redactor.
+#[derive(Debug)]
+pub enum RedactionError {
+    /// A custom pattern is not a valid regular expression.
+    InvalidPattern { pattern: String, message: String },
+}
+
+impl fmt::Display for RedactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPattern { pattern, message } => {
+                write!(f, "invalid redaction pattern '{}': {}", pattern, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RedactionError {}
+
+/// Which categories a redactor applies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Rules {
+    hostnames: bool,
+    usernames: bool,
+    paths: bool,
+    network: bool,
+    emails: bool,
+    custom: bool,
+}
+
+impl Rules {
+    fn preset(profile: RedactionProfile) -> Self {
+        let base = Self {
+            hostnames: true,
+            usernames: true,
+            paths: true,
+            custom: true,
+            ..Self::default()
+        };
+        match profile {
+            RedactionProfile::None => Self::default(),
+            RedactionProfile::Minimal => base,
+            RedactionProfile::Standard => Self { network: true, ..base },
+            RedactionProfile::Maximum => Self { network: true, emails: true, ..base },
+        }
+    }
+}
+
+/// Applies a redaction profile to envelopes, JSON and text.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    profile: RedactionProfile,
+    rules: Rules,
+    salt: String,
+    hosts: BTreeSet<String>,
+    users: BTreeSet<String>,
+    custom: Vec<(Regex, String)>,
+    counts: BTreeMap<(String, RedactionCategory), usize>,
+}
+
+impl Redactor {
+    /// A redactor applying one of the presets.
+    pub fn new(profile: RedactionProfile) -> Self {
+        Self {
+            profile,
+            rules: Rules::preset(profile),
+            salt: String::new(),
+            hosts: BTreeSet::new(),
+            users: BTreeSet::new(),
+            custom: Vec::new(),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// A redactor for a Message Intent: the profile preset, narrowed by the
+    /// per-category switches, plus any custom patterns.
+    pub fn from_intent(redaction: &IntentRedaction) -> Result<Self, RedactionError> {
+        let mut redactor = Self::new(redaction.profile.into());
+        redactor.rules.hostnames &= redaction.redact_hostname;
+        redactor.rules.usernames &= redaction.redact_username;
+        redactor.rules.paths &= redaction.redact_paths;
+        redactor.rules.network &= redaction.redact_ips;
+
+        for custom in &redaction.custom_patterns {
+            if let Some(pattern) = &custom.pattern {
+                let replacement = custom.replacement.as_deref().unwrap_or("[REDACTED]");
+                redactor = redactor.with_pattern(pattern, replacement)?;
+            }
+        }
+        Ok(redactor)
+    }
+
+    /// Salt pseudonyms, so labels cannot be matched against a dictionary of
+    /// likely names. Use the same salt for every file of one bundle.
+    pub fn with_salt(mut self, salt: &str) -> Self {
+        self.salt = salt.to_string();
+        self
+    }
+
+    /// Add a custom regular expression and its replacement (`$1` etc. allowed).
+    pub fn with_pattern(mut self, pattern: &str, replacement: &str) -> Result<Self, RedactionError> {
+        let regex = Regex::new(pattern).map_err(|e| RedactionError::InvalidPattern {
+            pattern: pattern.to_string(),
+            message: e.to_string(),
+        })?;
+        self.custom.push((regex, replacement.to_string()));
+        Ok(self)
+    }
+
+    /// Treat `name` as a hostname wherever it appears.
+    pub fn add_host(&mut self, name: &str) {
+        if is_identifying(name) && !name.eq_ignore_ascii_case("localhost") {
+            self.hosts.insert(name.to_string());
+        }
+    }
+
+    /// Treat `name` as a username wherever it appears as a whole token.
+    pub fn add_user(&mut self, name: &str) {
+        if is_identifying(name) && name != "root" {
+            self.users.insert(name.to_string());
+        }
+    }
+
+    pub fn profile(&self) -> RedactionProfile {
+        self.profile
+    }
+
+    /// Stable label for a host.
+    pub fn host_pseudonym(&self, name: &str) -> String {
+        format!("host-{}", tag(&self.salt, &name.to_ascii_lowercase()))
+    }
+
+    /// Stable label for a user.
+    pub fn user_pseudonym(&self, name: &str) -> String {
+        user_label(&self.salt, name)
+    }
+
+    /// Redact free text. `location` is recorded in the log.
+    pub fn redact_text(&mut self, location: &str, text: &str) -> String {
+        if self.rules == Rules::default() {
+            return text.to_string();
+        }
+        let mut out = text.to_string();
+
+        if self.rules.custom {
+            for i in 0..self.custom.len() {
+                let (regex, replacement) = &self.custom[i];
+                let n = regex.find_iter(&out).count();
+                if n > 0 {
+                    out = regex.replace_all(&out, replacement.as_str()).into_owned();
+                    self.record(location, RedactionCategory::Custom, n);
+                }
+            }
+        }
+
+        if self.rules.emails {
+            out = self.replace(location, RedactionCategory::Email, email_re(), &out, |_| {
+                Some("[email]".to_string())
+            });
+        }
+
+        // Learn users from home directories before pseudonymizing them
+        for caps in home_re().captures_iter(&out) {
+            let user = caps[2].to_string();
+            self.add_user(&user);
+        }
+        if self.rules.paths {
+            let usernames = self.rules.usernames;
+            let salt = self.salt.clone();
+            out = self.replace(location, RedactionCategory::HomePath, home_re(), &out, |caps: &Captures| {
+                let user = &caps[2];
+                let label = if usernames { user_label(&salt, user) } else { user.to_string() };
+                Some(format!("~{}", label))
+            });
+        }
+
+        if self.rules.usernames {
+            for user in self.users.clone() {
+                let label = self.user_pseudonym(&user);
+                out = self.replace_user(location, &user, &label, &out);
+            }
+        }
+        if self.rules.hostnames {
+            for host in self.hosts.clone() {
+                let label = self.host_pseudonym(&host);
+                out = self.replace_word(location, RedactionCategory::Hostname, &host, &label, &out);
+            }
+        }
+
+        if self.rules.network {
+            out = self.replace(location, RedactionCategory::Mac, mac_re(), &out, |_| {
+                Some("xx:xx:xx:xx:xx:xx".to_string())
+            });
+            out = self.replace(location, RedactionCategory::Ipv6, ipv6_re(), &out, |caps: &Captures| {
+                let addr: Ipv6Addr = caps[0].parse().ok()?;
+                (!addr.is_loopback() && !addr.is_unspecified()).then(|| "[ipv6]".to_string())
+            });
+            out = self.replace(location, RedactionCategory::Ipv4, ipv4_re(), &out, |caps: &Captures| {
+                let addr: Ipv4Addr = caps[0].parse().ok()?;
+                (!addr.is_loopback() && !addr.is_unspecified()).then(|| "x.x.x.x".to_string())
+            });
+        }
+
+        out
+    }
+
+    /// Redact every string (not keys) in a JSON value, in place.
+    pub fn redact_json(&mut self, location: &str, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.redact_text(location, s),
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    self.redact_json(&format!("{}/{}", location, i), item);
+                }
+            }
+            Value::Object(map) => {
+                for (key, item) in map.iter_mut() {
+                    let key = key.replace('~', "~0").replace('/', "~1");
+                    self.redact_json(&format!("{}/{}", location, key), item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Redact the free-text fields of an envelope and record the profile on it.
+    ///
+    /// Identifiers, hashes and artifact paths are left intact so the envelope
+    /// still lines up with its artifacts. Existing signatures are dropped:
+    /// the redacted envelope is a new document and must be signed again.
+    pub fn redact_envelope(&mut self, envelope: &mut EvidenceEnvelope) {
+        let host = envelope.source.host.hostname.clone();
+        self.add_host(&host);
+        // The field itself is always replaced; only free-text matching needs
+        // the name to be distinctive enough
+        if self.rules.hostnames && !host.is_empty() && host != "unknown" {
+            envelope.source.host.hostname = self.host_pseudonym(&host);
+            self.record("/source/host/hostname", RedactionCategory::Hostname, 1);
+        }
+
+        for (i, artifact) in envelope.artifacts.iter_mut().enumerate() {
+            if let Some(d) = artifact.description.take() {
+                artifact.description = Some(self.redact_text(&format!("/artifacts/{}/description", i), &d));
+            }
+        }
+        for (i, finding) in envelope.findings.iter_mut().enumerate() {
+            finding.title = self.redact_text(&format!("/findings/{}/title", i), &finding.title);
+            if let Some(d) = finding.description.take() {
+                finding.description = Some(self.redact_text(&format!("/findings/{}/description", i), &d));
+            }
+            if let Some(r) = finding.recommendation.take() {
+                finding.recommendation = Some(self.redact_text(&format!("/findings/{}/recommendation", i), &r));
+            }
+        }
+        if let Some(metrics) = envelope.metrics.as_mut() {
+            self.redact_json("/metrics", metrics);
+        }
+
+        envelope.redaction_profile = self.profile;
+        if let Some(provenance) = envelope.provenance.as_mut() {
+            provenance.signatures.clear();
+        }
+    }
+
+    /// Redact a text artifact (log, report) in place. Files that are not
+    /// UTF-8 are left untouched and reported as `false`.
+    pub fn redact_file(&mut self, path: &Path, location: &str) -> io::Result<bool> {
+        let bytes = std::fs::read(path)?;
+        let Ok(text) = String::from_utf8(bytes) else {
+            return Ok(false);
+        };
+        let redacted = self.redact_text(&format!("artifact:{}", location), &text);
+        if redacted != text {
+            std::fs::write(path, redacted)?;
+        }
+        Ok(true)
+    }
+
+    /// What has been redacted so far.
+    pub fn log(&self) -> RedactionLog {
+        RedactionLog {
+            profile: self.profile,
+            records: self
+                .counts
+                .iter()
+                .map(|((location, category), count)| RedactionRecord {
+                    location: location.clone(),
+                    category: *category,
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+
+    fn record(&mut self, location: &str, category: RedactionCategory, n: usize) {
+        *self.counts.entry((location.to_string(), category)).or_default() += n;
+    }
+
+    fn replace<F>(&mut self, location: &str, category: RedactionCategory, regex: &Regex, text: &str, f: F) -> String
+    where
+        F: Fn(&Captures) -> Option<String>,
+    {
+        let mut n = 0;
+        let out = regex
+            .replace_all(text, |caps: &Captures| match f(caps) {
+                Some(replacement) => {
+                    n += 1;
+                    replacement
+                }
+                None => caps[0].to_string(),
+            })
+            .into_owned();
+        if n > 0 {
+            self.record(location, category, n);
+        }
+        out
+    }
+
+    fn replace_word(
+        &mut self,
+        location: &str,
+        category: RedactionCategory,
+        word: &str,
+        label: &str,
+        text: &str,
+    ) -> String {
+        let Ok(regex) = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(word))) else {
+            return text.to_string();
+        };
+        self.replace(location, category, &regex, text, |_| Some(label.to_string()))
+    }
+
+    /// Replace `user` where it stands as a token of its own (see [`is_user_token`]).
+    fn replace_user(&mut self, location: &str, user: &str, label: &str, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        let mut n = 0;
+        for (start, _) in text.match_indices(user) {
+            let end = start + user.len();
+            if is_user_token(&text[..start], &text[end..]) {
+                out.push_str(&text[last..start]);
+                out.push_str(label);
+                last = end;
+                n += 1;
+            }
+        }
+        out.push_str(&text[last..]);
+        if n > 0 {
+            self.record(location, RedactionCategory::Username, n);
+        }
+        out
+    }
+}
+
+/// Whether a name between `before` and `after` is a token of its own: set off
+/// by whitespace, quotes, brackets or list punctuation, or the value of a
+/// `user=`-style key, or the user of `user@host`. Names joined to other words
+/// or path segments (`pci-stub`, `/dev/…`, `pci:v…`) are left alone.
+fn is_user_token(before: &str, after: &str) -> bool {
+    let lower = before.to_ascii_lowercase();
+    let starts = match before.chars().next_back() {
+        None => true,
+        Some(c) => {
+            c.is_whitespace()
+                || "\"'`([{<,;".contains(c)
+                || ["user=", "username=", "login=", "owner="].iter().any(|key| lower.ends_with(key))
+        }
+    };
+    let mut rest = after.chars();
+    let ends = match rest.next() {
+        None => true,
+        Some(c) if c.is_whitespace() || "\"'`)]}>,;@".contains(c) => true,
+        // Sentence punctuation, but not `alice.conf` or `pci:v…`
+        Some('.' | ':' | '!' | '?') => rest.next().map_or(true, char::is_whitespace),
+        Some(_) => false,
+    };
+    starts && ends
+}
+
+fn tag(salt: &str, name: &str) -> String {
+    let digest = digest::digest(&digest::SHA256, format!("{}\0{}", salt, name).as_bytes());
+    hex::encode(&digest.as_ref()[..4])
+}
+
+fn user_label(salt: &str, name: &str) -> String {
+    format!("user-{}", tag(salt, name))
+}
+
+/// Names too short or generic to redact would shred unrelated text.
+fn is_identifying(name: &str) -> bool {
+    name.len() >= 3 && name != "unknown"
+}
+
+fn home_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(/home|/var/home|/Users)/([A-Za-z0-9._-]+)").expect("valid regex"))
+}
+
+fn email_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").expect("valid regex")
+    })
+}
+
+fn mac_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\b[0-9a-f]{2}(?:[:-][0-9a-f]{2}){5}\b").expect("valid regex"))
+}
+
+fn ipv4_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").expect("valid regex"))
+}
+
+/// Candidates only; each match is confirmed by parsing, which rules out
+/// times, PCI addresses and MACs.
+fn ipv6_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)[0-9a-f]{0,4}(?::[0-9a-f]{0,4}){2,7}(?:%\w+)?").expect("valid regex"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::system_report_to_envelope;
+    use crate::message_intent::RedactionPattern;
+
+    const LOG: &str = "Jan 1 alice@workstation sshd: login from 192.168.1.20 (fe80::1ff:fe23:4567:890a) \
+                       by alice, mac 3c:7c:3f:aa:bb:cc, config /home/alice/.ssh/config, loopback 127.0.0.1 ::1, \
+                       device 0000:01:00.0 at 12:34:56";
+
+    fn redactor(profile: RedactionProfile) -> Redactor {
+        let mut r = Redactor::new(profile);
+        r.add_host("workstation");
+        r
+    }
+
+    #[test]
+    fn test_none_changes_nothing() {
+        let mut r = redactor(RedactionProfile::None);
+        assert_eq!(r.redact_text("log", LOG), LOG);
+        assert_eq!(r.log().total(), 0);
+    }
+
+    #[test]
+    fn test_standard_profile() {
+        let mut r = redactor(RedactionProfile::Standard);
+        let out = r.redact_text("log", LOG);
+        let user = r.user_pseudonym("alice");
+        let host = r.host_pseudonym("workstation");
+
+        assert!(!out.contains("alice") && !out.contains("workstation"), "{}", out);
+        assert!(out.contains(&format!("~{}/.ssh/config", user)));
+        assert!(out.contains(&format!("by {}", user)));
+        assert!(out.contains(&host));
+        assert!(out.contains("from x.x.x.x ([ipv6])"));
+        assert!(out.contains("mac xx:xx:xx:xx:xx:xx"));
+        assert!(out.contains("127.0.0.1 ::1"));
+        assert!(out.contains("device 0000:01:00.0 at 12:34:56"));
+
+        let log = r.log();
+        assert_eq!(log.count(RedactionCategory::HomePath), 1);
+        assert_eq!(log.count(RedactionCategory::Ipv4), 1);
+        assert_eq!(log.count(RedactionCategory::Ipv6), 1);
+        assert_eq!(log.count(RedactionCategory::Mac), 1);
+        assert!(serde_json::to_string(&log).unwrap().find("alice").is_none());
+    }
+
+    #[test]
+    fn test_minimal_keeps_network_maximum_masks_email() {
+        let mut minimal = redactor(RedactionProfile::Minimal);
+        let out = minimal.redact_text("log", LOG);
+        assert!(out.contains("192.168.1.20") && out.contains("3c:7c:3f:aa:bb:cc"));
+
+        let mut maximum = redactor(RedactionProfile::Maximum);
+        let out = maximum.redact_text("log", "contact ops@example.org");
+        assert_eq!(out, "contact [email]");
+    }
+
+    #[test]
+    fn test_pseudonyms_are_stable() {
+        let a = Redactor::new(RedactionProfile::Standard);
+        let b = Redactor::new(RedactionProfile::Maximum);
+        assert_eq!(a.host_pseudonym("box"), b.host_pseudonym("BOX"));
+        assert_ne!(a.user_pseudonym("alice"), a.user_pseudonym("bob"));
+        let salted = Redactor::new(RedactionProfile::Standard).with_salt("bundle-1");
+        assert_ne!(a.user_pseudonym("alice"), salted.user_pseudonym("alice"));
+    }
+
+    #[test]
+    fn test_intent_switches_and_custom_patterns() {
+        let intent = IntentRedaction {
+            profile: RedactionLevel::Standard,
+            redact_hostname: true,
+            redact_username: true,
+            redact_paths: true,
+            redact_ips: false,
+            custom_patterns: vec![RedactionPattern {
+                pattern: Some(r"serial=\w+".to_string()),
+                replacement: Some("serial=[serial]".to_string()),
+            }],
+        };
+        let mut r = Redactor::from_intent(&intent).unwrap();
+        let out = r.redact_text("msg", "serial=ABC123 at 10.0.0.1");
+        assert_eq!(out, "serial=[serial] at 10.0.0.1");
+        assert_eq!(r.log().count(RedactionCategory::Custom), 1);
+
+        let bad = IntentRedaction {
+            custom_patterns: vec![RedactionPattern { pattern: Some("(".to_string()), replacement: None }],
+            ..intent
+        };
+        assert!(Redactor::from_intent(&bad).is_err());
+    }
+
+    #[test]
+    fn test_redact_envelope() {
+        let report = serde_json::json!({
+            "devices": [{
+                "issues": [{
+                    "severity": "High",
+                    "issue_type": "ZombieDevice",
+                    "description": "Device on workstation logged to /home/alice/gpu.log",
+                }]
+            }]
+        });
+        let mut env = system_report_to_envelope(&report, "workstation");
+        env.metrics = Some(serde_json::json!({ "peer": { "value": "10.1.2.3" }, "count": { "value": 3 } }));
+
+        let mut r = Redactor::new(RedactionProfile::Standard);
+        r.redact_envelope(&mut env);
+        crate::validate::validate_envelope(&env).unwrap();
+
+        let json = serde_json::to_string(&env).unwrap();
+        assert!(!json.contains("workstation") && !json.contains("alice") && !json.contains("10.1.2.3"));
+        assert_eq!(env.source.host.hostname, r.host_pseudonym("workstation"));
+        assert_eq!(env.artifacts[0].path, "scan-report.json");
+        assert!(r.log().records.iter().any(|rec| rec.location == "/metrics/peer/value"));
+    }
+
+    #[test]
+    fn test_users_only_matched_as_tokens() {
+        let mut r = Redactor::new(RedactionProfile::Minimal);
+        r.add_user("dev");
+        let text = "options pci-stub.ids=10de:1b80 modalias pci:v000010DE on /dev/sda \
+                    from /home/pci/.bashrc, user=pci, login by pci. dev@box";
+        let out = r.redact_text("log", text);
+        let pci = r.user_pseudonym("pci");
+        let dev = r.user_pseudonym("dev");
+
+        assert!(out.contains("pci-stub.ids=10de:1b80 modalias pci:v000010DE on /dev/sda"), "{}", out);
+        assert!(out.contains(&format!("~{}/.bashrc", pci)));
+        assert!(out.contains(&format!("user={},", pci)));
+        assert!(out.contains(&format!("by {}.", pci)));
+        assert!(out.contains(&format!("{}@box", dev)));
+        assert_eq!(r.log().count(RedactionCategory::Username), 3);
+    }
+}
@@ -0,0 +1,522 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Retention - decide which Run Bundles under a records root to keep,
+//! compress or delete, and carry that out.
+//!
+//! Decisions are made per bundle, first match wins:
+//!
+//! | Rule | Action |
+//! |------|--------|
+//! | Referenced by a live receipt's undo data (or its plan) | keep |
+//! | Holds undo data but no receipt saying when it expires | keep |
+//! | Holds a receipt | keep (compressed when old) |
+//! | Manifest `policy: permanent` / `manual`, or `keep_until` in the future | keep |
+//! | Manifest `policy: until_undo_expires` with undo still live | keep |
+//! | Manifest `policy: timed` past `keep_until` | delete |
+//! | Older than the type's `max_age_days`, or beyond its `keep_latest` | delete |
+//! | Older than `compress_after_days` (manifest, else type) | compress |
+//!
+//! Undo data is live while `undo_bundle.available` is set and `expires_at`
+//! has not passed. Anything that cannot be read as a bundle is left alone.
+//! [`plan_retention`] only reports; [`enforce`] acts on a report.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use crate::bundle::{BundleError, RunBundleReader};
+use crate::run_bundle::{BundleType, ContentType, RetentionPolicy};
+use crate::Receipt;
+
+/// Retention rules for one bundle type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypePolicy {
+    /// Never delete bundles of this type.
+    pub keep_forever: bool,
+    /// Delete bundles older than this.
+    pub max_age_days: Option<u32>,
+    /// Keep only the newest N bundles of this type.
+    pub keep_latest: Option<usize>,
+    /// Compress bundles older than this into `<root>.tar.gz`.
+    pub compress_after_days: Option<u32>,
+}
+
+/// Retention rules for each bundle type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionRules {
+    pub scan: TypePolicy,
+    pub plan: TypePolicy,
+    pub execution: TypePolicy,
+    pub export: TypePolicy,
+    pub archive: TypePolicy,
+}
+
+impl Default for RetentionRules {
+    fn default() -> Self {
+        Self {
+            scan: TypePolicy {
+                keep_forever: false,
+                max_age_days: Some(30),
+                keep_latest: Some(20),
+                compress_after_days: Some(7),
+            },
+            plan: TypePolicy {
+                keep_forever: false,
+                max_age_days: Some(90),
+                keep_latest: None,
+                compress_after_days: Some(30),
+            },
+            execution: TypePolicy {
+                keep_forever: true,
+                max_age_days: None,
+                keep_latest: None,
+                compress_after_days: Some(90),
+            },
+            export: TypePolicy {
+                keep_forever: true,
+                ..TypePolicy::default()
+            },
+            archive: TypePolicy {
+                keep_forever: true,
+                ..TypePolicy::default()
+            },
+        }
+    }
+}
+
+impl RetentionRules {
+    pub fn for_type(&self, bundle_type: BundleType) -> &TypePolicy {
+        match bundle_type {
+            BundleType::Scan => &self.scan,
+            BundleType::Plan => &self.plan,
+            BundleType::Execution => &self.execution,
+            BundleType::Export => &self.export,
+            BundleType::Archive => &self.archive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    Keep,
+    Compress,
+    Delete,
+}
+
+impl fmt::Display for RetentionAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => write!(f, "keep"),
+            Self::Compress => write!(f, "compress"),
+            Self::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+/// What retention decided for one bundle, and why.
+#[derive(Debug, Clone)]
+pub struct RetentionDecision {
+    pub root: PathBuf,
+    pub bundle_type: Option<BundleType>,
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    pub action: RetentionAction,
+    pub reason: String,
+}
+
+/// Outcome of a retention scan.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionReport {
+    pub decisions: Vec<RetentionDecision>,
+    /// Directories under the records root that are not readable bundles.
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl RetentionReport {
+    pub fn with_action(&self, action: RetentionAction) -> impl Iterator<Item = &RetentionDecision> {
+        self.decisions.iter().filter(move |d| d.action == action)
+    }
+
+    /// Bytes freed by deletions (compression savings are not estimated).
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.with_action(RetentionAction::Delete).map(|d| d.size_bytes).sum()
+    }
+}
+
+impl fmt::Display for RetentionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for d in &self.decisions {
+            writeln!(f, "{:<8} {}  ({})", d.action.to_string().to_uppercase(), d.root.display(), d.reason)?;
+        }
+        for (path, why) in &self.skipped {
+            writeln!(f, "{:<8} {}  ({})", "SKIP", path.display(), why)?;
+        }
+        write!(
+            f,
+            "{} kept, {} to compress, {} to delete ({} bytes)",
+            self.with_action(RetentionAction::Keep).count(),
+            self.with_action(RetentionAction::Compress).count(),
+            self.with_action(RetentionAction::Delete).count(),
+            self.reclaimable_bytes()
+        )
+    }
+}
+
+/// A bundle loaded for retention decisions.
+struct Candidate {
+    reader: RunBundleReader,
+    receipt: Option<Receipt>,
+    holds_receipt: bool,
+    holds_undo: bool,
+    size_bytes: u64,
+}
+
+/// Decide what to do with every bundle directly under `records_root`.
+pub fn plan_retention(records_root: &Path, rules: &RetentionRules, now: DateTime<Utc>) -> Result<RetentionReport, BundleError> {
+    let mut report = RetentionReport::default();
+    let mut candidates = Vec::new();
+
+    let mut entries: Vec<_> = std::fs::read_dir(records_root)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        match load(&path) {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => report.skipped.push((path, e.to_string())),
+        }
+    }
+
+    let protected = protected_roots(&candidates, now);
+
+    // Newest first, so keep_latest counts from the most recent bundle
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.reader.manifest().created_at));
+    let mut seen_per_type: Vec<(BundleType, usize)> = Vec::new();
+
+    for candidate in &candidates {
+        let manifest = candidate.reader.manifest();
+        let bundle_type = manifest.bundle_type;
+        let rank = bundle_type.map(|t| {
+            match seen_per_type.iter_mut().find(|(seen, _)| *seen == t) {
+                Some((_, n)) => {
+                    *n += 1;
+                    *n
+                }
+                None => {
+                    seen_per_type.push((t, 1));
+                    1
+                }
+            }
+        });
+
+        let (action, reason) = decide(candidate, rank, &protected, rules, now);
+        report.decisions.push(RetentionDecision {
+            root: candidate.reader.root().to_path_buf(),
+            bundle_type,
+            created_at: manifest.created_at,
+            size_bytes: candidate.size_bytes,
+            action,
+            reason,
+        });
+    }
+
+    report.decisions.sort_by(|a, b| a.root.cmp(&b.root));
+    Ok(report)
+}
+
+/// Carry out a report: compress and delete bundles. Returns the archives written.
+pub fn enforce(report: &RetentionReport) -> Result<Vec<PathBuf>, BundleError> {
+    let mut archives = Vec::new();
+    for decision in &report.decisions {
+        match decision.action {
+            RetentionAction::Keep => {}
+            RetentionAction::Compress => archives.push(compress_bundle(&decision.root)?),
+            RetentionAction::Delete => std::fs::remove_dir_all(&decision.root)?,
+        }
+    }
+    Ok(archives)
+}
+
+/// Pack a bundle directory into `<root>.tar.gz` beside it and remove the directory.
+pub fn compress_bundle(root: &Path) -> Result<PathBuf, BundleError> {
+    let name = root
+        .file_name()
+        .ok_or_else(|| BundleError::Naming(format!("{} has no directory name", root.display())))?;
+    let archive = root.with_file_name(format!("{}.tar.gz", name.to_string_lossy()));
+    if archive.exists() {
+        return Err(BundleError::Naming(format!("{} already exists", archive.display())));
+    }
+
+    let file = std::fs::File::create(&archive)?;
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+    tar.append_dir_all(name, root)?;
+    tar.into_inner()?.finish()?.sync_all()?;
+
+    std::fs::remove_dir_all(root)?;
+    Ok(archive)
+}
+
+fn load(root: &Path) -> Result<Candidate, BundleError> {
+    let reader = RunBundleReader::open(root)?;
+    let receipt = reader.receipt()?;
+    let contents = &reader.manifest().contents;
+    let holds_receipt = contents.iter().any(|c| c.content_type == Some(ContentType::Receipt));
+    let holds_undo = contents.iter().any(|c| c.content_type == Some(ContentType::Undo));
+    let size_bytes = contents.iter().filter_map(|c| c.size_bytes).sum();
+    Ok(Candidate {
+        reader,
+        receipt,
+        holds_receipt,
+        holds_undo,
+        size_bytes,
+    })
+}
+
+fn undo_is_live(receipt: &Receipt, now: DateTime<Utc>) -> bool {
+    receipt
+        .undo_bundle
+        .as_ref()
+        .is_some_and(|u| u.available && u.expires_at.map_or(true, |at| at > now))
+}
+
+/// Bundles that live undo data depends on: the receipt's own bundle, any
+/// bundle its undo paths point into, and the bundle holding its plan.
+fn protected_roots(candidates: &[Candidate], now: DateTime<Utc>) -> BTreeSet<PathBuf> {
+    let mut protected = BTreeSet::new();
+    for candidate in candidates {
+        let Some(receipt) = candidate.receipt.as_ref().filter(|r| undo_is_live(r, now)) else {
+            continue;
+        };
+        let root = candidate.reader.root();
+        protected.insert(root.to_path_buf());
+
+        let undo = receipt.undo_bundle.as_ref().expect("live undo");
+        let paths = undo.path.iter().chain(undo.steps.iter().filter_map(|s| s.backup_path.as_ref()));
+        for path in paths {
+            let target = root.join(path);
+            for other in candidates {
+                if target.starts_with(other.reader.root()) {
+                    protected.insert(other.reader.root().to_path_buf());
+                }
+            }
+        }
+
+        for other in candidates {
+            if other.reader.plan().ok().flatten().is_some_and(|p| p.plan_id == receipt.plan_ref) {
+                protected.insert(other.reader.root().to_path_buf());
+            }
+        }
+    }
+    protected
+}
+
+fn decide(
+    candidate: &Candidate,
+    rank: Option<usize>,
+    protected: &BTreeSet<PathBuf>,
+    rules: &RetentionRules,
+    now: DateTime<Utc>,
+) -> (RetentionAction, String) {
+    let manifest = candidate.reader.manifest();
+    let retention = manifest.retention.as_ref();
+    let age_days = (now - manifest.created_at).num_days();
+    let type_policy = manifest.bundle_type.map(|t| rules.for_type(t)).cloned().unwrap_or_default();
+    let compress_after = retention.and_then(|r| r.compress_after_days).or(type_policy.compress_after_days);
+    let compress_or_keep = |why: &str| match compress_after {
+        Some(days) if age_days >= i64::from(days) => (RetentionAction::Compress, format!("{}, older than {} days", why, days)),
+        _ => (RetentionAction::Keep, why.to_string()),
+    };
+
+    if protected.contains(candidate.reader.root()) {
+        return (RetentionAction::Keep, "referenced by live undo data".to_string());
+    }
+    // Undo reads the bundle in place, so it is never compressed away before it expires
+    if candidate.holds_undo && candidate.receipt.is_none() {
+        return (RetentionAction::Keep, "holds undo data with no expiry".to_string());
+    }
+    if candidate.holds_receipt {
+        return compress_or_keep("holds a receipt");
+    }
+
+    let keep_until = retention.and_then(|r| r.keep_until);
+    match retention.and_then(|r| r.policy) {
+        Some(RetentionPolicy::Permanent) => return compress_or_keep("permanent policy"),
+        Some(RetentionPolicy::Manual) => return (RetentionAction::Keep, "manual policy".to_string()),
+        Some(RetentionPolicy::UntilUndoExpires) if candidate.receipt.as_ref().is_some_and(|r| undo_is_live(r, now)) => {
+            return (RetentionAction::Keep, "undo has not expired".to_string());
+        }
+        Some(RetentionPolicy::Timed) => match keep_until {
+            Some(until) if until <= now => return (RetentionAction::Delete, format!("kept until {}", until)),
+            _ => {}
+        },
+        _ => {}
+    }
+    if keep_until.is_some_and(|until| until > now) {
+        return (RetentionAction::Keep, "keep_until has not passed".to_string());
+    }
+
+    if type_policy.keep_forever {
+        return compress_or_keep("type is kept forever");
+    }
+    if let Some(max) = type_policy.max_age_days {
+        if age_days > i64::from(max) {
+            return (RetentionAction::Delete, format!("older than {} days", max));
+        }
+    }
+    if let (Some(limit), Some(rank)) = (type_policy.keep_latest, rank) {
+        if rank > limit {
+            return (RetentionAction::Delete, format!("beyond the newest {}", limit));
+        }
+    }
+    compress_or_keep("within retention")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::RunBundleWriter;
+    use crate::conversions;
+    use crate::run_bundle::{BundleRetention, BundleSourceTool, RunBundle};
+    use chrono::Duration;
+
+    fn write(parent: &Path, bundle_type: BundleType, age_days: i64, retention: Option<BundleRetention>) -> PathBuf {
+        let mut manifest = RunBundle::new(bundle_type);
+        manifest.source_tool = Some(BundleSourceTool::HardwareCrashTeam);
+        manifest.created_at = Utc::now() - Duration::days(age_days);
+        manifest.retention = retention;
+        let mut writer = RunBundleWriter::create_with(parent, manifest).unwrap();
+        writer.write_log("run.log", b"ok\n").unwrap();
+        writer.finish().unwrap()
+    }
+
+    /// A plan bundle and an execution bundle whose receipt points back at it.
+    fn write_chain(parent: &Path, age_days: i64, expires_at: Option<DateTime<Utc>>) -> (PathBuf, PathBuf) {
+        let envelope = conversions::system_report_to_envelope(&serde_json::json!({ "devices": [] }), "h");
+        let native = serde_json::json!({
+            "plan": {
+                "steps": [{ "description": "claim", "command": "true" }],
+                "undo_steps": [{ "description": "release", "command": "true" }]
+            }
+        });
+        let plan = conversions::remediation_plan_to_procedure(&native["plan"], envelope.envelope_id);
+
+        let mut manifest = RunBundle::new(BundleType::Plan);
+        manifest.created_at = Utc::now() - Duration::days(age_days);
+        let mut writer = RunBundleWriter::create_with(parent, manifest).unwrap();
+        writer.write_plan(&plan).unwrap();
+        let plan_root = writer.finish().unwrap();
+
+        let mut receipt = conversions::remediation_receipt_to_contract(&native, plan.plan_id, envelope.envelope_id);
+        receipt.undo_bundle.as_mut().unwrap().expires_at = expires_at;
+        let mut manifest = RunBundle::new(BundleType::Execution);
+        manifest.created_at = Utc::now() - Duration::days(age_days);
+        let mut writer = RunBundleWriter::create_with(parent, manifest).unwrap();
+        writer.write_receipt(&receipt).unwrap();
+        (plan_root, writer.finish().unwrap())
+    }
+
+    fn action_for(report: &RetentionReport, root: &Path) -> RetentionAction {
+        report.decisions.iter().find(|d| d.root == root).unwrap().action
+    }
+
+    #[test]
+    fn test_age_and_count_pruning() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = write(dir.path(), BundleType::Scan, 45, None);
+        let fresh = write(dir.path(), BundleType::Scan, 0, None);
+        let aging = write(dir.path(), BundleType::Scan, 10, None);
+        std::fs::create_dir(dir.path().join("not-a-bundle")).unwrap();
+
+        let report = plan_retention(dir.path(), &RetentionRules::default(), Utc::now()).unwrap();
+        assert_eq!(action_for(&report, &old), RetentionAction::Delete);
+        assert_eq!(action_for(&report, &fresh), RetentionAction::Keep);
+        assert_eq!(action_for(&report, &aging), RetentionAction::Compress);
+        assert_eq!(report.skipped.len(), 1);
+
+        let rules = RetentionRules {
+            scan: TypePolicy {
+                keep_latest: Some(1),
+                ..TypePolicy::default()
+            },
+            ..RetentionRules::default()
+        };
+        let report = plan_retention(dir.path(), &rules, Utc::now()).unwrap();
+        assert_eq!(action_for(&report, &fresh), RetentionAction::Keep);
+        assert_eq!(action_for(&report, &aging), RetentionAction::Delete);
+        assert!(report.to_string().contains("beyond the newest 1"));
+    }
+
+    #[test]
+    fn test_live_undo_protects_plan_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plan_root, execution_root) = write_chain(dir.path(), 200, None);
+
+        let report = plan_retention(dir.path(), &RetentionRules::default(), Utc::now()).unwrap();
+        assert_eq!(action_for(&report, &plan_root), RetentionAction::Keep);
+        assert_eq!(action_for(&report, &execution_root), RetentionAction::Keep);
+
+        // Once undo has expired, the plan ages out; the receipt is kept (compressed)
+        let later = Utc::now() + Duration::days(1);
+        let dir2 = tempfile::tempdir().unwrap();
+        let (plan_root, execution_root) = write_chain(dir2.path(), 200, Some(Utc::now()));
+        let report = plan_retention(dir2.path(), &RetentionRules::default(), later).unwrap();
+        assert_eq!(action_for(&report, &plan_root), RetentionAction::Delete);
+        assert_eq!(action_for(&report, &execution_root), RetentionAction::Compress);
+    }
+
+    #[test]
+    fn test_undo_without_receipt_kept_uncompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = RunBundle::new(BundleType::Execution);
+        manifest.created_at = Utc::now() - Duration::days(200);
+        let mut writer = RunBundleWriter::create_with(dir.path(), manifest).unwrap();
+        writer.write_undo("remediation-receipt.json", b"{}").unwrap();
+        writer.write_receipt_human("Applied\n").unwrap();
+        let root = writer.finish().unwrap();
+
+        let report = plan_retention(dir.path(), &RetentionRules::default(), Utc::now()).unwrap();
+        assert_eq!(action_for(&report, &root), RetentionAction::Keep);
+        assert!(enforce(&report).unwrap().is_empty());
+        assert!(root.is_dir());
+    }
+
+    #[test]
+    fn test_manifest_policy_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = |policy, keep_until| BundleRetention {
+            keep_until,
+            policy: Some(policy),
+            compress_after_days: None,
+            delete_temp_after_completion: true,
+        };
+        let permanent = write(dir.path(), BundleType::Scan, 400, Some(retention(RetentionPolicy::Manual, None)));
+        let timed = write(
+            dir.path(),
+            BundleType::Export,
+            2,
+            Some(retention(RetentionPolicy::Timed, Some(Utc::now() - Duration::days(1)))),
+        );
+
+        let report = plan_retention(dir.path(), &RetentionRules::default(), Utc::now()).unwrap();
+        assert_eq!(action_for(&report, &permanent), RetentionAction::Keep);
+        assert_eq!(action_for(&report, &timed), RetentionAction::Delete);
+    }
+
+    #[test]
+    fn test_enforce_compresses_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = write(dir.path(), BundleType::Scan, 45, None);
+        let aging = write(dir.path(), BundleType::Scan, 10, None);
+
+        let report = plan_retention(dir.path(), &RetentionRules::default(), Utc::now()).unwrap();
+        let archives = enforce(&report).unwrap();
+        assert!(!old.exists());
+        assert!(!aging.exists());
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].is_file());
+        assert!(archives[0].to_string_lossy().ends_with(".tar.gz"));
+    }
+}
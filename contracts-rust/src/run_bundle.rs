@@ -27,7 +27,7 @@ pub struct RunBundle {
     pub integrity: Option<BundleIntegrity>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum BundleSourceTool {
     BigUp,
@@ -35,9 +35,11 @@ pub enum BundleSourceTool {
     AAndE,
     Sysobs,
     Psa,
+    #[serde(rename = "hardware-crash-team")]
+    HardwareCrashTeam,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum BundleType {
     Scan,
@@ -123,6 +125,18 @@ pub struct NamingRules {
     pub reserved_names: Vec<String>,
 }
 
+impl Default for NamingRules {
+    fn default() -> Self {
+        Self {
+            max_filename_length: default_max_filename(),
+            max_path_length: default_max_path(),
+            allowed_chars: default_allowed_chars(),
+            timestamp_format: default_timestamp_format(),
+            reserved_names: default_reserved_names(),
+        }
+    }
+}
+
 fn default_max_filename() -> u32 { 200 }
 fn default_max_path() -> u32 { 260 }
 fn default_allowed_chars() -> String { "a-zA-Z0-9._-".to_string() }
@@ -147,7 +161,7 @@ pub struct BundleContent {
     pub created_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ContentType {
     Manifest,
@@ -187,7 +201,7 @@ fn default_true() -> bool {
     true
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum RetentionPolicy {
     Permanent,
@@ -205,6 +219,15 @@ pub struct BundleIntegrity {
     pub signature_file: String,
 }
 
+impl Default for BundleIntegrity {
+    fn default() -> Self {
+        Self {
+            checksum_file: default_checksum_file(),
+            signature_file: default_sig_file(),
+        }
+    }
+}
+
 fn default_checksum_file() -> String { "SHA256SUMS".to_string() }
 fn default_sig_file() -> String { "SHA256SUMS.sig".to_string() }
 
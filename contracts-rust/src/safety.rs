@@ -0,0 +1,497 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Plan Safety - derive a ProcedurePlan's risk, reversibility, privileges
+//! and reboot need from its steps, and check what the producer declared.
+//!
+//! Each step is profiled on its own:
+//!
+//! | Property | Derived from |
+//! |----------|--------------|
+//! | privilege | `parameters.privilege`, the action (services, drivers, uninstalls need root), privileged commands in `preview` (`sudo`, `rpm-ostree`, `systemctl`, `modprobe`, writes to `/sys`, `/etc`, ...) and system paths in `target.path` |
+//! | reboot | `parameters.requires_reboot`, or a boot-time command in `preview` (`kargs`, `grubby`, `dracut`, ...) |
+//! | reversibility | `full` with an `undo_instruction` (or for a restart), else `none` for deletions and uninstalls and `partial` otherwise; a step declaring less is believed |
+//! | risk | `expert` if irreversible; `guided` if partial, privileged or needing a reboot; else `safe`; a step declaring more is believed |
+//!
+//! The plan's derived values are the worst over its steps. Declaring less
+//! than derived (a `safe` plan with a root step, `full` reversibility with a
+//! step that has no undo, missing privileges, an undeclared reboot) is an
+//! error, as are duplicate `step_id`s and `order` that is not `1..=n` in
+//! listed order. Missing declarations and unconfirmed risky steps are
+//! warnings. Plans with errors must not be offered for approval.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::plan::*;
+
+/// Commands that change system state and need root.
+const PRIVILEGED_COMMANDS: &[&str] = &[
+    "rpm-ostree", "systemctl", "modprobe", "rmmod", "insmod", "grubby", "grub-mkconfig", "grub2-mkconfig",
+    "kernelstub", "bootctl", "kernel-install", "dracut", "update-initramfs", "mkinitcpio", "dnf", "apt", "apt-get", "zypper",
+    "pacman", "udevadm", "sysctl", "mount", "umount",
+];
+
+/// Command fragments whose effect only lands after a reboot.
+const REBOOT_MARKERS: &[&str] = &[
+    "kargs", "grubby", "grub-mkconfig", "grub2-mkconfig", "kernelstub", "bootctl", "kernel-install", "dracut",
+    "update-initramfs", "mkinitcpio", "reboot",
+];
+
+/// Path prefixes owned by the system.
+const SYSTEM_PATHS: &[&str] = &["/boot", "/etc", "/proc", "/sys", "/usr", "/lib", "/var/lib", "/opt"];
+
+/// What the analyzer worked out for one step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepProfile {
+    pub step_id: String,
+    pub privilege: Privilege,
+    pub reversibility: Reversibility,
+    pub risk: RiskLevel,
+    pub requires_reboot: bool,
+}
+
+/// Plan-level values derived from the steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedSafety {
+    pub risk: RiskLevel,
+    pub reversibility: Reversibility,
+    /// Every privilege above `user` that some step needs.
+    pub privileges: Vec<Privilege>,
+    pub requires_reboot: bool,
+    pub steps: Vec<StepProfile>,
+}
+
+impl DerivedSafety {
+    /// Overwrite the plan's declared values with the derived ones.
+    pub fn apply_to(&self, plan: &mut ProcedurePlan) {
+        plan.overall_risk = Some(self.risk);
+        plan.overall_reversibility = Some(self.reversibility);
+        plan.requires_privileges = self.privileges.clone();
+        plan.requires_reboot = self.requires_reboot;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IssueSeverity {
+    Warning,
+    Error,
+}
+
+/// One inconsistency found in a plan. `step_id: None` means the plan-level
+/// declaration is at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SafetyIssue {
+    NoSteps,
+    DuplicateStepId { step_id: String },
+    /// Step `order` must run `1..=n` in the order the steps are listed.
+    OrderNotContiguous { step_id: String, expected: u32, found: u32 },
+    RiskUnderstated { step_id: Option<String>, declared: RiskLevel, derived: RiskLevel },
+    ReversibilityOverstated { step_id: Option<String>, declared: Reversibility, derived: Reversibility },
+    PrivilegeUndeclared { privilege: Privilege, step_id: String },
+    RebootUndeclared { step_id: String },
+    /// The plan leaves `overall_risk` or `overall_reversibility` unset.
+    Undeclared { field: &'static str },
+    /// A privileged or irreversible step runs without `requires_confirmation`.
+    Unconfirmed { step_id: String },
+}
+
+impl SafetyIssue {
+    pub fn severity(&self) -> IssueSeverity {
+        match self {
+            Self::Undeclared { .. } | Self::Unconfirmed { .. } => IssueSeverity::Warning,
+            _ => IssueSeverity::Error,
+        }
+    }
+}
+
+fn scope(step_id: &Option<String>) -> String {
+    match step_id {
+        Some(id) => format!("step {}", id),
+        None => "plan".to_string(),
+    }
+}
+
+impl fmt::Display for SafetyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSteps => write!(f, "plan has no steps"),
+            Self::DuplicateStepId { step_id } => write!(f, "step id {} is used more than once", step_id),
+            Self::OrderNotContiguous { step_id, expected, found } => {
+                write!(f, "step {} has order {} where {} was expected", step_id, found, expected)
+            }
+            Self::RiskUnderstated { step_id, declared, derived } => write!(
+                f,
+                "{} is declared {:?} risk but its steps make it {:?}",
+                scope(step_id),
+                declared,
+                derived
+            ),
+            Self::ReversibilityOverstated { step_id, declared, derived } => write!(
+                f,
+                "{} is declared {:?} reversibility but can only be {:?} undone",
+                scope(step_id),
+                declared,
+                derived
+            ),
+            Self::PrivilegeUndeclared { privilege, step_id } => {
+                write!(f, "step {} needs {:?} privileges the plan does not declare", step_id, privilege)
+            }
+            Self::RebootUndeclared { step_id } => {
+                write!(f, "step {} needs a reboot but the plan does not declare one", step_id)
+            }
+            Self::Undeclared { field } => write!(f, "plan does not declare {}", field),
+            Self::Unconfirmed { step_id } => {
+                write!(f, "step {} is privileged or irreversible but does not ask for confirmation", step_id)
+            }
+        }
+    }
+}
+
+/// Outcome of analyzing a plan.
+#[derive(Debug, Clone)]
+pub struct SafetyVerdict {
+    pub derived: DerivedSafety,
+    pub issues: Vec<SafetyIssue>,
+}
+
+impl SafetyVerdict {
+    /// True when the plan has no errors and may be shown for approval.
+    pub fn is_approvable(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &SafetyIssue> {
+        self.issues.iter().filter(|i| i.severity() == IssueSeverity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &SafetyIssue> {
+        self.issues.iter().filter(|i| i.severity() == IssueSeverity::Warning)
+    }
+}
+
+impl fmt::Display for SafetyVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = &self.derived;
+        write!(
+            f,
+            "risk {:?}, reversibility {:?}, privileges {:?}, reboot {}",
+            d.risk, d.reversibility, d.privileges, d.requires_reboot
+        )?;
+        for issue in &self.issues {
+            let tag = match issue.severity() {
+                IssueSeverity::Error => "error",
+                IssueSeverity::Warning => "warning",
+            };
+            write!(f, "\n  {}: {}", tag, issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Profile a single step.
+pub fn profile_step(step: &PlanStep) -> StepProfile {
+    let command = step.preview.as_deref().unwrap_or_default();
+    let param = |key: &str| step.parameters.as_ref().and_then(|p| p.get(key));
+
+    let mut privilege = param("privilege")
+        .and_then(|p| serde_json::from_value(p.clone()).ok())
+        .unwrap_or(Privilege::User);
+    let action_needs_root = matches!(
+        step.action,
+        StepAction::StopService
+            | StepAction::StartService
+            | StepAction::RestartService
+            | StepAction::DisableService
+            | StepAction::EnableService
+            | StepAction::UninstallProgram
+            | StepAction::UpdateDriver
+            | StepAction::RepairPermissions
+            | StepAction::Defragment
+    );
+    let path_needs_root = step
+        .target
+        .as_ref()
+        .and_then(|t| t.path.as_deref())
+        .is_some_and(|p| SYSTEM_PATHS.iter().any(|s| p == *s || p.starts_with(&format!("{}/", s))));
+    if action_needs_root || path_needs_root || command_needs_root(command) {
+        privilege = privilege.max(Privilege::Root);
+    } else if step.action == StepAction::ModifyRegistry {
+        privilege = privilege.max(Privilege::Admin);
+    }
+
+    let requires_reboot = param("requires_reboot").and_then(|r| r.as_bool()).unwrap_or(false)
+        || words(command).any(|w| REBOOT_MARKERS.contains(&w));
+
+    let mut reversibility = if step.undo_instruction.is_some() || step.action == StepAction::RestartService {
+        Reversibility::Full
+    } else if matches!(
+        step.action,
+        StepAction::DeleteFile | StepAction::DeleteDirectory | StepAction::UninstallProgram
+    ) {
+        Reversibility::None
+    } else {
+        Reversibility::Partial
+    };
+    if let Some(declared) = step.reversibility {
+        reversibility = reversibility.max(declared);
+    }
+
+    let mut risk = match reversibility {
+        Reversibility::None => RiskLevel::Expert,
+        Reversibility::Partial => RiskLevel::Guided,
+        Reversibility::Full if privilege > Privilege::User || requires_reboot => RiskLevel::Guided,
+        Reversibility::Full => RiskLevel::Safe,
+    };
+    if let Some(declared) = step.risk {
+        risk = risk.max(declared);
+    }
+
+    StepProfile {
+        step_id: step.step_id.clone(),
+        privilege,
+        reversibility,
+        risk,
+        requires_reboot,
+    }
+}
+
+/// Derive plan-level values from the steps alone.
+pub fn derive(steps: &[PlanStep]) -> DerivedSafety {
+    let steps: Vec<StepProfile> = steps.iter().map(profile_step).collect();
+    let privileges: BTreeSet<Privilege> = steps
+        .iter()
+        .map(|s| s.privilege)
+        .filter(|p| *p > Privilege::User)
+        .collect();
+    DerivedSafety {
+        risk: steps.iter().map(|s| s.risk).max().unwrap_or(RiskLevel::Safe),
+        reversibility: steps.iter().map(|s| s.reversibility).max().unwrap_or(Reversibility::Full),
+        privileges: privileges.into_iter().collect(),
+        requires_reboot: steps.iter().any(|s| s.requires_reboot),
+        steps,
+    }
+}
+
+/// Derive the plan's safety properties and check its declarations against them.
+pub fn analyze(plan: &ProcedurePlan) -> SafetyVerdict {
+    let derived = derive(&plan.steps);
+    let mut issues = Vec::new();
+
+    if plan.steps.is_empty() {
+        issues.push(SafetyIssue::NoSteps);
+    }
+    let mut seen = BTreeSet::new();
+    for step in &plan.steps {
+        if !seen.insert(step.step_id.as_str()) {
+            issues.push(SafetyIssue::DuplicateStepId {
+                step_id: step.step_id.clone(),
+            });
+        }
+    }
+    // One report is enough: after a gap every later step is off by one too
+    if let Some((i, step)) = plan.steps.iter().enumerate().find(|(i, s)| s.order != *i as u32 + 1) {
+        issues.push(SafetyIssue::OrderNotContiguous {
+            step_id: step.step_id.clone(),
+            expected: i as u32 + 1,
+            found: step.order,
+        });
+    }
+
+    for (step, profile) in plan.steps.iter().zip(&derived.steps) {
+        let step_id = Some(step.step_id.clone());
+        if let Some(declared) = step.risk.filter(|r| *r < profile.risk) {
+            issues.push(SafetyIssue::RiskUnderstated {
+                step_id: step_id.clone(),
+                declared,
+                derived: profile.risk,
+            });
+        }
+        if let Some(declared) = step.reversibility.filter(|r| *r < profile.reversibility) {
+            issues.push(SafetyIssue::ReversibilityOverstated {
+                step_id,
+                declared,
+                derived: profile.reversibility,
+            });
+        }
+        let risky = profile.privilege > Privilege::User || profile.reversibility == Reversibility::None;
+        if risky && !step.requires_confirmation {
+            issues.push(SafetyIssue::Unconfirmed {
+                step_id: step.step_id.clone(),
+            });
+        }
+    }
+
+    match plan.overall_risk {
+        Some(declared) if declared < derived.risk => issues.push(SafetyIssue::RiskUnderstated {
+            step_id: None,
+            declared,
+            derived: derived.risk,
+        }),
+        Some(_) => {}
+        None => issues.push(SafetyIssue::Undeclared { field: "overall_risk" }),
+    }
+    match plan.overall_reversibility {
+        Some(declared) if declared < derived.reversibility => issues.push(SafetyIssue::ReversibilityOverstated {
+            step_id: None,
+            declared,
+            derived: derived.reversibility,
+        }),
+        Some(_) => {}
+        None => issues.push(SafetyIssue::Undeclared {
+            field: "overall_reversibility",
+        }),
+    }
+
+    for privilege in &derived.privileges {
+        if !plan.requires_privileges.iter().any(|declared| declared >= privilege) {
+            let step = derived.steps.iter().find(|s| s.privilege == *privilege).expect("derived from a step");
+            issues.push(SafetyIssue::PrivilegeUndeclared {
+                privilege: *privilege,
+                step_id: step.step_id.clone(),
+            });
+        }
+    }
+    if !plan.requires_reboot {
+        if let Some(step) = derived.steps.iter().find(|s| s.requires_reboot) {
+            issues.push(SafetyIssue::RebootUndeclared {
+                step_id: step.step_id.clone(),
+            });
+        }
+    }
+
+    SafetyVerdict { derived, issues }
+}
+
+fn words(command: &str) -> impl Iterator<Item = &str> {
+    command
+        .split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')'))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.rsplit('/').next().unwrap_or(w))
+}
+
+fn command_needs_root(command: &str) -> bool {
+    if command.contains("systemctl --user") {
+        return false;
+    }
+    let escalates = words(command).any(|w| matches!(w, "sudo" | "pkexec" | "doas"));
+    let privileged = words(command).any(|w| PRIVILEGED_COMMANDS.contains(&w));
+    let writes_system = command
+        .split('>')
+        .skip(1)
+        .any(|rest| SYSTEM_PATHS.iter().any(|p| rest.trim_start().starts_with(p)));
+    escalates || privileged || writes_system
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions;
+    use uuid::Uuid;
+
+    fn step(order: u32, preview: &str, undo: Option<&str>) -> PlanStep {
+        PlanStep {
+            step_id: format!("step-{}", order),
+            order,
+            action: StepAction::RunCommand,
+            title: preview.to_string(),
+            description: None,
+            preview: Some(preview.to_string()),
+            risk: None,
+            reversibility: None,
+            undo_instruction: undo.map(str::to_string),
+            target: None,
+            parameters: None,
+            finding_refs: Vec::new(),
+            requires_confirmation: true,
+            estimated_duration_seconds: None,
+        }
+    }
+
+    #[test]
+    fn test_derive_from_steps() {
+        let steps = vec![
+            step(1, "rm -rf ~/.cache/thumbnails", Some("none needed")),
+            step(2, "sudo rpm-ostree kargs --append=pci-stub.ids=10de:13b0", Some("rpm-ostree kargs --delete=...")),
+            step(3, "echo 1 > /sys/bus/pci/devices/0000:01:00.0/remove", None),
+        ];
+        let derived = derive(&steps);
+        assert_eq!(derived.steps[0].risk, RiskLevel::Safe);
+        assert!(derived.steps[1].requires_reboot);
+        assert_eq!(derived.steps[2].privilege, Privilege::Root);
+        assert_eq!(derived.reversibility, Reversibility::Partial);
+        assert_eq!(derived.risk, RiskLevel::Guided);
+        assert_eq!(derived.privileges, vec![Privilege::Root]);
+
+        let mut plan = ProcedurePlan::new(Uuid::new_v4(), steps);
+        derived.apply_to(&mut plan);
+        let verdict = analyze(&plan);
+        assert!(verdict.issues.is_empty(), "{}", verdict);
+    }
+
+    #[test]
+    fn test_overstated_declarations_rejected() {
+        let mut plan = ProcedurePlan::new(
+            Uuid::new_v4(),
+            vec![step(1, "echo ok", Some("echo undo")), step(2, "systemctl disable cups", None)],
+        );
+        plan.overall_risk = Some(RiskLevel::Safe);
+        plan.overall_reversibility = Some(Reversibility::Full);
+        plan.steps[1].reversibility = Some(Reversibility::Full);
+
+        let verdict = analyze(&plan);
+        assert!(!verdict.is_approvable());
+        let errors: Vec<_> = verdict.errors().cloned().collect();
+        assert!(errors.contains(&SafetyIssue::RiskUnderstated {
+            step_id: None,
+            declared: RiskLevel::Safe,
+            derived: RiskLevel::Guided
+        }));
+        assert!(errors.contains(&SafetyIssue::ReversibilityOverstated {
+            step_id: None,
+            declared: Reversibility::Full,
+            derived: Reversibility::Partial
+        }));
+        assert!(errors.iter().any(|e| matches!(e, SafetyIssue::ReversibilityOverstated { step_id: Some(id), .. } if id == "step-2")));
+        assert!(errors.contains(&SafetyIssue::PrivilegeUndeclared {
+            privilege: Privilege::Root,
+            step_id: "step-2".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_ordering_invariants() {
+        let mut plan = ProcedurePlan::new(
+            Uuid::new_v4(),
+            vec![step(1, "true", Some("true")), step(3, "true", Some("true")), step(4, "true", Some("true"))],
+        );
+        plan.steps[2].step_id = "step-1".to_string();
+        plan.steps[1].requires_confirmation = false;
+        derive(&plan.steps).apply_to(&mut plan);
+
+        let verdict = analyze(&plan);
+        let errors: Vec<_> = verdict.errors().cloned().collect();
+        assert_eq!(errors.len(), 2, "{}", verdict);
+        assert!(errors.contains(&SafetyIssue::DuplicateStepId {
+            step_id: "step-1".to_string()
+        }));
+        assert!(errors.contains(&SafetyIssue::OrderNotContiguous {
+            step_id: "step-3".to_string(),
+            expected: 2,
+            found: 3
+        }));
+        assert_eq!(verdict.warnings().count(), 0);
+    }
+
+    #[test]
+    fn test_converted_hardware_plan_is_consistent() {
+        let native = serde_json::json!({
+            "device": "01:00.0",
+            "steps": [{ "description": "claim", "command": "rpm-ostree kargs --append=pci-stub.ids=10de:13b0", "needs_sudo": true, "needs_reboot": true }],
+            "undo_steps": [{ "description": "release", "command": "rpm-ostree kargs --delete=pci-stub.ids=10de:13b0" }],
+            "requires_reboot": true
+        });
+        let plan = conversions::remediation_plan_to_procedure(&native, Uuid::new_v4());
+        let verdict = analyze(&plan);
+        assert!(verdict.issues.is_empty(), "{}", verdict);
+        assert_eq!(plan.overall_reversibility, Some(Reversibility::Full));
+    }
+}
@@ -0,0 +1,572 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Signing - Ed25519 signatures over canonicalized contract documents.
+//!
+//! Envelopes, plans and receipts are forwarded between machines by helpers;
+//! signatures prove they were not edited in transit. A signature covers:
+//!
+//! - the document with its signatures array removed, and
+//! - every signature that precedes it in the array.
+//!
+//! So a forwarder countersigns what it received (document plus existing
+//! signatures) and nobody downstream can drop or reorder earlier signers
+//! without invalidating the later ones. `parent_envelope_id` is part of the
+//! signed document, so the link to a parent envelope cannot be rewritten
+//! either; [`verify_chain`] then checks that the parent is present and
+//! verifies too.
+//!
+//! Canonical form is JSON with object keys sorted and no insignificant
+//! whitespace. Verification works on raw JSON so that fields this crate does
+//! not model (and timestamp spellings chrono would normalize) still count.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{SecondsFormat, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::validate::Contract;
+use crate::{EvidenceEnvelope, ProcedurePlan, Receipt};
+
+/// The only algorithm this crate produces or accepts.
+pub const ALGORITHM: &str = "ed25519";
+
+/// A contract document that carries its own signatures.
+pub trait Signed: Contract {
+    /// Object keys leading to the signatures array.
+    const SIGNATURES_AT: &'static [&'static str];
+}
+
+impl Signed for EvidenceEnvelope {
+    const SIGNATURES_AT: &'static [&'static str] = &["provenance", "signatures"];
+}
+
+impl Signed for ProcedurePlan {
+    const SIGNATURES_AT: &'static [&'static str] = &["signatures"];
+}
+
+impl Signed for Receipt {
+    const SIGNATURES_AT: &'static [&'static str] = &["signatures"];
+}
+
+/// Errors raised while signing or loading key material.
+#[derive(Debug)]
+pub enum SigningError {
+    /// The key bytes are not a valid Ed25519 PKCS#8 document.
+    InvalidKey(String),
+    /// The document could not be converted to or from JSON.
+    Json(serde_json::Error),
+    /// The document is not a JSON object, or its signatures field is malformed.
+    Malformed(String),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidKey(msg) => write!(f, "invalid signing key: {}", msg),
+            Self::Json(e) => write!(f, "JSON error: {}", e),
+            Self::Malformed(msg) => write!(f, "malformed document: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+impl From<serde_json::Error> for SigningError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// An Ed25519 private key bound to a signer name.
+pub struct SigningKey {
+    signer: String,
+    pair: Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// Generate a fresh key. Returns the key and its PKCS#8 encoding, which is
+    /// what should be written to disk.
+    pub fn generate(signer: &str) -> Result<(Self, Vec<u8>), SigningError> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| SigningError::InvalidKey("key generation failed".to_string()))?;
+        let key = Self::from_pkcs8(signer, pkcs8.as_ref())?;
+        Ok((key, pkcs8.as_ref().to_vec()))
+    }
+
+    /// Load a key from its PKCS#8 encoding.
+    pub fn from_pkcs8(signer: &str, pkcs8: &[u8]) -> Result<Self, SigningError> {
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| SigningError::InvalidKey(e.to_string()))?;
+        Ok(Self {
+            signer: signer.to_string(),
+            pair,
+        })
+    }
+
+    /// Name recorded in the `signer` field of produced signatures.
+    pub fn signer(&self) -> &str {
+        &self.signer
+    }
+
+    /// Base64 public key, as stored in a [`TrustStore`].
+    pub fn public_key_base64(&self) -> String {
+        BASE64.encode(self.pair.public_key().as_ref())
+    }
+}
+
+/// Signer name to base64 Ed25519 public key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub signers: BTreeMap<String, String>,
+}
+
+impl TrustStore {
+    /// Parse a trust store from JSON.
+    pub fn from_json(json: &str) -> Result<Self, SigningError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serialize the trust store as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, SigningError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Trust `public_key` (base64) for `signer`, replacing any previous key.
+    pub fn trust(&mut self, signer: &str, public_key: &str) {
+        self.signers.insert(signer.to_string(), public_key.to_string());
+    }
+
+    fn public_key(&self, signer: &str) -> Option<Vec<u8>> {
+        self.signers.get(signer).and_then(|k| BASE64.decode(k).ok())
+    }
+}
+
+/// Outcome of checking one signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// Signed by a trusted key and the content is unchanged.
+    Valid,
+    /// The signer is not in the trust store.
+    UnknownSigner,
+    /// The algorithm is not Ed25519.
+    UnsupportedAlgorithm,
+    /// The signature does not match: the content was modified after signing.
+    Invalid,
+}
+
+/// One checked signature.
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    pub signer: String,
+    pub timestamp: String,
+    pub status: SignatureStatus,
+}
+
+/// Overall verdict for a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// No signatures at all.
+    Unsigned,
+    /// Every signature is valid and from a trusted signer.
+    Trusted,
+    /// No signature is invalid, but at least one could not be checked.
+    Untrusted,
+    /// At least one signature does not match the content.
+    Tampered,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Unsigned => "unsigned",
+            Self::Trusted => "trusted",
+            Self::Untrusted => "untrusted",
+            Self::Tampered => "tampered",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Result of verifying every signature on a document.
+#[derive(Debug, Clone, Default)]
+pub struct Verification {
+    pub signatures: Vec<SignatureCheck>,
+}
+
+impl Verification {
+    pub fn verdict(&self) -> Verdict {
+        if self.signatures.is_empty() {
+            Verdict::Unsigned
+        } else if self.signatures.iter().any(|s| s.status == SignatureStatus::Invalid) {
+            Verdict::Tampered
+        } else if self.signatures.iter().all(|s| s.status == SignatureStatus::Valid) {
+            Verdict::Trusted
+        } else {
+            Verdict::Untrusted
+        }
+    }
+}
+
+/// Sign a contract value in place, appending to its signatures.
+pub fn sign<T: Signed + serde::de::DeserializeOwned>(
+    doc: &mut T,
+    key: &SigningKey,
+) -> Result<(), SigningError> {
+    let mut json = serde_json::to_value(&*doc)?;
+    sign_json::<T>(&mut json, key)?;
+    *doc = serde_json::from_value(json)?;
+    Ok(())
+}
+
+/// Sign raw JSON for contract `T` in place, appending to its signatures.
+pub fn sign_json<T: Signed>(json: &mut Value, key: &SigningKey) -> Result<(), SigningError> {
+    let (document, prior) = split::<T>(json)?;
+    // Whole seconds with a `Z` suffix is exactly how chrono serializes the
+    // timestamp back, so signing through the typed structs round-trips
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let payload = signing_payload(&document, &prior, key.signer(), ALGORITHM, &timestamp);
+    let signature = key.pair.sign(&payload);
+
+    signatures_mut::<T>(json)?.push(serde_json::json!({
+        "signer": key.signer(),
+        "algorithm": ALGORITHM,
+        "signature": BASE64.encode(signature.as_ref()),
+        "timestamp": timestamp,
+    }));
+    Ok(())
+}
+
+/// Verify every signature on a contract value.
+pub fn verify<T: Signed>(doc: &T, trust: &TrustStore) -> Result<Verification, SigningError> {
+    verify_json::<T>(&serde_json::to_value(doc)?, trust)
+}
+
+/// Verify every signature on raw JSON for contract `T`.
+pub fn verify_json<T: Signed>(json: &Value, trust: &TrustStore) -> Result<Verification, SigningError> {
+    let (document, signatures) = split::<T>(json)?;
+    let mut checks = Vec::with_capacity(signatures.len());
+
+    for (i, sig) in signatures.iter().enumerate() {
+        let field = |name: &str| sig.get(name).and_then(Value::as_str).unwrap_or_default();
+        let (signer, algorithm, timestamp) = (field("signer"), field("algorithm"), field("timestamp"));
+
+        let status = if !algorithm.eq_ignore_ascii_case(ALGORITHM) {
+            SignatureStatus::UnsupportedAlgorithm
+        } else if let Some(public_key) = trust.public_key(signer) {
+            let payload = signing_payload(&document, &signatures[..i], signer, algorithm, timestamp);
+            let valid = BASE64
+                .decode(field("signature"))
+                .map(|bytes| {
+                    UnparsedPublicKey::new(&ED25519, &public_key)
+                        .verify(&payload, &bytes)
+                        .is_ok()
+                })
+                .unwrap_or(false);
+            if valid {
+                SignatureStatus::Valid
+            } else {
+                SignatureStatus::Invalid
+            }
+        } else {
+            SignatureStatus::UnknownSigner
+        };
+
+        checks.push(SignatureCheck {
+            signer: signer.to_string(),
+            timestamp: timestamp.to_string(),
+            status,
+        });
+    }
+
+    Ok(Verification { signatures: checks })
+}
+
+/// One envelope in a provenance chain.
+#[derive(Debug, Clone)]
+pub struct ChainLink {
+    pub envelope_id: String,
+    pub parent_envelope_id: Option<String>,
+    /// False when the parent envelope was not among those supplied.
+    pub parent_found: bool,
+    pub verification: Verification,
+}
+
+/// Verification of a set of envelopes linked by `parent_envelope_id`.
+#[derive(Debug, Clone, Default)]
+pub struct ChainReport {
+    pub links: Vec<ChainLink>,
+}
+
+impl ChainReport {
+    /// True when every envelope is trusted and every parent is present.
+    pub fn is_intact(&self) -> bool {
+        self.links
+            .iter()
+            .all(|l| l.parent_found && l.verification.verdict() == Verdict::Trusted)
+    }
+}
+
+/// Verify a set of raw envelopes and the parent links between them.
+pub fn verify_chain(envelopes: &[Value], trust: &TrustStore) -> Result<ChainReport, SigningError> {
+    let id_of = |v: &Value| v.get("envelope_id").and_then(Value::as_str).map(str::to_string);
+    let known: HashSet<String> = envelopes.iter().filter_map(id_of).collect();
+
+    let mut links = Vec::with_capacity(envelopes.len());
+    for envelope in envelopes {
+        let parent = envelope
+            .pointer("/provenance/parent_envelope_id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        links.push(ChainLink {
+            envelope_id: id_of(envelope).unwrap_or_default(),
+            parent_found: parent.as_ref().map_or(true, |p| known.contains(p)),
+            parent_envelope_id: parent,
+            verification: verify_json::<EvidenceEnvelope>(envelope, trust)?,
+        });
+    }
+    Ok(ChainReport { links })
+}
+
+/// Deterministic byte form of a JSON value: sorted keys, no whitespace.
+pub fn canonicalize(value: &Value) -> Vec<u8> {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out.into_bytes()
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+fn signing_payload(document: &Value, prior: &[Value], signer: &str, algorithm: &str, timestamp: &str) -> Vec<u8> {
+    canonicalize(&serde_json::json!({
+        "document": document,
+        "prior": prior,
+        "signer": signer,
+        "algorithm": algorithm,
+        "timestamp": timestamp,
+    }))
+}
+
+/// Separate a document from its signatures. An emptied container (e.g. a
+/// `provenance` holding nothing but signatures) is dropped, so signing an
+/// envelope without provenance and verifying it afterwards see the same bytes.
+fn split<T: Signed>(json: &Value) -> Result<(Value, Vec<Value>), SigningError> {
+    let mut document = json.clone();
+    let (field, parents) = T::SIGNATURES_AT.split_last().expect("signatures path is non-empty");
+
+    let mut signatures = Vec::new();
+    if let Some(container) = object_at(&mut document, parents)? {
+        match container.remove(*field) {
+            Some(Value::Array(items)) => signatures = items,
+            Some(Value::Null) | None => {}
+            Some(_) => return Err(SigningError::Malformed(format!("{} is not an array", field))),
+        }
+    }
+
+    // Drop containers left empty, innermost first
+    for depth in (1..=parents.len()).rev() {
+        let (last, outer) = parents[..depth].split_last().expect("depth >= 1");
+        if let Some(container) = object_at(&mut document, outer)? {
+            if container.get(*last).and_then(Value::as_object).is_some_and(|m| m.is_empty()) {
+                container.remove(*last);
+            }
+        }
+    }
+
+    Ok((document, signatures))
+}
+
+fn object_at<'a>(
+    value: &'a mut Value,
+    path: &[&str],
+) -> Result<Option<&'a mut serde_json::Map<String, Value>>, SigningError> {
+    let mut current = value;
+    for key in path {
+        match current.get_mut(*key) {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+    match current {
+        Value::Object(map) => Ok(Some(map)),
+        Value::Null => Ok(None),
+        _ => Err(SigningError::Malformed(format!("/{} is not an object", path.join("/")))),
+    }
+}
+
+fn signatures_mut<T: Signed>(json: &mut Value) -> Result<&mut Vec<Value>, SigningError> {
+    let mut current = json;
+    for key in T::SIGNATURES_AT {
+        let map = current
+            .as_object_mut()
+            .ok_or_else(|| SigningError::Malformed(format!("cannot place signatures under {}", key)))?;
+        current = map.entry(key.to_string()).or_insert_with(|| {
+            if *key == "signatures" {
+                Value::Array(Vec::new())
+            } else {
+                Value::Object(serde_json::Map::new())
+            }
+        });
+        if current.is_null() {
+            *current = Value::Object(serde_json::Map::new());
+        }
+    }
+    current
+        .as_array_mut()
+        .ok_or_else(|| SigningError::Malformed("signatures is not an array".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipt::ReceiptStatus;
+    use crate::validate::{validate_envelope, validate_receipt};
+    use crate::conversions::system_report_to_envelope;
+    use crate::envelope::Provenance;
+    use uuid::Uuid;
+
+    fn envelope() -> EvidenceEnvelope {
+        system_report_to_envelope(&serde_json::json!({ "devices": [] }), "test-host")
+    }
+
+    fn key_and_trust(signer: &str) -> (SigningKey, TrustStore) {
+        let (key, _) = SigningKey::generate(signer).unwrap();
+        let mut trust = TrustStore::default();
+        trust.trust(signer, &key.public_key_base64());
+        (key, trust)
+    }
+
+    #[test]
+    fn test_canonical_form_sorts_keys() {
+        let a: Value = serde_json::from_str(r#"{"b": 1, "a": {"d": [1, 2], "c": "x"}}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a": {"c": "x", "d": [1, 2]}, "b": 1}"#).unwrap();
+        assert_eq!(canonicalize(&a), canonicalize(&b));
+        assert_eq!(canonicalize(&a), br#"{"a":{"c":"x","d":[1,2]},"b":1}"#.to_vec());
+    }
+
+    #[test]
+    fn test_sign_and_verify_envelope() {
+        let (key, trust) = key_and_trust("helper-a");
+        let mut env = envelope();
+        assert_eq!(verify(&env, &trust).unwrap().verdict(), Verdict::Unsigned);
+
+        sign(&mut env, &key).unwrap();
+        validate_envelope(&env).unwrap();
+        assert_eq!(env.provenance.as_ref().unwrap().signatures.len(), 1);
+        assert_eq!(verify(&env, &trust).unwrap().verdict(), Verdict::Trusted);
+
+        // Survives a trip through a file
+        let raw: Value = serde_json::from_str(&serde_json::to_string_pretty(&env).unwrap()).unwrap();
+        assert_eq!(verify_json::<EvidenceEnvelope>(&raw, &trust).unwrap().verdict(), Verdict::Trusted);
+    }
+
+    #[test]
+    fn test_tampering_detected() {
+        let (key, trust) = key_and_trust("helper-a");
+        let mut env = envelope();
+        sign(&mut env, &key).unwrap();
+
+        env.source.host.hostname = "someone-else".to_string();
+        let result = verify(&env, &trust).unwrap();
+        assert_eq!(result.verdict(), Verdict::Tampered);
+        assert_eq!(result.signatures[0].status, SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn test_unknown_signer_is_untrusted() {
+        let (key, _) = key_and_trust("helper-a");
+        let mut env = envelope();
+        sign(&mut env, &key).unwrap();
+
+        let result = verify(&env, &TrustStore::default()).unwrap();
+        assert_eq!(result.verdict(), Verdict::Untrusted);
+        assert_eq!(result.signatures[0].status, SignatureStatus::UnknownSigner);
+    }
+
+    #[test]
+    fn test_countersignatures_cover_prior_signers() {
+        let (first, mut trust) = key_and_trust("origin");
+        let (second, _) = SigningKey::generate("forwarder").unwrap();
+        trust.trust("forwarder", &second.public_key_base64());
+
+        let mut env = envelope();
+        sign(&mut env, &first).unwrap();
+        sign(&mut env, &second).unwrap();
+        assert_eq!(verify(&env, &trust).unwrap().verdict(), Verdict::Trusted);
+
+        // Dropping the original signer breaks the forwarder's signature
+        env.provenance.as_mut().unwrap().signatures.remove(0);
+        assert_eq!(verify(&env, &trust).unwrap().verdict(), Verdict::Tampered);
+    }
+
+    #[test]
+    fn test_receipt_and_plan_signing() {
+        let (key, trust) = key_and_trust("executor");
+        let mut receipt = Receipt::new(Uuid::new_v4(), Uuid::new_v4(), ReceiptStatus::Completed, Vec::new());
+        sign(&mut receipt, &key).unwrap();
+        validate_receipt(&receipt).unwrap();
+        assert_eq!(verify(&receipt, &trust).unwrap().verdict(), Verdict::Trusted);
+
+        let mut plan = ProcedurePlan::new(Uuid::new_v4(), Vec::new());
+        sign(&mut plan, &key).unwrap();
+        plan.requires_reboot = true;
+        assert_eq!(verify(&plan, &trust).unwrap().verdict(), Verdict::Tampered);
+    }
+
+    #[test]
+    fn test_chain_requires_parent() {
+        let (key, trust) = key_and_trust("helper-a");
+        let mut parent = envelope();
+        sign(&mut parent, &key).unwrap();
+
+        let mut child = envelope();
+        child.provenance = Some(Provenance {
+            parent_envelope_id: Some(parent.envelope_id),
+            signatures: Vec::new(),
+        });
+        sign(&mut child, &key).unwrap();
+
+        let parent = serde_json::to_value(&parent).unwrap();
+        let mut child = serde_json::to_value(&child).unwrap();
+        assert!(verify_chain(&[parent.clone(), child.clone()], &trust).unwrap().is_intact());
+        assert!(!verify_chain(std::slice::from_ref(&child), &trust).unwrap().is_intact());
+
+        // Re-parenting the child invalidates its signature
+        child["provenance"]["parent_envelope_id"] = Value::String(Uuid::new_v4().to_string());
+        let report = verify_chain(&[parent, child], &trust).unwrap();
+        assert_eq!(report.links[1].verification.verdict(), Verdict::Tampered);
+    }
+}
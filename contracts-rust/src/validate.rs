@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Schema validation - checks contract values against the canonical JSON schemas.
+//!
+//! The schemas in `contracts/schemas/` are the source of truth; the structs in
+//! this crate mirror them by hand. Validating what we produce (and what we
+//! accept) against the embedded schema files makes any drift between the two
+//! fail loudly instead of reaching the Ward UI.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use jsonschema::Validator;
+use serde::Serialize;
+
+use crate::{
+    AmbientPayload, EvidenceEnvelope, MessageIntent, PackManifest, ProcedurePlan, Receipt,
+    RunBundle, SystemWeather,
+};
+
+const EVIDENCE_ENVELOPE_SCHEMA: &str =
+    include_str!("../../contracts/schemas/evidence-envelope.schema.json");
+const PROCEDURE_PLAN_SCHEMA: &str =
+    include_str!("../../contracts/schemas/procedure-plan.schema.json");
+const RECEIPT_SCHEMA: &str = include_str!("../../contracts/schemas/receipt.schema.json");
+const SYSTEM_WEATHER_SCHEMA: &str =
+    include_str!("../../contracts/schemas/system-weather.schema.json");
+const MESSAGE_INTENT_SCHEMA: &str =
+    include_str!("../../contracts/schemas/message-intent.schema.json");
+const RUN_BUNDLE_SCHEMA: &str = include_str!("../../contracts/schemas/run-bundle.schema.json");
+const PACK_MANIFEST_SCHEMA: &str =
+    include_str!("../../contracts/schemas/pack-manifest.schema.json");
+const AMBIENT_PAYLOAD_SCHEMA: &str =
+    include_str!("../../contracts/schemas/ambient-payload.schema.json");
+
+/// A contract type with a canonical JSON schema.
+pub trait Contract: Serialize {
+    /// Schema name, matching the keys used by the Deno validator.
+    const NAME: &'static str;
+
+    /// Compiled validator for this contract's schema.
+    fn validator() -> &'static Validator;
+}
+
+macro_rules! contract {
+    ($ty:ty, $name:literal, $schema:ident) => {
+        impl Contract for $ty {
+            const NAME: &'static str = $name;
+
+            fn validator() -> &'static Validator {
+                static VALIDATOR: OnceLock<Validator> = OnceLock::new();
+                VALIDATOR.get_or_init(|| compile($name, $schema))
+            }
+        }
+    };
+}
+
+contract!(EvidenceEnvelope, "evidenceEnvelope", EVIDENCE_ENVELOPE_SCHEMA);
+contract!(ProcedurePlan, "procedurePlan", PROCEDURE_PLAN_SCHEMA);
+contract!(Receipt, "receipt", RECEIPT_SCHEMA);
+contract!(SystemWeather, "systemWeather", SYSTEM_WEATHER_SCHEMA);
+contract!(MessageIntent, "messageIntent", MESSAGE_INTENT_SCHEMA);
+contract!(RunBundle, "runBundle", RUN_BUNDLE_SCHEMA);
+contract!(PackManifest, "packManifest", PACK_MANIFEST_SCHEMA);
+contract!(AmbientPayload, "ambientPayload", AMBIENT_PAYLOAD_SCHEMA);
+
+/// Compile an embedded schema. The schemas ship with the crate, so a failure
+/// here is a build defect rather than a runtime condition.
+///
+/// `uuid` is only a standard format from draft 2019-09 onwards; the schemas
+/// are draft-07, so it is registered explicitly to match ajv-formats in the
+/// Deno validator.
+fn compile(name: &str, source: &str) -> Validator {
+    let schema: serde_json::Value = serde_json::from_str(source)
+        .unwrap_or_else(|e| panic!("embedded {} schema is not valid JSON: {}", name, e));
+    jsonschema::options()
+        .should_validate_formats(true)
+        .with_format("uuid", |s: &str| uuid::Uuid::try_parse(s).is_ok())
+        .build(&schema)
+        .unwrap_or_else(|e| panic!("embedded {} schema failed to compile: {}", name, e))
+}
+
+/// A single schema violation, addressed by JSON pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    /// JSON pointer into the instance (e.g. `/source/tool`), `/` for the root.
+    pub path: String,
+    /// JSON pointer into the schema that rejected the value.
+    pub schema_path: String,
+    /// The schema keyword that failed (e.g. `enum`, `required`, `pattern`).
+    pub keyword: String,
+    /// Human-readable description.
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.path, self.message, self.keyword)
+    }
+}
+
+/// All schema violations found in one document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors {
+    /// Name of the contract that was checked.
+    pub contract: &'static str,
+    /// Individual violations, in the order the validator reported them.
+    pub errors: Vec<SchemaError>,
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed schema validation with {} error(s)",
+            self.contract,
+            self.errors.len()
+        )?;
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Validate a contract value against its schema.
+pub fn validate<T: Contract>(value: &T) -> Result<(), ValidationErrors> {
+    let json = serde_json::to_value(value).map_err(|e| ValidationErrors {
+        contract: T::NAME,
+        errors: vec![SchemaError {
+            path: "/".to_string(),
+            schema_path: String::new(),
+            keyword: "serialize".to_string(),
+            message: e.to_string(),
+        }],
+    })?;
+    validate_json::<T>(&json)
+}
+
+/// Validate raw JSON against the schema for `T`, before deserializing it.
+pub fn validate_json<T: Contract>(json: &serde_json::Value) -> Result<(), ValidationErrors> {
+    let errors: Vec<SchemaError> = T::validator()
+        .iter_errors(json)
+        .map(|e| {
+            let schema_path = e.schema_path.as_str().to_string();
+            let keyword = schema_path
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let path = e.instance_path.as_str();
+            SchemaError {
+                path: if path.is_empty() { "/".to_string() } else { path.to_string() },
+                schema_path,
+                keyword,
+                message: e.to_string(),
+            }
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors {
+            contract: T::NAME,
+            errors,
+        })
+    }
+}
+
+/// Validate an Evidence Envelope.
+pub fn validate_envelope(envelope: &EvidenceEnvelope) -> Result<(), ValidationErrors> {
+    validate(envelope)
+}
+
+/// Validate a Procedure Plan.
+pub fn validate_plan(plan: &ProcedurePlan) -> Result<(), ValidationErrors> {
+    validate(plan)
+}
+
+/// Validate a Receipt.
+pub fn validate_receipt(receipt: &Receipt) -> Result<(), ValidationErrors> {
+    validate(receipt)
+}
+
+/// Validate a System Weather payload.
+pub fn validate_weather(weather: &SystemWeather) -> Result<(), ValidationErrors> {
+    validate(weather)
+}
+
+/// Validate a Message Intent.
+pub fn validate_intent(intent: &MessageIntent) -> Result<(), ValidationErrors> {
+    validate(intent)
+}
+
+/// Validate a Run Bundle manifest.
+pub fn validate_bundle(bundle: &RunBundle) -> Result<(), ValidationErrors> {
+    validate(bundle)
+}
+
+/// Validate a Pack Manifest.
+pub fn validate_pack(pack: &PackManifest) -> Result<(), ValidationErrors> {
+    validate(pack)
+}
+
+/// Validate an Ambient Payload.
+pub fn validate_ambient(payload: &AmbientPayload) -> Result<(), ValidationErrors> {
+    validate(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversions::*;
+    use crate::message_intent::IntentAudience;
+    use crate::run_bundle::BundleType;
+    use uuid::Uuid;
+
+    fn sample_report() -> serde_json::Value {
+        serde_json::json!({
+            "devices": [{
+                "slot": "01:00.0",
+                "issues": [{
+                    "severity": "Critical",
+                    "issue_type": "ZombieDevice",
+                    "description": "GPU powered on with no driver",
+                    "remediation": "Bind to pci-stub or vfio-pci"
+                }]
+            }]
+        })
+    }
+
+    #[test]
+    fn test_constructors_conform() {
+        validate_weather(&SystemWeather::calm("All systems nominal")).unwrap();
+        validate_ambient(&AmbientPayload::calm("All systems nominal")).unwrap();
+        validate_intent(&MessageIntent::new(IntentAudience::User, "Disk space warning")).unwrap();
+        validate_bundle(&RunBundle::new(BundleType::Scan)).unwrap();
+    }
+
+    #[test]
+    fn test_conversions_conform() {
+        let envelope = system_report_to_envelope(&sample_report(), "test-host");
+        validate_envelope(&envelope).unwrap();
+
+        let plan_json = serde_json::json!({
+            "device": "01:00.0",
+            "strategy": "PciStub",
+            "steps": [{
+                "description": "Add pci-stub.ids kernel parameter",
+                "command": "rpm-ostree kargs --append=pci-stub.ids=10de:13b0",
+                "needs_sudo": true,
+                "needs_reboot": true
+            }],
+            "requires_reboot": true
+        });
+        let plan = remediation_plan_to_procedure(&plan_json, envelope.envelope_id);
+        validate_plan(&plan).unwrap();
+
+        let receipt_json = serde_json::json!({ "plan": plan_json });
+        let receipt = remediation_receipt_to_contract(&receipt_json, plan.plan_id, envelope.envelope_id);
+        validate_receipt(&receipt).unwrap();
+    }
+
+    #[test]
+    fn test_errors_are_path_addressed() {
+        let mut json = serde_json::to_value(system_report_to_envelope(&sample_report(), "h")).unwrap();
+        json["source"]["tool"] = serde_json::json!("not-a-tool");
+        json.as_object_mut().unwrap().remove("artifacts");
+
+        let err = validate_json::<EvidenceEnvelope>(&json).unwrap_err();
+        assert_eq!(err.contract, "evidenceEnvelope");
+        assert!(err.errors.iter().any(|e| e.path == "/source/tool" && e.keyword == "enum"));
+        assert!(err.errors.iter().any(|e| e.path == "/" && e.keyword == "required"));
+    }
+
+    #[test]
+    fn test_format_violations_reported() {
+        let mut json = serde_json::to_value(ProcedurePlan::new(Uuid::new_v4(), Vec::new())).unwrap();
+        json["envelope_ref"] = serde_json::json!("not-a-uuid");
+
+        let err = validate_json::<ProcedurePlan>(&json).unwrap_err();
+        assert!(err.errors.iter().any(|e| e.path == "/envelope_ref" && e.keyword == "format"));
+        assert!(err.to_string().contains("procedurePlan"));
+    }
+}
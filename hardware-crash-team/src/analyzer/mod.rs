@@ -20,7 +20,7 @@
     "ACS",
 ];
 
-const ACPI_ERROR_PATTERNS: &[&str] = &[
+pub(crate) const ACPI_ERROR_PATTERNS: &[&str] = &[
     "ACPI Error",
     "ACPI BIOS Error",
     "ACPI Exception",
@@ -349,11 +349,11 @@ fn extract_pci_device(line: &str) -> Option<String> {
 }
 
 /// Extract ACPI device path from a log line
-fn extract_acpi_device(line: &str) -> Option<String> {
+pub(crate) fn extract_acpi_device(line: &str) -> Option<String> {
     // Look for ACPI paths like _SB.PCI0 or \_SB._OSC
     if let Some(pos) = line.find("_SB") {
         let end = line[pos..]
-            .find(|c: char| c.is_whitespace() || c == ')' || c == ']')
+            .find(|c: char| c.is_whitespace() || c == ')' || c == ']' || c == ',')
             .unwrap_or(line.len() - pos);
         return Some(line[pos..pos + end].to_string());
     }
@@ -0,0 +1,543 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Run Bundles for scan, plan and apply.
+//!
+//! Each stage writes a new bundle next to the one it consumed and carries the
+//! earlier stage's files forward, so the final execution bundle holds the
+//! whole envelope -> plan -> receipt chain. Bundles are verified before use.
+
+use crate::remediation;
+use crate::remediation::executor::Executor;
+use crate::types::*;
+use ambientops_contracts::bundle::{RunBundleReader, RunBundleWriter};
+use ambientops_contracts::case_file::CaseFile;
+use ambientops_contracts::conversions::{self, SCAN_REPORT_FILE};
+use ambientops_contracts::retention::{self, RetentionAction, RetentionRules};
+use ambientops_contracts::run_bundle::{BundleSourceTool, BundleType, ContentType};
+use ambientops_contracts::receipt::ReceiptEvidence;
+use ambientops_contracts::redaction::{RedactionLog, REDACTION_LOG_FILE};
+use ambientops_contracts::EvidenceEnvelope;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Native plan, kept beside the contract plan so `apply` can execute it.
+const NATIVE_PLAN_FILE: &str = "remediation-plan.json";
+/// Native receipt in the undo directory, read back by `undo`.
+const NATIVE_RECEIPT_FILE: &str = "remediation-receipt.json";
+/// Device state captured before the plan ran.
+const PRE_STATE_FILE: &str = "pre-state.json";
+/// Outcome check written by `verify-fix`.
+const VERIFICATION_FILE: &str = "verification.json";
+
+/// Write a scan bundle holding the envelope, its report artifact and the log
+/// of what was redacted from them.
+pub fn write_scan(
+    parent: &Path,
+    report_json: &serde_json::Value,
+    envelope: &mut EvidenceEnvelope,
+    redactions: &RedactionLog,
+) -> Result<PathBuf> {
+    let mut writer = RunBundleWriter::create(parent, BundleSourceTool::HardwareCrashTeam, BundleType::Scan)?;
+    let report_path = writer.write_snapshot(SCAN_REPORT_FILE, &serde_json::to_vec_pretty(report_json)?)?;
+    envelope.artifacts[0].path = report_path;
+    writer.write_envelope(envelope)?;
+    writer.write_log(REDACTION_LOG_FILE, &serde_json::to_vec_pretty(redactions)?)?;
+
+    let devices = report_json.get("devices").and_then(|d| d.as_array()).map_or(0, Vec::len);
+    writer.write_summary(&format!(
+        "# Hardware scan\n\n- Host: {}\n- Envelope: {}\n- Devices: {}\n- Findings: {}\n- Redacted: {} value(s) ({:?} profile)\n",
+        envelope.source.host.hostname,
+        envelope.envelope_id,
+        devices,
+        envelope.findings.len(),
+        redactions.total(),
+        redactions.profile
+    ))?;
+    Ok(writer.finish()?)
+}
+
+/// Write a plan bundle from a verified scan bundle.
+pub fn write_plan(scan_bundle: &Path, plan: &RemediationPlan) -> Result<PathBuf> {
+    let scan = open(scan_bundle)?;
+    let envelope = scan
+        .envelope()?
+        .with_context(|| format!("{} has no envelope", scan_bundle.display()))?;
+
+    let mut proc_plan = conversions::remediation_plan_to_procedure(&serde_json::to_value(plan)?, envelope.envelope_id);
+    conversions::link_device_findings(&mut proc_plan, &envelope, &plan.device);
+    remediation::check_procedure(&proc_plan)?;
+    let mut writer = RunBundleWriter::create(parent_of(scan_bundle), BundleSourceTool::HardwareCrashTeam, BundleType::Plan)?;
+    writer.import(&scan)?;
+    writer.write_plan(&proc_plan)?;
+    writer.write_file(NATIVE_PLAN_FILE, ContentType::Plan, &serde_json::to_vec_pretty(plan)?)?;
+    writer.write_summary(&format!(
+        "# Remediation plan\n\n- Device: {}\n- Strategy: {:?}\n- Risk: {:?}\n- Requires reboot: {}\n- Plan: {}\n- Envelope: {}\n",
+        plan.device, plan.strategy, plan.risk, plan.requires_reboot, proc_plan.plan_id, envelope.envelope_id
+    ))?;
+    Ok(writer.finish()?)
+}
+
+/// Read the native plan from a verified plan bundle or a standalone plan
+/// file and run the checks it must pass before it is shown for approval.
+///
+/// Refuses a bundle whose envelope -> plan chain does not resolve.
+pub fn load_plan(path: &Path) -> Result<RemediationPlan> {
+    let plan: RemediationPlan = if path.is_dir() {
+        let bundle = open(path)?;
+        check_chain(&bundle)?;
+        serde_json::from_slice(&bundle.read_bytes(NATIVE_PLAN_FILE)?)?
+    } else {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Cannot read plan {}", path.display()))?;
+        serde_json::from_str(&content)?
+    };
+    remediation::check_plan(&plan)?;
+    Ok(plan)
+}
+
+/// Apply `plan`, as [`load_plan`] read it from a verified plan bundle, and
+/// write an execution bundle.
+pub fn apply(plan_bundle: &Path, plan: RemediationPlan, executor: &Executor) -> Result<PathBuf> {
+    let bundle = open(plan_bundle)?;
+    check_chain(&bundle)?;
+    let proc_plan = bundle
+        .plan()?
+        .with_context(|| format!("{} has no plan", plan_bundle.display()))?;
+
+    let native = executor.apply(plan);
+    let native_json = serde_json::to_value(&native)?;
+    let mut receipt =
+        conversions::remediation_receipt_to_contract(&native_json, proc_plan.plan_id, proc_plan.envelope_ref);
+
+    let mut writer = RunBundleWriter::create(parent_of(plan_bundle), BundleSourceTool::HardwareCrashTeam, BundleType::Execution)?;
+    writer.import(&bundle)?;
+    receipt.evidence = Some(write_evidence(&mut writer, &native)?);
+    let undo_path = writer.write_undo(NATIVE_RECEIPT_FILE, &serde_json::to_vec_pretty(&native)?)?;
+    if let Some(undo) = receipt.undo_bundle.as_mut() {
+        undo.path = Some(undo_path);
+    }
+    writer.write_receipt(&receipt)?;
+    writer.write_receipt_human(&receipt_text(&native, Some(&receipt)))?;
+    Ok(writer.finish()?)
+}
+
+/// Apply `plan`, as [`load_plan`] read it from a standalone plan file, and
+/// record it as an execution bundle under `records`.
+///
+/// There is no envelope or contract plan to link to, so the bundle carries
+/// only the native plan, receipt and undo data.
+pub fn apply_file(plan: RemediationPlan, records: &Path, executor: &Executor) -> Result<PathBuf> {
+    let native = executor.apply(plan);
+
+    let mut writer = RunBundleWriter::create(records, BundleSourceTool::HardwareCrashTeam, BundleType::Execution)?;
+    writer.write_file(NATIVE_PLAN_FILE, ContentType::Plan, &serde_json::to_vec_pretty(&native.plan)?)?;
+    write_evidence(&mut writer, &native)?;
+    writer.write_undo(NATIVE_RECEIPT_FILE, &serde_json::to_vec_pretty(&native)?)?;
+    writer.write_receipt_human(&receipt_text(&native, None))?;
+    Ok(writer.finish()?)
+}
+
+/// Read the native receipt back out of a verified execution bundle.
+pub fn read_receipt(execution_bundle: &Path) -> Result<RemediationReceipt> {
+    native_receipt(&open(execution_bundle)?)
+}
+
+/// Verify the outcome recorded in an execution bundle against `report` and
+/// write a new execution bundle beside it carrying the updated receipts.
+///
+/// The contract receipt keeps its id; the check becomes its after-snapshot.
+pub fn verify(execution_bundle: &Path, report: &SystemReport) -> Result<(PathBuf, RemediationReceipt)> {
+    let bundle = open(execution_bundle)?;
+    let mut native = native_receipt(&bundle)?;
+    let verification = remediation::verify::verify(&native, report)?;
+    remediation::verify::record(&mut native, verification);
+
+    let mut writer = RunBundleWriter::create(parent_of(execution_bundle), BundleSourceTool::HardwareCrashTeam, BundleType::Execution)?;
+    writer.import(&bundle)?;
+    let after = writer.write_snapshot(VERIFICATION_FILE, &serde_json::to_vec_pretty(&native.verification)?)?;
+    let undo_path = writer.write_undo(NATIVE_RECEIPT_FILE, &serde_json::to_vec_pretty(&native)?)?;
+    match bundle.receipt()? {
+        Some(previous) => {
+            let mut receipt = conversions::remediation_receipt_to_contract(
+                &serde_json::to_value(&native)?,
+                previous.plan_ref,
+                previous.envelope_ref,
+            );
+            receipt.receipt_id = previous.receipt_id;
+            receipt.created_at = previous.created_at;
+            receipt.completed_at = Some(chrono::Utc::now());
+            receipt.evidence = previous.evidence.map(|mut evidence| {
+                evidence.after_snapshot = Some(after);
+                evidence
+            });
+            if let Some(undo) = receipt.undo_bundle.as_mut() {
+                undo.path = Some(undo_path);
+            }
+            writer.write_receipt(&receipt)?;
+            writer.write_receipt_human(&receipt_text(&native, Some(&receipt)))?;
+        }
+        None => {
+            writer.write_receipt_human(&receipt_text(&native, None))?;
+        }
+    }
+    Ok((writer.finish()?, native))
+}
+
+/// Undo the receipt in an execution bundle. When undo steps ran, a new
+/// execution bundle beside it records them, so undoing that bundle again
+/// skips them; it is returned even when a step failed, with the error.
+pub fn undo(execution_bundle: &Path, executor: &Executor) -> Result<Option<PathBuf>> {
+    let bundle = open(execution_bundle)?;
+    let mut native = native_receipt(&bundle)?;
+    let recorded = native.undone.len();
+    let result = executor.undo(&mut native);
+    if native.undone.len() == recorded {
+        return result.map(|_| None);
+    }
+
+    let mut writer = RunBundleWriter::create(parent_of(execution_bundle), BundleSourceTool::HardwareCrashTeam, BundleType::Execution)?;
+    writer.import(&bundle)?;
+    let mut logs = Vec::new();
+    for outcome in &native.undone[recorded..] {
+        logs.push(writer.write_log(&format!("undo-{}.log", outcome.step), step_log(outcome).as_bytes())?);
+    }
+    let undo_path = writer.write_undo(NATIVE_RECEIPT_FILE, &serde_json::to_vec_pretty(&native)?)?;
+    match bundle.receipt()? {
+        Some(previous) => {
+            let mut receipt = conversions::remediation_receipt_to_contract(
+                &serde_json::to_value(&native)?,
+                previous.plan_ref,
+                previous.envelope_ref,
+            );
+            receipt.receipt_id = previous.receipt_id;
+            receipt.created_at = previous.created_at;
+            receipt.evidence = previous.evidence.map(|mut evidence| {
+                evidence.logs.extend(logs);
+                evidence
+            });
+            if let Some(undo) = receipt.undo_bundle.as_mut() {
+                undo.path = Some(undo_path);
+            }
+            writer.write_receipt(&receipt)?;
+            writer.write_receipt_human(&receipt_text(&native, Some(&receipt)))?;
+        }
+        None => {
+            writer.write_receipt_human(&receipt_text(&native, None))?;
+        }
+    }
+    let root = writer.finish()?;
+    result.map(|_| Some(root.clone())).with_context(|| format!("Undo recorded in {}", root.display()))
+}
+
+/// Default records root: `$HCT_RECORDS_DIR`, else
+/// `$XDG_DATA_HOME/hardware-crash-team/records` (or `~/.local/share/...`).
+pub fn records_root() -> PathBuf {
+    if let Some(dir) = std::env::var_os("HCT_RECORDS_DIR") {
+        return PathBuf::from(dir);
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    data.join("hardware-crash-team").join("records")
+}
+
+/// Report (and unless `dry_run`, enforce) retention over a records root.
+pub fn prune(records: &Path, dry_run: bool) -> Result<()> {
+    let report = retention::plan_retention(records, &RetentionRules::default(), chrono::Utc::now())
+        .with_context(|| format!("Cannot scan records in {}", records.display()))?;
+    println!("{}", report);
+
+    if dry_run {
+        println!("\nDry run: nothing changed. Re-run without --dry-run to apply.");
+        return Ok(());
+    }
+    let archives = retention::enforce(&report)?;
+    println!(
+        "\nCompressed {} bundle(s), deleted {}.",
+        archives.len(),
+        report.with_action(RetentionAction::Delete).count()
+    );
+    Ok(())
+}
+
+/// Fail unless every reference between the bundle's contract documents resolves.
+fn check_chain(bundle: &RunBundleReader) -> Result<()> {
+    let case = CaseFile::from_bundle(bundle)?;
+    let report = case.check();
+    if !report.is_intact() {
+        let problems: Vec<String> = report.problems.iter().map(|p| format!("  - {}", p)).collect();
+        anyhow::bail!(
+            "{} has a broken envelope chain:\n{}",
+            bundle.root().display(),
+            problems.join("\n")
+        );
+    }
+    Ok(())
+}
+
+fn native_receipt(bundle: &RunBundleReader) -> Result<RemediationReceipt> {
+    let path = match bundle.receipt()? {
+        Some(receipt) => receipt
+            .undo_bundle
+            .and_then(|u| u.path)
+            .with_context(|| format!("{} has no undo data", bundle.root().display()))?,
+        None => format!("undo/{}", NATIVE_RECEIPT_FILE),
+    };
+    Ok(serde_json::from_slice(&bundle.read_bytes(&path)?)?)
+}
+
+fn open(root: &Path) -> Result<RunBundleReader> {
+    RunBundleReader::open_verified(root).with_context(|| format!("Cannot use bundle {}", root.display()))
+}
+
+fn parent_of(bundle: &Path) -> &Path {
+    match bundle.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+/// Pre-apply state as a snapshot and each step's output as a log.
+fn write_evidence(writer: &mut RunBundleWriter, native: &RemediationReceipt) -> Result<ReceiptEvidence> {
+    let before = writer.write_snapshot(PRE_STATE_FILE, &serde_json::to_vec_pretty(&native.pre_state)?)?;
+    let mut logs = Vec::new();
+    let runs = native.results.iter().map(|r| ("step", r)).chain(native.rollback.iter().map(|r| ("rollback", r)));
+    for (kind, outcome) in runs.filter(|(_, r)| r.status != StepRunStatus::Skipped) {
+        logs.push(writer.write_log(&format!("{}-{}.log", kind, outcome.step), step_log(outcome).as_bytes())?);
+    }
+    Ok(ReceiptEvidence { before_snapshot: Some(before), after_snapshot: None, logs, diffs: Vec::new() })
+}
+
+fn step_log(outcome: &StepOutcome) -> String {
+    format!(
+        "$ {}\nstarted: {}\ncompleted: {}\nexit: {}\n\n{}",
+        outcome.command,
+        outcome.started_at,
+        outcome.completed_at,
+        outcome.exit_code.map_or("-".to_string(), |c| c.to_string()),
+        outcome.output
+    )
+}
+
+fn receipt_text(native: &RemediationReceipt, receipt: Option<&ambientops_contracts::Receipt>) -> String {
+    let mut text = match receipt {
+        Some(r) => format!("Remediation receipt {}\nPlan: {} ({})\n", r.receipt_id, r.plan_ref, native.plan.id),
+        None => format!("Remediation receipt\nPlan: {}\n", native.plan.id),
+    };
+    text.push_str(&format!(
+        "Device: {}\nApplied: {}\nStatus: {:?}\nReboot pending: {}\n\nSteps:\n",
+        native.plan.device, native.applied_at, native.status, native.reboot_pending
+    ));
+    for (i, step) in native.plan.steps.iter().enumerate() {
+        let status = native.results.get(i).map_or("not run".to_string(), |r| format!("{:?}", r.status));
+        text.push_str(&format!("  {}. [{}] {}\n     $ {}\n", i + 1, status, step.description, step.command));
+    }
+    if !native.rollback.is_empty() {
+        text.push_str("\nRollback:\n");
+        for outcome in &native.rollback {
+            text.push_str(&format!("  {}. [{:?}] $ {}\n", outcome.step, outcome.status, outcome.command));
+        }
+    }
+    if !native.undone.is_empty() {
+        text.push_str("\nUndone:\n");
+        for outcome in &native.undone {
+            text.push_str(&format!("  {}. [{:?}] $ {}\n", outcome.step, outcome.status, outcome.command));
+        }
+    }
+    if let Some(verification) = &native.verification {
+        text.push_str(&format!(
+            "\nVerified: {} (kernel {}): {:?}\n",
+            verification.verified_at, verification.kernel_version, verification.outcome
+        ));
+        for check in &verification.checks {
+            let result = if check.passed() { "OK" } else { "FAIL" };
+            text.push_str(&format!("  [{}] {}: expected {}, found {}\n", result, check.target, check.expected, check.observed));
+            for issue in &check.remaining_issues {
+                text.push_str(&format!("       still: {}\n", issue));
+            }
+        }
+    }
+    if matches!(native.status, ApplyStatus::Completed | ApplyStatus::Failed)
+        && !native.still_applied().is_empty()
+    {
+        text.push_str("\nUndo with: hardware-crash-team undo <this bundle>\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambientops_contracts::envelope::RedactionProfile;
+
+    fn plan() -> RemediationPlan {
+        RemediationPlan {
+            id: "plan-01-00.0-1".to_string(),
+            device: "01:00.0".to_string(),
+            strategy: RemediationStrategy::PciStub,
+            steps: vec![RemediationStep {
+                description: "Claim device".to_string(),
+                command: "rpm-ostree kargs --append=pci-stub.ids=10de:13b0".to_string(),
+                needs_sudo: true,
+                needs_reboot: true,
+            }],
+            undo_steps: vec![RemediationStep {
+                description: "Release device".to_string(),
+                command: "rpm-ostree kargs --delete=pci-stub.ids=10de:13b0".to_string(),
+                needs_sudo: true,
+                needs_reboot: true,
+            }],
+            requires_reboot: true,
+            risk: RiskLevel::Low,
+        }
+    }
+
+    fn redactions() -> RedactionLog {
+        RedactionLog { profile: RedactionProfile::Standard, records: Vec::new() }
+    }
+
+    #[test]
+    fn test_scan_plan_apply_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let mut envelope = conversions::system_report_to_envelope(&report, "test-host");
+
+        let scan = write_scan(dir.path(), &report, &mut envelope, &redactions()).unwrap();
+        assert_eq!(envelope.artifacts[0].path, "snapshots/scan-report.json");
+        assert!(RunBundleReader::open_verified(&scan).unwrap().contains("logs/redaction-log.json"));
+        let planned = write_plan(&scan, &plan()).unwrap();
+        let executed = apply(&planned, load_plan(&planned).unwrap(), &Executor::dry_run()).unwrap();
+
+        let bundle = RunBundleReader::open_verified(&executed).unwrap();
+        let proc_plan = bundle.plan().unwrap().unwrap();
+        let receipt = bundle.receipt().unwrap().unwrap();
+        assert_eq!(proc_plan.envelope_ref, envelope.envelope_id);
+        assert_eq!(receipt.plan_ref, proc_plan.plan_id);
+        assert!(bundle.contains("snapshots/scan-report.json"));
+        assert_eq!(read_receipt(&executed).unwrap().plan.id, "plan-01-00.0-1");
+    }
+
+    #[test]
+    fn test_tampered_bundle_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let mut envelope = conversions::system_report_to_envelope(&report, "test-host");
+        let scan = write_scan(dir.path(), &report, &mut envelope, &redactions()).unwrap();
+        let planned = write_plan(&scan, &plan()).unwrap();
+
+        let native = planned.join(NATIVE_PLAN_FILE);
+        let edited = std::fs::read_to_string(&native).unwrap().replace("--append", "--delete");
+        std::fs::write(&native, edited).unwrap();
+        assert!(load_plan(&planned).is_err());
+    }
+
+    #[test]
+    fn test_plan_links_findings_and_broken_chain_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({
+            "devices": [{ "issues": [{ "severity": "Critical", "description": "Device 0000:01:00.0 is in D0 with no driver" }] }]
+        });
+        let mut envelope = conversions::system_report_to_envelope(&report, "test-host");
+        let scan = write_scan(dir.path(), &report, &mut envelope, &redactions()).unwrap();
+        let planned = write_plan(&scan, &plan()).unwrap();
+        let proc_plan = RunBundleReader::open_verified(&planned).unwrap().plan().unwrap().unwrap();
+        assert_eq!(proc_plan.steps[0].finding_refs, vec![envelope.findings[0].finding_id.clone()]);
+
+        // A plan bundle whose plan names an envelope it does not carry
+        let orphan = conversions::remediation_plan_to_procedure(&serde_json::to_value(plan()).unwrap(), uuid::Uuid::new_v4());
+        let mut writer = RunBundleWriter::create(dir.path(), BundleSourceTool::HardwareCrashTeam, BundleType::Plan).unwrap();
+        writer.write_plan(&orphan).unwrap();
+        writer.write_file(NATIVE_PLAN_FILE, ContentType::Plan, &serde_json::to_vec_pretty(&plan()).unwrap()).unwrap();
+        let broken = writer.finish().unwrap();
+        let err = load_plan(&broken).unwrap_err();
+        assert!(err.to_string().contains("broken envelope chain"), "{}", err);
+    }
+
+    #[test]
+    fn test_verify_writes_updated_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let mut envelope = conversions::system_report_to_envelope(&report, "test-host");
+        let scan = write_scan(dir.path(), &report, &mut envelope, &redactions()).unwrap();
+        let mut plan = plan();
+        plan.steps[0].command = "true".to_string();
+        plan.steps[0].needs_sudo = false;
+        let planned = write_plan(&scan, &plan).unwrap();
+        let executed = apply(&planned, load_plan(&planned).unwrap(), &Executor::unescalated()).unwrap();
+        let applied = RunBundleReader::open_verified(&executed).unwrap().receipt().unwrap().unwrap();
+        assert!(read_receipt(&executed).unwrap().reboot_pending);
+
+        // After the reboot the device came up bound to something else
+        let live: SystemReport = serde_json::from_value(serde_json::json!({
+            "timestamp": "2026-02-12T00:00:00Z",
+            "kernel_version": "6.8.0-test",
+            "devices": [{
+                "slot": "0000:01:00.0", "pci_id": "10de:13b0", "description": "", "vendor": "10de", "class": "",
+                "driver": "nouveau", "kernel_modules": [], "power_state": "D0", "enabled": true,
+                "iommu_group": null, "memory_regions": [], "issues": []
+            }],
+            "iommu": { "enabled": false, "iommu_type": null, "group_count": 0, "interrupt_remapping": false },
+            "acpi_errors": [],
+            "risk_level": "Clean"
+        }))
+        .unwrap();
+        let (verified, native) = verify(&executed, &live).unwrap();
+        assert_ne!(verified, executed);
+        assert_eq!(native.status, ApplyStatus::Completed);
+        assert_eq!(native.verification.as_ref().unwrap().outcome, VerifyOutcome::Failed);
+        assert!(!native.reboot_pending);
+
+        let bundle = RunBundleReader::open_verified(&verified).unwrap();
+        let receipt = bundle.receipt().unwrap().unwrap();
+        assert_eq!(receipt.receipt_id, applied.receipt_id);
+        assert!(matches!(receipt.status, ambientops_contracts::receipt::ReceiptStatus::Failed));
+        assert_eq!(receipt.summary.unwrap().items_checked, Some(1));
+        assert_eq!(receipt.evidence.unwrap().after_snapshot.as_deref(), Some("snapshots/verification.json"));
+        assert_eq!(read_receipt(&verified).unwrap().verification.unwrap().checks[0].observed, "driver nouveau, D0, enabled");
+    }
+
+    #[test]
+    fn test_undo_recorded_in_new_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "devices": [] });
+        let mut envelope = conversions::system_report_to_envelope(&report, "test-host");
+        let scan = write_scan(dir.path(), &report, &mut envelope, &redactions()).unwrap();
+        let mut plan = plan();
+        for step in plan.steps.iter_mut().chain(plan.undo_steps.iter_mut()) {
+            step.command = "true".to_string();
+            step.needs_sudo = false;
+        }
+        let planned = write_plan(&scan, &plan).unwrap();
+        let executed = apply(&planned, load_plan(&planned).unwrap(), &Executor::unescalated()).unwrap();
+
+        let undone = undo(&executed, &Executor::unescalated()).unwrap().unwrap();
+        assert_eq!(read_receipt(&undone).unwrap().undone.len(), 1);
+        let receipt = RunBundleReader::open_verified(&undone).unwrap().receipt().unwrap().unwrap();
+        assert!(!receipt.undo_bundle.unwrap().available);
+
+        // The recorded bundle has nothing left to undo
+        assert!(undo(&undone, &Executor::unescalated()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_plan_file_recorded_as_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_path = dir.path().join("plan.json");
+        std::fs::write(&plan_path, serde_json::to_string(&plan()).unwrap()).unwrap();
+
+        let records = dir.path().join("records");
+        let executed = apply_file(load_plan(&plan_path).unwrap(), &records, &Executor::dry_run()).unwrap();
+        assert!(executed.starts_with(&records));
+        assert_eq!(read_receipt(&executed).unwrap().plan.undo_steps.len(), 1);
+
+        // Hand-written plans get the same checks before they are shown
+        let mut unpaired = plan();
+        unpaired.undo_steps.clear();
+        std::fs::write(&plan_path, serde_json::to_string(&unpaired).unwrap()).unwrap();
+        assert!(load_plan(&plan_path).is_err());
+        let mut empty = plan();
+        empty.steps.clear();
+        empty.undo_steps.clear();
+        std::fs::write(&plan_path, serde_json::to_string(&empty).unwrap()).unwrap();
+        assert!(load_plan(&plan_path).unwrap_err().to_string().contains("safety analysis"));
+    }
+}
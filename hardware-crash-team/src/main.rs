@@ -7,7 +7,7 @@
 //! and presenting remediation options with human oversight.
 
 use clap::{Parser, Subcommand};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde_json;
 
 mod scanner;
@@ -16,6 +16,8 @@
 mod types;
 mod tui;
 mod sarif;
+mod signing;
+mod bundle;
 
 /// Hardware Crash Team - diagnose and fix hardware-induced crashes
 #[derive(Parser)]
@@ -46,6 +48,28 @@ enum Commands {
         /// Output as contract-conformant EvidenceEnvelope JSON
         #[arg(long)]
         envelope: bool,
+
+        /// Redaction profile for --envelope output: none, minimal, standard, maximum
+        #[arg(long, default_value = "standard", requires = "envelope")]
+        redact: String,
+
+        /// Write a scan Run Bundle (envelope, report, summary) under this directory
+        #[arg(long, requires = "envelope", conflicts_with = "output")]
+        bundle: Option<std::path::PathBuf>,
+
+        /// Scan a snapshot from `capture` (archive or unpacked directory) instead of this machine
+        #[arg(long)]
+        from: Option<std::path::PathBuf>,
+
+        /// With --envelope --output, replace an existing report artifact and redaction log beside the envelope
+        #[arg(long, requires = "output")]
+        force: bool,
+    },
+
+    /// Archive the sysfs/procfs files a scan reads, for scanning elsewhere with `scan --from`
+    Capture {
+        /// Snapshot archive to write (default: hct-snapshot-<timestamp>.tar.gz)
+        output: Option<std::path::PathBuf>,
     },
 
     /// Analyze crash logs and correlate with hardware events
@@ -62,37 +86,135 @@ enum Commands {
     /// Present remediation options for identified issues
     Plan {
         /// Device(s) to remediate (PCI slot, e.g., "01:00.0"). Multiple devices supported.
-        #[arg(required = true)]
+        #[arg(required_unless_present = "cleanup_claims")]
         devices: Vec<String>,
 
-        /// Strategy: pci-stub, vfio-pci, dual, power-off, disable, unbind
+        /// Bootloader for kernel-argument changes: rpm-ostree, grubby, grub, systemd-boot, kernelstub
+        /// (default: detected)
+        #[arg(long)]
+        bootloader: Option<String>,
+
+        /// Plan removal of stale and duplicated pci-stub/vfio-pci claims from the kernel arguments
+        #[arg(long, conflicts_with_all = ["devices", "strategy", "procedure", "bundle"])]
+        cleanup_claims: bool,
+
+        /// Strategy: pci-stub, vfio-pci, dual, power-off, disable, unbind (default: the top-ranked one)
         #[arg(short, long)]
         strategy: Option<String>,
 
         /// Output as contract-conformant ProcedurePlan JSON
         #[arg(long)]
         procedure: bool,
+
+        /// Evidence envelope the ProcedurePlan is made from (with --procedure)
+        #[arg(long, requires = "procedure")]
+        envelope: Option<std::path::PathBuf>,
+
+        /// Scan bundle to plan from; writes a plan bundle beside it
+        #[arg(long, conflicts_with = "procedure")]
+        bundle: Option<std::path::PathBuf>,
     },
 
     /// Apply a remediation plan (requires confirmation)
     Apply {
-        /// Plan file from `plan` command
+        /// Plan file or plan bundle from `plan` command
         plan: std::path::PathBuf,
 
         /// Skip confirmation prompt
         #[arg(long)]
         yes: bool,
+
+        /// Show the steps and record a receipt without running anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Records directory for the execution bundle of a plan file
+        /// (default: $HCT_RECORDS_DIR or ~/.local/share/hardware-crash-team/records)
+        #[arg(long)]
+        records: Option<std::path::PathBuf>,
     },
 
     /// Undo a previously applied remediation
     Undo {
-        /// Receipt file from `apply` command
+        /// Receipt file or execution bundle from `apply` command
         receipt: std::path::PathBuf,
+
+        /// Skip confirmation prompt
+        #[arg(long)]
+        yes: bool,
+
+        /// Show the undo steps without running them
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Show system hardware overview
     Status,
 
+    /// Apply retention to recorded bundles: keep receipts, compress and prune old scans and plans
+    Prune {
+        /// Records directory (default: $HCT_RECORDS_DIR or ~/.local/share/hardware-crash-team/records)
+        records: Option<std::path::PathBuf>,
+
+        /// Only report what would be kept, compressed or deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Generate an Ed25519 signing key for envelopes, plans and receipts
+    Keygen {
+        /// Where to write the private key (PKCS#8)
+        #[arg(short, long, default_value = "hct-signing.key")]
+        output: std::path::PathBuf,
+
+        /// Signer name (defaults to this host's name)
+        #[arg(long)]
+        signer: Option<String>,
+
+        /// Add the public key to this trust store (created if missing)
+        #[arg(long)]
+        trust: Option<std::path::PathBuf>,
+    },
+
+    /// Sign a contract document (EvidenceEnvelope, ProcedurePlan or Receipt)
+    Sign {
+        /// Document to sign; signed in place unless --output is given
+        document: std::path::PathBuf,
+
+        /// Private key from `keygen`
+        #[arg(short, long)]
+        key: std::path::PathBuf,
+
+        /// Signer name (defaults to this host's name)
+        #[arg(long)]
+        signer: Option<String>,
+
+        /// Write the signed document here instead
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+
+    /// Verify signatures and envelope parent chains (use verify-fix to check a remediation took effect)
+    Verify {
+        /// Documents to verify; envelopes are also checked against each other's parent links
+        #[arg(required = true)]
+        documents: Vec<std::path::PathBuf>,
+
+        /// Trust store mapping signer names to public keys
+        #[arg(short, long)]
+        trust: std::path::PathBuf,
+    },
+
+    /// Check that applied remediations took effect (after the reboot, if one was needed)
+    ///
+    /// This is the post-apply `verify <receipt>` check. It is named verify-fix
+    /// because `verify` already checks document signatures against a trust store.
+    VerifyFix {
+        /// Receipt files or execution bundles from `apply`
+        #[arg(required = true)]
+        receipts: Vec<std::path::PathBuf>,
+    },
+
     /// Launch interactive TUI (requires --features tui)
     Tui,
 }
@@ -108,22 +230,74 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Scan { format, output, verbose, envelope } => {
-            println!("Scanning system hardware...");
-            let report = scanner::scan_system(verbose)?;
+        Commands::Scan { format, output, verbose, envelope, redact, bundle, from, force } => {
+            let (report, hostname, username) = match from {
+                Some(snapshot_path) => {
+                    println!("Scanning snapshot {}...", snapshot_path.display());
+                    let snapshot = scanner::source::Snapshot::open(&snapshot_path)?;
+                    let info = snapshot.info();
+                    (scanner::scan_source(&snapshot, verbose)?, info.hostname.clone(), info.username.clone())
+                }
+                None => {
+                    println!("Scanning system hardware...");
+                    (scanner::scan_system(verbose)?, gethostname(), std::env::var("USER").ok())
+                }
+            };
 
             if envelope {
-                let report_json = serde_json::to_value(&report)?;
-                let hostname = gethostname();
-                let env = ambientops_contracts::conversions::system_report_to_envelope(
+                let profile: ambientops_contracts::envelope::RedactionProfile =
+                    redact.parse().map_err(anyhow::Error::msg)?;
+                let mut redactor = ambientops_contracts::redaction::Redactor::new(profile);
+                redactor.add_host(&hostname);
+                // A snapshot's user is the one who captured it; users in home paths are found either way
+                if let Some(user) = &username {
+                    redactor.add_user(user);
+                }
+
+                // Redact the report before it is hashed into the envelope
+                let mut report_json = serde_json::to_value(&report)?;
+                redactor.redact_json("/report", &mut report_json);
+                let mut env = ambientops_contracts::conversions::system_report_to_envelope(
                     &report_json,
                     &hostname,
                 );
+                redactor.redact_envelope(&mut env);
+                let redactions = redactor.log();
+                ambientops_contracts::validate::validate_envelope(&env)?;
                 let formatted = serde_json::to_string_pretty(&env)?;
 
-                if let Some(output_path) = output {
+                if let Some(parent) = bundle {
+                    let root = bundle::write_scan(&parent, &report_json, &mut env, &redactions)?;
+                    println!("Scan bundle saved to: {}", root.display());
+                    println!("Redacted {} value(s) ({} profile)", redactions.total(), redact);
+                    println!("Plan with: hardware-crash-team plan <device> --bundle {}", root.display());
+                } else if let Some(output_path) = output {
+                    // Ship the report artifact next to the envelope so consumers can verify it
+                    let dir = output_path.parent().unwrap_or(std::path::Path::new(""));
+                    let report_path = dir.join(ambientops_contracts::conversions::SCAN_REPORT_FILE);
+                    let log_path = dir.join(ambientops_contracts::redaction::REDACTION_LOG_FILE);
+                    for existing in [&report_path, &log_path] {
+                        if existing.exists() && !force {
+                            anyhow::bail!(
+                                "Refusing to overwrite existing {} (use --force, or --bundle for a fresh directory)",
+                                existing.display()
+                            );
+                        }
+                    }
+                    std::fs::write(&report_path, serde_json::to_vec_pretty(&report_json)?)?;
+                    let (written, _) = ambientops_contracts::integrity::hash_file(
+                        ambientops_contracts::envelope::HashAlgorithm::Sha256,
+                        &report_path,
+                    )?;
+                    if env.artifacts[0].hash.as_ref() != Some(&written) {
+                        anyhow::bail!("Report artifact does not match the envelope hash");
+                    }
+
                     std::fs::write(&output_path, &formatted)?;
+                    std::fs::write(&log_path, serde_json::to_vec_pretty(&redactions)?)?;
                     println!("EvidenceEnvelope saved to: {}", output_path.display());
+                    println!("Report artifact saved to: {}", report_path.display());
+                    println!("Redacted {} value(s) ({} profile), logged to: {}", redactions.total(), redact, log_path.display());
                 } else {
                     println!("{}", formatted);
                 }
@@ -155,26 +329,78 @@ fn main() -> Result<()> {
             analyzer::print_diagnosis(&analysis);
         }
 
-        Commands::Plan { devices, strategy, procedure } => {
-            if devices.len() == 1 {
+        Commands::Plan { devices, strategy, procedure, envelope, bundle, bootloader, cleanup_claims } => {
+            // Recommending a strategy needs a scan; take it once for the rankings and the plans
+            let report = match strategy {
+                None if !cleanup_claims => Some(scanner::scan_system(false)?),
+                _ => None,
+            };
+            if let (Some(report), false) = (&report, procedure) {
+                remediation::recommend::print_rankings(report, &devices, bootloader.as_deref())?;
+            }
+            if cleanup_claims {
+                println!("Generating kernel argument cleanup plan...");
+                let report = scanner::scan_system(false)?;
+                let bootloader = remediation::bootloader::select(bootloader.as_deref())?;
+                println!("Kernel arguments managed by: {}", bootloader.name());
+                let plan = remediation::create_claim_cleanup_plan(&report.kernel_claims, bootloader.as_ref())?;
+                remediation::check_safety(&plan)?;
+                remediation::print_plan(&plan);
+            } else if let Some(scan_bundle) = bundle {
+                let mut plans = if devices.len() == 1 {
+                    vec![remediation::create_plan(&devices[0], strategy.as_deref(), bootloader.as_deref(), report.as_ref())?]
+                } else {
+                    remediation::create_multi_plan(&devices, strategy.as_deref(), bootloader.as_deref(), report.as_ref())?.plans
+                };
+                if plans.len() != 1 {
+                    anyhow::bail!(
+                        "Strategy produces {} separate plans; bundle one device at a time or use a kernel-arg strategy",
+                        plans.len()
+                    );
+                }
+                let plan = plans.remove(0);
+                remediation::check_safety(&plan)?;
+                remediation::show_plan(&plan);
+                let root = bundle::write_plan(&scan_bundle, &plan)?;
+                println!("\nPlan bundle saved to: {}", root.display());
+                println!("Apply with: hardware-crash-team apply {}", root.display());
+            } else if devices.len() == 1 {
                 let device = &devices[0];
                 println!("Generating remediation plan for device {}...", device);
-                let plan = remediation::create_plan(device, strategy.as_deref())?;
+                let plan = remediation::create_plan(device, strategy.as_deref(), bootloader.as_deref(), report.as_ref())?;
 
                 if procedure {
+                    // A ProcedurePlan must point at the envelope it remediates
+                    let envelope_path = envelope.context(
+                        "--procedure needs --envelope <file> to link the plan to (or use --bundle)",
+                    )?;
+                    let content = std::fs::read_to_string(&envelope_path)
+                        .with_context(|| format!("Cannot read envelope {}", envelope_path.display()))?;
+                    let envelope_json: serde_json::Value = serde_json::from_str(&content)?;
+                    ambientops_contracts::validate::validate_json::<ambientops_contracts::EvidenceEnvelope>(&envelope_json)?;
+                    let envelope: ambientops_contracts::EvidenceEnvelope = serde_json::from_value(envelope_json)?;
+
                     let plan_json = serde_json::to_value(&plan)?;
-                    let envelope_ref = uuid::Uuid::new_v4();
-                    let proc_plan = ambientops_contracts::conversions::remediation_plan_to_procedure(
+                    let mut proc_plan = ambientops_contracts::conversions::remediation_plan_to_procedure(
                         &plan_json,
-                        envelope_ref,
+                        envelope.envelope_id,
                     );
+                    if ambientops_contracts::conversions::link_device_findings(&mut proc_plan, &envelope, device) == 0 {
+                        eprintln!("Warning: envelope {} has no findings for device {}", envelope.envelope_id, device);
+                    }
+                    ambientops_contracts::validate::validate_plan(&proc_plan)?;
+                    remediation::check_procedure(&proc_plan)?;
                     println!("{}", serde_json::to_string_pretty(&proc_plan)?);
                 } else {
+                    remediation::check_safety(&plan)?;
                     remediation::print_plan(&plan);
                 }
             } else {
                 println!("Generating multi-device remediation plan for {} devices...", devices.len());
-                let multi = remediation::create_multi_plan(&devices, strategy.as_deref())?;
+                let multi = remediation::create_multi_plan(&devices, strategy.as_deref(), bootloader.as_deref(), report.as_ref())?;
+                for plan in &multi.plans {
+                    remediation::check_safety(plan)?;
+                }
 
                 if procedure {
                     println!("{}", serde_json::to_string_pretty(&multi)?);
@@ -184,20 +410,52 @@ fn main() -> Result<()> {
             }
         }
 
-        Commands::Apply { plan, yes } => {
+        Commands::Apply { plan, yes, dry_run, records } => {
             println!("Applying remediation plan from {}...", plan.display());
-            if !yes {
-                println!("This will modify kernel parameters. Continue? [y/N]");
-                // In real implementation, read stdin
-                println!("(Use --yes to skip this prompt)");
+            let loaded = bundle::load_plan(&plan)?;
+            remediation::show_plan(&loaded);
+            if !yes && !dry_run && !confirm("\nThis will modify the system configuration. Continue?")? {
+                println!("Aborted.");
                 return Ok(());
             }
-            remediation::apply_plan(&plan)?;
+            let executor = if dry_run { remediation::executor::Executor::dry_run() } else { remediation::executor::Executor::new()? };
+            let root = if plan.is_dir() {
+                bundle::apply(&plan, loaded, &executor)?
+            } else {
+                let records = records.unwrap_or_else(bundle::records_root);
+                bundle::apply_file(loaded, &records, &executor)?
+            };
+            println!("\nExecution bundle saved to: {}", root.display());
+            let native = bundle::read_receipt(&root)?;
+            match native.status {
+                types::ApplyStatus::Completed if native.reboot_pending => println!(
+                    "Done. Reboot to activate the change, then check it with: hardware-crash-team verify-fix {}",
+                    root.display()
+                ),
+                types::ApplyStatus::Completed => println!("Done."),
+                types::ApplyStatus::DryRun => println!("Dry run: nothing changed. Re-run without --dry-run to apply."),
+                types::ApplyStatus::RolledBack => anyhow::bail!("A step failed; the completed steps were rolled back"),
+                types::ApplyStatus::Failed => anyhow::bail!(
+                    "A step failed and rollback did not complete; inspect the receipt and run: hardware-crash-team undo {}",
+                    root.display()
+                ),
+            }
         }
 
-        Commands::Undo { receipt } => {
+        Commands::Undo { receipt, yes, dry_run } => {
             println!("Undoing remediation from {}...", receipt.display());
-            remediation::undo(&receipt)?;
+            if !yes && !dry_run && !confirm("This will revert the recorded changes. Continue?")? {
+                println!("Aborted.");
+                return Ok(());
+            }
+            let executor = if dry_run { remediation::executor::Executor::dry_run() } else { remediation::executor::Executor::new()? };
+            if receipt.is_dir() {
+                if let Some(root) = bundle::undo(&receipt, &executor)? {
+                    println!("\nUndo recorded in: {}", root.display());
+                }
+            } else {
+                remediation::undo(&receipt, &executor)?;
+            }
         }
 
         Commands::Status => {
@@ -207,6 +465,58 @@ fn main() -> Result<()> {
             scanner::print_status(&report);
         }
 
+        Commands::Capture { output } => {
+            let output = output.unwrap_or_else(|| {
+                format!("hct-snapshot-{}.tar.gz", chrono::Utc::now().format("%Y%m%dT%H%M%SZ")).into()
+            });
+            let captured = scanner::capture(&output, &gethostname(), std::env::var("USER").ok().as_deref())?;
+            println!("Captured {} path(s) to {}", captured, output.display());
+            println!("Scan it anywhere with: hardware-crash-team scan --from {}", output.display());
+        }
+
+        Commands::Prune { records, dry_run } => {
+            let records = records.unwrap_or_else(bundle::records_root);
+            bundle::prune(&records, dry_run)?;
+        }
+
+        Commands::Keygen { output, signer, trust } => {
+            let signer = signer.unwrap_or_else(gethostname);
+            signing::keygen(&output, &signer, trust.as_deref())?;
+        }
+
+        Commands::Sign { document, key, signer, output } => {
+            let signer = signer.unwrap_or_else(gethostname);
+            signing::sign_file(&document, &key, &signer, output.as_deref())?;
+        }
+
+        Commands::Verify { documents, trust } => {
+            signing::verify_files(&documents, &trust)?;
+        }
+
+        Commands::VerifyFix { receipts } => {
+            println!("Scanning system hardware...");
+            let report = scanner::scan_system(false)?;
+            let mut unverified = 0;
+            for receipt in &receipts {
+                println!("\nVerifying remediation from {}...", receipt.display());
+                let native = if receipt.is_dir() {
+                    let (root, native) = bundle::verify(receipt, &report)?;
+                    println!("Verified execution bundle saved to: {}", root.display());
+                    native
+                } else {
+                    remediation::verify_file(receipt, &report)?
+                };
+                let verification = native.verification.context("Verification was not recorded")?;
+                remediation::verify::print_verification(&verification);
+                if verification.outcome != types::VerifyOutcome::Verified {
+                    unverified += 1;
+                }
+            }
+            if unverified > 0 {
+                anyhow::bail!("{} remediation(s) did not take full effect", unverified);
+            }
+        }
+
         Commands::Tui => {
             tui::run()?;
         }
@@ -215,6 +525,16 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Ask a yes/no question on the terminal; anything but y/yes is no.
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::Write;
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 fn gethostname() -> String {
     std::fs::read_to_string("/etc/hostname")
         .unwrap_or_else(|_| "unknown".to_string())
@@ -0,0 +1,531 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Bootloader backends for kernel-argument remediations
+//!
+//! Kernel-argument strategies describe the change as [`KargEdit`]s; a
+//! [`Bootloader`] turns them into the steps that make it on this system.
+//! Each step gets an undo step at the same index, which the executor runs
+//! last first; mostly that is the backend rendering the inverse edits.
+//!
+//! | Backend | Systems | Commands |
+//! |---------|---------|----------|
+//! | `rpm-ostree` | Silverblue, Kinoite, CoreOS | `rpm-ostree kargs` |
+//! | `grubby` | Fedora, RHEL | `grubby --update-kernel=ALL` |
+//! | `grub` | Debian, Ubuntu, Arch | edit `/etc/default/grub`, `grub-mkconfig` |
+//! | `systemd-boot` | systemd-boot with kernel-install | edit `/etc/kernel/cmdline`, `kernel-install add` |
+//! | `kernelstub` | Pop!_OS | `kernelstub --add-options` |
+
+use super::shell_quote;
+use crate::scanner::source::{RootedFs, SysfsSource};
+use crate::types::RemediationStep;
+use anyhow::Result;
+
+/// Names accepted by `--bootloader`.
+pub const BACKENDS: &[&str] = &["rpm-ostree", "grubby", "grub", "systemd-boot", "kernelstub"];
+
+const DEFAULT_GRUB: &str = "/etc/default/grub";
+const KERNEL_CMDLINE: &str = "/etc/kernel/cmdline";
+const SBIN_DIRS: &[&str] = &["/usr/sbin", "/usr/bin", "/sbin", "/bin"];
+const SYSTEMD_BOOT_EFI: &[&str] = &["/efi/EFI/systemd", "/boot/EFI/systemd", "/boot/efi/EFI/systemd"];
+
+/// One change to the kernel arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KargEdit {
+    /// Add `key=value` (or a bare flag)
+    Append(String),
+    /// Remove an argument, matched exactly
+    Delete(String),
+    /// Change `key=old` to `key=new`
+    Replace { key: String, old: String, new: String },
+}
+
+impl KargEdit {
+    /// The edit that undoes this one.
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Append(arg) => Self::Delete(arg.clone()),
+            Self::Delete(arg) => Self::Append(arg.clone()),
+            Self::Replace { key, old, new } => Self::Replace { key: key.clone(), old: new.clone(), new: old.clone() },
+        }
+    }
+
+    /// As removals and additions, for tools without a replace operation.
+    fn split(edits: &[KargEdit]) -> (Vec<String>, Vec<String>) {
+        let mut remove = Vec::new();
+        let mut add = Vec::new();
+        for edit in edits {
+            match edit {
+                Self::Append(arg) => add.push(arg.clone()),
+                Self::Delete(arg) => remove.push(arg.clone()),
+                Self::Replace { key, old, new } => {
+                    remove.push(format!("{}={}", key, old));
+                    add.push(format!("{}={}", key, new));
+                }
+            }
+        }
+        (remove, add)
+    }
+}
+
+/// Undo edits for `edits`: each inverted, in reverse order.
+pub fn inverse(edits: &[KargEdit]) -> Vec<KargEdit> {
+    edits.iter().rev().map(KargEdit::inverse).collect()
+}
+
+/// A way of changing the kernel arguments the next boot uses.
+pub trait Bootloader {
+    /// Name as given to `--bootloader`.
+    fn name(&self) -> &'static str;
+    /// Steps that make `edits`; the first carries `description`.
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep>;
+    /// One step undoing each of `steps(_, edits)`, at the same index; run
+    /// last first they put the arguments back. The first carries `description`.
+    fn undo_steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        self.steps(description, &inverse(edits))
+    }
+}
+
+fn step(description: &str, command: String) -> RemediationStep {
+    RemediationStep {
+        description: description.to_string(),
+        command,
+        needs_sudo: true,
+        needs_reboot: true,
+    }
+}
+
+/// Undo for an edit-then-regenerate pair. The regeneration is undone first,
+/// while the edit is still in place, so undoing the edit regenerates again.
+fn regenerate_after(mut undo: Vec<RemediationStep>) -> Vec<RemediationStep> {
+    undo[0].command = format!("{} && {}", undo[0].command, undo[1].command);
+    undo
+}
+
+/// For tools without a replace operation: a removal, then an addition,
+/// each paired with the command that reverts it. Separate commands so a
+/// replaced key is removed before it is re-added.
+fn split_commands(edits: &[KargEdit], remove: impl Fn(&str) -> String, add: impl Fn(&str) -> String) -> Vec<(String, String)> {
+    let (removed, added) = KargEdit::split(edits);
+    let mut pairs = Vec::new();
+    if !removed.is_empty() {
+        let args = shell_quote(&removed.join(" "));
+        pairs.push((remove(&args), add(&args)));
+    }
+    if !added.is_empty() {
+        let args = shell_quote(&added.join(" "));
+        pairs.push((add(&args), remove(&args)));
+    }
+    pairs
+}
+
+/// Steps for the applying (or with `undo`, the reverting) side of `pairs`.
+fn split_steps(description: &str, pairs: Vec<(String, String)>, undo: bool) -> Vec<RemediationStep> {
+    let rest = if undo { "Remove the added kernel arguments" } else { "Add the new kernel arguments" };
+    pairs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (apply, revert))| step(if i == 0 { description } else { rest }, if undo { revert } else { apply }))
+        .collect()
+}
+
+/// Escape for the pattern side of a `sed -E` substitution.
+fn sed_pattern(s: &str) -> String {
+    s.chars().fold(String::new(), |mut out, c| {
+        if r"\/.[]()*+?{}|^$".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+/// Escape for the replacement side of a `sed` substitution.
+fn sed_replacement(s: &str) -> String {
+    s.replace('\\', r"\\").replace('/', r"\/").replace('&', r"\&")
+}
+
+/// rpm-ostree: one `kargs` transaction per step.
+pub struct RpmOstree;
+
+impl Bootloader for RpmOstree {
+    fn name(&self) -> &'static str {
+        "rpm-ostree"
+    }
+
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        let args: Vec<String> = edits
+            .iter()
+            .map(|edit| match edit {
+                KargEdit::Append(arg) => format!("--append={}", arg),
+                KargEdit::Delete(arg) => format!("--delete={}", arg),
+                KargEdit::Replace { key, old, new } => format!("--replace={}={}={}", key, old, new),
+            })
+            .collect();
+        vec![step(description, format!("rpm-ostree kargs {}", args.join(" ")))]
+    }
+}
+
+/// grubby: edits every installed kernel's entry.
+pub struct Grubby;
+
+impl Bootloader for Grubby {
+    fn name(&self) -> &'static str {
+        "grubby"
+    }
+
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        split_steps(description, Self::commands(edits), false)
+    }
+
+    fn undo_steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        split_steps(description, Self::commands(edits), true)
+    }
+}
+
+impl Grubby {
+    fn commands(edits: &[KargEdit]) -> Vec<(String, String)> {
+        split_commands(
+            edits,
+            |args| format!("grubby --update-kernel=ALL --remove-args={}", args),
+            |args| format!("grubby --update-kernel=ALL --args={}", args),
+        )
+    }
+}
+
+/// GRUB without grubby: edit `/etc/default/grub` and regenerate grub.cfg.
+pub struct GrubMkconfig {
+    /// `grub-mkconfig` or `grub2-mkconfig`
+    pub command: &'static str,
+    /// Generated configuration to overwrite
+    pub config: String,
+}
+
+impl Bootloader for GrubMkconfig {
+    fn name(&self) -> &'static str {
+        "grub"
+    }
+
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        // New arguments go in GRUB_CMDLINE_LINUX so recovery entries get them
+        // too; removals and replacements look in both variables. Values may
+        // be single- or double-quoted and followed by a comment.
+        const BOTH: &str = "/^GRUB_CMDLINE_LINUX(_DEFAULT)?=/";
+        let expressions: Vec<String> = edits
+            .iter()
+            .flat_map(|edit| match edit {
+                KargEdit::Append(arg) => ['"', '\'']
+                    .map(|q| format!(r"s/^(GRUB_CMDLINE_LINUX={q})([^{q}]*){q}/\1\2 {}{q}/", sed_replacement(arg)))
+                    .to_vec(),
+                KargEdit::Delete(arg) => vec![format!(r#"{} s/(["' ]){}(["' ])/\1\2/g"#, BOTH, sed_pattern(arg))],
+                KargEdit::Replace { key, old, new } => vec![format!(
+                    r#"{} s/(["' ]){}={}(["' ])/\1{}={}\2/g"#,
+                    BOTH,
+                    sed_pattern(key),
+                    sed_pattern(old),
+                    sed_replacement(key),
+                    sed_replacement(new)
+                )],
+            })
+            .map(|e| format!("-e {}", shell_quote(&e)))
+            .collect();
+        // sed succeeds even when no line matched: fail unless each added argument landed
+        let checks: String = edits
+            .iter()
+            .filter_map(|edit| match edit {
+                KargEdit::Append(arg) => Some(format!(
+                    " && grep -qE {} {}",
+                    shell_quote(&format!(r#"^GRUB_CMDLINE_LINUX=.*["' ]{}["' ]"#, sed_pattern(arg).replace(r"\/", "/"))),
+                    DEFAULT_GRUB
+                )),
+                _ => None,
+            })
+            .collect();
+        vec![
+            step(description, format!("sed -i -E {} {}{}", expressions.join(" "), DEFAULT_GRUB, checks)),
+            step("Regenerate the GRUB configuration", format!("{} -o {}", self.command, self.config)),
+        ]
+    }
+
+    fn undo_steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        regenerate_after(self.steps(description, &inverse(edits)))
+    }
+}
+
+/// systemd-boot (or any kernel-install layout): edit `/etc/kernel/cmdline`
+/// and reinstall the running kernel's entry.
+pub struct KernelInstall;
+
+impl Bootloader for KernelInstall {
+    fn name(&self) -> &'static str {
+        "systemd-boot"
+    }
+
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        let expressions: Vec<String> = edits
+            .iter()
+            .map(|edit| match edit {
+                KargEdit::Append(arg) => format!("s/$/ {}/", sed_replacement(arg)),
+                KargEdit::Delete(arg) => format!(r"s/(^| ){}( |$)/\1\2/g", sed_pattern(arg)),
+                KargEdit::Replace { key, old, new } => format!(
+                    r"s/(^| ){}={}( |$)/\1{}={}\2/g",
+                    sed_pattern(key),
+                    sed_pattern(old),
+                    sed_replacement(key),
+                    sed_replacement(new)
+                ),
+            })
+            .map(|e| format!("-e {}", shell_quote(&e)))
+            .collect();
+        vec![
+            step(description, format!("sed -i -E {} {}", expressions.join(" "), KERNEL_CMDLINE)),
+            step(
+                "Reinstall the boot entry for the running kernel",
+                r#"kernel-install add "$(uname -r)" "/usr/lib/modules/$(uname -r)/vmlinuz""#.to_string(),
+            ),
+        ]
+    }
+
+    fn undo_steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        regenerate_after(self.steps(description, &inverse(edits)))
+    }
+}
+
+/// kernelstub: Pop!_OS's systemd-boot manager.
+pub struct Kernelstub;
+
+impl Bootloader for Kernelstub {
+    fn name(&self) -> &'static str {
+        "kernelstub"
+    }
+
+    fn steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        split_steps(description, Self::commands(edits), false)
+    }
+
+    fn undo_steps(&self, description: &str, edits: &[KargEdit]) -> Vec<RemediationStep> {
+        split_steps(description, Self::commands(edits), true)
+    }
+}
+
+impl Kernelstub {
+    fn commands(edits: &[KargEdit]) -> Vec<(String, String)> {
+        split_commands(
+            edits,
+            |args| format!("kernelstub --delete-options={}", args),
+            |args| format!("kernelstub --add-options={}", args),
+        )
+    }
+}
+
+/// The backend named `name`, or the one detected on this machine.
+pub fn select(name: Option<&str>) -> Result<Box<dyn Bootloader>> {
+    let live = RootedFs::live();
+    match name {
+        Some(name) => by_name(&live, name),
+        None => detect(&live),
+    }
+}
+
+/// The backend called `name`, with paths taken from `source`.
+pub fn by_name(source: &dyn SysfsSource, name: &str) -> Result<Box<dyn Bootloader>> {
+    Ok(match name {
+        "rpm-ostree" => Box::new(RpmOstree),
+        "grubby" => Box::new(Grubby),
+        "grub" => Box::new(grub_mkconfig(source)),
+        "systemd-boot" | "kernel-install" => Box::new(KernelInstall),
+        "kernelstub" => Box::new(Kernelstub),
+        other => anyhow::bail!("Unknown bootloader: {}. Use: {}", other, BACKENDS.join(", ")),
+    })
+}
+
+/// Work out which backend manages this machine's kernel arguments. The
+/// order matters: ostree systems also ship grub, Pop!_OS ships
+/// `/etc/kernel/cmdline`, and Fedora ships both grubby and grub2-mkconfig.
+pub fn detect(source: &dyn SysfsSource) -> Result<Box<dyn Bootloader>> {
+    if source.exists("/run/ostree-booted") {
+        return Ok(Box::new(RpmOstree));
+    }
+    if source.exists("/etc/kernelstub/configuration") {
+        return Ok(Box::new(Kernelstub));
+    }
+    if source.exists(KERNEL_CMDLINE) && SYSTEMD_BOOT_EFI.iter().any(|p| source.exists(p)) {
+        return Ok(Box::new(KernelInstall));
+    }
+    if find_binary(source, "grubby").is_some() {
+        return Ok(Box::new(Grubby));
+    }
+    if source.exists(DEFAULT_GRUB) {
+        return Ok(Box::new(grub_mkconfig(source)));
+    }
+    anyhow::bail!(
+        "Cannot tell which bootloader manages the kernel arguments; pass --bootloader ({})",
+        BACKENDS.join(", ")
+    )
+}
+
+fn grub_mkconfig(source: &dyn SysfsSource) -> GrubMkconfig {
+    // Fedora and openSUSE install GRUB as grub2
+    let grub2 = source.exists("/boot/grub2/grub.cfg") || find_binary(source, "grub2-mkconfig").is_some();
+    if grub2 {
+        GrubMkconfig { command: "grub2-mkconfig", config: "/boot/grub2/grub.cfg".to_string() }
+    } else {
+        GrubMkconfig { command: "grub-mkconfig", config: "/boot/grub/grub.cfg".to_string() }
+    }
+}
+
+fn find_binary(source: &dyn SysfsSource, name: &str) -> Option<String> {
+    SBIN_DIRS.iter().map(|dir| format!("{}/{}", dir, name)).find(|path| source.exists(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits() -> Vec<KargEdit> {
+        vec![
+            KargEdit::Append("pci-stub.ids=10de:13b0".to_string()),
+            KargEdit::Replace { key: "vfio-pci.ids".to_string(), old: "10de:13b0,1002:73bf".to_string(), new: "10de:13b0".to_string() },
+            KargEdit::Delete("rd.driver.pre=vfio-pci".to_string()),
+        ]
+    }
+
+    fn commands(backend: &dyn Bootloader, edits: &[KargEdit]) -> Vec<String> {
+        backend.steps("edit", edits).into_iter().map(|s| s.command).collect()
+    }
+
+    #[test]
+    fn test_backend_commands() {
+        assert_eq!(
+            commands(&RpmOstree, &edits()),
+            vec!["rpm-ostree kargs --append=pci-stub.ids=10de:13b0 --replace=vfio-pci.ids=10de:13b0,1002:73bf=10de:13b0 --delete=rd.driver.pre=vfio-pci"]
+        );
+        assert_eq!(
+            commands(&Grubby, &edits()),
+            vec![
+                "grubby --update-kernel=ALL --remove-args='vfio-pci.ids=10de:13b0,1002:73bf rd.driver.pre=vfio-pci'",
+                "grubby --update-kernel=ALL --args='pci-stub.ids=10de:13b0 vfio-pci.ids=10de:13b0'",
+            ]
+        );
+        assert_eq!(
+            commands(&Kernelstub, &[KargEdit::Append("pci-stub.ids=10de:13b0".to_string())]),
+            vec!["kernelstub --add-options=pci-stub.ids=10de:13b0"]
+        );
+
+        let grub = GrubMkconfig { command: "grub-mkconfig", config: "/boot/grub/grub.cfg".to_string() };
+        let grub = commands(&grub, &edits());
+        assert_eq!(grub.len(), 2);
+        assert!(grub[0].starts_with(r#"sed -i -E -e 's/^(GRUB_CMDLINE_LINUX=")([^"]*)"/\1\2 pci-stub.ids=10de:13b0"/'"#));
+        assert!(grub[0].contains(r#"s/(["'\'' ])rd\.driver\.pre=vfio-pci(["'\'' ])/\1\2/g"#));
+        assert!(grub[0].ends_with(r#" /etc/default/grub && grep -qE '^GRUB_CMDLINE_LINUX=.*["'\'' ]pci-stub\.ids=10de:13b0["'\'' ]' /etc/default/grub"#));
+        assert_eq!(grub[1], "grub-mkconfig -o /boot/grub/grub.cfg");
+    }
+
+    #[test]
+    fn test_undo_steps_pair_with_steps() {
+        let grub = GrubMkconfig { command: "grub-mkconfig", config: "/boot/grub/grub.cfg".to_string() };
+        let backends: [&dyn Bootloader; 5] = [&RpmOstree, &Grubby, &grub, &KernelInstall, &Kernelstub];
+        for backend in backends {
+            assert_eq!(backend.steps("edit", &edits()).len(), backend.undo_steps("undo", &edits()).len(), "{}", backend.name());
+        }
+
+        // Undone last first: the added arguments go, then the removed ones return
+        let undo: Vec<String> = Grubby.undo_steps("undo", &edits()).into_iter().map(|s| s.command).collect();
+        assert_eq!(
+            undo,
+            vec![
+                "grubby --update-kernel=ALL --args='vfio-pci.ids=10de:13b0,1002:73bf rd.driver.pre=vfio-pci'",
+                "grubby --update-kernel=ALL --remove-args='pci-stub.ids=10de:13b0 vfio-pci.ids=10de:13b0'",
+            ]
+        );
+
+        // Undoing the regeneration first changes nothing; undoing the edit regenerates
+        let undo = grub.undo_steps("undo", &edits());
+        assert!(undo[0].command.starts_with("sed -i -E "));
+        assert!(undo[0].command.ends_with(" && grub-mkconfig -o /boot/grub/grub.cfg"));
+        assert_eq!(undo[1].command, "grub-mkconfig -o /boot/grub/grub.cfg");
+    }
+
+    #[test]
+    fn test_sed_edits_apply_and_undo() {
+        // Run the generated expressions through sed itself where it is available
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("cmdline");
+        let original = "root=/dev/sda1 vfio-pci.ids=10de:13b0,1002:73bf rd.driver.pre=vfio-pci quiet";
+        std::fs::write(&file, format!("{}\n", original)).unwrap();
+
+        let run = |edits: &[KargEdit]| {
+            let command = commands(&KernelInstall, edits)[0].replace(KERNEL_CMDLINE, &file.to_string_lossy());
+            std::process::Command::new("sh").arg("-c").arg(&command).status().map(|s| s.success()).unwrap_or(false)
+        };
+        if !run(&edits()) {
+            return;
+        }
+        let edited = std::fs::read_to_string(&file).unwrap();
+        let words: Vec<&str> = edited.split_whitespace().collect();
+        assert_eq!(words, vec!["root=/dev/sda1", "vfio-pci.ids=10de:13b0", "quiet", "pci-stub.ids=10de:13b0"]);
+
+        assert!(run(&inverse(&edits())));
+        let restored = std::fs::read_to_string(&file).unwrap();
+        let mut expected: Vec<&str> = original.split_whitespace().collect();
+        let mut words: Vec<&str> = restored.split_whitespace().collect();
+        expected.sort();
+        words.sort();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn test_grub_append_handles_quotes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("grub");
+        let grub = GrubMkconfig { command: "grub-mkconfig", config: "/boot/grub/grub.cfg".to_string() };
+        let append = [KargEdit::Append("pci-stub.ids=10de:13b0".to_string())];
+        let run = |content: &str, edits: &[KargEdit]| {
+            std::fs::write(&file, content).unwrap();
+            let command = commands(&grub, edits)[0].replace(DEFAULT_GRUB, &file.to_string_lossy());
+            let ok = std::process::Command::new("sh").arg("-c").arg(&command).status().map(|s| s.success()).unwrap_or(false);
+            (ok, std::fs::read_to_string(&file).unwrap())
+        };
+
+        let single = "GRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\nGRUB_CMDLINE_LINUX='rhgb acpi_osi=\"!Windows 2012\"' # installer\n";
+        let (ok, edited) = run(single, &append);
+        if !ok {
+            return;
+        }
+        assert_eq!(
+            edited,
+            "GRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\nGRUB_CMDLINE_LINUX='rhgb acpi_osi=\"!Windows 2012\" pci-stub.ids=10de:13b0' # installer\n"
+        );
+        let (ok, restored) = run(&edited, &inverse(&append));
+        assert!(ok);
+        assert_eq!(restored.lines().nth(1), Some("GRUB_CMDLINE_LINUX='rhgb acpi_osi=\"!Windows 2012\" ' # installer"));
+
+        let (ok, edited) = run("GRUB_CMDLINE_LINUX=\"\"  \n", &append);
+        assert!(ok);
+        assert_eq!(edited, "GRUB_CMDLINE_LINUX=\" pci-stub.ids=10de:13b0\"  \n");
+
+        // Nothing to append to: the step fails instead of reporting success
+        let (ok, edited) = run("GRUB_CMDLINE_LINUX=rhgb\n", &append);
+        assert!(!ok);
+        assert_eq!(edited, "GRUB_CMDLINE_LINUX=rhgb\n");
+    }
+
+    #[test]
+    fn test_detect() {
+        let dir = tempfile::tempdir().unwrap();
+        let touch = |path: &str| {
+            let path = dir.path().join(path.trim_start_matches('/'));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        };
+        let detect_name = || detect(&RootedFs::new(dir.path()).unwrap()).map(|b| b.name());
+
+        assert!(detect_name().is_err());
+        touch("/etc/default/grub");
+        assert_eq!(detect_name().unwrap(), "grub");
+        touch("/usr/sbin/grubby");
+        assert_eq!(detect_name().unwrap(), "grubby");
+        touch("/run/ostree-booted");
+        assert_eq!(detect_name().unwrap(), "rpm-ostree");
+
+        assert!(by_name(&RootedFs::new(dir.path()).unwrap(), "lilo").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Plan execution
+//!
+//! Runs a plan's steps in order through `sh -c`, escalating the ones that
+//! need root with `sudo` (or `pkexec`) unless hct already runs as root.
+//! Device state is captured into the receipt first. The first failing step
+//! stops the run; the undo steps of the steps that completed are then run
+//! to put the system back. A dry-run executor runs nothing and marks every
+//! step skipped. Each plan step is undone by the undo step at its index,
+//! last step first.
+
+use crate::scanner::source::{RootedFs, SysfsSource};
+use crate::types::*;
+use anyhow::Result;
+use std::process::{Command, Stdio};
+
+/// How much of a step's output a receipt keeps.
+const OUTPUT_TAIL: usize = 4096;
+
+/// Runs plan and undo steps.
+pub struct Executor {
+    dry_run: bool,
+    /// Prefix for steps that need root; `None` when already root
+    escalate: Option<&'static str>,
+}
+
+impl Executor {
+    /// A real executor. Fails if steps would need root and neither root,
+    /// `sudo` nor `pkexec` is available.
+    pub fn new() -> Result<Self> {
+        let escalate = if is_root() {
+            None
+        } else if let Some(tool) = ["sudo", "pkexec"].into_iter().find(|t| on_path(t)) {
+            Some(tool)
+        } else {
+            anyhow::bail!("Remediation needs root: run as root, or install sudo or pkexec");
+        };
+        Ok(Self { dry_run: false, escalate })
+    }
+
+    /// An executor that runs nothing.
+    pub fn dry_run() -> Self {
+        Self { dry_run: true, escalate: None }
+    }
+
+    /// A real executor that never escalates, for tests.
+    #[cfg(test)]
+    pub(crate) fn unescalated() -> Self {
+        Self { dry_run: false, escalate: None }
+    }
+
+    /// Run a plan and return its receipt. Failure is recorded in the
+    /// receipt, not returned as an error.
+    pub fn apply(&self, plan: RemediationPlan) -> RemediationReceipt {
+        let pre_state = PreState::capture(&RootedFs::live(), &plan.device);
+        let applied_at = chrono::Utc::now().to_rfc3339();
+        println!("Applying plan: {}", plan.id);
+
+        let mut results = Vec::new();
+        let mut failed = false;
+        for (i, step) in plan.steps.iter().enumerate() {
+            if failed {
+                results.push(StepOutcome::skipped(i + 1, &step.command));
+                continue;
+            }
+            let outcome = self.run(i + 1, plan.steps.len(), step);
+            failed = outcome.status == StepRunStatus::Failed;
+            results.push(outcome);
+        }
+
+        let mut rollback = Vec::new();
+        let status = if self.dry_run {
+            ApplyStatus::DryRun
+        } else if !failed {
+            ApplyStatus::Completed
+        } else {
+            let completed = succeeded(&results);
+            println!("Step failed; rolling back {} completed step(s)", completed.len());
+            rollback = self.run_undo(&plan, &completed);
+            let restored = rollback.iter().all(|r| r.status == StepRunStatus::Success);
+            if restored {
+                for result in results.iter_mut().filter(|r| r.status == StepRunStatus::Success) {
+                    result.status = StepRunStatus::RolledBack;
+                }
+                ApplyStatus::RolledBack
+            } else {
+                ApplyStatus::Failed
+            }
+        };
+
+        RemediationReceipt {
+            reboot_pending: status == ApplyStatus::Completed && plan.requires_reboot,
+            plan,
+            applied_at,
+            pre_state,
+            status,
+            results,
+            rollback,
+            undone: Vec::new(),
+            verification: None,
+        }
+    }
+
+    /// Undo what a receipt applied and record the undo steps run in it.
+    /// Only steps that succeeded and were not undone since, by a rollback or
+    /// an earlier `undo`, are undone; a rolled-back or dry run has nothing to undo.
+    pub fn undo(&self, receipt: &mut RemediationReceipt) -> Result<()> {
+        if matches!(receipt.status, ApplyStatus::DryRun | ApplyStatus::RolledBack) {
+            println!("Nothing to undo: plan {} was {:?}", receipt.plan.id, receipt.status);
+            return Ok(());
+        }
+        let applied = receipt.still_applied();
+        if applied.is_empty() {
+            println!("Nothing to undo: every step of plan {} is already undone", receipt.plan.id);
+            return Ok(());
+        }
+        println!("Undoing plan: {}", receipt.plan.id);
+        let outcomes = self.run_undo(&receipt.plan, &applied);
+        let failed = outcomes
+            .iter()
+            .find(|o| o.status == StepRunStatus::Failed)
+            .map(|o| format!("Undo step {} failed: {}", o.step, o.command));
+        receipt.undone.extend(outcomes.into_iter().filter(|o| o.status != StepRunStatus::Skipped));
+        match failed {
+            Some(message) => Err(anyhow::anyhow!(message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Undo the plan steps in `applied` (indices in plan order), last first,
+    /// each with the undo step at its index. Stops at the first failure.
+    fn run_undo(&self, plan: &RemediationPlan, applied: &[usize]) -> Vec<StepOutcome> {
+        let mut outcomes = Vec::new();
+        for &i in applied.iter().rev() {
+            let Some(step) = plan.undo_steps.get(i) else {
+                println!("  Step {} has no undo step; revert it by hand: {}", i + 1, plan.steps[i].command);
+                continue;
+            };
+            let outcome = self.run(i + 1, plan.undo_steps.len(), step);
+            let failed = outcome.status == StepRunStatus::Failed;
+            outcomes.push(outcome);
+            if failed {
+                break;
+            }
+        }
+        outcomes
+    }
+
+    fn run(&self, number: usize, total: usize, step: &RemediationStep) -> StepOutcome {
+        println!("  [{}/{}] {}", number, total, step.description);
+        if self.dry_run {
+            println!("    {}$ {}", if step.needs_sudo { "sudo " } else { "" }, step.command);
+            println!("    [DRY RUN - not executed]");
+            return StepOutcome::skipped(number, &step.command);
+        }
+
+        let started_at = chrono::Utc::now().to_rfc3339();
+        let mut command = match (step.needs_sudo, self.escalate) {
+            (true, Some(tool)) => {
+                let mut c = Command::new(tool);
+                c.arg("sh");
+                c
+            }
+            _ => Command::new("sh"),
+        };
+        let result = command
+            .arg("-c")
+            .arg(&step.command)
+            .stdin(Stdio::inherit())
+            .output();
+
+        let (status, exit_code, output) = match result {
+            Ok(out) => {
+                let mut text = String::from_utf8_lossy(&out.stdout).to_string();
+                text.push_str(&String::from_utf8_lossy(&out.stderr));
+                let status = if out.status.success() { StepRunStatus::Success } else { StepRunStatus::Failed };
+                (status, out.status.code(), tail(&text))
+            }
+            Err(e) => (StepRunStatus::Failed, None, format!("Cannot run step: {}", e)),
+        };
+        match status {
+            StepRunStatus::Success => println!("    ok"),
+            _ => {
+                println!("    FAILED (exit {})", exit_code.map_or("-".to_string(), |c| c.to_string()));
+                for line in output.lines().take(10) {
+                    println!("      {}", line);
+                }
+            }
+        }
+        StepOutcome {
+            step: number,
+            command: step.command.clone(),
+            status,
+            exit_code,
+            output,
+            started_at,
+            completed_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+impl RemediationReceipt {
+    /// Plan steps (0-based) that succeeded and have not been undone since.
+    pub fn still_applied(&self) -> Vec<usize> {
+        let undone = |step: usize| {
+            self.rollback
+                .iter()
+                .chain(&self.undone)
+                .any(|o| o.step == step && o.status == StepRunStatus::Success)
+        };
+        succeeded(&self.results).into_iter().filter(|&i| !undone(i + 1)).collect()
+    }
+}
+
+/// Plan steps (0-based) whose run succeeded.
+fn succeeded(results: &[StepOutcome]) -> Vec<usize> {
+    results.iter().filter(|r| r.status == StepRunStatus::Success).map(|r| r.step - 1).collect()
+}
+
+impl StepOutcome {
+    fn skipped(step: usize, command: &str) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Self {
+            step,
+            command: command.to_string(),
+            status: StepRunStatus::Skipped,
+            exit_code: None,
+            output: String::new(),
+            started_at: now.clone(),
+            completed_at: now,
+        }
+    }
+}
+
+impl PreState {
+    /// Capture the command line and the state of each device named in a
+    /// plan's `device` ("0000:01:00.0" or "0000:01:00.0, 0000:01:00.1").
+    pub fn capture(source: &dyn SysfsSource, devices: &str) -> Self {
+        let devices = devices
+            .split(',')
+            .map(str::trim)
+            .filter(|slot| source.exists(&format!("/sys/bus/pci/devices/{}", slot)))
+            .map(|slot| {
+                let path = format!("/sys/bus/pci/devices/{}", slot);
+                let read = |file: &str| source.read(&format!("{}/{}", path, file)).map(|v| v.trim().to_string());
+                DeviceState {
+                    slot: slot.to_string(),
+                    driver: source
+                        .read_link(&format!("{}/driver", path))
+                        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string())),
+                    power_state: read("power_state").unwrap_or_default(),
+                    power_control: read("power/control"),
+                    enabled: read("enable").map(|e| e != "0"),
+                }
+            })
+            .collect();
+        Self {
+            captured_at: chrono::Utc::now().to_rfc3339(),
+            cmdline: source.read("/proc/cmdline").unwrap_or_default().trim().to_string(),
+            devices,
+        }
+    }
+}
+
+fn tail(text: &str) -> String {
+    if text.len() <= OUTPUT_TAIL {
+        return text.to_string();
+    }
+    let mut start = text.len() - OUTPUT_TAIL;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
+
+fn is_root() -> bool {
+    // Effective UID is the second field of the Uid line
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|s| s.lines().find(|l| l.starts_with("Uid:")).and_then(|l| l.split_whitespace().nth(2).map(|u| u == "0")))
+        .unwrap_or(false)
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(command: String) -> RemediationStep {
+        RemediationStep { description: command.clone(), command, needs_sudo: false, needs_reboot: false }
+    }
+
+    /// A plan of `touch`/`rm` pairs over files in `dir`, with `fail_at`
+    /// replaced by a failing command.
+    fn plan(dir: &std::path::Path, fail_at: Option<usize>) -> RemediationPlan {
+        let file = |i: usize| dir.join(format!("step-{}", i)).to_string_lossy().to_string();
+        RemediationPlan {
+            id: "plan-test".to_string(),
+            device: "0000:ff:00.0".to_string(),
+            strategy: RemediationStrategy::SysfsDisable,
+            steps: (0..3)
+                .map(|i| step(if Some(i) == fail_at { "echo boom >&2; exit 3".to_string() } else { format!("touch {}", file(i)) }))
+                .collect(),
+            undo_steps: (0..3).map(|i| step(format!("rm {}", file(i)))).collect(),
+            requires_reboot: false,
+            risk: RiskLevel::Low,
+        }
+    }
+
+    #[test]
+    fn test_apply_and_undo() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut receipt = Executor::unescalated().apply(plan(dir.path(), None));
+        assert_eq!(receipt.status, ApplyStatus::Completed);
+        assert!(receipt.results.iter().all(|r| r.status == StepRunStatus::Success && r.exit_code == Some(0)));
+        assert!(dir.path().join("step-2").exists());
+
+        Executor::unescalated().undo(&mut receipt).unwrap();
+        // Last step first, each by its own undo step
+        let steps: Vec<usize> = receipt.undone.iter().map(|o| o.step).collect();
+        assert_eq!(steps, vec![3, 2, 1]);
+        assert!(receipt.undone[0].command.ends_with("step-2"));
+        assert!(!dir.path().join("step-0").exists());
+
+        // A second undo has nothing left to run
+        Executor::unescalated().undo(&mut receipt).unwrap();
+        assert_eq!(receipt.undone.len(), 3);
+    }
+
+    #[test]
+    fn test_failed_undo_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut receipt = Executor::unescalated().apply(plan(dir.path(), None));
+        // Step 2's undo fails once: its file is back as a directory rm will not remove
+        std::fs::remove_file(dir.path().join("step-1")).unwrap();
+        std::fs::create_dir(dir.path().join("step-1")).unwrap();
+        assert!(Executor::unescalated().undo(&mut receipt).is_err());
+        assert_eq!(receipt.still_applied(), vec![0, 1]);
+        assert!(dir.path().join("step-0").exists());
+
+        std::fs::remove_dir(dir.path().join("step-1")).unwrap();
+        std::fs::write(dir.path().join("step-1"), "").unwrap();
+        Executor::unescalated().undo(&mut receipt).unwrap();
+        assert!(receipt.still_applied().is_empty());
+        let steps: Vec<usize> = receipt.undone.iter().map(|o| o.step).collect();
+        assert_eq!(steps, vec![3, 2, 2, 1]);
+    }
+
+    #[test]
+    fn test_failure_stops_and_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let receipt = Executor::unescalated().apply(plan(dir.path(), Some(1)));
+        assert_eq!(receipt.status, ApplyStatus::RolledBack);
+        let statuses: Vec<StepRunStatus> = receipt.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![StepRunStatus::RolledBack, StepRunStatus::Failed, StepRunStatus::Skipped]);
+        assert_eq!(receipt.results[1].exit_code, Some(3));
+        assert_eq!(receipt.results[1].output.trim(), "boom");
+        // Only the completed step was undone
+        assert_eq!(receipt.rollback.len(), 1);
+        assert!(!dir.path().join("step-0").exists());
+        assert!(!receipt.reboot_pending);
+
+        // Nothing left to undo
+        let mut receipt = receipt;
+        Executor::unescalated().undo(&mut receipt).unwrap();
+        assert!(receipt.undone.is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_runs_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let receipt = Executor::dry_run().apply(plan(dir.path(), None));
+        assert_eq!(receipt.status, ApplyStatus::DryRun);
+        assert!(receipt.results.iter().all(|r| r.status == StepRunStatus::Skipped));
+        assert!(!dir.path().join("step-0").exists());
+    }
+
+    #[test]
+    fn test_legacy_receipt_has_nothing_to_undo() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut json = serde_json::to_value(Executor::dry_run().apply(plan(dir.path(), None))).unwrap();
+        let legacy = json.as_object_mut().unwrap();
+        for field in ["status", "results", "rollback"] {
+            legacy.remove(field);
+        }
+        legacy.insert("pre_state".to_string(), "".into());
+
+        let mut receipt: RemediationReceipt = serde_json::from_value(json).unwrap();
+        assert_eq!(receipt.status, ApplyStatus::DryRun);
+        assert!(receipt.pre_state.devices.is_empty());
+        Executor::unescalated().undo(&mut receipt).unwrap();
+        assert!(receipt.undone.is_empty());
+    }
+
+    #[test]
+    fn test_pre_state_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = dir.path().join("sys/bus/pci/devices/0000:01:00.0");
+        std::fs::create_dir_all(dev.join("power")).unwrap();
+        std::fs::write(dev.join("power_state"), "D0\n").unwrap();
+        std::fs::write(dev.join("power/control"), "on\n").unwrap();
+        std::fs::write(dev.join("enable"), "1\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("proc")).unwrap();
+        std::fs::write(dir.path().join("proc/cmdline"), "root=/dev/sda1 quiet\n").unwrap();
+
+        let state = PreState::capture(&RootedFs::new(dir.path()).unwrap(), "0000:01:00.0, 0000:02:00.0");
+        assert_eq!(state.cmdline, "root=/dev/sda1 quiet");
+        assert_eq!(state.devices.len(), 1);
+        assert_eq!(state.devices[0].power_state, "D0");
+        assert_eq!(state.devices[0].power_control.as_deref(), Some("on"));
+        assert_eq!(state.devices[0].enabled, Some(true));
+        assert_eq!(state.devices[0].driver, None);
+    }
+}
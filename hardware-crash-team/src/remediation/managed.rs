@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Managed configuration files
+//!
+//! The persistent strategies that need no bootloader change write their own
+//! file under `/etc`: a modprobe.d snippet that hands devices to vfio-pci
+//! before their drivers load, or a udev rule that acts on a device whenever
+//! it appears. Every such file starts with [`MARKER`]. Writing refuses to
+//! replace a file without it and undo only removes files that still carry
+//! it, so hand-written configuration is never touched.
+
+use super::shell_quote;
+use crate::scanner::source::SysfsSource;
+use crate::types::RemediationStep;
+
+/// First line of every file hct writes.
+pub const MARKER: &str = "# Managed by hardware-crash-team; remove with `hardware-crash-team undo`, do not edit";
+
+/// Modules that are themselves the claim and must never be blacklisted.
+const STUB_MODULES: &[&str] = &["vfio_pci", "pci_stub"];
+
+/// modprobe.d snippet for the devices in `slots`.
+pub fn modprobe_path(slots: &[&str]) -> String {
+    format!("/etc/modprobe.d/hct-{}.conf", file_stem(slots))
+}
+
+/// udev rule for the device at `slot`. Late in the order so hct acts after
+/// the distribution's rules.
+pub fn udev_path(slot: &str) -> String {
+    format!("/etc/udev/rules.d/90-hct-{}.rules", file_stem(&[slot]))
+}
+
+fn file_stem(slots: &[&str]) -> String {
+    slots.iter().map(|s| s.replace(':', "-")).collect::<Vec<_>>().join("_")
+}
+
+/// A device handed to vfio-pci through modprobe.d.
+pub struct ModprobeClaim {
+    pub slot: String,
+    /// `vendor:device`
+    pub pci_id: String,
+    /// Loadable modules that would otherwise claim it
+    pub modules: Vec<String>,
+}
+
+/// Contents of the modprobe.d snippet, marker first. vfio-pci takes one
+/// `ids=` list, so all devices of a plan share a single `options` line.
+/// Modules bound to no device outside `claims` (per `bound_elsewhere`) are
+/// blacklisted as well; the rest only get a softdep so vfio-pci loads first.
+pub fn modprobe_conf(claims: &[ModprobeClaim], bound_elsewhere: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut ids: Vec<&str> = Vec::new();
+    let mut modules: Vec<&str> = Vec::new();
+    for claim in claims {
+        if !ids.contains(&claim.pci_id.as_str()) {
+            ids.push(&claim.pci_id);
+        }
+        for module in &claim.modules {
+            if !STUB_MODULES.contains(&module.as_str()) && !modules.contains(&module.as_str()) {
+                modules.push(module);
+            }
+        }
+    }
+
+    let slots: Vec<&str> = claims.iter().map(|c| c.slot.as_str()).collect();
+    let mut lines = vec![
+        MARKER.to_string(),
+        format!("# Claim {} with vfio-pci before their drivers load", slots.join(", ")),
+        format!("options vfio-pci ids={}", ids.join(",")),
+    ];
+    lines.extend(modules.iter().map(|m| format!("softdep {} pre: vfio-pci", m)));
+    lines.extend(modules.iter().filter(|m| !bound_elsewhere(m)).map(|m| format!("blacklist {}", m)));
+    lines
+}
+
+/// What a udev rule does to its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdevAction {
+    /// Let runtime PM power it down (`power/control` = auto)
+    PowerAuto,
+    /// Unbind whatever driver binds it
+    Unbind,
+    /// Remove it from the bus
+    Remove,
+}
+
+/// Contents of the udev rule, marker first. The rule matches the slot and
+/// the IDs, so a different card moved into the slot is left alone.
+pub fn udev_rule(slot: &str, pci_id: &str, action: UdevAction) -> Vec<String> {
+    let (vendor, device) = pci_id.split_once(':').unwrap_or(("0000", "0000"));
+    let matches = format!(
+        r#"SUBSYSTEM=="pci", KERNEL=="{}", ATTR{{vendor}}=="0x{}", ATTR{{device}}=="0x{}""#,
+        slot, vendor, device
+    );
+    let rule = match action {
+        UdevAction::PowerAuto => format!(r#"ACTION=="add", {}, ATTR{{power/control}}="auto""#, matches),
+        // Drivers bind after the add event, so act on the bind that follows
+        UdevAction::Unbind => format!(
+            r#"ACTION=="bind", {}, RUN+="/bin/sh -c 'echo {} > /sys/bus/pci/devices/{}/driver/unbind'""#,
+            matches, slot, slot
+        ),
+        UdevAction::Remove => format!(r#"ACTION=="add", {}, ATTR{{remove}}="1""#, matches),
+    };
+    vec![MARKER.to_string(), rule]
+}
+
+/// Step that writes `lines` to `path`, refusing a file hct did not write.
+pub fn write_step(description: &str, path: &str, lines: &[String], needs_reboot: bool) -> RemediationStep {
+    let path_q = shell_quote(path);
+    let body: Vec<String> = lines.iter().map(|l| shell_quote(l)).collect();
+    let script = format!(
+        "if [ -e {p} ] && [ \"$(head -n 1 {p})\" != {m} ]; then echo {p} is not managed by hardware-crash-team >&2; exit 1; fi; \
+         printf '%s\\n' {body} > {p}",
+        p = path_q,
+        m = shell_quote(MARKER),
+        body = body.join(" "),
+    );
+    RemediationStep {
+        description: description.to_string(),
+        command: format!("sh -c {}", shell_quote(&script)),
+        needs_sudo: true,
+        needs_reboot,
+    }
+}
+
+/// Step that removes `path` if hct still owns it.
+pub fn remove_step(description: &str, path: &str, needs_reboot: bool) -> RemediationStep {
+    let script = format!(
+        "if [ \"$(head -n 1 {p} 2>/dev/null)\" = {m} ]; then rm -f {p}; fi",
+        p = shell_quote(path),
+        m = shell_quote(MARKER),
+    );
+    RemediationStep {
+        description: description.to_string(),
+        command: format!("sh -c {}", shell_quote(&script)),
+        needs_sudo: true,
+        needs_reboot,
+    }
+}
+
+/// How this machine builds its initramfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitramfsBuilder {
+    /// ostree: the initramfs is only rebuilt with /etc files it tracks
+    RpmOstree,
+    Dracut,
+    InitramfsTools,
+    Mkinitcpio,
+}
+
+impl InitramfsBuilder {
+    pub fn detect(source: &dyn SysfsSource) -> Option<Self> {
+        if source.exists("/run/ostree-booted") {
+            return Some(Self::RpmOstree);
+        }
+        let has = |name: &str| ["/usr/bin", "/usr/sbin", "/bin", "/sbin"].iter().any(|d| source.exists(&format!("{}/{}", d, name)));
+        if has("dracut") {
+            Some(Self::Dracut)
+        } else if has("update-initramfs") {
+            Some(Self::InitramfsTools)
+        } else if has("mkinitcpio") {
+            Some(Self::Mkinitcpio)
+        } else {
+            None
+        }
+    }
+
+    /// Steps that carry `path` into the initramfs, and that take it back out
+    /// once it has been removed.
+    pub fn steps(self, path: &str) -> (RemediationStep, RemediationStep) {
+        let (apply, undo) = match self {
+            Self::RpmOstree => (
+                format!("rpm-ostree initramfs-etc --track={}", path),
+                format!("rpm-ostree initramfs-etc --untrack={}", path),
+            ),
+            Self::Dracut => ("dracut -f --regenerate-all".to_string(), "dracut -f --regenerate-all".to_string()),
+            Self::InitramfsTools => ("update-initramfs -u -k all".to_string(), "update-initramfs -u -k all".to_string()),
+            Self::Mkinitcpio => ("mkinitcpio -P".to_string(), "mkinitcpio -P".to_string()),
+        };
+        let step = |description: &str, command: String| RemediationStep {
+            description: description.to_string(),
+            command,
+            needs_sudo: true,
+            needs_reboot: true,
+        };
+        (step("Rebuild the initramfs with the new configuration", apply), step("Rebuild the initramfs without it", undo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modprobe_conf() {
+        let claims = vec![
+            ModprobeClaim { slot: "0000:01:00.0".into(), pci_id: "10de:13b0".into(), modules: vec!["nouveau".into(), "vfio_pci".into()] },
+            ModprobeClaim { slot: "0000:01:00.1".into(), pci_id: "10de:0fbc".into(), modules: vec!["snd_hda_intel".into()] },
+        ];
+        let lines = modprobe_conf(&claims, |m| m == "snd_hda_intel");
+        assert_eq!(lines[0], MARKER);
+        assert_eq!(
+            &lines[2..],
+            [
+                "options vfio-pci ids=10de:13b0,10de:0fbc",
+                "softdep nouveau pre: vfio-pci",
+                "softdep snd_hda_intel pre: vfio-pci",
+                // The iGPU's audio still needs snd_hda_intel
+                "blacklist nouveau",
+            ]
+        );
+        assert_eq!(modprobe_path(&["0000:01:00.0", "0000:01:00.1"]), "/etc/modprobe.d/hct-0000-01-00.0_0000-01-00.1.conf");
+    }
+
+    #[test]
+    fn test_udev_rules() {
+        let rule = udev_rule("0000:01:00.0", "10de:13b0", UdevAction::PowerAuto);
+        assert_eq!(
+            rule[1],
+            r#"ACTION=="add", SUBSYSTEM=="pci", KERNEL=="0000:01:00.0", ATTR{vendor}=="0x10de", ATTR{device}=="0x13b0", ATTR{power/control}="auto""#
+        );
+        assert!(udev_rule("0000:01:00.0", "10de:13b0", UdevAction::Unbind)[1].starts_with(r#"ACTION=="bind""#));
+        assert!(udev_rule("0000:01:00.0", "10de:13b0", UdevAction::Remove)[1].ends_with(r#"ATTR{remove}="1""#));
+    }
+
+    #[test]
+    fn test_write_and_remove_respect_ownership() {
+        let dir = tempfile::tempdir().unwrap();
+        let ours = dir.path().join("hct.conf").to_string_lossy().to_string();
+        let theirs = dir.path().join("theirs.conf").to_string_lossy().to_string();
+        std::fs::write(&theirs, "blacklist nouveau\n").unwrap();
+        let run = |step: RemediationStep| {
+            std::process::Command::new("sh").arg("-c").arg(&step.command).status().unwrap().success()
+        };
+        let lines = vec![MARKER.to_string(), "options vfio-pci ids=10de:13b0".to_string()];
+
+        assert!(run(write_step("write", &ours, &lines, true)));
+        assert_eq!(std::fs::read_to_string(&ours).unwrap(), format!("{}\n", lines.join("\n")));
+        // Rewriting our own file is fine; someone else's is refused
+        assert!(run(write_step("write", &ours, &lines, true)));
+        assert!(!run(write_step("write", &theirs, &lines, true)));
+        assert_eq!(std::fs::read_to_string(&theirs).unwrap(), "blacklist nouveau\n");
+
+        assert!(run(remove_step("remove", &theirs, true)));
+        assert!(std::path::Path::new(&theirs).exists());
+        assert!(run(remove_step("remove", &ours, true)));
+        assert!(!std::path::Path::new(&ours).exists());
+    }
+}
@@ -4,117 +4,79 @@
 //! Generates, applies, and undoes remediation plans for hardware issues.
 //! All destructive operations require explicit human confirmation.
 
+pub mod bootloader;
+pub mod executor;
+pub mod managed;
+pub mod recommend;
+pub mod verify;
+
 use crate::types::*;
-use anyhow::Result;
+use ambientops_contracts::conversions;
+use ambientops_contracts::safety::{self, SafetyVerdict};
+use ambientops_contracts::ProcedurePlan;
+use anyhow::{Context, Result};
+use bootloader::{Bootloader, KargEdit};
+use executor::Executor;
+use managed::UdevAction;
+use crate::scanner::iommu;
+use crate::scanner::modules::{normalize_module, ModuleIndex};
+use crate::scanner::source::{RootedFs, SysfsSource};
 use std::path::Path;
 
-/// Create a remediation plan for a device
-pub fn create_plan(device: &str, strategy: Option<&str>) -> Result<RemediationPlan> {
-    let strategy = parse_strategy(strategy)?;
+/// Create a remediation plan for a device. Kernel-argument strategies are
+/// written for `bootloader` (a name from [`bootloader::BACKENDS`]), or for
+/// the one detected on this machine. Without a named strategy the
+/// recommender picks one from `report`, the live scan.
+pub fn create_plan(
+    device: &str,
+    strategy: Option<&str>,
+    bootloader: Option<&str>,
+    report: Option<&SystemReport>,
+) -> Result<RemediationPlan> {
+    let preferred = match strategy {
+        Some(_) => None,
+        None => {
+            let report = report.context("Recommending a strategy needs a scan")?;
+            Some(recommend::default_strategy(&RootedFs::live(), report, device, bootloader)?)
+        }
+    };
+    let strategy = parse_strategy(strategy, preferred)?;
+    check_iommu_groups(&strategy, &[device.to_string()])?;
     let pci_id = read_device_pci_id(device)?;
     let (vendor, dev_id) = pci_id.split_once(':').unwrap_or(("0000", "0000"));
     let plan_id = format!("plan-{}-{}", device.replace(':', "-"), chrono::Utc::now().timestamp());
 
     let plan = match strategy {
-        RemediationStrategy::DualNullDriver => {
-            RemediationPlan {
-                id: plan_id,
-                device: device.to_string(),
-                strategy: RemediationStrategy::DualNullDriver,
-                steps: vec![
-                    RemediationStep {
-                        description: format!("Claim device {} with pci-stub and vfio-pci null drivers", device),
-                        command: format!(
-                            "rpm-ostree kargs --append=pci-stub.ids={}:{},{}:{} --append=vfio-pci.ids={}:{},{}:{} --append=rd.driver.pre=vfio-pci",
-                            vendor, dev_id, vendor, dev_id,
-                            vendor, dev_id, vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
-                undo_steps: vec![
-                    RemediationStep {
-                        description: format!("Remove pci-stub and vfio-pci claims for device {}", device),
-                        command: format!(
-                            "rpm-ostree kargs --delete=pci-stub.ids={}:{},{}:{} --delete=vfio-pci.ids={}:{},{}:{} --delete=rd.driver.pre=vfio-pci",
-                            vendor, dev_id, vendor, dev_id,
-                            vendor, dev_id, vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
-                requires_reboot: true,
-                risk: RiskLevel::Low,
-            }
-        }
-
-        RemediationStrategy::PciStub => {
+        RemediationStrategy::DualNullDriver | RemediationStrategy::PciStub | RemediationStrategy::VfioPci => {
+            let (claim, release) = match strategy {
+                RemediationStrategy::DualNullDriver => (
+                    format!("Claim device {} with pci-stub and vfio-pci null drivers", device),
+                    format!("Remove pci-stub and vfio-pci claims for device {}", device),
+                ),
+                RemediationStrategy::PciStub => (
+                    format!("Claim device {} with pci-stub kernel null driver", device),
+                    format!("Remove pci-stub claim for device {}", device),
+                ),
+                _ => (
+                    format!("Claim device {} with vfio-pci (IOMMU-backed isolation)", device),
+                    format!("Remove vfio-pci claim for device {}", device),
+                ),
+            };
+            let bootloader = bootloader::select(bootloader)?;
+            let edits = claim_edits(&strategy, &format!("{}:{}", vendor, dev_id));
             RemediationPlan {
                 id: plan_id,
                 device: device.to_string(),
-                strategy: RemediationStrategy::PciStub,
-                steps: vec![
-                    RemediationStep {
-                        description: format!("Claim device {} with pci-stub kernel null driver", device),
-                        command: format!(
-                            "rpm-ostree kargs --append=pci-stub.ids={}:{}",
-                            vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
-                undo_steps: vec![
-                    RemediationStep {
-                        description: format!("Remove pci-stub claim for device {}", device),
-                        command: format!(
-                            "rpm-ostree kargs --delete=pci-stub.ids={}:{}",
-                            vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
-                requires_reboot: true,
-                risk: RiskLevel::Low,
-            }
-        }
-
-        RemediationStrategy::VfioPci => {
-            RemediationPlan {
-                id: plan_id,
-                device: device.to_string(),
-                strategy: RemediationStrategy::VfioPci,
-                steps: vec![
-                    RemediationStep {
-                        description: format!("Claim device {} with vfio-pci (IOMMU-backed isolation)", device),
-                        command: format!(
-                            "rpm-ostree kargs --append=vfio-pci.ids={}:{} --append=rd.driver.pre=vfio-pci",
-                            vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
-                undo_steps: vec![
-                    RemediationStep {
-                        description: format!("Remove vfio-pci claim for device {}", device),
-                        command: format!(
-                            "rpm-ostree kargs --delete=vfio-pci.ids={}:{} --delete=rd.driver.pre=vfio-pci",
-                            vendor, dev_id
-                        ),
-                        needs_sudo: true,
-                        needs_reboot: true,
-                    },
-                ],
+                steps: bootloader.steps(&claim, &edits),
+                undo_steps: bootloader.undo_steps(&release, &edits),
+                strategy,
                 requires_reboot: true,
                 risk: RiskLevel::Low,
             }
         }
 
         RemediationStrategy::AcpiPowerOff => {
+            let control = read_live_power_control(device);
             RemediationPlan {
                 id: plan_id,
                 device: device.to_string(),
@@ -140,6 +102,15 @@ pub fn create_plan(device: &str, strategy: Option<&str>) -> Result<RemediationPl
                     },
                 ],
                 undo_steps: vec![
+                    RemediationStep {
+                        description: format!("Restore device {} power control to {}", device, control),
+                        command: format!(
+                            "echo {} > /sys/bus/pci/devices/{}/power/control",
+                            control, device
+                        ),
+                        needs_sudo: true,
+                        needs_reboot: false,
+                    },
                     RemediationStep {
                         description: "Rescan PCI bus to re-discover removed device".to_string(),
                         command: "echo 1 > /sys/bus/pci/rescan".to_string(),
@@ -186,11 +157,7 @@ pub fn create_plan(device: &str, strategy: Option<&str>) -> Result<RemediationPl
 
         RemediationStrategy::DriverUnbind => {
             // Read current driver to know where to unbind from
-            let driver_path = format!("/sys/bus/pci/devices/{}/driver", device);
-            let driver_name = std::fs::read_link(&driver_path)
-                .ok()
-                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
-                .unwrap_or_else(|| "unknown".to_string());
+            let driver_name = read_live_driver(device).unwrap_or_else(|| "unknown".to_string());
 
             RemediationPlan {
                 id: plan_id,
@@ -222,15 +189,195 @@ pub fn create_plan(device: &str, strategy: Option<&str>) -> Result<RemediationPl
                 risk: RiskLevel::Low,
             }
         }
+
+        RemediationStrategy::ModprobeConf => create_modprobe_plan(plan_id, &[device.to_string()])?,
+
+        RemediationStrategy::UdevPowerAuto | RemediationStrategy::UdevUnbind | RemediationStrategy::UdevRemove => {
+            let (action, verb) = match strategy {
+                RemediationStrategy::UdevPowerAuto => (UdevAction::PowerAuto, "power down"),
+                RemediationStrategy::UdevUnbind => (UdevAction::Unbind, "unbind"),
+                _ => (UdevAction::Remove, "remove"),
+            };
+            let path = managed::udev_path(device);
+
+            // Act now too, so the plan needs no reboot. That goes first: the
+            // undo runs last first, and restoring the device while the rule is
+            // still installed would only set it off again.
+            let sysfs = format!("/sys/bus/pci/devices/{}", device);
+            let now = match action {
+                UdevAction::PowerAuto => Some((
+                    format!("echo auto > {}/power/control", sysfs),
+                    format!("echo {} > {}/power/control", read_live_power_control(device), sysfs),
+                )),
+                UdevAction::Unbind => read_live_driver(device).map(|driver| {
+                    (
+                        format!("echo {} > {}/driver/unbind", device, sysfs),
+                        format!("echo {} > /sys/bus/pci/drivers/{}/bind", device, driver),
+                    )
+                }),
+                UdevAction::Remove => Some((format!("echo 1 > {}/remove", sysfs), "echo 1 > /sys/bus/pci/rescan".to_string())),
+            };
+            let mut steps = Vec::new();
+            let mut undo_steps = Vec::new();
+            if let Some((apply, undo)) = now {
+                steps.push(RemediationStep {
+                    description: format!("Apply to device {} now", device),
+                    command: apply,
+                    needs_sudo: true,
+                    needs_reboot: false,
+                });
+                undo_steps.push(RemediationStep {
+                    description: format!("Restore device {}", device),
+                    command: undo,
+                    needs_sudo: true,
+                    needs_reboot: false,
+                });
+            }
+
+            let mut remove = managed::remove_step(&format!("Remove udev rule {}", path), &path, false);
+            remove.command = format!("{} && {}", remove.command, udev_reload_step().command);
+            steps.push(managed::write_step(
+                &format!("Install udev rule {} to {} device {} whenever it appears", path, verb, device),
+                &path,
+                &managed::udev_rule(device, &pci_id, action),
+                false,
+            ));
+            undo_steps.push(remove);
+            steps.push(udev_reload_step());
+            undo_steps.push(udev_reload_step());
+
+            RemediationPlan {
+                id: plan_id,
+                device: device.to_string(),
+                risk: strategy.risk_level(),
+                strategy,
+                steps,
+                undo_steps,
+                requires_reboot: false,
+            }
+        }
+
+        RemediationStrategy::ClaimCleanup => {
+            anyhow::bail!("Claim cleanup is not per-device; use `plan --cleanup-claims`")
+        }
     };
 
+    check_undo(&plan)?;
     Ok(plan)
 }
 
-/// Create a multi-device remediation plan
-pub fn create_multi_plan(devices: &[String], strategy: Option<&str>) -> Result<MultiDevicePlan> {
-    let strategy = parse_strategy(strategy)?;
+/// Plan removal of stale and duplicated null-driver claims from the next
+/// boot's kernel arguments. IDs still matching a present device keep their
+/// first occurrence; `rd.driver.pre=vfio-pci` goes once no vfio-pci IDs remain.
+pub fn create_claim_cleanup_plan(claims: &KernelClaims, bootloader: &dyn Bootloader) -> Result<RemediationPlan> {
+    let stale: Vec<(&str, &str)> = claims.stale().map(|c| (c.stub.as_str(), c.id.as_str())).collect();
+    let mut kept: Vec<(String, String)> = Vec::new(); // (stub, id)
+    let mut edits = Vec::new();
+
+    for param in &claims.next_boot_params {
+        let Some((key, value)) = param.split_once('=') else { continue };
+        let Some(stub) = key.strip_suffix(".ids") else { continue };
+        let ids: Vec<&str> = value
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .filter(|id| {
+                let id = id.to_lowercase();
+                if stale.contains(&(stub, id.as_str())) || kept.contains(&(stub.to_string(), id.clone())) {
+                    return false;
+                }
+                kept.push((stub.to_string(), id));
+                true
+            })
+            .collect();
+        let new_value = ids.join(",");
+        if new_value == value {
+            continue;
+        }
+        if new_value.is_empty() {
+            edits.push(KargEdit::Delete(param.clone()));
+        } else {
+            edits.push(KargEdit::Replace { key: key.to_string(), old: value.to_string(), new: new_value });
+        }
+    }
+
+    let vfio_remains = kept.iter().any(|(stub, _)| stub == "vfio-pci");
+    if !vfio_remains && !edits.is_empty() && claims.next_boot_params.iter().any(|p| p == "rd.driver.pre=vfio-pci") {
+        edits.push(KargEdit::Delete("rd.driver.pre=vfio-pci".to_string()));
+    }
+
+    if edits.is_empty() {
+        anyhow::bail!("No stale or duplicated null-driver claims to clean up");
+    }
+
+    let plan = RemediationPlan {
+        id: format!("plan-cleanup-claims-{}", chrono::Utc::now().timestamp()),
+        device: "kernel-cmdline".to_string(),
+        strategy: RemediationStrategy::ClaimCleanup,
+        steps: bootloader.steps("Remove stale and duplicated null-driver claims from the kernel arguments", &edits),
+        undo_steps: bootloader.undo_steps("Restore the removed null-driver claims", &edits),
+        requires_reboot: true,
+        risk: RiskLevel::Low,
+    };
+    check_undo(&plan)?;
+    Ok(plan)
+}
+
+/// Create a multi-device remediation plan. Without a named strategy each
+/// device gets the one recommended from `report`, and devices sharing a
+/// strategy share its plans.
+pub fn create_multi_plan(
+    devices: &[String],
+    strategy: Option<&str>,
+    bootloader: Option<&str>,
+    report: Option<&SystemReport>,
+) -> Result<MultiDevicePlan> {
     let plan_id = format!("multi-plan-{}", chrono::Utc::now().timestamp());
+    let mut groups: Vec<(RemediationStrategy, Vec<String>)> = Vec::new();
+    if strategy.is_some() {
+        groups.push((parse_strategy(strategy, None)?, devices.to_vec()));
+    } else {
+        let report = report.context("Recommending a strategy needs a scan")?;
+        let live = RootedFs::live();
+        for dev in devices {
+            let chosen = recommend::default_strategy(&live, report, dev, bootloader)?;
+            match groups.iter_mut().find(|(s, _)| strategy_name(s) == strategy_name(&chosen)) {
+                Some((_, group)) => group.push(dev.clone()),
+                None => groups.push((chosen, vec![dev.clone()])),
+            }
+        }
+    }
+
+    let mut plans = Vec::new();
+    for (strategy, group) in &groups {
+        let group_id = if groups.len() > 1 {
+            format!("{}-{}", plan_id, strategy_name(strategy).unwrap_or_default())
+        } else {
+            plan_id.clone()
+        };
+        plans.extend(create_group_plans(&group_id, strategy, group, bootloader)?);
+    }
+
+    Ok(MultiDevicePlan {
+        id: plan_id,
+        devices: devices.to_vec(),
+        plans,
+        requires_reboot: groups.iter().any(|(s, _)| s.requires_reboot()),
+        risk: if groups.iter().any(|(s, _)| matches!(s.risk_level(), RiskLevel::Medium)) {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        },
+    })
+}
+
+/// Plans claiming `devices` with one strategy.
+fn create_group_plans(
+    plan_id: &str,
+    strategy: &RemediationStrategy,
+    devices: &[String],
+    bootloader: Option<&str>,
+) -> Result<Vec<RemediationPlan>> {
+    check_iommu_groups(strategy, devices)?;
 
     // Collect PCI IDs for all devices
     let mut device_ids: Vec<(String, String, String)> = Vec::new(); // (slot, vendor, device)
@@ -241,36 +388,97 @@ pub fn create_multi_plan(devices: &[String], strategy: Option<&str>) -> Result<M
     }
 
     // For kernel arg strategies, combine into single command
-    let plans = match strategy {
+    Ok(match strategy {
         RemediationStrategy::PciStub | RemediationStrategy::VfioPci | RemediationStrategy::DualNullDriver => {
             // Combined kernel args for all devices
             let combined_plan = create_combined_kargs_plan(
-                &plan_id, &device_ids, &strategy,
+                plan_id, &device_ids, strategy, bootloader::select(bootloader)?.as_ref(),
             );
+            check_undo(&combined_plan)?;
             vec![combined_plan]
         }
 
+        // One snippet: vfio-pci takes a single ids= list
+        RemediationStrategy::ModprobeConf => vec![create_modprobe_plan(format!("{}-combined", plan_id), devices)?],
+
         // Per-device strategies
         _ => {
             let mut plans = Vec::new();
             for dev in devices {
-                plans.push(create_plan(dev, strategy_name(&strategy))?);
+                plans.push(create_plan(dev, strategy_name(strategy), bootloader, None)?);
             }
             plans
         }
-    };
-
-    Ok(MultiDevicePlan {
-        id: plan_id,
-        devices: devices.to_vec(),
-        plans,
-        requires_reboot: strategy.requires_reboot(),
-        risk: strategy.risk_level(),
     })
 }
 
-/// Print a remediation plan for human review
+/// Run the contract safety analysis over a native plan before it is shown.
+///
+/// The plan's own `requires_reboot` is judged against its steps; everything
+/// else is derived from the steps by the conversion.
+pub fn check_safety(plan: &RemediationPlan) -> Result<SafetyVerdict> {
+    let mut procedure = conversions::remediation_plan_to_procedure(&serde_json::to_value(plan)?, uuid::Uuid::nil());
+    procedure.requires_reboot = plan.requires_reboot;
+    check_procedure(&procedure)
+}
+
+/// Refuse a ProcedurePlan that fails safety analysis; print its warnings.
+pub fn check_procedure(plan: &ProcedurePlan) -> Result<SafetyVerdict> {
+    let verdict = safety::analyze(plan);
+    if !verdict.is_approvable() {
+        anyhow::bail!("Plan failed safety analysis: {}", verdict);
+    }
+    for warning in verdict.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    Ok(verdict)
+}
+
+/// Fail unless every step has the undo step at its index, which is how the
+/// executor pairs them.
+pub fn check_undo(plan: &RemediationPlan) -> Result<()> {
+    if plan.steps.len() != plan.undo_steps.len() {
+        anyhow::bail!(
+            "Plan {} has {} step(s) but {} undo step(s); each step needs one undo step",
+            plan.id,
+            plan.steps.len(),
+            plan.undo_steps.len()
+        );
+    }
+    Ok(())
+}
+
+/// Run the checks a plan read back from disk must pass before it is shown
+/// for approval: undo pairing, safety analysis and the IOMMU group split.
+pub fn check_plan(plan: &RemediationPlan) -> Result<()> {
+    check_undo(plan)?;
+    check_safety(plan)?;
+    let slots: Vec<String> = plan
+        .device
+        .split(',')
+        .map(str::trim)
+        .filter(|slot| !slot.is_empty())
+        .map(str::to_string)
+        .collect();
+    check_iommu_groups(&plan.strategy, &slots)
+}
+
+/// Print a remediation plan for human review and save it to the working directory
 pub fn print_plan(plan: &RemediationPlan) {
+    show_plan(plan);
+
+    // Save plan to file
+    let plan_file = format!("{}.json", plan.id);
+    if let Ok(json) = serde_json::to_string_pretty(plan) {
+        if std::fs::write(&plan_file, &json).is_ok() {
+            println!("\nPlan saved to: {}", plan_file);
+            println!("Apply with: hardware-crash-team apply {}", plan_file);
+        }
+    }
+}
+
+/// Print a remediation plan for human review
+pub fn show_plan(plan: &RemediationPlan) {
     println!("\nRemediation Plan: {}", plan.id);
     println!("==================");
     println!("Target device: {}", plan.device);
@@ -287,20 +495,11 @@ pub fn print_plan(plan: &RemediationPlan) {
         }
     }
 
-    println!("\nUndo steps (if needed):");
-    for (i, step) in plan.undo_steps.iter().enumerate() {
+    println!("\nUndo steps (if needed, last step first):");
+    for (i, step) in plan.undo_steps.iter().enumerate().rev() {
         println!("  {}. {}", i + 1, step.description);
         println!("     Command: {}{}", if step.needs_sudo { "sudo " } else { "" }, step.command);
     }
-
-    // Save plan to file
-    let plan_file = format!("{}.json", plan.id);
-    if let Ok(json) = serde_json::to_string_pretty(plan) {
-        if std::fs::write(&plan_file, &json).is_ok() {
-            println!("\nPlan saved to: {}", plan_file);
-            println!("Apply with: hardware-crash-team apply {}", plan_file);
-        }
-    }
 }
 
 /// Print a multi-device remediation plan
@@ -317,59 +516,42 @@ pub fn print_multi_plan(multi: &MultiDevicePlan) {
     }
 }
 
-/// Apply a remediation plan
-pub fn apply_plan(plan_path: &Path) -> Result<()> {
-    let content = std::fs::read_to_string(plan_path)?;
-    let plan: RemediationPlan = serde_json::from_str(&content)?;
-
-    println!("Applying plan: {}", plan.id);
-
-    for step in &plan.steps {
-        println!("  Executing: {}", step.description);
-        if step.needs_sudo {
-            println!("    (requires sudo) $ sudo {}", step.command);
-            // In real implementation: std::process::Command::new("sudo")...
-            println!("    [DRY RUN - would execute above command]");
-        }
+/// Undo a previously applied remediation from a receipt file and write the
+/// undo steps that ran back into it
+pub fn undo(receipt_path: &Path, executor: &Executor) -> Result<()> {
+    let content = std::fs::read_to_string(receipt_path)?;
+    let mut receipt: RemediationReceipt = serde_json::from_str(&content)?;
+    let recorded = receipt.undone.len();
+    let result = executor.undo(&mut receipt);
+    if receipt.undone.len() > recorded {
+        std::fs::write(receipt_path, serde_json::to_string_pretty(&receipt)?)?;
     }
-
-    // Save receipt
-    let receipt = RemediationReceipt {
-        plan,
-        applied_at: chrono::Utc::now().to_rfc3339(),
-        reboot_pending: true,
-        pre_state: String::new(),
-    };
-
-    let receipt_file = format!("receipt-{}.json", receipt.applied_at.replace(':', "-"));
-    let json = serde_json::to_string_pretty(&receipt)?;
-    std::fs::write(&receipt_file, &json)?;
-    println!("\nReceipt saved to: {}", receipt_file);
-
-    Ok(())
+    result
 }
 
-/// Undo a previously applied remediation
-pub fn undo(receipt_path: &Path) -> Result<()> {
+/// Verify a receipt file against `report` and write the result back into it
+pub fn verify_file(receipt_path: &Path, report: &SystemReport) -> Result<RemediationReceipt> {
     let content = std::fs::read_to_string(receipt_path)?;
-    let receipt: RemediationReceipt = serde_json::from_str(&content)?;
+    let mut receipt: RemediationReceipt = serde_json::from_str(&content)?;
+    let verification = verify::verify(&receipt, report)?;
+    verify::record(&mut receipt, verification);
+    std::fs::write(receipt_path, serde_json::to_string_pretty(&receipt)?)?;
+    Ok(receipt)
+}
 
-    println!("Undoing plan: {}", receipt.plan.id);
+// Helper functions
 
-    for step in &receipt.plan.undo_steps {
-        println!("  Executing: {}", step.description);
-        if step.needs_sudo {
-            println!("    (requires sudo) $ sudo {}", step.command);
-            println!("    [DRY RUN - would execute above command]");
-        }
+/// Quote for a POSIX shell.
+pub(crate) fn shell_quote(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_.,:=/+@".contains(c)) {
+        return s.to_string();
     }
-
-    Ok(())
+    format!("'{}'", s.replace('\'', r"'\''"))
 }
 
-// Helper functions
-
-fn parse_strategy(strategy: Option<&str>) -> Result<RemediationStrategy> {
+/// The named strategy, or `preferred` (the recommender's pick) when none
+/// is named, else the dual null-driver claim.
+fn parse_strategy(strategy: Option<&str>, preferred: Option<RemediationStrategy>) -> Result<RemediationStrategy> {
     match strategy {
         Some("pci-stub") => Ok(RemediationStrategy::PciStub),
         Some("vfio-pci") => Ok(RemediationStrategy::VfioPci),
@@ -377,12 +559,19 @@ fn parse_strategy(strategy: Option<&str>) -> Result<RemediationStrategy> {
         Some("power-off") => Ok(RemediationStrategy::AcpiPowerOff),
         Some("disable") => Ok(RemediationStrategy::SysfsDisable),
         Some("unbind") => Ok(RemediationStrategy::DriverUnbind),
-        Some(other) => anyhow::bail!("Unknown strategy: {}. Use: pci-stub, vfio-pci, dual, power-off, disable, unbind", other),
-        None => Ok(RemediationStrategy::DualNullDriver),
+        Some("modprobe") => Ok(RemediationStrategy::ModprobeConf),
+        Some("udev-power") => Ok(RemediationStrategy::UdevPowerAuto),
+        Some("udev-unbind") => Ok(RemediationStrategy::UdevUnbind),
+        Some("udev-remove") => Ok(RemediationStrategy::UdevRemove),
+        Some(other) => anyhow::bail!(
+            "Unknown strategy: {}. Use: pci-stub, vfio-pci, dual, power-off, disable, unbind, modprobe, udev-power, udev-unbind, udev-remove",
+            other
+        ),
+        None => Ok(preferred.unwrap_or(RemediationStrategy::DualNullDriver)),
     }
 }
 
-fn strategy_name(strategy: &RemediationStrategy) -> Option<&str> {
+fn strategy_name(strategy: &RemediationStrategy) -> Option<&'static str> {
     match strategy {
         RemediationStrategy::PciStub => Some("pci-stub"),
         RemediationStrategy::VfioPci => Some("vfio-pci"),
@@ -390,6 +579,11 @@ fn strategy_name(strategy: &RemediationStrategy) -> Option<&str> {
         RemediationStrategy::AcpiPowerOff => Some("power-off"),
         RemediationStrategy::SysfsDisable => Some("disable"),
         RemediationStrategy::DriverUnbind => Some("unbind"),
+        RemediationStrategy::ModprobeConf => Some("modprobe"),
+        RemediationStrategy::UdevPowerAuto => Some("udev-power"),
+        RemediationStrategy::UdevUnbind => Some("udev-unbind"),
+        RemediationStrategy::UdevRemove => Some("udev-remove"),
+        RemediationStrategy::ClaimCleanup => None,
     }
 }
 
@@ -397,65 +591,167 @@ fn create_combined_kargs_plan(
     plan_id: &str,
     device_ids: &[(String, String, String)],
     strategy: &RemediationStrategy,
+    bootloader: &dyn Bootloader,
 ) -> RemediationPlan {
     let all_slots = device_ids.iter().map(|(s, _, _)| s.as_str()).collect::<Vec<_>>().join(", ");
-
-    let mut apply_args = Vec::new();
-    let mut undo_args = Vec::new();
-
-    match strategy {
-        RemediationStrategy::PciStub => {
-            let ids: Vec<String> = device_ids.iter().map(|(_, v, d)| format!("{}:{}", v, d)).collect();
-            let combined = ids.join(",");
-            apply_args.push(format!("--append=pci-stub.ids={}", combined));
-            undo_args.push(format!("--delete=pci-stub.ids={}", combined));
-        }
-        RemediationStrategy::VfioPci => {
-            let ids: Vec<String> = device_ids.iter().map(|(_, v, d)| format!("{}:{}", v, d)).collect();
-            let combined = ids.join(",");
-            apply_args.push(format!("--append=vfio-pci.ids={}", combined));
-            apply_args.push("--append=rd.driver.pre=vfio-pci".to_string());
-            undo_args.push(format!("--delete=vfio-pci.ids={}", combined));
-            undo_args.push("--delete=rd.driver.pre=vfio-pci".to_string());
-        }
-        RemediationStrategy::DualNullDriver => {
-            let ids: Vec<String> = device_ids.iter().map(|(_, v, d)| format!("{}:{}", v, d)).collect();
-            let combined = ids.join(",");
-            apply_args.push(format!("--append=pci-stub.ids={}", combined));
-            apply_args.push(format!("--append=vfio-pci.ids={}", combined));
-            apply_args.push("--append=rd.driver.pre=vfio-pci".to_string());
-            undo_args.push(format!("--delete=pci-stub.ids={}", combined));
-            undo_args.push(format!("--delete=vfio-pci.ids={}", combined));
-            undo_args.push("--delete=rd.driver.pre=vfio-pci".to_string());
-        }
-        _ => unreachable!("Only kernel arg strategies should use combined plan"),
-    }
+    let ids: Vec<String> = device_ids.iter().map(|(_, v, d)| format!("{}:{}", v, d)).collect();
+    let edits = claim_edits(strategy, &ids.join(","));
 
     RemediationPlan {
         id: format!("{}-combined", plan_id),
         device: all_slots.clone(),
         strategy: strategy.clone(),
-        steps: vec![
-            RemediationStep {
-                description: format!("Claim devices [{}] via kernel args", all_slots),
-                command: format!("rpm-ostree kargs {}", apply_args.join(" ")),
-                needs_sudo: true,
-                needs_reboot: true,
-            },
-        ],
-        undo_steps: vec![
-            RemediationStep {
-                description: format!("Remove kernel arg claims for devices [{}]", all_slots),
-                command: format!("rpm-ostree kargs {}", undo_args.join(" ")),
-                needs_sudo: true,
-                needs_reboot: true,
-            },
-        ],
+        steps: bootloader.steps(&format!("Claim devices [{}] via kernel args", all_slots), &edits),
+        undo_steps: bootloader.undo_steps(&format!("Remove kernel arg claims for devices [{}]", all_slots), &edits),
+        requires_reboot: true,
+        risk: RiskLevel::Low,
+    }
+}
+
+/// Hand `slots` to vfio-pci through a managed modprobe.d snippet and carry
+/// it into the initramfs, where early-loading drivers would otherwise win.
+fn create_modprobe_plan(plan_id: String, slots: &[String]) -> Result<RemediationPlan> {
+    let live = RootedFs::live();
+    let release = live.read("/proc/sys/kernel/osrelease").unwrap_or_default();
+    let index = ModuleIndex::load(&live, release.trim());
+    let mut claims = Vec::new();
+    for slot in slots {
+        claims.push(managed::ModprobeClaim {
+            slot: slot.clone(),
+            pci_id: read_device_pci_id(slot)?,
+            modules: device_modules(&live, &index, slot),
+        });
+    }
+    let slot_refs: Vec<&str> = slots.iter().map(String::as_str).collect();
+    let path = managed::modprobe_path(&slot_refs);
+    let lines = managed::modprobe_conf(&claims, |module| module_bound_elsewhere(&live, module, slots));
+
+    let mut steps = vec![managed::write_step(
+        &format!("Write {} claiming [{}] for vfio-pci", path, slots.join(", ")),
+        &path,
+        &lines,
+        true,
+    )];
+    let mut undo_steps = vec![managed::remove_step(&format!("Remove {}", path), &path, true)];
+    if let Some(initramfs) = managed::InitramfsBuilder::detect(&live) {
+        // Undone before the file goes, so a rebuild there removes it first
+        let (apply, undo) = initramfs.steps(&path);
+        steps.push(apply);
+        undo_steps.push(match initramfs {
+            managed::InitramfsBuilder::RpmOstree => undo,
+            _ => RemediationStep { command: format!("{} && {}", undo_steps[0].command, undo.command), ..undo },
+        });
+    } else {
+        // A module loaded from the initramfs ignores the new file until it is rebuilt
+        eprintln!(
+            "Warning: no initramfs builder (dracut, update-initramfs, mkinitcpio) found; rebuild the initramfs by hand after writing {} or the claim may not hold at boot",
+            path
+        );
+    }
+
+    let plan = RemediationPlan {
+        id: plan_id,
+        device: slots.join(", "),
+        strategy: RemediationStrategy::ModprobeConf,
+        steps,
+        undo_steps,
         requires_reboot: true,
         risk: RiskLevel::Low,
+    };
+    check_undo(&plan)?;
+    Ok(plan)
+}
+
+/// Loadable modules that would claim `slot`: the bound driver's and the
+/// modalias candidates. Built-ins are left out; modprobe.d cannot stop them.
+fn device_modules(source: &dyn SysfsSource, index: &ModuleIndex, slot: &str) -> Vec<String> {
+    let path = format!("/sys/bus/pci/devices/{}", slot);
+    let mut modules: Vec<String> = source
+        .read_link(&format!("{}/driver/module", path))
+        .and_then(|p| p.file_name().map(|n| normalize_module(&n.to_string_lossy())))
+        .into_iter()
+        .collect();
+    if let Some(modalias) = source.read(&format!("{}/modalias", path)) {
+        for module in index.candidates(modalias.trim()) {
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
+        }
+    }
+    modules.retain(|m| !index.is_builtin(m));
+    modules
+}
+
+/// Whether `module` drives a PCI device other than `slots`, going through
+/// `/sys/module/<module>/drivers` since driver and module names can differ.
+fn module_bound_elsewhere(source: &dyn SysfsSource, module: &str, slots: &[String]) -> bool {
+    let drivers = source.list(&format!("/sys/module/{}/drivers", module)).unwrap_or_default();
+    drivers.iter().filter_map(|d| d.strip_prefix("pci:")).any(|driver| {
+        source
+            .list(&format!("/sys/bus/pci/drivers/{}", driver))
+            .unwrap_or_default()
+            .iter()
+            .any(|entry| entry.contains(':') && !slots.contains(entry))
+    })
+}
+
+fn udev_reload_step() -> RemediationStep {
+    RemediationStep {
+        description: "Reload udev rules".to_string(),
+        command: "udevadm control --reload".to_string(),
+        needs_sudo: true,
+        needs_reboot: false,
     }
 }
 
+/// Refuse a vfio-pci claim that would strand an in-use device sharing an
+/// IOMMU group with the targets; warn about idle members left unclaimed.
+fn check_iommu_groups(strategy: &RemediationStrategy, slots: &[String]) -> Result<()> {
+    if !matches!(
+        strategy,
+        RemediationStrategy::VfioPci | RemediationStrategy::DualNullDriver | RemediationStrategy::ModprobeConf
+    ) {
+        return Ok(());
+    }
+    let split = iommu::vfio_split(&iommu::groups(&RootedFs::live()), slots);
+    for warning in &split.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if !split.blockers.is_empty() {
+        let blockers: Vec<String> = split.blockers.iter().map(|b| format!("  - {}", b)).collect();
+        anyhow::bail!("Claiming with vfio-pci would split an IOMMU group:\n{}", blockers.join("\n"));
+    }
+    Ok(())
+}
+
+/// Driver currently bound to `slot`, if any
+fn read_live_driver(slot: &str) -> Option<String> {
+    std::fs::read_link(format!("/sys/bus/pci/devices/{}/driver", slot))
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+}
+
+/// Runtime power management setting of `slot` ("on" if it cannot be read)
+fn read_live_power_control(slot: &str) -> String {
+    std::fs::read_to_string(format!("/sys/bus/pci/devices/{}/power/control", slot))
+        .map(|c| c.trim().to_string())
+        .unwrap_or_else(|_| "on".to_string())
+}
+
+/// Kernel arguments that claim `ids` (comma-separated `vendor:device`) for a null-driver strategy
+fn claim_edits(strategy: &RemediationStrategy, ids: &str) -> Vec<KargEdit> {
+    let mut edits = Vec::new();
+    if matches!(strategy, RemediationStrategy::PciStub | RemediationStrategy::DualNullDriver) {
+        edits.push(KargEdit::Append(format!("pci-stub.ids={}", ids)));
+    }
+    if matches!(strategy, RemediationStrategy::VfioPci | RemediationStrategy::DualNullDriver) {
+        edits.push(KargEdit::Append(format!("vfio-pci.ids={}", ids)));
+        edits.push(KargEdit::Append("rd.driver.pre=vfio-pci".to_string()));
+    }
+    assert!(!edits.is_empty(), "Only kernel arg strategies have claim edits");
+    edits
+}
+
 /// Read a device's PCI ID from sysfs
 fn read_device_pci_id(slot: &str) -> Result<String> {
     let base = format!("/sys/bus/pci/devices/{}", slot);
@@ -483,19 +779,47 @@ mod tests {
 
     #[test]
     fn test_parse_strategy_all_valid() {
-        assert!(matches!(parse_strategy(Some("pci-stub")).unwrap(), RemediationStrategy::PciStub));
-        assert!(matches!(parse_strategy(Some("vfio-pci")).unwrap(), RemediationStrategy::VfioPci));
-        assert!(matches!(parse_strategy(Some("dual")).unwrap(), RemediationStrategy::DualNullDriver));
-        assert!(matches!(parse_strategy(Some("both")).unwrap(), RemediationStrategy::DualNullDriver));
-        assert!(matches!(parse_strategy(Some("power-off")).unwrap(), RemediationStrategy::AcpiPowerOff));
-        assert!(matches!(parse_strategy(Some("disable")).unwrap(), RemediationStrategy::SysfsDisable));
-        assert!(matches!(parse_strategy(Some("unbind")).unwrap(), RemediationStrategy::DriverUnbind));
-        assert!(matches!(parse_strategy(None).unwrap(), RemediationStrategy::DualNullDriver));
+        assert!(matches!(parse_strategy(Some("pci-stub"), None).unwrap(), RemediationStrategy::PciStub));
+        assert!(matches!(parse_strategy(Some("vfio-pci"), None).unwrap(), RemediationStrategy::VfioPci));
+        assert!(matches!(parse_strategy(Some("dual"), None).unwrap(), RemediationStrategy::DualNullDriver));
+        assert!(matches!(parse_strategy(Some("both"), None).unwrap(), RemediationStrategy::DualNullDriver));
+        assert!(matches!(parse_strategy(Some("power-off"), None).unwrap(), RemediationStrategy::AcpiPowerOff));
+        assert!(matches!(parse_strategy(Some("disable"), None).unwrap(), RemediationStrategy::SysfsDisable));
+        assert!(matches!(parse_strategy(Some("unbind"), None).unwrap(), RemediationStrategy::DriverUnbind));
+        assert!(matches!(parse_strategy(Some("modprobe"), None).unwrap(), RemediationStrategy::ModprobeConf));
+        assert!(matches!(parse_strategy(Some("udev-power"), None).unwrap(), RemediationStrategy::UdevPowerAuto));
+        assert!(matches!(parse_strategy(Some("udev-unbind"), None).unwrap(), RemediationStrategy::UdevUnbind));
+        assert!(matches!(parse_strategy(Some("udev-remove"), None).unwrap(), RemediationStrategy::UdevRemove));
+        assert!(matches!(parse_strategy(None, None).unwrap(), RemediationStrategy::DualNullDriver));
+    }
+
+    #[test]
+    fn test_safety_check() {
+        let devices = vec![("01:00.0".to_string(), "10de".to_string(), "13b0".to_string())];
+        let mut plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::DualNullDriver, &bootloader::RpmOstree);
+        let verdict = check_safety(&plan).unwrap();
+        assert!(verdict.derived.requires_reboot);
+
+        // A kargs change that claims to need no reboot is refused
+        plan.requires_reboot = false;
+        assert!(check_safety(&plan).is_err());
     }
 
     #[test]
     fn test_parse_strategy_invalid() {
-        assert!(parse_strategy(Some("bogus")).is_err());
+        assert!(parse_strategy(Some("bogus"), None).is_err());
+    }
+
+    #[test]
+    fn test_preferred_strategy() {
+        assert!(matches!(parse_strategy(None, Some(RemediationStrategy::PciStub)).unwrap(), RemediationStrategy::PciStub));
+        assert!(matches!(
+            parse_strategy(Some("vfio-pci"), Some(RemediationStrategy::PciStub)).unwrap(),
+            RemediationStrategy::VfioPci
+        ));
+        for quirk in crate::scanner::quirks::QuirkDb::bundled().quirks() {
+            assert!(parse_strategy(quirk.strategy.as_deref(), None).is_ok(), "{}", quirk.id);
+        }
     }
 
     #[test]
@@ -504,7 +828,7 @@ fn test_combined_kargs_pci_stub() {
             ("01:00.0".to_string(), "10de".to_string(), "13b0".to_string()),
             ("01:00.1".to_string(), "10de".to_string(), "0fbc".to_string()),
         ];
-        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::PciStub);
+        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::PciStub, &bootloader::RpmOstree);
         assert!(plan.steps[0].command.contains("pci-stub.ids=10de:13b0,10de:0fbc"));
         assert!(plan.steps[0].needs_reboot);
     }
@@ -514,7 +838,7 @@ fn test_combined_kargs_vfio() {
         let devices = vec![
             ("01:00.0".to_string(), "10de".to_string(), "13b0".to_string()),
         ];
-        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::VfioPci);
+        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::VfioPci, &bootloader::RpmOstree);
         assert!(plan.steps[0].command.contains("vfio-pci.ids=10de:13b0"));
         assert!(plan.steps[0].command.contains("rd.driver.pre=vfio-pci"));
     }
@@ -525,7 +849,7 @@ fn test_combined_kargs_dual() {
             ("01:00.0".to_string(), "10de".to_string(), "13b0".to_string()),
             ("01:00.1".to_string(), "10de".to_string(), "0fbc".to_string()),
         ];
-        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::DualNullDriver);
+        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::DualNullDriver, &bootloader::RpmOstree);
         assert!(plan.steps[0].command.contains("pci-stub.ids=10de:13b0,10de:0fbc"));
         assert!(plan.steps[0].command.contains("vfio-pci.ids=10de:13b0,10de:0fbc"));
         assert!(plan.steps[0].command.contains("rd.driver.pre=vfio-pci"));
@@ -536,7 +860,7 @@ fn test_undo_is_inverse_of_apply_pci_stub() {
         let devices = vec![
             ("01:00.0".to_string(), "10de".to_string(), "13b0".to_string()),
         ];
-        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::PciStub);
+        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::PciStub, &bootloader::RpmOstree);
         // Apply has --append, undo has --delete, same IDs
         assert!(plan.steps[0].command.contains("--append=pci-stub.ids=10de:13b0"));
         assert!(plan.undo_steps[0].command.contains("--delete=pci-stub.ids=10de:13b0"));
@@ -547,7 +871,7 @@ fn test_undo_is_inverse_of_apply_dual() {
         let devices = vec![
             ("01:00.0".to_string(), "10de".to_string(), "13b0".to_string()),
         ];
-        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::DualNullDriver);
+        let plan = create_combined_kargs_plan("test", &devices, &RemediationStrategy::DualNullDriver, &bootloader::RpmOstree);
         assert!(plan.steps[0].command.contains("--append="));
         assert!(plan.undo_steps[0].command.contains("--delete="));
         // Both should reference the same IDs
@@ -555,6 +879,62 @@ fn test_undo_is_inverse_of_apply_dual() {
         assert!(plan.undo_steps[0].command.contains("vfio-pci.ids=10de:13b0"));
     }
 
+    #[test]
+    fn test_claim_cleanup_plan() {
+        let claim = |stub: &str, id: &str, devices: &[&str], occurrences| StubClaim {
+            stub: stub.to_string(),
+            id: id.to_string(),
+            devices: devices.iter().map(|d| d.to_string()).collect(),
+            state: ClaimState::Active,
+            occurrences,
+        };
+        let claims = KernelClaims {
+            next_boot_source: Some("/etc/kernel/cmdline".to_string()),
+            next_boot_params: vec![
+                "pci-stub.ids=10de:13b0,1002:73bf".to_string(),
+                "pci-stub.ids=10de:13b0".to_string(),
+                "vfio-pci.ids=1002:73bf".to_string(),
+                "rd.driver.pre=vfio-pci".to_string(),
+            ],
+            claims: vec![
+                claim("pci-stub", "10de:13b0", &["0000:01:00.0"], 2),
+                claim("pci-stub", "1002:73bf", &[], 1),
+                claim("vfio-pci", "1002:73bf", &[], 1),
+            ],
+        };
+        let plan = create_claim_cleanup_plan(&claims, &bootloader::RpmOstree).unwrap();
+        assert!(matches!(plan.strategy, RemediationStrategy::ClaimCleanup));
+        assert_eq!(
+            plan.steps[0].command,
+            "rpm-ostree kargs --replace=pci-stub.ids=10de:13b0,1002:73bf=10de:13b0 \
+             --delete=pci-stub.ids=10de:13b0 --delete=vfio-pci.ids=1002:73bf --delete=rd.driver.pre=vfio-pci"
+        );
+        assert_eq!(
+            plan.undo_steps[0].command,
+            "rpm-ostree kargs --append=rd.driver.pre=vfio-pci --append=vfio-pci.ids=1002:73bf \
+             --append=pci-stub.ids=10de:13b0 --replace=pci-stub.ids=10de:13b0=10de:13b0,1002:73bf"
+        );
+        assert!(check_safety(&plan).is_ok());
+
+        // Nothing stale or duplicated: nothing to plan
+        let clean = KernelClaims {
+            next_boot_params: vec!["pci-stub.ids=10de:13b0".to_string()],
+            claims: vec![claim("pci-stub", "10de:13b0", &["0000:01:00.0"], 1)],
+            ..Default::default()
+        };
+        assert!(create_claim_cleanup_plan(&clean, &bootloader::RpmOstree).is_err());
+    }
+
+    #[test]
+    fn test_persistent_strategies_reboot() {
+        // modprobe.d only takes effect when the drivers next load; udev rules act immediately too
+        assert!(RemediationStrategy::ModprobeConf.requires_reboot());
+        assert!(!RemediationStrategy::UdevPowerAuto.requires_reboot());
+        assert!(!RemediationStrategy::UdevUnbind.requires_reboot());
+        assert!(!RemediationStrategy::UdevRemove.requires_reboot());
+        assert!(matches!(RemediationStrategy::UdevRemove.risk_level(), RiskLevel::Medium));
+    }
+
     #[test]
     fn test_acpi_power_off_no_reboot() {
         // AcpiPowerOff shouldn't need a reboot — verify via strategy trait
@@ -583,10 +963,14 @@ fn test_strategy_name_roundtrip() {
             RemediationStrategy::AcpiPowerOff,
             RemediationStrategy::SysfsDisable,
             RemediationStrategy::DriverUnbind,
+            RemediationStrategy::ModprobeConf,
+            RemediationStrategy::UdevPowerAuto,
+            RemediationStrategy::UdevUnbind,
+            RemediationStrategy::UdevRemove,
         ];
         for s in strategies {
             let name = strategy_name(&s).unwrap();
-            let parsed = parse_strategy(Some(name)).unwrap();
+            let parsed = parse_strategy(Some(name), None).unwrap();
             // Verify we can roundtrip
             assert_eq!(strategy_name(&parsed), strategy_name(&s));
         }
@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Strategy recommender
+//!
+//! Ranks the strategies for one device from what the scan found (its
+//! issues, whether a driver holds it, IOMMU state and group), what the
+//! platform allows (bootloader backend, Secure Boot and lockdown) and the
+//! quirk database. Each strategy starts from a base score for how
+//! persistent and contained it is; every fact that helps or hurts it
+//! adjusts the score and adds a line of rationale. Strategies that would be
+//! refused outright are kept in the list, marked unavailable, with why.
+
+use super::{bootloader, strategy_name};
+use crate::scanner::iommu;
+use crate::scanner::quirks::{QuirkDb, QuirkTarget};
+use crate::scanner::slot_matches;
+use crate::scanner::source::{RootedFs, SysfsSource};
+use crate::types::*;
+use anyhow::Result;
+
+const SECURE_BOOT_VAR: &str = "/sys/firmware/efi/efivars/SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+const LOCKDOWN: &str = "/sys/kernel/security/lockdown";
+
+/// Drivers that already are a claim.
+const NULL_DRIVERS: &[&str] = &["pci-stub", "vfio-pci"];
+
+/// Selects the strategies a score adjustment applies to.
+type Applies = fn(&RemediationStrategy) -> bool;
+
+/// Strategies in tie-break order, with their base scores.
+const CANDIDATES: &[(RemediationStrategy, i32)] = &[
+    (RemediationStrategy::DualNullDriver, 55),
+    (RemediationStrategy::PciStub, 50),
+    (RemediationStrategy::VfioPci, 50),
+    (RemediationStrategy::ModprobeConf, 45),
+    (RemediationStrategy::UdevUnbind, 35),
+    (RemediationStrategy::UdevPowerAuto, 30),
+    (RemediationStrategy::UdevRemove, 30),
+    (RemediationStrategy::AcpiPowerOff, 25),
+    (RemediationStrategy::SysfsDisable, 20),
+    (RemediationStrategy::DriverUnbind, 15),
+];
+
+/// One ranked strategy.
+#[derive(Debug, Clone)]
+pub struct Recommendation {
+    pub strategy: RemediationStrategy,
+    /// CLI name (e.g., "pci-stub")
+    pub name: &'static str,
+    pub score: i32,
+    /// False if a plan with this strategy would be refused or do nothing
+    pub available: bool,
+    pub rationale: Vec<String>,
+}
+
+/// What the platform allows, beyond the scan.
+#[derive(Debug, Clone, Default)]
+pub struct Platform {
+    /// Backend managing the kernel arguments, `None` if none was found
+    pub bootloader: Option<&'static str>,
+    pub secure_boot: bool,
+    /// Active lockdown mode ("integrity", "confidentiality")
+    pub lockdown: Option<String>,
+}
+
+impl Platform {
+    /// Read the platform from `source`, with `bootloader` naming the backend
+    /// instead of detecting it.
+    pub fn read(source: &dyn SysfsSource, bootloader: Option<&str>) -> Self {
+        let backend = match bootloader {
+            Some(name) => bootloader::by_name(source, name),
+            None => bootloader::detect(source),
+        };
+        Self {
+            bootloader: backend.ok().map(|b| b.name()),
+            // efivarfs: four attribute bytes, then the value
+            secure_boot: source.read_bytes(SECURE_BOOT_VAR).is_some_and(|v| v.get(4) == Some(&1)),
+            lockdown: source.read(LOCKDOWN).and_then(|l| parse_lockdown(&l)),
+        }
+    }
+}
+
+/// The bracketed mode in "none [integrity] confidentiality", unless it is "none".
+fn parse_lockdown(content: &str) -> Option<String> {
+    let start = content.find('[')?;
+    let end = start + content[start..].find(']')?;
+    let mode = &content[start + 1..end];
+    (mode != "none").then(|| mode.to_string())
+}
+
+/// Rank the strategies for `slot` on the system `source` presents, as
+/// `report` scanned it.
+pub fn recommend(
+    source: &dyn SysfsSource,
+    report: &SystemReport,
+    slot: &str,
+    bootloader: Option<&str>,
+) -> Result<Vec<Recommendation>> {
+    let Some(device) = report.devices.iter().find(|d| slot_matches(&d.slot, slot)) else {
+        anyhow::bail!("Device {} not found in the scan", slot);
+    };
+    let release = source.read("/proc/sys/kernel/osrelease").unwrap_or_default();
+    let db = QuirkDb::load(source);
+    let target = QuirkTarget::read(source, &device.slot);
+    let quirk = db
+        .matching(&target, release.trim())
+        .into_iter()
+        .find_map(|q| Some((q.id.as_str(), q.strategy.as_deref()?)));
+    Ok(rank(device, report, &Platform::read(source, bootloader), quirk))
+}
+
+/// The top-ranked available strategy for `slot`, as `report` scanned it.
+pub fn default_strategy(
+    source: &dyn SysfsSource,
+    report: &SystemReport,
+    slot: &str,
+    bootloader: Option<&str>,
+) -> Result<RemediationStrategy> {
+    let ranking = recommend(source, report, slot, bootloader)?;
+    Ok(ranking
+        .into_iter()
+        .find(|r| r.available)
+        .map_or(RemediationStrategy::DualNullDriver, |r| r.strategy))
+}
+
+/// Rank every strategy for `device`, best first. `quirk` is the matching
+/// quirk's id and preferred strategy name.
+pub fn rank(device: &PciDevice, report: &SystemReport, platform: &Platform, quirk: Option<(&str, &str)>) -> Vec<Recommendation> {
+    let mut ranking: Vec<Recommendation> = CANDIDATES
+        .iter()
+        .map(|(strategy, base)| Recommendation {
+            strategy: strategy.clone(),
+            name: strategy_name(strategy).unwrap_or_default(),
+            score: *base,
+            available: true,
+            rationale: if transient(strategy) {
+                vec!["takes effect now but is lost at the next reboot".to_string()]
+            } else {
+                Vec::new()
+            },
+        })
+        .collect();
+    let mut adjust = |applies: Applies, delta: i32, why: &str| {
+        for r in ranking.iter_mut().filter(|r| applies(&r.strategy)) {
+            r.score += delta;
+            r.rationale.push(why.to_string());
+        }
+    };
+    // Who holds the device
+    let has = |check: fn(&IssueType) -> bool| device.issues.iter().any(|i| check(&i.issue_type));
+    let mut unavailable: Vec<(Applies, String)> = Vec::new();
+    match device.driver.as_deref() {
+        None => {
+            unavailable.push((unbinds, "no driver is bound to unbind".to_string()));
+            if device.power_state == PowerState::D0 {
+                adjust(
+                    |s| matches!(s, RemediationStrategy::UdevPowerAuto),
+                    15,
+                    "driverless in D0: runtime PM can suspend it without a claim",
+                );
+            }
+        }
+        Some(driver) if NULL_DRIVERS.contains(&driver) => {
+            adjust(claims, 10, "already held by a null driver; a boot-time claim keeps it that way");
+        }
+        Some(_) => {
+            adjust(unbinds, 10, "a host driver holds it; unbinding frees it without a reboot");
+            adjust(
+                |s| matches!(s, RemediationStrategy::UdevPowerAuto),
+                -20,
+                "the bound driver already manages its power",
+            );
+        }
+    }
+
+    // DMA isolation
+    if report.iommu.enabled {
+        adjust(vfio, 10, "IOMMU is on: vfio-pci isolates the device's DMA");
+        let split = iommu::vfio_split(&report.iommu_groups, std::slice::from_ref(&device.slot));
+        if let Some(blocker) = split.blockers.first() {
+            unavailable.push((vfio, blocker.clone()));
+        } else if !split.warnings.is_empty() {
+            adjust(vfio, -5, "other members of its IOMMU group stay unclaimed");
+        }
+    } else {
+        adjust(
+            |s| matches!(s, RemediationStrategy::VfioPci | RemediationStrategy::ModprobeConf),
+            -25,
+            "IOMMU is off: vfio-pci would claim it without DMA isolation",
+        );
+    }
+
+    // Kernel arguments
+    match platform.bootloader {
+        None => unavailable.push((claims, "no bootloader backend detected for kernel arguments (pass --bootloader)".to_string())),
+        Some("systemd-boot") if platform.secure_boot || platform.lockdown.is_some() => adjust(
+            claims,
+            -20,
+            "Secure Boot/lockdown with systemd-boot: a signed unified kernel image may ignore /etc/kernel/cmdline",
+        ),
+        Some(_) => {}
+    }
+
+    // What is wrong with it
+    let link_trouble = device.issues.iter().any(|i| {
+        matches!(i.issue_type, IssueType::AerErrors | IssueType::UpstreamErrors | IssueType::LinkDegraded)
+            && i.severity >= IssueSeverity::High
+    });
+    if link_trouble {
+        adjust(removes, 20, "PCIe errors: taking it off the bus stops the error traffic");
+    }
+    if has(|t| matches!(t, IssueType::ZombieDevice | IssueType::UnmanagedMemory | IssueType::SpuriousInterrupts)) {
+        adjust(
+            |s| claims(s) || matches!(s, RemediationStrategy::ModprobeConf),
+            10,
+            "a null driver takes ownership of the unmanaged device",
+        );
+    }
+    if has(|t| matches!(t, IssueType::PartialBinding)) {
+        adjust(
+            |s| matches!(s, RemediationStrategy::PciStub | RemediationStrategy::DualNullDriver),
+            10,
+            "pci-stub claims the function before the host driver binds",
+        );
+    }
+
+    if let Some((id, preferred)) = quirk {
+        let why = format!("quirk {} recommends it", id);
+        for r in ranking.iter_mut().filter(|r| r.name == preferred) {
+            r.score += 30;
+            r.rationale.push(why.clone());
+        }
+    }
+    for (applies, why) in unavailable {
+        for r in ranking.iter_mut().filter(|r| applies(&r.strategy)) {
+            r.available = false;
+            r.rationale.push(why.clone());
+        }
+    }
+
+    // Stable: ties keep the candidate order
+    ranking.sort_by(|a, b| b.available.cmp(&a.available).then(b.score.cmp(&a.score)));
+    ranking
+}
+
+/// Kernel-argument null-driver claims.
+fn claims(s: &RemediationStrategy) -> bool {
+    matches!(s, RemediationStrategy::PciStub | RemediationStrategy::VfioPci | RemediationStrategy::DualNullDriver)
+}
+
+/// Strategies that hand the device to vfio-pci, whole IOMMU group and all.
+fn vfio(s: &RemediationStrategy) -> bool {
+    matches!(s, RemediationStrategy::VfioPci | RemediationStrategy::DualNullDriver | RemediationStrategy::ModprobeConf)
+}
+
+fn unbinds(s: &RemediationStrategy) -> bool {
+    matches!(s, RemediationStrategy::DriverUnbind | RemediationStrategy::UdevUnbind)
+}
+
+fn removes(s: &RemediationStrategy) -> bool {
+    matches!(s, RemediationStrategy::AcpiPowerOff | RemediationStrategy::UdevRemove)
+}
+
+fn transient(s: &RemediationStrategy) -> bool {
+    matches!(s, RemediationStrategy::AcpiPowerOff | RemediationStrategy::SysfsDisable | RemediationStrategy::DriverUnbind)
+}
+
+/// Print the ranking for each of `devices` on the live system, as `report` scanned it.
+pub fn print_rankings(report: &SystemReport, devices: &[String], bootloader: Option<&str>) -> Result<()> {
+    let live = RootedFs::live();
+    for device in devices {
+        print_ranking(device, &recommend(&live, report, device, bootloader)?);
+    }
+    Ok(())
+}
+
+/// Print a ranking, best first.
+fn print_ranking(slot: &str, ranking: &[Recommendation]) {
+    println!("Strategies for {} (best first):", slot);
+    for (idx, r) in ranking.iter().enumerate() {
+        let mark = if !r.available {
+            "  -"
+        } else if idx == 0 {
+            "  *"
+        } else {
+            "   "
+        };
+        println!("{} {:<12} {:>4}", mark, r.name, r.score);
+        for why in &r.rationale {
+            println!("                     {}", why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(iommu: bool, groups: Vec<IommuGroup>) -> SystemReport {
+        let mut report = SystemReport::test(Vec::new());
+        report.iommu.enabled = iommu;
+        report.iommu.group_count = groups.len() as u32;
+        report.iommu_groups = groups;
+        report
+    }
+
+    fn group(members: &[(&str, Option<&str>)]) -> IommuGroup {
+        IommuGroup {
+            id: 1,
+            members: members
+                .iter()
+                .map(|(slot, driver)| GroupMember { slot: slot.to_string(), driver: driver.map(str::to_string), bridge: false })
+                .collect(),
+            mixed: members.len() > 1,
+        }
+    }
+
+    fn grub() -> Platform {
+        Platform { bootloader: Some("grub"), ..Default::default() }
+    }
+
+    fn names(ranking: &[Recommendation]) -> Vec<&str> {
+        ranking.iter().filter(|r| r.available).map(|r| r.name).collect()
+    }
+
+    #[test]
+    fn test_zombie_with_iommu_prefers_vfio_claims() {
+        let zombie = PciDevice::test("0000:01:00.0").with_issue(IssueType::ZombieDevice, IssueSeverity::High);
+        let ranking = rank(&zombie, &report(true, vec![group(&[("0000:01:00.0", None)])]), &grub(), None);
+        assert_eq!(&names(&ranking)[..4], &["dual", "vfio-pci", "modprobe", "pci-stub"]);
+        let unbind = ranking.iter().find(|r| r.name == "unbind").unwrap();
+        assert!(!unbind.available);
+
+        // Without an IOMMU, vfio-pci falls behind pci-stub
+        let ranking = rank(&zombie, &report(false, Vec::new()), &grub(), None);
+        assert_eq!(&names(&ranking)[..2], &["dual", "pci-stub"]);
+        assert!(names(&ranking).iter().position(|n| *n == "vfio-pci") > names(&ranking).iter().position(|n| *n == "udev-power"));
+    }
+
+    #[test]
+    fn test_group_blocker_and_missing_bootloader() {
+        let zombie = PciDevice::test("0000:01:00.0").with_issue(IssueType::ZombieDevice, IssueSeverity::High);
+        let shared = report(true, vec![group(&[("0000:01:00.0", None), ("0000:02:00.0", Some("e1000e"))])]);
+        let ranking = rank(&zombie, &shared, &grub(), None);
+        assert_eq!(names(&ranking)[0], "pci-stub");
+        let vfio = ranking.iter().find(|r| r.name == "vfio-pci").unwrap();
+        assert!(!vfio.available);
+        assert!(vfio.rationale.last().unwrap().contains("e1000e"));
+
+        let ranking = rank(&zombie, &shared, &Platform::default(), None);
+        assert!(names(&ranking).iter().all(|n| !["pci-stub", "vfio-pci", "dual", "modprobe"].contains(n)));
+        assert_eq!(names(&ranking)[0], "udev-power");
+    }
+
+    #[test]
+    fn test_bound_device_platform_and_quirk() {
+        let errors = PciDevice::test("0000:01:00.0").with_driver("amdgpu").with_issue(IssueType::UpstreamErrors, IssueSeverity::High);
+        let platform = Platform { bootloader: Some("systemd-boot"), secure_boot: true, lockdown: None };
+        let ranking = rank(&errors, &report(false, Vec::new()), &platform, None);
+        assert_eq!(names(&ranking)[0], "udev-remove");
+        assert!(ranking.iter().find(|r| r.name == "pci-stub").unwrap().rationale.iter().any(|w| w.contains("Secure Boot")));
+
+        let audio = PciDevice::test("0000:01:00.0").with_driver("snd_hda_intel").with_issue(IssueType::PartialBinding, IssueSeverity::Warning);
+        let ranking = rank(&audio, &report(true, vec![group(&[("0000:01:00.0", None)])]), &grub(), Some(("nvidia-hda-partial-binding", "pci-stub")));
+        assert_eq!(names(&ranking)[0], "pci-stub");
+        assert!(ranking[0].rationale.iter().any(|w| w.contains("nvidia-hda-partial-binding")));
+    }
+
+    #[test]
+    fn test_platform_read() {
+        use crate::scanner::source::RootedFs;
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vars = dir.path().join("sys/firmware/efi/efivars");
+        fs::create_dir_all(&vars).unwrap();
+        fs::write(vars.join("SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c"), [6, 0, 0, 0, 1]).unwrap();
+        fs::create_dir_all(dir.path().join("sys/kernel/security")).unwrap();
+        fs::write(dir.path().join("sys/kernel/security/lockdown"), "none [integrity] confidentiality\n").unwrap();
+        fs::create_dir_all(dir.path().join("etc/default")).unwrap();
+        fs::write(dir.path().join("etc/default/grub"), "GRUB_CMDLINE_LINUX=\"\"\n").unwrap();
+
+        let platform = Platform::read(&RootedFs::new(dir.path()).unwrap(), None);
+        assert!(platform.secure_boot);
+        assert_eq!(platform.lockdown.as_deref(), Some("integrity"));
+        assert_eq!(platform.bootloader, Some("grub"));
+        assert_eq!(parse_lockdown("[none] integrity confidentiality"), None);
+    }
+}
@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Post-apply verification
+//!
+//! Compares a fresh scan against the outcome a receipt's strategy should
+//! have produced on each target device (bound to the null driver, disabled,
+//! removed, ...) and confirms the ZombieDevice and UnmanagedMemory issues
+//! the remediation was for are gone. Kernel argument strategies only take
+//! effect after a reboot, so this is what settles whether they worked.
+
+use crate::scanner::slot_matches;
+use crate::types::*;
+use anyhow::Result;
+
+/// Check a receipt's targets against `report`. Refuses receipts whose plan
+/// never ran or was rolled back.
+pub fn verify(receipt: &RemediationReceipt, report: &SystemReport) -> Result<Verification> {
+    if matches!(receipt.status, ApplyStatus::DryRun | ApplyStatus::RolledBack) {
+        anyhow::bail!("Nothing to verify: plan {} was {:?}", receipt.plan.id, receipt.status);
+    }
+    let strategy = &receipt.plan.strategy;
+    let checks = if matches!(strategy, RemediationStrategy::ClaimCleanup) {
+        vec![check_claims(&report.kernel_claims)]
+    } else {
+        receipt
+            .plan
+            .device
+            .split(',')
+            .map(str::trim)
+            .filter(|slot| !slot.is_empty())
+            .map(|slot| check_device(strategy, slot, report.devices.iter().find(|d| slot_matches(&d.slot, slot))))
+            .collect()
+    };
+    let passed = checks.iter().filter(|c| c.passed()).count();
+    let outcome = if passed == checks.len() {
+        VerifyOutcome::Verified
+    } else if passed > 0 {
+        VerifyOutcome::Partial
+    } else {
+        VerifyOutcome::Failed
+    };
+    Ok(Verification {
+        verified_at: chrono::Utc::now().to_rfc3339(),
+        kernel_version: report.kernel_version.clone(),
+        checks,
+        outcome,
+    })
+}
+
+/// Record a verification in the receipt: the reboot is behind it. The apply
+/// status is left as the run ended; the contract receipt takes its status
+/// (Completed, Partial or Failed) from the verification outcome.
+pub fn record(receipt: &mut RemediationReceipt, verification: Verification) {
+    receipt.reboot_pending = false;
+    receipt.verification = Some(verification);
+}
+
+/// Print a verification as a table of targets.
+pub fn print_verification(verification: &Verification) {
+    println!("Verification (kernel {}): {:?}", verification.kernel_version, verification.outcome);
+    for check in &verification.checks {
+        println!(
+            "  [{}] {}: expected {}, found {}",
+            if check.passed() { "OK" } else { "FAIL" },
+            check.target,
+            check.expected,
+            check.observed
+        );
+        for issue in &check.remaining_issues {
+            println!("         still: {}", issue);
+        }
+    }
+}
+
+fn check_device(strategy: &RemediationStrategy, slot: &str, device: Option<&PciDevice>) -> TargetCheck {
+    let driver = device.and_then(|d| d.driver.as_deref());
+    let (expected, met) = match strategy {
+        RemediationStrategy::PciStub => ("bound to pci-stub", driver == Some("pci-stub")),
+        RemediationStrategy::VfioPci | RemediationStrategy::ModprobeConf => ("bound to vfio-pci", driver == Some("vfio-pci")),
+        RemediationStrategy::DualNullDriver => (
+            "bound to pci-stub or vfio-pci",
+            matches!(driver, Some("pci-stub") | Some("vfio-pci")),
+        ),
+        RemediationStrategy::AcpiPowerOff | RemediationStrategy::UdevRemove => ("removed from the PCI bus", device.is_none()),
+        RemediationStrategy::SysfsDisable => ("disabled", device.is_some_and(|d| !d.enabled)),
+        RemediationStrategy::DriverUnbind | RemediationStrategy::UdevUnbind => {
+            ("present with no driver", device.is_some() && driver.is_none())
+        }
+        RemediationStrategy::UdevPowerAuto => (
+            "runtime-suspended (D3)",
+            device.is_some_and(|d| matches!(d.power_state, PowerState::D3Hot | PowerState::D3Cold)),
+        ),
+        RemediationStrategy::ClaimCleanup => unreachable!("claim cleanup is checked against the kernel claims"),
+    };
+    let observed = match device {
+        None => "absent from the PCI bus".to_string(),
+        Some(d) => format!(
+            "{}, {:?}, {}",
+            d.driver.as_deref().map_or("no driver".to_string(), |drv| format!("driver {}", drv)),
+            d.power_state,
+            if d.enabled { "enabled" } else { "disabled" }
+        ),
+    };
+    let remaining_issues = device
+        .map(|d| {
+            d.issues
+                .iter()
+                .filter(|i| matches!(i.issue_type, IssueType::ZombieDevice | IssueType::UnmanagedMemory))
+                .map(|i| i.description.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    TargetCheck {
+        target: slot.to_string(),
+        expected: expected.to_string(),
+        observed,
+        outcome_met: met,
+        remaining_issues,
+    }
+}
+
+fn check_claims(claims: &KernelClaims) -> TargetCheck {
+    let stale = claims.stale().count();
+    let duplicated = claims.duplicates().count();
+    TargetCheck {
+        target: "kernel-cmdline".to_string(),
+        expected: "no stale or duplicated null-driver claims".to_string(),
+        observed: format!("{} stale, {} duplicated", stale, duplicated),
+        outcome_met: stale == 0 && duplicated == 0,
+        remaining_issues: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ambientops_contracts::conversions::remediation_receipt_to_contract;
+    use ambientops_contracts::receipt::ReceiptStatus;
+
+    /// Status of the contract receipt converted from `receipt`
+    fn contract_status(receipt: &RemediationReceipt) -> ReceiptStatus {
+        let json = serde_json::to_value(receipt).unwrap();
+        remediation_receipt_to_contract(&json, uuid::Uuid::nil(), uuid::Uuid::nil()).status
+    }
+
+    fn receipt(strategy: RemediationStrategy, device: &str) -> RemediationReceipt {
+        RemediationReceipt {
+            plan: RemediationPlan {
+                id: "plan-test".to_string(),
+                device: device.to_string(),
+                strategy,
+                steps: Vec::new(),
+                undo_steps: Vec::new(),
+                requires_reboot: true,
+                risk: RiskLevel::Low,
+            },
+            applied_at: "2026-02-12T00:00:00Z".to_string(),
+            reboot_pending: true,
+            pre_state: PreState::default(),
+            status: ApplyStatus::Completed,
+            results: Vec::new(),
+            rollback: Vec::new(),
+            undone: Vec::new(),
+            verification: None,
+        }
+    }
+
+    #[test]
+    fn test_stub_claim_verified() {
+        let mut receipt = receipt(RemediationStrategy::PciStub, "01:00.0");
+        let verification = verify(&receipt, &SystemReport::test(vec![PciDevice::test("0000:01:00.0").with_driver("pci-stub")])).unwrap();
+        assert!(verification.checks[0].passed());
+
+        assert_eq!(verification.outcome, VerifyOutcome::Verified);
+        record(&mut receipt, verification);
+        assert!(matches!(contract_status(&receipt), ReceiptStatus::Completed));
+        assert!(!receipt.reboot_pending);
+    }
+
+    #[test]
+    fn test_partial_and_failed() {
+        let mut both = receipt(RemediationStrategy::VfioPci, "0000:01:00.0, 0000:01:00.1");
+        let live = SystemReport::test(vec![
+            PciDevice::test("0000:01:00.0").with_driver("vfio-pci"),
+            PciDevice::test("0000:01:00.1")
+                .with_issue(IssueType::ZombieDevice, IssueSeverity::High)
+                .with_issue(IssueType::UnmanagedMemory, IssueSeverity::High),
+        ]);
+        let verification = verify(&both, &live).unwrap();
+        assert_eq!(verification.checks[1].observed, "no driver, D0, enabled");
+        assert_eq!(verification.checks[1].remaining_issues.len(), 2);
+        assert_eq!(verification.outcome, VerifyOutcome::Partial);
+        record(&mut both, verification);
+        // The apply itself went through; one of the two targets took effect
+        assert_eq!(both.status, ApplyStatus::Completed);
+        assert!(matches!(contract_status(&both), ReceiptStatus::Partial));
+
+        // Bound as planned, but the issue checks still flag the device
+        let mut unbound = receipt(RemediationStrategy::DriverUnbind, "01:00.1");
+        let verification = verify(&unbound, &live).unwrap();
+        assert!(verification.checks[0].outcome_met);
+        assert_eq!(verification.outcome, VerifyOutcome::Failed);
+        record(&mut unbound, verification);
+        assert!(matches!(contract_status(&unbound), ReceiptStatus::Failed));
+        assert_eq!(unbound.verification.unwrap().outcome, VerifyOutcome::Failed);
+    }
+
+    #[test]
+    fn test_removed_device_and_claim_cleanup() {
+        let removed = verify(&receipt(RemediationStrategy::UdevRemove, "02:00.0"), &SystemReport::test(Vec::new())).unwrap();
+        assert!(removed.checks[0].passed());
+        assert_eq!(removed.checks[0].observed, "absent from the PCI bus");
+
+        let mut live = SystemReport::test(Vec::new());
+        live.kernel_claims.claims.push(StubClaim {
+            stub: "pci-stub".to_string(),
+            id: "1002:73bf".to_string(),
+            devices: Vec::new(),
+            state: ClaimState::Active,
+            occurrences: 1,
+        });
+        let cleanup = verify(&receipt(RemediationStrategy::ClaimCleanup, "kernel-cmdline"), &live).unwrap();
+        assert!(!cleanup.checks[0].passed());
+        assert_eq!(cleanup.checks[0].observed, "1 stale, 0 duplicated");
+
+        let mut rolled_back = receipt(RemediationStrategy::PciStub, "01:00.0");
+        rolled_back.status = ApplyStatus::RolledBack;
+        assert!(verify(&rolled_back, &live).is_err());
+    }
+}
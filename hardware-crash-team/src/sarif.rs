@@ -207,7 +207,7 @@ fn system_report_to_sarif(report: &SystemReport) -> SarifLog {
     }
 }
 
-/// Build the ReportingDescriptor rules array for all 9 IssueType variants
+/// Build the ReportingDescriptor rules array for all 12 IssueType variants
 fn build_rules() -> Vec<ReportingDescriptor> {
     vec![
         make_rule("HCT001", "ZombieDevice", "Device powered on with no driver", "PCI device is in D0 (full power) state with no kernel driver bound, consuming power and potentially causing bus errors.", "error"),
@@ -219,6 +219,9 @@ fn build_rules() -> Vec<ReportingDescriptor> {
         make_rule("HCT007", "BlacklistedButActive", "Blacklisted driver still active", "Kernel driver is blacklisted via modprobe.d but the device remains powered and active.", "error"),
         make_rule("HCT008", "UnmanagedMemory", "Unmanaged BAR memory regions", "PCI BAR memory regions are mapped into the system address space with no driver managing access.", "error"),
         make_rule("HCT009", "PowerStateConflict", "Power state conflict", "Device power state does not match expected state for its driver binding status.", "warning"),
+        make_rule("HCT010", "AerErrors", "PCIe AER errors", "Device reported PCIe Advanced Error Reporting errors; uncorrectable errors or a high correctable rate point at a failing link, riser or slot.", "error"),
+        make_rule("HCT011", "LinkDegraded", "PCIe link downtrained", "PCIe link trained to fewer lanes or a lower speed than the device supports, typically from a bad riser, dirty contacts or a marginal slot.", "warning"),
+        make_rule("HCT012", "UpstreamErrors", "Errors on an upstream bridge", "A root port or bridge above the device reported PCIe errors; the device shares that link and is likely to fail with it.", "warning"),
     ]
 }
 
@@ -251,6 +254,9 @@ fn issue_type_to_rule_id(issue_type: &IssueType) -> &'static str {
         IssueType::BlacklistedButActive => "HCT007",
         IssueType::UnmanagedMemory => "HCT008",
         IssueType::PowerStateConflict => "HCT009",
+        IssueType::AerErrors => "HCT010",
+        IssueType::LinkDegraded => "HCT011",
+        IssueType::UpstreamErrors => "HCT012",
     }
 }
 
@@ -266,6 +272,9 @@ fn issue_type_to_rule_index(issue_type: &IssueType) -> usize {
         IssueType::BlacklistedButActive => 6,
         IssueType::UnmanagedMemory => 7,
         IssueType::PowerStateConflict => 8,
+        IssueType::AerErrors => 9,
+        IssueType::LinkDegraded => 10,
+        IssueType::UpstreamErrors => 11,
     }
 }
 
@@ -329,8 +338,14 @@ fn empty_report() -> SystemReport {
                 iommu_type: Some("Intel VT-d".to_string()),
                 group_count: 14,
                 interrupt_remapping: true,
+                units: 1,
+                dma_mode: None,
+                disabled_reason: None,
             },
+            iommu_groups: Vec::new(),
+            topology: Vec::new(),
             acpi_errors: vec![],
+            kernel_claims: KernelClaims::default(),
             risk_level: RiskLevel::Clean,
         }
     }
@@ -348,12 +363,16 @@ fn device_with_issue(
             description: "Test device".to_string(),
             vendor: "Test".to_string(),
             class: "VGA compatible controller".to_string(),
+            device_class: DeviceClass::Display,
+            subsystem: None,
             driver: None,
             kernel_modules: vec![],
             power_state: PowerState::D0,
             enabled: true,
             iommu_group: Some(1),
             memory_regions: vec![],
+            aer: None,
+            link: None,
             issues: vec![DeviceIssue {
                 severity,
                 issue_type,
@@ -414,6 +433,9 @@ fn test_rule_id_mapping() {
             issue_type_to_rule_id(&IssueType::PowerStateConflict),
             "HCT009"
         );
+        assert_eq!(issue_type_to_rule_id(&IssueType::AerErrors), "HCT010");
+        assert_eq!(issue_type_to_rule_id(&IssueType::LinkDegraded), "HCT011");
+        assert_eq!(issue_type_to_rule_id(&IssueType::UpstreamErrors), "HCT012");
     }
 
     #[test]
@@ -424,7 +446,7 @@ fn test_rule_index_consistency() {
             let expected_id = format!("HCT{:03}", i + 1);
             assert_eq!(rule.id, expected_id, "Rule at index {} has wrong ID", i);
         }
-        assert_eq!(rules.len(), 9);
+        assert_eq!(rules.len(), 12);
     }
 
     #[test]
@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! ACPI error detection
+//!
+//! Parses ACPI interpreter errors out of the kernel log and ties each one
+//! to the PCI device whose firmware node owns the failing method, using the
+//! `firmware_node/path` attribute sysfs exposes for every PCI function.
+
+use super::source::SysfsSource;
+use super::PCI_DEVICES;
+use crate::analyzer::{extract_acpi_device, ACPI_ERROR_PATTERNS};
+use crate::types::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Prefixes the ACPICA interpreter puts in front of its messages.
+const MESSAGE_PREFIXES: &[&str] = &["ACPI BIOS Error (bug): ", "ACPI BIOS Error: ", "ACPI Error: ", "ACPI Exception: "];
+
+/// Parse every ACPI error in the kernel log, once per method and error code,
+/// resolve it to a PCI slot and flag the device it belongs to.
+pub fn scan(source: &dyn SysfsSource, kernel_log: &str, devices: &mut [PciDevice]) -> Vec<AcpiError> {
+    let mut errors = parse_kernel_log(kernel_log);
+    if errors.is_empty() {
+        return errors;
+    }
+
+    let nodes = firmware_nodes(source, devices);
+    for error in &mut errors {
+        error.related_device = resolve_slot(&nodes, &error.method);
+    }
+
+    for device in devices.iter_mut() {
+        for error in errors.iter().filter(|e| e.related_device.as_deref() == Some(&device.slot)) {
+            device.issues.push(DeviceIssue {
+                severity: IssueSeverity::Warning,
+                issue_type: IssueType::AcpiError,
+                description: format!(
+                    "Firmware method {} failed with {} on device {}",
+                    error.method, error.error_code, device.slot
+                ),
+                remediation: "Update the BIOS/UEFI firmware; if the fault persists, keep the device powered down".to_string(),
+            });
+        }
+    }
+    errors
+}
+
+/// Parse ACPI errors from kernel log text, dropping repeats.
+pub fn parse_kernel_log(log: &str) -> Vec<AcpiError> {
+    let mut seen = BTreeSet::new();
+    log.lines()
+        .filter(|line| ACPI_ERROR_PATTERNS.iter().any(|p| line.contains(p)))
+        .filter_map(parse_line)
+        .filter(|e| seen.insert((e.method.clone(), e.error_code.clone())))
+        .collect()
+}
+
+/// Parse one kernel log line. Only interpreter messages and lines naming a
+/// `\_SB` path count: the kernel also logs informational status codes such
+/// as "_OSC: platform retains control of PCIe features (AE_NOT_FOUND)".
+fn parse_line(line: &str) -> Option<AcpiError> {
+    let interpreter = MESSAGE_PREFIXES.iter().any(|p| line.contains(p));
+    let method = match extract_acpi_device(line) {
+        Some(path) => normalize_path(&path),
+        None if interpreter => extract_method_token(line).map(|m| normalize_path(&m)).unwrap_or_default(),
+        None => return None,
+    };
+    let error_code = extract_error_code(line).unwrap_or_default();
+    if method.is_empty() && error_code.is_empty() {
+        return None;
+    }
+
+    Some(AcpiError {
+        method,
+        error_code,
+        description: describe(line),
+        related_device: None,
+    })
+}
+
+/// First `AE_*` status code in the line.
+fn extract_error_code(line: &str) -> Option<String> {
+    let start = line.find("AE_")?;
+    let end = line[start..]
+        .find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+        .map_or(line.len(), |n| start + n);
+    Some(line[start..end].to_string())
+}
+
+/// ACPI paths outside `\_SB` (e.g. `\_GPE._L6F`) or bare method names (`_PRW`).
+fn extract_method_token(line: &str) -> Option<String> {
+    line.split(|c: char| c.is_whitespace() || matches!(c, ',' | '[' | ']' | '(' | ')'))
+        .find(|token| {
+            token.len() >= 4
+                && (token.starts_with('\\') || token.starts_with('_'))
+                && token.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || matches!(c, '_' | '.' | '\\'))
+        })
+        .map(str::to_string)
+}
+
+/// Message text without the ACPICA prefix, log prefix or version/module suffix.
+fn describe(line: &str) -> String {
+    let message = MESSAGE_PREFIXES
+        .iter()
+        .find_map(|prefix| line.find(prefix).map(|pos| &line[pos + prefix.len()..]))
+        .unwrap_or(line)
+        .trim();
+    // ACPICA appends "(20230628/psparse-529)"
+    match message.rfind(" (") {
+        Some(pos) if message[pos + 2..].starts_with(|c: char| c.is_ascii_digit()) && message[pos..].contains('/') => {
+            message[..pos].trim_end().to_string()
+        }
+        _ => message.to_string(),
+    }
+}
+
+/// Canonical form of an ACPI namespace path: no root backslash and no
+/// trailing `_` padding on segments, so `\_SB_.PCI0.HEC_` becomes `_SB.PCI0.HEC`.
+pub fn normalize_path(path: &str) -> String {
+    path.trim_start_matches('\\')
+        .split('.')
+        .map(|segment| {
+            let trimmed = segment.trim_end_matches('_');
+            if trimmed.is_empty() { segment } else { trimmed }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Normalized firmware node path -> PCI slot for every device that has one.
+fn firmware_nodes(source: &dyn SysfsSource, devices: &[PciDevice]) -> BTreeMap<String, String> {
+    devices
+        .iter()
+        .filter_map(|device| {
+            let path = source.read(&format!("{}/{}/firmware_node/path", PCI_DEVICES, device.slot))?;
+            Some((normalize_path(path.trim()), device.slot.clone()))
+        })
+        .collect()
+}
+
+/// Slot of the deepest device whose firmware node contains `method`.
+fn resolve_slot(nodes: &BTreeMap<String, String>, method: &str) -> Option<String> {
+    nodes
+        .iter()
+        .filter(|(node, _)| {
+            method == node.as_str() || (method.starts_with(node.as_str()) && method.as_bytes().get(node.len()) == Some(&b'.'))
+        })
+        .max_by_key(|(node, _)| node.len())
+        .map(|(_, slot)| slot.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+ACPI BIOS Error (bug): Could not resolve symbol [\\_SB.PCI0.GPP0.SWUS], AE_NOT_FOUND (20230628/dswload2-162)
+ACPI Error: Aborting method \\_SB.PCI0.GPP0.SWUS._PRW due to previous error (AE_NOT_FOUND) (20230628/psparse-529)
+ACPI BIOS Error (bug): Could not resolve symbol [\\_SB.PCI0.GPP0.SWUS], AE_NOT_FOUND (20230628/dswload2-162)
+ACPI Error: Method parse/execution failed \\_SB_.PCI0.LPCB.EC0_._Q66, AE_AML_UNINITIALIZED_ELEMENT (20190816/psparse-529)
+ACPI Exception: AE_NOT_FOUND, Evaluating _PRW (20230628/scan-123)
+acpi PNP0A08:00: _OSC: platform retains control of PCIe features (AE_NOT_FOUND)
+usb 1-1: new high-speed USB device number 2 using xhci_hcd
+";
+
+    #[test]
+    fn test_parse_kernel_log() {
+        let errors = parse_kernel_log(LOG);
+        assert_eq!(errors.len(), 4, "repeated line should be dropped: {:?}", errors);
+
+        assert_eq!(errors[0].method, "_SB.PCI0.GPP0.SWUS");
+        assert_eq!(errors[0].error_code, "AE_NOT_FOUND");
+        assert_eq!(errors[0].description, "Could not resolve symbol [\\_SB.PCI0.GPP0.SWUS], AE_NOT_FOUND");
+
+        assert_eq!(errors[1].method, "_SB.PCI0.GPP0.SWUS._PRW");
+        assert_eq!(errors[2].method, "_SB.PCI0.LPCB.EC0._Q66");
+        assert_eq!(errors[2].error_code, "AE_AML_UNINITIALIZED_ELEMENT");
+        assert_eq!(errors[3].method, "_PRW");
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("\\_SB_.PCI0.HEC_"), "_SB.PCI0.HEC");
+        assert_eq!(normalize_path("_SB.PCI0._OSC"), "_SB.PCI0._OSC");
+    }
+
+    #[test]
+    fn test_resolve_slot_picks_deepest_node() {
+        let nodes = BTreeMap::from([
+            ("_SB.PCI0".to_string(), "0000:00:00.0".to_string()),
+            ("_SB.PCI0.GPP0".to_string(), "0000:00:01.1".to_string()),
+            ("_SB.PCI0.GPP1".to_string(), "0000:00:01.2".to_string()),
+        ]);
+        assert_eq!(resolve_slot(&nodes, "_SB.PCI0.GPP0.SWUS._PRW").as_deref(), Some("0000:00:01.1"));
+        assert_eq!(resolve_slot(&nodes, "_SB.PCI0._OSC").as_deref(), Some("0000:00:00.0"));
+        // GPP10 is not inside GPP1
+        assert_eq!(resolve_slot(&nodes, "_SB.PCI0.GPP10").as_deref(), Some("0000:00:00.0"));
+        assert_eq!(resolve_slot(&nodes, "_GPE._L6F"), None);
+    }
+}
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Kernel command line
+//!
+//! Parses the running command line (`/proc/cmdline`) and the one the next
+//! boot will use, then maps the null-driver claims on each (`pci-stub.ids=`,
+//! `vfio-pci.ids=`) onto the devices actually present. The next-boot line
+//! comes from the default Boot Loader Spec entry (which rpm-ostree, grubby
+//! and kernel-install all maintain), else `/etc/kernel/cmdline`, else
+//! `/etc/default/grub`.
+
+use super::source::SysfsSource;
+use crate::types::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Null drivers whose `<stub>.ids=` arguments claim devices at boot.
+pub const STUBS: &[&str] = &["pci-stub", "vfio-pci"];
+
+const BLS_ENTRIES: &str = "/boot/loader/entries";
+const GRUBENV: &[&str] = &["/boot/grub2/grubenv", "/boot/grub/grubenv"];
+
+/// A kernel command line, split into `key[=value]` parameters in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KernelCmdline {
+    params: Vec<(String, Option<String>)>,
+}
+
+impl KernelCmdline {
+    /// Split on whitespace outside double quotes; quotes are removed.
+    pub fn parse(line: &str) -> Self {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut quoted = false;
+        for c in line.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                c if c.is_whitespace() && !quoted => {
+                    if !word.is_empty() {
+                        words.push(std::mem::take(&mut word));
+                    }
+                }
+                c => word.push(c),
+            }
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+
+        let params = words
+            .into_iter()
+            .map(|w| match w.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (w, None),
+            })
+            .collect();
+        Self { params }
+    }
+
+    /// Values of every `key=` parameter, in order.
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.params
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .filter_map(|(_, v)| v.as_deref())
+    }
+
+    /// `(stub, id)` for every ID claimed, duplicates included.
+    pub fn claims(&self) -> Vec<(&'static str, String)> {
+        STUBS
+            .iter()
+            .flat_map(|stub| {
+                self.values(&format!("{}.ids", stub))
+                    .flat_map(|v| v.split(','))
+                    .filter(|id| !id.is_empty())
+                    .map(|id| (*stub, id.to_lowercase()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The claim parameters (and `rd.driver.pre=vfio-pci`) as written.
+    pub fn claim_params(&self) -> Vec<String> {
+        self.params
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = value.as_deref()?;
+                let relevant = STUBS.iter().any(|s| key == &format!("{}.ids", s))
+                    || (key == "rd.driver.pre" && value == "vfio-pci");
+                relevant.then(|| format!("{}={}", key, value))
+            })
+            .collect()
+    }
+}
+
+/// Arguments of the running kernel.
+pub fn running(source: &dyn SysfsSource) -> Option<KernelCmdline> {
+    source.read("/proc/cmdline").map(|c| KernelCmdline::parse(&c))
+}
+
+/// Arguments the next boot will use, and where they were found.
+pub fn next_boot(source: &dyn SysfsSource) -> Option<(String, KernelCmdline)> {
+    if let Some(found) = default_bls_entry(source) {
+        return Some(found);
+    }
+    if let Some(line) = source.read("/etc/kernel/cmdline") {
+        return Some(("/etc/kernel/cmdline".to_string(), KernelCmdline::parse(&line)));
+    }
+    let grub = source.read("/etc/default/grub")?;
+    let line: Vec<String> = ["GRUB_CMDLINE_LINUX", "GRUB_CMDLINE_LINUX_DEFAULT"]
+        .iter()
+        .filter_map(|key| shell_assignment(&grub, key))
+        .collect();
+    Some(("/etc/default/grub".to_string(), KernelCmdline::parse(&line.join(" "))))
+}
+
+/// `options` of the entry GRUB or systemd-boot starts by default: grubenv's
+/// `saved_entry` if set, else the highest version.
+fn default_bls_entry(source: &dyn SysfsSource) -> Option<(String, KernelCmdline)> {
+    let grubenv: BTreeMap<String, String> = GRUBENV
+        .iter()
+        .find_map(|path| source.read(path))
+        .map(|env| {
+            env.lines()
+                .filter(|l| !l.starts_with('#'))
+                .filter_map(|l| l.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut entries: Vec<(String, String, String)> = Vec::new(); // (name, version, options)
+    for name in source.list(BLS_ENTRIES).unwrap_or_default() {
+        let Some(stem) = name.strip_suffix(".conf") else { continue };
+        let Some(content) = source.read(&format!("{}/{}", BLS_ENTRIES, name)) else { continue };
+        let field = |key: &str| {
+            content
+                .lines()
+                .find_map(|l| l.strip_prefix(key).filter(|rest| rest.starts_with(char::is_whitespace)))
+                .map(|rest| rest.trim().to_string())
+                .unwrap_or_default()
+        };
+        entries.push((stem.to_string(), field("version"), field("options")));
+    }
+
+    let chosen = match grubenv.get("saved_entry").and_then(|saved| entries.iter().find(|e| &e.0 == saved)) {
+        Some(entry) => entry,
+        None => entries
+            .iter()
+            .max_by(|a, b| compare_versions(&a.1, &b.1).then_with(|| compare_versions(&a.0, &b.0)))?,
+    };
+    // Older grubby entries defer to grubenv: "options $kernelopts"
+    let options = chosen
+        .2
+        .replace("$kernelopts", grubenv.get("kernelopts").map_or("", String::as_str));
+    Some((format!("{}/{}.conf", BLS_ENTRIES, chosen.0), KernelCmdline::parse(&options)))
+}
+
+/// Value of `KEY="..."` in a shell-style file.
+fn shell_assignment(content: &str, key: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+        Some(value.trim_matches(|c| c == '"' || c == '\'').to_string())
+    })
+}
+
+/// Natural order: digit runs compare as numbers ("6.10" > "6.9").
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> Vec<(bool, &str)> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        let bytes = s.as_bytes();
+        for i in 1..=bytes.len() {
+            if i == bytes.len() || bytes[i].is_ascii_digit() != bytes[start].is_ascii_digit() {
+                out.push((bytes[start].is_ascii_digit(), &s[start..i]));
+                start = i;
+            }
+        }
+        out
+    }
+    if a.is_empty() || b.is_empty() {
+        return a.len().cmp(&b.len());
+    }
+    for (x, y) in chunks(a).iter().zip(chunks(b).iter()) {
+        let order = match (x, y) {
+            ((true, x), (true, y)) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            ((_, x), (_, y)) => x.cmp(y),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Whether a claim ID (`vendor:device[:subvendor:...]`, `ffffffff` = any)
+/// matches a device's `vendor:device`.
+fn id_matches(claim: &str, pci_id: &str) -> bool {
+    let mut claim = claim.split(':');
+    let mut device = pci_id.split(':');
+    (0..2).all(|_| match (claim.next(), device.next()) {
+        (Some(c), Some(d)) => c.eq_ignore_ascii_case("ffffffff") || c.trim_start_matches('0') == d.trim_start_matches('0'),
+        _ => false,
+    })
+}
+
+/// Map the claims on both command lines onto the present devices.
+pub fn claims(source: &dyn SysfsSource, devices: &[PciDevice]) -> KernelClaims {
+    let running = running(source).unwrap_or_default();
+    let next = next_boot(source);
+    analyze(&running, next.as_ref().map(|(origin, line)| (origin.as_str(), line)), devices)
+}
+
+fn analyze(running: &KernelCmdline, next: Option<(&str, &KernelCmdline)>, devices: &[PciDevice]) -> KernelClaims {
+    let next_line = next.map_or(running, |(_, line)| line);
+    let now = running.claims();
+    let then = next_line.claims();
+
+    let mut claims: Vec<StubClaim> = Vec::new();
+    for (stub, id) in now.iter().chain(then.iter()) {
+        if claims.iter().any(|c| c.stub == *stub && &c.id == id) {
+            continue;
+        }
+        let key = (*stub, id.clone());
+        let state = match (now.contains(&key), then.contains(&key)) {
+            (true, true) => ClaimState::Active,
+            (false, _) => ClaimState::PendingReboot,
+            (true, false) => ClaimState::PendingRemoval,
+        };
+        claims.push(StubClaim {
+            stub: stub.to_string(),
+            id: id.clone(),
+            devices: devices.iter().filter(|d| id_matches(id, &d.pci_id)).map(|d| d.slot.clone()).collect(),
+            state,
+            occurrences: then.iter().filter(|c| **c == key).count(),
+        });
+    }
+
+    KernelClaims {
+        next_boot_source: next.map(|(origin, _)| origin.to_string()),
+        next_boot_params: next_line.claim_params(),
+        claims,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmdline() {
+        let line = KernelCmdline::parse(
+            "BOOT_IMAGE=(hd0,gpt2)/vmlinuz root=UUID=abc rhgb \"acpi_osi=!Windows 2012\" pci-stub.ids=10DE:13b0,10de:0fbc vfio-pci.ids=10de:13b0 rd.driver.pre=vfio-pci",
+        );
+        assert_eq!(line.values("root").collect::<Vec<_>>(), vec!["UUID=abc"]);
+        assert_eq!(line.values("acpi_osi").collect::<Vec<_>>(), vec!["!Windows 2012"]);
+        assert_eq!(
+            line.claims(),
+            vec![
+                ("pci-stub", "10de:13b0".to_string()),
+                ("pci-stub", "10de:0fbc".to_string()),
+                ("vfio-pci", "10de:13b0".to_string()),
+            ]
+        );
+        assert_eq!(line.claim_params().len(), 3);
+    }
+
+    #[test]
+    fn test_claim_states_and_problems() {
+        let devices = vec![
+            PciDevice::test("0000:01:00.0"),
+            PciDevice { pci_id: "10de:0fbc".to_string(), ..PciDevice::test("0000:01:00.1") },
+        ];
+        let running = KernelCmdline::parse("pci-stub.ids=10de:13b0,1002:73bf vfio-pci.ids=10de:13b0");
+        let next = KernelCmdline::parse(
+            "pci-stub.ids=10de:13b0,1002:73bf pci-stub.ids=10de:13b0 vfio-pci.ids=10de:0fbc",
+        );
+        let claims = analyze(&running, Some(("/boot/loader/entries/ostree-2.conf", &next)), &devices);
+
+        let find = |stub: &str, id: &str| claims.claims.iter().find(|c| c.stub == stub && c.id == id).unwrap();
+        assert_eq!(find("pci-stub", "10de:13b0").state, ClaimState::Active);
+        assert_eq!(find("pci-stub", "10de:13b0").devices, vec!["0000:01:00.0"]);
+        assert_eq!(find("vfio-pci", "10de:13b0").state, ClaimState::PendingRemoval);
+        assert_eq!(find("vfio-pci", "10de:0fbc").state, ClaimState::PendingReboot);
+
+        assert_eq!(claims.stale().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["1002:73bf"]);
+        assert_eq!(claims.duplicates().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["10de:13b0"]);
+        assert_eq!(claims.double_claimed(), vec!["10de:13b0"]);
+    }
+
+    #[test]
+    fn test_next_boot_prefers_default_bls_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = dir.path().join("boot/loader/entries");
+        std::fs::create_dir_all(&entries).unwrap();
+        std::fs::write(entries.join("ostree-1-fedora.conf"), "title Fedora (old)\nversion 1\noptions root=/dev/sda1\n").unwrap();
+        std::fs::write(
+            entries.join("ostree-2-fedora.conf"),
+            "title Fedora\nversion 2\noptions root=/dev/sda1 pci-stub.ids=10de:13b0\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.path().join("etc/kernel")).unwrap();
+        std::fs::write(dir.path().join("etc/kernel/cmdline"), "root=/dev/sda1\n").unwrap();
+
+        let source = super::super::source::RootedFs::new(dir.path()).unwrap();
+        let (origin, line) = next_boot(&source).unwrap();
+        assert_eq!(origin, "/boot/loader/entries/ostree-2-fedora.conf");
+        assert_eq!(line.claims(), vec![("pci-stub", "10de:13b0".to_string())]);
+
+        assert_eq!(compare_versions("6.10.0", "6.9.12"), Ordering::Greater);
+    }
+}
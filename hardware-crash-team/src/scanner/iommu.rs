@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! IOMMU state and group membership
+//!
+//! Whether the IOMMU is on comes from the groups the kernel built; what the
+//! hardware offers comes from the firmware's DMAR (Intel VT-d) or IVRS
+//! (AMD-Vi) table in `/sys/firmware/acpi/tables`, and what the kernel made
+//! of it from its log and command line. A machine with the tables but no
+//! groups has an IOMMU that is switched off, and says why.
+//!
+//! vfio-pci hands a whole IOMMU group to its user, so a group is only safe
+//! to claim when every endpoint in it is claimed too. The kernel already
+//! folds ACS into the groups it builds: a bridge without ACS puts everything
+//! below it in one group. PCI-to-PCI bridges themselves stay with the host
+//! (vfio tolerates them bound to pcieport), so only endpoints count.
+
+use super::cmdline::KernelCmdline;
+use super::source::SysfsSource;
+use super::{slot_matches, IOMMU_GROUPS};
+use crate::types::*;
+
+const DMAR_TABLE: &str = "/sys/firmware/acpi/tables/DMAR";
+const IVRS_TABLE: &str = "/sys/firmware/acpi/tables/IVRS";
+/// Standard ACPI table header length.
+const ACPI_HEADER: usize = 36;
+/// DMAR flags bit: the platform supports interrupt remapping.
+const DMAR_INTR_REMAP: u8 = 1 << 0;
+/// IVHD block types (legacy, extended and ACPI-HID capable), lowest first.
+const IVHD_TYPES: &[u8] = &[0x10, 0x11, 0x40];
+
+/// Remapping hardware a DMAR or IVRS table describes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirmwareIommu {
+    /// "Intel VT-d" or "AMD-Vi"
+    pub vendor: &'static str,
+    /// DRHD or IVHD units
+    pub units: u32,
+    /// Firmware advertises interrupt remapping
+    pub interrupt_remapping: bool,
+}
+
+/// Parse a DMAR table: the flags byte after the host address width, then
+/// remapping structures (type, length) of which type 0 is a DRHD unit.
+pub fn parse_dmar(table: &[u8]) -> Option<FirmwareIommu> {
+    if table.len() < ACPI_HEADER + 12 || &table[..4] != b"DMAR" {
+        return None;
+    }
+    let flags = table[ACPI_HEADER + 1];
+    let units = structures(table, ACPI_HEADER + 12, |t| {
+        let kind = u16::from_le_bytes([t[0], t[1]]);
+        let len = u16::from_le_bytes([t[2], t[3]]) as usize;
+        (kind == 0, len)
+    });
+    Some(FirmwareIommu { vendor: "Intel VT-d", units, interrupt_remapping: flags & DMAR_INTR_REMAP != 0 })
+}
+
+/// Parse an IVRS table: IVinfo and a reserved quadword, then IVHD/IVMD
+/// blocks (type, flags, length). AMD-Vi remaps interrupts on every IVHD.
+/// Firmware describes each IOMMU once per IVHD type it offers and the
+/// kernel only reads the highest, so only blocks of that type are counted.
+pub fn parse_ivrs(table: &[u8]) -> Option<FirmwareIommu> {
+    if table.len() < ACPI_HEADER + 12 || &table[..4] != b"IVRS" {
+        return None;
+    }
+    let blocks = |kind: u8| {
+        structures(table, ACPI_HEADER + 12, |t| (t[0] == kind, u16::from_le_bytes([t[2], t[3]]) as usize))
+    };
+    let units = IVHD_TYPES.iter().rev().map(|&kind| blocks(kind)).find(|&n| n > 0).unwrap_or(0);
+    Some(FirmwareIommu { vendor: "AMD-Vi", units, interrupt_remapping: units > 0 })
+}
+
+/// Count the structures `classify` marks, walking (type, length) headers
+/// from `start` up to the table's own length.
+fn structures(table: &[u8], start: usize, classify: impl Fn(&[u8]) -> (bool, usize)) -> u32 {
+    let declared = u32::from_le_bytes([table[4], table[5], table[6], table[7]]) as usize;
+    let end = declared.min(table.len());
+    let mut offset = start;
+    let mut count = 0;
+    while offset + 4 <= end {
+        let (counted, len) = classify(&table[offset..]);
+        if len < 4 {
+            break;
+        }
+        count += u32::from(counted);
+        offset += len;
+    }
+    count
+}
+
+/// IOMMU status from firmware tables, groups, kernel log and command line.
+pub fn status(source: &dyn SysfsSource, kernel_log: &str) -> IommuStatus {
+    let group_count = source.list(IOMMU_GROUPS).map_or(0, |g| g.len() as u32);
+    let firmware = firmware(source);
+    let cmdline = source.read("/proc/cmdline").map(|c| KernelCmdline::parse(&c)).unwrap_or_default();
+    let enabled = group_count > 0;
+
+    let iommu_type = firmware
+        .as_ref()
+        .map(|f| f.vendor.to_string())
+        .or_else(|| enabled.then(|| "Unknown".to_string()));
+    let interrupt_remapping = enabled
+        && match log_interrupt_remapping(kernel_log) {
+            Some(state) => state,
+            None => {
+                !cmdline.values("intremap").any(|v| v == "off")
+                    && firmware.as_ref().is_some_and(|f| f.interrupt_remapping)
+            }
+        };
+    let dma_mode = if enabled { dma_mode(source, &cmdline) } else { None };
+    let disabled_reason = (!enabled).then(|| disabled_reason(firmware.as_ref(), &cmdline, kernel_log));
+
+    IommuStatus {
+        enabled,
+        iommu_type,
+        group_count,
+        interrupt_remapping,
+        units: firmware.as_ref().map_or(0, |f| f.units),
+        dma_mode,
+        disabled_reason,
+    }
+}
+
+/// The firmware table, parsed if readable (root only); its presence alone
+/// still names the vendor.
+fn firmware(source: &dyn SysfsSource) -> Option<FirmwareIommu> {
+    if let Some(table) = source.read_bytes(DMAR_TABLE) {
+        return parse_dmar(&table);
+    }
+    if let Some(table) = source.read_bytes(IVRS_TABLE) {
+        return parse_ivrs(&table);
+    }
+    let vendor = if source.exists(DMAR_TABLE) {
+        "Intel VT-d"
+    } else if source.exists(IVRS_TABLE) {
+        "AMD-Vi"
+    } else {
+        return None;
+    };
+    Some(FirmwareIommu { vendor, units: 0, interrupt_remapping: false })
+}
+
+/// What the kernel said about interrupt remapping, if anything.
+fn log_interrupt_remapping(log: &str) -> Option<bool> {
+    let mut state = None;
+    for line in log.lines() {
+        if line.contains("DMAR-IR: Enabled IRQ remapping") || line.contains("AMD-Vi: Interrupt remapping enabled") {
+            state = Some(true);
+        } else if line.contains("IRQ remapping") && (line.contains("disabled") || line.contains("Failed")) {
+            state = Some(false);
+        }
+    }
+    state
+}
+
+/// DMA translation mode: the default domain type of the groups, else the
+/// command line.
+fn dma_mode(source: &dyn SysfsSource, cmdline: &KernelCmdline) -> Option<DmaMode> {
+    let group = source.list(IOMMU_GROUPS)?.into_iter().next()?;
+    match source.read(&format!("{}/{}/type", IOMMU_GROUPS, group)).as_deref().map(str::trim) {
+        Some("identity") => return Some(DmaMode::Passthrough),
+        Some("DMA-FQ") => return Some(DmaMode::Lazy),
+        Some("DMA") => return Some(DmaMode::Strict),
+        _ => {}
+    }
+    if cmdline.values("iommu").any(|v| v == "pt") || cmdline.values("iommu.passthrough").any(|v| v == "1") {
+        return Some(DmaMode::Passthrough);
+    }
+    cmdline
+        .values("iommu.strict")
+        .last()
+        .map(|strict| if strict == "0" { DmaMode::Lazy } else { DmaMode::Strict })
+}
+
+fn disabled_reason(firmware: Option<&FirmwareIommu>, cmdline: &KernelCmdline, log: &str) -> String {
+    let Some(firmware) = firmware else {
+        return "No DMAR or IVRS table: VT-d/AMD-Vi is absent or disabled in firmware setup".to_string();
+    };
+    let switch = if firmware.vendor == "AMD-Vi" { "amd_iommu" } else { "intel_iommu" };
+    for key in [switch, "iommu"] {
+        if cmdline.values(key).any(|v| v.split(',').any(|o| o == "off")) {
+            return format!("Disabled on the kernel command line ({}=off)", key);
+        }
+    }
+    if let Some(line) = log
+        .lines()
+        .find(|l| (l.contains("DMAR:") || l.contains("AMD-Vi:")) && (l.contains("disabled") || l.contains("Firmware Bug")))
+    {
+        return format!("Kernel: {}", line.trim());
+    }
+    if switch == "intel_iommu" && !cmdline.values(switch).any(|v| v.split(',').any(|o| o == "on")) {
+        return "Firmware has VT-d but the kernel did not enable it; add intel_iommu=on".to_string();
+    }
+    format!("Firmware has {} but the kernel did not enable it", firmware.vendor)
+}
+
+/// Flag devices a driverless or vfio-bound state leaves doing DMA with no
+/// IOMMU between them and memory.
+pub fn isolation_issues(status: &IommuStatus, devices: &mut [PciDevice]) {
+    for device in devices {
+        let exposed = device.driver.is_none() || device.driver.as_deref() == Some("vfio-pci");
+        if !exposed || (!device.enabled && device.power_state != PowerState::D0) {
+            continue;
+        }
+        let why = if !status.enabled {
+            status.disabled_reason.clone().unwrap_or_else(|| "IOMMU disabled".to_string())
+        } else if device.iommu_group.is_none() {
+            "device is in no IOMMU group".to_string()
+        } else {
+            continue;
+        };
+        device.issues.push(DeviceIssue {
+            severity: IssueSeverity::Warning,
+            issue_type: IssueType::NoIommuIsolation,
+            description: format!("Device {} can reach memory by DMA without IOMMU isolation: {}", device.slot, why),
+            remediation: "Enable the IOMMU (VT-d/AMD-Vi in firmware, intel_iommu=on) before relying on vfio-pci".to_string(),
+        });
+    }
+}
+
+/// PCI-to-PCI and semi-transparent bridge class codes (base and subclass).
+pub(super) const BRIDGE_CLASSES: &[u32] = &[0x0604, 0x0609];
+/// Drivers that leave a device viable in a group handed to vfio.
+const VFIO_VIABLE: &[&str] = &["vfio-pci", "pci-stub", "pcieport"];
+
+/// Every IOMMU group and its members, by group number.
+pub fn groups(source: &dyn SysfsSource) -> Vec<IommuGroup> {
+    let mut groups: Vec<IommuGroup> = source
+        .list(IOMMU_GROUPS)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            let id: u32 = name.parse().ok()?;
+            let members = source
+                .list(&format!("{}/{}/devices", IOMMU_GROUPS, name))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|slot| member(source, slot))
+                .collect();
+            Some(IommuGroup::new(id, members))
+        })
+        .collect();
+    groups.sort_by_key(|g| g.id);
+    groups
+}
+
+fn member(source: &dyn SysfsSource, slot: String) -> GroupMember {
+    let path = format!("/sys/bus/pci/devices/{}", slot);
+    let class = source
+        .read(&format!("{}/class", path))
+        .and_then(|c| u32::from_str_radix(c.trim().trim_start_matches("0x"), 16).ok());
+    let driver = source
+        .read_link(&format!("{}/driver", path))
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()));
+    GroupMember {
+        bridge: class.is_some_and(|c| BRIDGE_CLASSES.contains(&(c >> 8))),
+        slot,
+        driver,
+    }
+}
+
+impl IommuGroup {
+    fn new(id: u32, members: Vec<GroupMember>) -> Self {
+        let mut physical: Vec<&str> = members.iter().filter(|m| !m.bridge).map(|m| physical_slot(&m.slot)).collect();
+        physical.sort_unstable();
+        physical.dedup();
+        let mixed = physical.len() > 1;
+        Self { id, members, mixed }
+    }
+}
+
+/// "0000:01:00.1" -> "0000:01:00": the functions of one physical device.
+pub(super) fn physical_slot(slot: &str) -> &str {
+    slot.rsplit_once('.').map_or(slot, |(device, _)| device)
+}
+
+/// What claiming `targets` with vfio-pci would do to their groups.
+#[derive(Debug, Default)]
+pub struct GroupSplit {
+    /// Group members a host driver is using, which the claim would strand
+    pub blockers: Vec<String>,
+    /// Members left unclaimed but idle, and targets without isolation
+    pub warnings: Vec<String>,
+}
+
+/// Check a vfio-pci claim of `targets` against the groups they sit in.
+pub fn vfio_split(groups: &[IommuGroup], targets: &[String]) -> GroupSplit {
+    let mut split = GroupSplit::default();
+    if groups.is_empty() {
+        split.warnings.push("IOMMU is off: vfio-pci will claim the device(s) but cannot isolate their DMA".to_string());
+        return split;
+    }
+
+    let is_target = |slot: &str| targets.iter().any(|t| slot_matches(slot, t));
+    let mut seen = Vec::new();
+    for target in targets {
+        let Some(group) = groups.iter().find(|g| g.members.iter().any(|m| slot_matches(&m.slot, target))) else {
+            split.warnings.push(format!("{} is in no IOMMU group; vfio-pci cannot isolate it", target));
+            continue;
+        };
+        if seen.contains(&group.id) {
+            continue;
+        }
+        seen.push(group.id);
+
+        let shared = if group.mixed { " (shared with other devices: no ACS isolation upstream)" } else { "" };
+        for other in group.members.iter().filter(|m| !m.bridge && !is_target(&m.slot)) {
+            match other.driver.as_deref() {
+                Some(driver) if !VFIO_VIABLE.contains(&driver) => split.blockers.push(format!(
+                    "{} in IOMMU group {}{} is in use by {}; add it to the plan or use another strategy",
+                    other.slot, group.id, shared, driver
+                )),
+                _ => split.warnings.push(format!(
+                    "{} in IOMMU group {}{} is not in the plan; bind it to vfio-pci before using the group",
+                    other.slot, group.id, shared
+                )),
+            }
+        }
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ACPI table: header, `fixed` bytes, then structures.
+    fn table(signature: &[u8; 4], fixed: &[u8], structures: &[Vec<u8>]) -> Vec<u8> {
+        let mut body: Vec<u8> = fixed.to_vec();
+        for s in structures {
+            body.extend(s);
+        }
+        let mut table = signature.to_vec();
+        table.extend(((ACPI_HEADER + body.len()) as u32).to_le_bytes());
+        table.resize(ACPI_HEADER, 0);
+        table.extend(body);
+        table
+    }
+
+    #[test]
+    fn test_parse_dmar() {
+        let drhd = |len: u16| [0u16.to_le_bytes(), len.to_le_bytes()].concat().into_iter().chain(std::iter::repeat(0).take(len as usize - 4)).collect();
+        let rmrr = [1u16.to_le_bytes(), 8u16.to_le_bytes()].concat().into_iter().chain([0; 4]).collect();
+        let mut fixed = vec![38, DMAR_INTR_REMAP];
+        fixed.resize(12, 0);
+        let dmar = parse_dmar(&table(b"DMAR", &fixed, &[drhd(16), rmrr, drhd(24)])).unwrap();
+        assert_eq!(dmar, FirmwareIommu { vendor: "Intel VT-d", units: 2, interrupt_remapping: true });
+
+        fixed[1] = 0;
+        assert!(!parse_dmar(&table(b"DMAR", &fixed, &[])).unwrap().interrupt_remapping);
+        assert!(parse_dmar(b"DMAR").is_none());
+    }
+
+    #[test]
+    fn test_parse_ivrs() {
+        let block = |kind: u8, len: u16| [kind, 0].into_iter().chain(len.to_le_bytes()).chain(std::iter::repeat(0).take(len as usize - 4)).collect();
+        let ivrs = parse_ivrs(&table(b"IVRS", &[0; 12], &[block(0x10, 24), block(0x20, 32), block(0x10, 24)])).unwrap();
+        assert_eq!(ivrs.units, 2);
+        assert!(ivrs.interrupt_remapping);
+
+        // Two IOMMUs, each listed as a legacy and an extended block
+        let both = parse_ivrs(&table(
+            b"IVRS",
+            &[0; 12],
+            &[block(0x10, 24), block(0x11, 40), block(0x10, 24), block(0x11, 40), block(0x20, 32)],
+        ))
+        .unwrap();
+        assert_eq!(both.units, 2);
+        let all = parse_ivrs(&table(b"IVRS", &[0; 12], &[block(0x10, 24), block(0x11, 40), block(0x40, 40)])).unwrap();
+        assert_eq!(all.units, 1);
+
+        assert_eq!(parse_ivrs(&table(b"IVRS", &[0; 12], &[block(0x20, 32)])).unwrap().units, 0);
+    }
+
+    #[test]
+    fn test_status_from_log_and_cmdline() {
+        use super::super::source::RootedFs;
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("sys/firmware/acpi/tables")).unwrap();
+        std::fs::write(root.join("sys/firmware/acpi/tables/DMAR"), table(b"DMAR", &[38, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], &[])).unwrap();
+        std::fs::create_dir_all(root.join("sys/kernel/iommu_groups")).unwrap();
+        std::fs::create_dir_all(root.join("proc")).unwrap();
+        std::fs::write(root.join("proc/cmdline"), "root=/dev/sda1 iommu=pt\n").unwrap();
+
+        // Tables but no groups: off, and the command line does not say on
+        let off = status(&RootedFs::new(root).unwrap(), "");
+        assert!(!off.enabled);
+        assert_eq!(off.iommu_type.as_deref(), Some("Intel VT-d"));
+        assert!(off.disabled_reason.unwrap().contains("intel_iommu=on"));
+
+        std::fs::create_dir_all(root.join("sys/kernel/iommu_groups/0")).unwrap();
+        let log = "DMAR: IOMMU enabled\nDMAR-IR: Enabled IRQ remapping in x2apic mode\n";
+        let on = status(&RootedFs::new(root).unwrap(), log);
+        assert!(on.enabled && on.interrupt_remapping);
+        assert_eq!(on.dma_mode, Some(DmaMode::Passthrough));
+        assert!(on.disabled_reason.is_none());
+
+        // Without log evidence the firmware flag (clear here) decides
+        assert!(!status(&RootedFs::new(root).unwrap(), "").interrupt_remapping);
+
+        std::fs::write(root.join("sys/kernel/iommu_groups/0/type"), "DMA-FQ\n").unwrap();
+        assert_eq!(status(&RootedFs::new(root).unwrap(), log).dma_mode, Some(DmaMode::Lazy));
+    }
+
+    fn member(slot: &str, driver: Option<&str>, bridge: bool) -> GroupMember {
+        GroupMember { slot: slot.to_string(), driver: driver.map(str::to_string), bridge }
+    }
+
+    fn targets(slots: &[&str]) -> Vec<String> {
+        slots.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_isolation_issues() {
+        let device = |slot: &str, driver: Option<&str>, group: Option<u32>| {
+            serde_json::from_value::<PciDevice>(serde_json::json!({
+                "slot": slot, "pci_id": "10de:13b0", "description": "", "vendor": "10de", "class": "",
+                "driver": driver, "kernel_modules": [], "power_state": "D0", "enabled": true,
+                "iommu_group": group, "memory_regions": [], "issues": []
+            }))
+            .unwrap()
+        };
+        let mut devices = vec![
+            device("0000:01:00.0", None, Some(1)),
+            device("0000:02:00.0", None, None),
+            device("0000:03:00.0", Some("ahci"), None),
+        ];
+        let mut on: IommuStatus = serde_json::from_value(serde_json::json!({
+            "enabled": true, "iommu_type": "Intel VT-d", "group_count": 1, "interrupt_remapping": true
+        }))
+        .unwrap();
+        isolation_issues(&on, &mut devices);
+        let flagged: Vec<usize> = devices.iter().map(|d| d.issues.len()).collect();
+        assert_eq!(flagged, vec![0, 1, 0]);
+
+        on.enabled = false;
+        on.disabled_reason = Some("Disabled on the kernel command line (iommu=off)".to_string());
+        isolation_issues(&on, &mut devices[..1]);
+        assert!(devices[0].issues[0].description.ends_with("(iommu=off)"));
+    }
+
+    #[test]
+    fn test_mixed_groups_ignore_bridges_and_functions() {
+        let gpu = IommuGroup::new(
+            1,
+            vec![
+                member("0000:00:01.0", Some("pcieport"), true),
+                member("0000:01:00.0", None, false),
+                member("0000:01:00.1", Some("snd_hda_intel"), false),
+            ],
+        );
+        assert!(!gpu.mixed);
+        let chipset = IommuGroup::new(2, vec![member("0000:02:00.0", Some("ahci"), false), member("0000:03:00.0", Some("xhci_hcd"), false)]);
+        assert!(chipset.mixed);
+    }
+
+    #[test]
+    fn test_vfio_split() {
+        let groups = vec![
+            IommuGroup::new(1, vec![member("0000:01:00.0", None, false), member("0000:01:00.1", Some("snd_hda_intel"), false)]),
+            IommuGroup::new(
+                2,
+                vec![
+                    member("0000:02:00.0", Some("nouveau"), false),
+                    member("0000:03:00.0", Some("ahci"), false),
+                    member("0000:04:00.0", None, false),
+                ],
+            ),
+        ];
+
+        // The GPU alone strands its audio function
+        let split = vfio_split(&groups, &targets(&["01:00.0"]));
+        assert_eq!(split.blockers.len(), 1);
+        assert!(split.blockers[0].starts_with("0000:01:00.1 in IOMMU group 1 is in use by snd_hda_intel"));
+        assert!(vfio_split(&groups, &targets(&["01:00.0", "01:00.1"])).blockers.is_empty());
+
+        // Sharing with the SATA controller; the idle device is only a warning
+        let split = vfio_split(&groups, &targets(&["0000:02:00.0"]));
+        assert_eq!(split.blockers.len(), 1);
+        assert!(split.blockers[0].contains("no ACS isolation"));
+        assert_eq!(split.warnings.len(), 1);
+
+        assert_eq!(vfio_split(&[], &targets(&["01:00.0"])).warnings.len(), 1);
+    }
+}
@@ -2,47 +2,79 @@
 //! PCI device scanner
 //!
 //! Enumerates PCI devices via sysfs, checks driver bindings,
-//! power states, IOMMU groups, and detects zombie hardware.
+//! power states, IOMMU groups, ACPI errors, and detects zombie hardware.
+
+pub mod acpi;
+pub mod cmdline;
+pub mod iommu;
+pub mod modules;
+pub mod pciids;
+pub mod pcie;
+pub mod quirks;
+pub mod source;
+pub mod topology;
 
 use crate::types::*;
 use anyhow::Result;
-use std::fs;
-use std::path::Path;
-use std::process::Command;
+use pciids::PciIds;
+use source::{RootedFs, SysfsSource};
+
+pub(crate) const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+const IOMMU_GROUPS: &str = "/sys/kernel/iommu_groups";
 
 /// Scan the entire system for hardware issues
 pub fn scan_system(verbose: bool) -> Result<SystemReport> {
-    let devices = scan_pci_devices(verbose)?;
-    let iommu = scan_iommu()?;
-    let acpi_errors = scan_acpi_errors()?;
+    scan_source(&RootedFs::live(), verbose)
+}
+
+/// Scan the live system and archive every file the scan read as a snapshot
+/// at `out`. Returns the number of paths captured.
+pub fn capture(out: &std::path::Path, hostname: &str, username: Option<&str>) -> Result<usize> {
+    let live = RootedFs::live();
+    let recorder = source::Recorder::new(&live);
+    scan_source(&recorder, false)?;
+    recorder.write_snapshot(out, &chrono::Utc::now().to_rfc3339(), hostname, username)?;
+    Ok(recorder.recorded())
+}
+
+/// Scan whatever `source` presents as the system: the live machine, a
+/// fixture tree or a captured snapshot.
+pub fn scan_source(source: &dyn SysfsSource, verbose: bool) -> Result<SystemReport> {
+    let mut devices = scan_pci_devices(source, verbose)?;
+    let kernel_log = source.kernel_log().unwrap_or_default();
+    let iommu = iommu::status(source, &kernel_log);
+    iommu::isolation_issues(&iommu, &mut devices);
+    let iommu_groups = iommu::groups(source);
+    let acpi_errors = acpi::scan(source, &kernel_log, &mut devices);
+    let topology = topology::build(source, &devices);
+    topology::propagate(&topology, &mut devices);
+    let kernel_claims = cmdline::claims(source, &devices);
 
     let risk_level = assess_risk(&devices, &acpi_errors);
 
     Ok(SystemReport {
-        timestamp: chrono::Utc::now().to_rfc3339(),
-        kernel_version: read_kernel_version(),
+        timestamp: source.captured_at().unwrap_or_else(|| chrono::Utc::now().to_rfc3339()),
+        kernel_version: read_kernel_version(source),
         devices,
         iommu,
+        iommu_groups,
+        topology,
         acpi_errors,
+        kernel_claims,
         risk_level,
     })
 }
 
 /// Scan all PCI devices via /sys/bus/pci/devices/
-fn scan_pci_devices(verbose: bool) -> Result<Vec<PciDevice>> {
-    let pci_path = Path::new("/sys/bus/pci/devices");
-    let mut devices = Vec::new();
-
-    if !pci_path.exists() {
-        anyhow::bail!("Cannot access /sys/bus/pci/devices - are you on Linux?");
-    }
-
-    for entry in fs::read_dir(pci_path)? {
-        let entry = entry?;
-        let slot_path = entry.path();
-        let slot = entry.file_name().to_string_lossy().to_string();
+fn scan_pci_devices(source: &dyn SysfsSource, verbose: bool) -> Result<Vec<PciDevice>> {
+    let Some(slots) = source.list(PCI_DEVICES) else {
+        anyhow::bail!("Cannot access {} - are you on Linux?", PCI_DEVICES);
+    };
 
-        let device = scan_single_device(&slot, &slot_path, verbose)?;
+    let shared = Shared::load(source);
+    let mut devices = Vec::new();
+    for slot in slots {
+        let device = scan_single_device(source, &shared, &slot, verbose)?;
         devices.push(device);
     }
 
@@ -50,20 +82,70 @@ fn scan_pci_devices(verbose: bool) -> Result<Vec<PciDevice>> {
     Ok(devices)
 }
 
-/// Scan a single PCI device
-fn scan_single_device(slot: &str, path: &Path, _verbose: bool) -> Result<PciDevice> {
-    let vendor_id = read_sysfs_hex(path, "vendor");
-    let device_id = read_sysfs_hex(path, "device");
-    let pci_id = format!("{}:{}", vendor_id, device_id);
+/// System-wide state every device is checked against, read once per scan
+struct Shared {
+    interrupts: Option<String>,
+    ids: PciIds,
+    modules: modules::ModuleIndex,
+    blacklist: modules::Blacklist,
+    quirks: quirks::QuirkDb,
+    release: String,
+}
 
-    let class = read_sysfs_string(path, "class");
-    let driver = read_driver(path);
-    let enabled = read_sysfs_string(path, "enable") == "1";
-    let power_state = read_power_state(path);
-    let iommu_group = read_iommu_group(path);
+impl Shared {
+    fn load(source: &dyn SysfsSource) -> Self {
+        let release = source
+            .read("/proc/sys/kernel/osrelease")
+            .map(|r| r.trim().to_string())
+            .unwrap_or_else(|| read_kernel_version(source));
+        Self {
+            interrupts: source.read("/proc/interrupts"),
+            ids: PciIds::load(source),
+            modules: modules::ModuleIndex::load(source, &release),
+            blacklist: modules::Blacklist::load(source),
+            quirks: quirks::QuirkDb::load(source),
+            release,
+        }
+    }
+}
 
-    let memory_regions = enumerate_bars(path);
-    let description = lspci_describe(slot);
+/// Scan a single PCI device
+fn scan_single_device(source: &dyn SysfsSource, shared: &Shared, slot: &str, _verbose: bool) -> Result<PciDevice> {
+    let ids = &shared.ids;
+    let path = format!("{}/{}", PCI_DEVICES, slot);
+    let vendor_id = read_sysfs_hex(source, &path, "vendor");
+    let device_id = read_sysfs_hex(source, &path, "device");
+    let pci_id = format!("{}:{}", vendor_id, device_id);
+    let vendor_num = u16::from_str_radix(&vendor_id, 16).ok();
+    let device_num = u16::from_str_radix(&device_id, 16).ok();
+
+    let class_hex = read_sysfs_hex(source, &path, "class");
+    let class_code = u32::from_str_radix(&class_hex, 16).ok();
+    let device_class = class_code.map_or(DeviceClass::Unknown, DeviceClass::from_code);
+    let class = class_code.map(|code| ids.class_label(code)).unwrap_or_default();
+    let vendor = vendor_num.and_then(|v| ids.vendor(v)).map_or_else(|| vendor_id.clone(), str::to_string);
+    let driver = read_driver(source, &path);
+    let driver_module = read_driver_module(source, &path);
+    let kernel_modules = shared.modules.candidates(&read_sysfs_string(source, &path, "modalias"));
+    let enabled = read_sysfs_string(source, &path, "enable") == "1";
+    let power_state = read_power_state(source, &path);
+    let iommu_group = read_iommu_group(source, &path);
+
+    let memory_regions = enumerate_bars(source, &path);
+    let aer = pcie::read_aer(source, &path);
+    let link = pcie::read_link(source, &path);
+    let sub_vendor = u16::from_str_radix(&read_sysfs_hex(source, &path, "subsystem_vendor"), 16).ok();
+    let sub_device = u16::from_str_radix(&read_sysfs_hex(source, &path, "subsystem_device"), 16).ok();
+    let (description, subsystem) = match (vendor_num, device_num) {
+        (Some(v), Some(d)) => {
+            let subsystem = match (sub_vendor, sub_device) {
+                (Some(sv), Some(sd)) if sv != 0 => ids.subsystem(v, d, sv, sd),
+                _ => None,
+            };
+            (ids.describe(v, d), subsystem)
+        }
+        _ => (String::new(), None),
+    };
 
     let mut issues = Vec::new();
 
@@ -80,20 +162,9 @@ fn scan_single_device(slot: &str, path: &Path, _verbose: bool) -> Result<PciDevi
         });
     }
 
-    // Detect partial bindings (e.g., audio codec on GPU chip)
-    if let Some(ref drv) = driver {
-        if drv == "snd_hda_intel" && pci_id.starts_with("10de:") {
-            issues.push(DeviceIssue {
-                severity: IssueSeverity::Warning,
-                issue_type: IssueType::PartialBinding,
-                description: format!(
-                    "NVIDIA audio codec {} bound to snd_hda_intel - partial GPU binding",
-                    slot
-                ),
-                remediation: "Claim with pci-stub to prevent partial binding".to_string(),
-            });
-        }
-    }
+    // Known-problem hardware (e.g., audio codec on GPU chip partially bound)
+    let target = quirks::QuirkTarget::read(source, slot);
+    issues.extend(shared.quirks.matching(&target, &shared.release).iter().map(|q| q.issue(slot)));
 
     // Detect unmanaged memory: device has BAR regions but no driver
     if driver.is_none() && !memory_regions.is_empty() {
@@ -111,8 +182,19 @@ fn scan_single_device(slot: &str, path: &Path, _verbose: bool) -> Result<PciDevi
         });
     }
 
+    // Detect PCIe errors and downtrained links
+    issues.extend(pcie::issues(slot, device_class, aer.as_ref(), link.as_ref()));
+
+    // Detect drivers bound despite a blacklist, or kept away only by one
+    let mut bound: Vec<&str> = driver.iter().chain(driver_module.iter()).map(String::as_str).collect();
+    bound.dedup();
+    let active = power_state == PowerState::D0 || enabled;
+    if let Some(issue) = modules::blacklist_issue(slot, &bound, &kernel_modules, &shared.modules, &shared.blacklist, active) {
+        issues.push(issue);
+    }
+
     // Detect spurious interrupts
-    if let Some(issue) = check_interrupts(slot, &driver) {
+    if let Some(issue) = shared.interrupts.as_deref().and_then(|content| parse_interrupt_issues(content, slot, &driver)) {
         issues.push(issue);
     }
 
@@ -120,57 +202,22 @@ fn scan_single_device(slot: &str, path: &Path, _verbose: bool) -> Result<PciDevi
         slot: slot.to_string(),
         pci_id,
         description,
-        vendor: vendor_id,
+        vendor,
         class,
+        device_class,
+        subsystem,
         driver,
-        kernel_modules: Vec::new(),
+        kernel_modules,
         power_state,
         enabled,
         iommu_group,
         memory_regions,
+        aer,
+        link,
         issues,
     })
 }
 
-/// Read IOMMU status
-fn scan_iommu() -> Result<IommuStatus> {
-    let groups_path = Path::new("/sys/kernel/iommu_groups");
-    let enabled = groups_path.exists();
-
-    let group_count = if enabled {
-        fs::read_dir(groups_path)?.count() as u32
-    } else {
-        0
-    };
-
-    Ok(IommuStatus {
-        enabled,
-        iommu_type: if enabled {
-            // Check for Intel VT-d or AMD-Vi
-            let dmar = Path::new("/sys/firmware/acpi/tables/DMAR");
-            let ivrs = Path::new("/sys/firmware/acpi/tables/IVRS");
-            if dmar.exists() {
-                Some("Intel VT-d".to_string())
-            } else if ivrs.exists() {
-                Some("AMD-Vi".to_string())
-            } else {
-                Some("Unknown".to_string())
-            }
-        } else {
-            None
-        },
-        group_count,
-        interrupt_remapping: enabled, // Simplified; real check reads DMAR table
-    })
-}
-
-/// Scan for ACPI errors in kernel log
-fn scan_acpi_errors() -> Result<Vec<AcpiError>> {
-    // In real implementation, parse journalctl -k for ACPI errors
-    // For now, return empty
-    Ok(Vec::new())
-}
-
 /// Assess overall system risk
 fn assess_risk(devices: &[PciDevice], acpi_errors: &[AcpiError]) -> RiskLevel {
     let critical = devices.iter()
@@ -210,6 +257,14 @@ pub fn print_status(report: &SystemReport) {
         if report.iommu.enabled { "enabled" } else { "disabled" },
         report.iommu.iommu_type.as_deref().unwrap_or("N/A")
     );
+    if report.iommu.enabled {
+        println!("  Interrupt remapping: {}", if report.iommu.interrupt_remapping { "enabled" } else { "disabled" });
+        if let Some(mode) = report.iommu.dma_mode {
+            println!("  DMA mode: {:?}", mode);
+        }
+    } else if let Some(reason) = &report.iommu.disabled_reason {
+        println!("  {}", reason);
+    }
     println!("PCI devices: {}", report.devices.len());
 
     let issues: Vec<_> = report.devices.iter()
@@ -226,6 +281,69 @@ pub fn print_status(report: &SystemReport) {
             }
         }
     }
+
+    // Errors tied to a device are listed with its issues above
+    let unresolved: Vec<_> = report.acpi_errors.iter().filter(|e| e.related_device.is_none()).collect();
+    if !unresolved.is_empty() {
+        println!("ACPI errors not tied to a PCI device: {}", unresolved.len());
+        for error in unresolved {
+            println!("  {} {} - {}", error.error_code, error.method, error.description);
+        }
+    }
+
+    print_claims(&report.kernel_claims);
+    print_mixed_groups(&report.iommu_groups);
+}
+
+fn print_mixed_groups(groups: &[IommuGroup]) {
+    let mixed: Vec<_> = groups.iter().filter(|g| g.mixed).collect();
+    if mixed.is_empty() {
+        return;
+    }
+    println!("IOMMU groups shared by several devices (claim all or none with vfio-pci):");
+    for group in mixed {
+        let members: Vec<String> = group
+            .members
+            .iter()
+            .filter(|m| !m.bridge)
+            .map(|m| format!("{} ({})", m.slot, m.driver.as_deref().unwrap_or("no driver")))
+            .collect();
+        println!("  Group {}: {}", group.id, members.join(", "));
+    }
+}
+
+fn print_claims(claims: &KernelClaims) {
+    if claims.claims.is_empty() {
+        return;
+    }
+    println!("Null-driver claims (next boot from {}):",
+        claims.next_boot_source.as_deref().unwrap_or("running command line")
+    );
+    for claim in &claims.claims {
+        let state = match claim.state {
+            ClaimState::Active => "active",
+            ClaimState::PendingReboot => "pending reboot",
+            ClaimState::PendingRemoval => "removed at next boot",
+        };
+        let devices = if claim.devices.is_empty() { "no device present".to_string() } else { claim.devices.join(", ") };
+        println!("  {}.ids={} [{}] - {}", claim.stub, claim.id, state, devices);
+    }
+
+    let stale: Vec<_> = claims.stale().map(|c| format!("{}.ids={}", c.stub, c.id)).collect();
+    let duplicates: Vec<_> = claims.duplicates().map(|c| format!("{}.ids={} (x{})", c.stub, c.id, c.occurrences)).collect();
+    if !stale.is_empty() {
+        println!("  Stale (matches no device): {}", stale.join(", "));
+    }
+    if !duplicates.is_empty() {
+        println!("  Duplicated: {}", duplicates.join(", "));
+    }
+    if !stale.is_empty() || !duplicates.is_empty() {
+        println!("  Run `hct plan --cleanup-claims` to remove them");
+    }
+    // The dual strategy does this on purpose: pci-stub binds first
+    for id in claims.double_claimed() {
+        println!("  Note: {} is claimed by both pci-stub and vfio-pci; pci-stub wins", id);
+    }
 }
 
 fn format_text_report(report: &SystemReport) -> String {
@@ -235,6 +353,17 @@ fn format_text_report(report: &SystemReport) -> String {
     out.push_str(&format!("Timestamp: {}\n", report.timestamp));
     out.push_str(&format!("Kernel: {}\n", report.kernel_version));
     out.push_str(&format!("Risk Level: {:?}\n\n", report.risk_level));
+    if !report.topology.is_empty() {
+        out.push_str("PCI Topology\n");
+        for (depth, node) in topology::flatten(&report.topology) {
+            let indent = "  ".repeat(depth);
+            if node.kind == TopologyKind::Domain {
+                out.push_str(&format!("{}{}\n", indent, node.slot));
+            } else {
+                out.push_str(&format!("{}{} ({:?})\n", indent, node.functions.join(" + "), node.kind));
+            }
+        }
+    }
     out
 }
 
@@ -243,14 +372,11 @@ fn format_text_report(report: &SystemReport) -> String {
 /// Each line in `/sys/bus/pci/devices/{slot}/resource` is:
 /// `start_addr end_addr flags` in hex.
 /// Size = end - start + 1. Flags bit 3 = prefetchable, bit 2 = 64-bit.
-fn enumerate_bars(device_path: &Path) -> Vec<MemoryRegion> {
-    let resource_path = device_path.join("resource");
-    let content = match fs::read_to_string(&resource_path) {
-        Ok(c) => c,
-        Err(_) => return Vec::new(),
-    };
-
-    parse_bars(&content)
+fn enumerate_bars(source: &dyn SysfsSource, device_path: &str) -> Vec<MemoryRegion> {
+    match source.read(&format!("{}/resource", device_path)) {
+        Some(content) => parse_bars(&content),
+        None => Vec::new(),
+    }
 }
 
 /// Parse BAR resource file content into MemoryRegion entries
@@ -304,37 +430,6 @@ fn parse_bars(content: &str) -> Vec<MemoryRegion> {
     regions
 }
 
-/// Get human-readable device description from lspci.
-/// Falls back to empty string if lspci is not installed.
-fn lspci_describe(slot: &str) -> String {
-    parse_lspci_output(
-        &Command::new("lspci")
-            .args(["-s", slot, "-q"])
-            .output()
-            .ok()
-            .and_then(|o| String::from_utf8(o.stdout).ok())
-            .unwrap_or_default()
-    )
-}
-
-/// Parse lspci output line for device description
-fn parse_lspci_output(output: &str) -> String {
-    // lspci output: "01:00.0 VGA compatible controller: NVIDIA Corporation GM206 [GeForce GTX 960]"
-    // We want everything after the first ": "
-    output
-        .lines()
-        .next()
-        .and_then(|line| line.split_once(": ").map(|(_, desc)| desc.trim().to_string()))
-        .unwrap_or_default()
-}
-
-/// Check /proc/interrupts for spurious interrupt activity on a device.
-/// A device generating many interrupts without a driver is suspicious.
-fn check_interrupts(slot: &str, driver: &Option<String>) -> Option<DeviceIssue> {
-    let content = fs::read_to_string("/proc/interrupts").ok()?;
-    parse_interrupt_issues(&content, slot, driver)
-}
-
 /// Parse /proc/interrupts content and detect issues for a given slot
 fn parse_interrupt_issues(content: &str, slot: &str, driver: &Option<String>) -> Option<DeviceIssue> {
     // Look for lines containing this device's slot or IRQ info
@@ -375,32 +470,35 @@ fn parse_interrupt_issues(content: &str, slot: &str, driver: &Option<String>) ->
 
 // Sysfs helper functions
 
-fn read_sysfs_string(path: &Path, file: &str) -> String {
-    fs::read_to_string(path.join(file))
+fn read_sysfs_string(source: &dyn SysfsSource, path: &str, file: &str) -> String {
+    source
+        .read(&format!("{}/{}", path, file))
         .unwrap_or_default()
         .trim()
         .to_string()
 }
 
-fn read_sysfs_hex(path: &Path, file: &str) -> String {
-    read_sysfs_string(path, file)
+fn read_sysfs_hex(source: &dyn SysfsSource, path: &str, file: &str) -> String {
+    read_sysfs_string(source, path, file)
         .trim_start_matches("0x")
         .to_string()
 }
 
-fn read_driver(path: &Path) -> Option<String> {
-    let driver_link = path.join("driver");
-    if driver_link.exists() {
-        fs::read_link(&driver_link)
-            .ok()
-            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
-    } else {
-        None
-    }
+fn read_driver(source: &dyn SysfsSource, path: &str) -> Option<String> {
+    source
+        .read_link(&format!("{}/driver", path))
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+}
+
+/// Module providing the bound driver (`driver/module`); absent for built-in drivers.
+fn read_driver_module(source: &dyn SysfsSource, path: &str) -> Option<String> {
+    source
+        .read_link(&format!("{}/driver/module", path))
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
 }
 
-fn read_power_state(path: &Path) -> PowerState {
-    match read_sysfs_string(path, "power_state").as_str() {
+fn read_power_state(source: &dyn SysfsSource, path: &str) -> PowerState {
+    match read_sysfs_string(source, path, "power_state").as_str() {
         "D0" => PowerState::D0,
         "D1" => PowerState::D1,
         "D2" => PowerState::D2,
@@ -410,22 +508,21 @@ fn read_power_state(path: &Path) -> PowerState {
     }
 }
 
-fn read_iommu_group(path: &Path) -> Option<u32> {
-    let iommu_link = path.join("iommu_group");
-    if iommu_link.exists() {
-        fs::read_link(&iommu_link)
-            .ok()
-            .and_then(|p| {
-                p.file_name()
-                    .and_then(|n| n.to_string_lossy().parse::<u32>().ok())
-            })
-    } else {
-        None
-    }
+fn read_iommu_group(source: &dyn SysfsSource, path: &str) -> Option<u32> {
+    source
+        .read_link(&format!("{}/iommu_group", path))
+        .and_then(|p| p.file_name().and_then(|n| n.to_string_lossy().parse::<u32>().ok()))
 }
 
-fn read_kernel_version() -> String {
-    fs::read_to_string("/proc/version")
+/// Whether a scanned slot ("0000:01:00.0") is the one a user named, which
+/// may omit the PCI domain ("01:00.0").
+pub fn slot_matches(scanned: &str, named: &str) -> bool {
+    scanned == named || scanned.split_once(':').is_some_and(|(_, rest)| rest == named)
+}
+
+fn read_kernel_version(source: &dyn SysfsSource) -> String {
+    source
+        .read("/proc/version")
         .unwrap_or_default()
         .split_whitespace()
         .nth(2)
@@ -436,7 +533,6 @@ fn read_kernel_version() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::*;
 
     fn make_device(slot: &str, driver: Option<&str>, power: PowerState, issues: Vec<DeviceIssue>) -> PciDevice {
         PciDevice {
@@ -445,12 +541,16 @@ fn make_device(slot: &str, driver: Option<&str>, power: PowerState, issues: Vec<
             description: String::new(),
             vendor: "10de".to_string(),
             class: "0300".to_string(),
+            device_class: DeviceClass::Display,
+            subsystem: None,
             driver: driver.map(|s| s.to_string()),
             kernel_modules: Vec::new(),
             power_state: power,
             enabled: true,
             iommu_group: None,
             memory_regions: Vec::new(),
+            aer: None,
+            link: None,
             issues,
         }
     }
@@ -476,8 +576,14 @@ fn test_system_report_json_roundtrip() {
                 iommu_type: Some("Intel VT-d".to_string()),
                 group_count: 14,
                 interrupt_remapping: true,
+                units: 1,
+                dma_mode: None,
+                disabled_reason: None,
             },
+            iommu_groups: Vec::new(),
+            topology: Vec::new(),
             acpi_errors: vec![],
+            kernel_claims: KernelClaims::default(),
             risk_level: RiskLevel::Clean,
         };
         let json = serde_json::to_string_pretty(&report).unwrap();
@@ -616,18 +722,14 @@ fn test_enumerate_bars_empty() {
     }
 
     #[test]
-    fn test_lspci_output_parsing() {
-        let output = "01:00.0 VGA compatible controller: NVIDIA Corporation GM206 [GeForce GTX 960]\n";
-        assert_eq!(
-            parse_lspci_output(output),
-            "NVIDIA Corporation GM206 [GeForce GTX 960]"
-        );
-    }
-
-    #[test]
-    fn test_lspci_output_empty() {
-        assert_eq!(parse_lspci_output(""), "");
-        assert_eq!(parse_lspci_output("no colon here"), "");
+    fn test_device_class_from_code() {
+        assert_eq!(DeviceClass::from_code(0x030000), DeviceClass::Display);
+        assert_eq!(DeviceClass::from_code(0x030200), DeviceClass::Display);
+        assert_eq!(DeviceClass::from_code(0x040300), DeviceClass::Audio);
+        assert_eq!(DeviceClass::from_code(0x040000), DeviceClass::Multimedia);
+        assert_eq!(DeviceClass::from_code(0x0c0330), DeviceClass::Usb);
+        assert_eq!(DeviceClass::from_code(0x0c0500), DeviceClass::SerialBus);
+        assert_eq!(DeviceClass::from_code(0x100000), DeviceClass::Other);
     }
 
     #[test]
@@ -677,6 +779,8 @@ fn test_unmanaged_memory_detection() {
             description: String::new(),
             vendor: "10de".to_string(),
             class: "0300".to_string(),
+            device_class: DeviceClass::Display,
+            subsystem: None,
             driver: None,
             kernel_modules: Vec::new(),
             power_state: PowerState::D0,
@@ -689,6 +793,8 @@ fn test_unmanaged_memory_detection() {
                 prefetchable: true,
                 width: 64,
             }],
+            aer: None,
+            link: None,
             issues: Vec::new(),
         };
 
@@ -697,4 +803,175 @@ fn test_unmanaged_memory_detection() {
         assert!(device.driver.is_none());
         assert!(!device.memory_regions.is_empty());
     }
+
+    /// A two-device tree laid out like `/`: a driverless GPU in D0 with a
+    /// BAR, and its audio function bound to snd_hda_intel.
+    fn fixture() -> tempfile::TempDir {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let devices = root.join("sys/bus/pci/devices");
+        let gpu = devices.join("0000:01:00.0");
+        let audio = devices.join("0000:01:00.1");
+        for (path, device) in [(&gpu, "0x13b0"), (&audio, "0x0fbc")] {
+            fs::create_dir_all(path).unwrap();
+            fs::write(path.join("vendor"), "0x10de\n").unwrap();
+            fs::write(path.join("device"), format!("{}\n", device)).unwrap();
+            fs::write(path.join("enable"), "1\n").unwrap();
+            fs::write(path.join("power_state"), "D0\n").unwrap();
+            symlink("../../../../kernel/iommu_groups/1", path.join("iommu_group")).unwrap();
+        }
+        fs::write(gpu.join("class"), "0x030000\n").unwrap();
+        fs::write(gpu.join("subsystem_vendor"), "0x17aa\n").unwrap();
+        fs::write(gpu.join("subsystem_device"), "0x2233\n").unwrap();
+        for (file, value) in [
+            ("current_link_speed", "8.0 GT/s PCIe"),
+            ("max_link_speed", "8.0 GT/s PCIe"),
+            ("current_link_width", "1"),
+            ("max_link_width", "16"),
+            ("aer_dev_correctable", "RxErr 0\nBadTLP 250\nTOTAL_ERR_COR 250"),
+            ("aer_dev_nonfatal", "Undefined 0\nTOTAL_ERR_NONFATAL 0"),
+            ("aer_dev_fatal", "Undefined 0\nTOTAL_ERR_FATAL 0"),
+        ] {
+            fs::write(gpu.join(file), format!("{}\n", value)).unwrap();
+        }
+        fs::write(audio.join("class"), "0x040300\n").unwrap();
+        fs::write(gpu.join("resource"), "0x00000000de000000 0x00000000deffffff 0x0000000000040200\n").unwrap();
+        fs::create_dir_all(root.join("sys/bus/pci/drivers/snd_hda_intel")).unwrap();
+        symlink("../../../bus/pci/drivers/snd_hda_intel", audio.join("driver")).unwrap();
+        fs::create_dir_all(root.join("sys/kernel/iommu_groups/1/devices")).unwrap();
+        for slot in ["0000:01:00.0", "0000:01:00.1"] {
+            symlink(format!("../../../../bus/pci/devices/{}", slot), root.join("sys/kernel/iommu_groups/1/devices").join(slot)).unwrap();
+        }
+        fs::create_dir_all(root.join("proc")).unwrap();
+        fs::write(root.join("proc/version"), "Linux version 6.8.0-test (fixture)\n").unwrap();
+        fs::write(root.join("proc/interrupts"), "           CPU0\n  0:         42   IO-APIC    2-edge      timer\n").unwrap();
+        fs::write(gpu.join("modalias"), "pci:v000010DEd000013B0sv000017AAsd00002233bc03sc00i00\n").unwrap();
+        fs::write(audio.join("modalias"), "pci:v000010DEd00000FBCsv000017AAsd00002233bc04sc03i00\n").unwrap();
+        fs::create_dir_all(root.join("proc/sys/kernel")).unwrap();
+        fs::write(root.join("proc/sys/kernel/osrelease"), "6.8.0-test\n").unwrap();
+        fs::write(root.join("proc/cmdline"), "root=/dev/sda1 modprobe.blacklist=snd-hda-intel pci-stub.ids=1002:73bf\n").unwrap();
+        fs::create_dir_all(root.join("etc/kernel")).unwrap();
+        fs::write(root.join("etc/kernel/cmdline"), "root=/dev/sda1 pci-stub.ids=10de:13b0,1002:73bf\n").unwrap();
+        let modules = root.join("lib/modules/6.8.0-test");
+        fs::create_dir_all(&modules).unwrap();
+        fs::write(
+            modules.join("modules.alias"),
+            "alias pci:v000010DEd*sv*sd*bc03sc*i* nouveau\nalias pci:v*d*sv*sd*bc04sc03i* snd_hda_intel\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("etc/modprobe.d")).unwrap();
+        fs::write(root.join("etc/modprobe.d/gpu.conf"), "blacklist nouveau\n").unwrap();
+        let node = root.join("sys/devices/LNXSYSTM:00/device:10");
+        fs::create_dir_all(&node).unwrap();
+        fs::write(node.join("path"), "\\_SB_.PCI0.GPP0.PEGP\n").unwrap();
+        symlink("../../../../devices/LNXSYSTM:00/device:10", gpu.join("firmware_node")).unwrap();
+        fs::write(
+            root.join("kernel.log"),
+            "ACPI Error: Aborting method \\_SB.PCI0.GPP0.PEGP._PS0 due to previous error (AE_AML_LOOP_TIMEOUT) (20230628/psparse-529)\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_scan_fixture_tree() {
+        let dir = fixture();
+        let report = scan_source(&RootedFs::new(dir.path()).unwrap(), false).unwrap();
+
+        assert_eq!(report.kernel_version, "6.8.0-test");
+        assert_eq!(report.devices.len(), 2);
+        assert_eq!(report.iommu.group_count, 1);
+        // GPU and its audio function are one physical device
+        assert_eq!(report.iommu_groups[0].members.len(), 2);
+        assert!(!report.iommu_groups[0].mixed);
+        // No /sys/devices links in the fixture: both functions sit on the
+        // root bus of the domain their slots name, as one device
+        assert_eq!(report.topology[0].slot, "pci0000:01");
+        assert_eq!(report.topology[0].children[0].functions, vec!["0000:01:00.0", "0000:01:00.1"]);
+
+        let gpu = &report.devices[0];
+        assert_eq!(gpu.pci_id, "10de:13b0");
+        // Names come from the bundled pci.ids: the fixture has no system copy
+        assert_eq!(gpu.vendor, "NVIDIA Corporation");
+        assert_eq!(gpu.description, "NVIDIA Corporation GM107GLM [Quadro M2000M]");
+        assert_eq!(gpu.class, "VGA compatible controller");
+        assert_eq!(gpu.device_class, DeviceClass::Display);
+        assert_eq!(gpu.subsystem.as_deref(), Some("Lenovo Device 2233"));
+        assert_eq!(gpu.iommu_group, Some(1));
+        assert!(gpu.issues.iter().any(|i| matches!(i.issue_type, IssueType::ZombieDevice)));
+        assert!(gpu.issues.iter().any(|i| matches!(i.issue_type, IssueType::UnmanagedMemory)));
+        assert!(gpu.issues.iter().any(|i| matches!(i.issue_type, IssueType::AcpiError)));
+        assert!(gpu.issues.iter().any(|i| matches!(i.issue_type, IssueType::LinkDegraded)));
+        assert!(gpu.issues.iter().any(|i| matches!(i.issue_type, IssueType::AerErrors)));
+        assert_eq!(gpu.aer.as_ref().map(|a| a.correctable), Some(250));
+        assert_eq!(gpu.link.as_ref().map(|l| l.current_width), Some(1));
+        assert!(report.devices[1].link.is_none());
+        assert_eq!(gpu.kernel_modules, vec!["nouveau"]);
+        let blacklisted = gpu.issues.iter().find(|i| matches!(i.issue_type, IssueType::BlacklistedButActive)).unwrap();
+        assert_eq!(blacklisted.severity, IssueSeverity::Warning);
+
+        assert_eq!(report.acpi_errors.len(), 1);
+        assert_eq!(report.acpi_errors[0].method, "_SB.PCI0.GPP0.PEGP._PS0");
+        assert_eq!(report.acpi_errors[0].error_code, "AE_AML_LOOP_TIMEOUT");
+        assert_eq!(report.acpi_errors[0].related_device.as_deref(), Some("0000:01:00.0"));
+
+        let audio = &report.devices[1];
+        assert_eq!(audio.driver.as_deref(), Some("snd_hda_intel"));
+        assert_eq!(audio.device_class, DeviceClass::Audio);
+        assert_eq!(audio.kernel_modules, vec!["snd_hda_intel"]);
+        let blacklisted = audio.issues.iter().find(|i| matches!(i.issue_type, IssueType::BlacklistedButActive)).unwrap();
+        assert_eq!(blacklisted.severity, IssueSeverity::High);
+        assert!(audio.issues.iter().any(|i| matches!(i.issue_type, IssueType::PartialBinding)));
+
+        let claims = &report.kernel_claims;
+        assert_eq!(claims.next_boot_source.as_deref(), Some("/etc/kernel/cmdline"));
+        assert_eq!(claims.claims.len(), 2);
+        assert_eq!(claims.claims[0].state, ClaimState::Active);
+        assert_eq!(claims.claims[1].state, ClaimState::PendingReboot);
+        assert_eq!(claims.claims[1].devices, vec!["0000:01:00.0"]);
+        assert_eq!(claims.stale().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["1002:73bf"]);
+    }
+
+    #[test]
+    fn test_capture_roundtrip_is_identical() {
+        let dir = fixture();
+        let live = RootedFs::new(dir.path()).unwrap();
+        let recorder = source::Recorder::new(&live);
+        let mut direct = scan_source(&recorder, false).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let archive = out.path().join("snapshot.tar.gz");
+        recorder.write_snapshot(&archive, "2026-01-01T00:00:00+00:00", "fixture-host", Some("fixture-user")).unwrap();
+
+        let snapshot = source::Snapshot::open(&archive).unwrap();
+        assert_eq!(snapshot.info().hostname, "fixture-host");
+        assert_eq!(snapshot.info().username.as_deref(), Some("fixture-user"));
+        let replayed = scan_source(&snapshot, false).unwrap();
+        direct.timestamp = replayed.timestamp.clone();
+        assert_eq!(replayed.timestamp, "2026-01-01T00:00:00+00:00");
+        assert_eq!(
+            serde_json::to_string(&direct).unwrap(),
+            serde_json::to_string(&replayed).unwrap()
+        );
+
+        // Same reads, same archive
+        let again = out.path().join("again.tar.gz");
+        recorder.write_snapshot(&again, "2026-01-01T00:00:00+00:00", "fixture-host", Some("fixture-user")).unwrap();
+        assert_eq!(std::fs::read(&archive).unwrap(), std::fs::read(&again).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_refuses_symlink_escape() {
+        let dir = fixture();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), "host data").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), dir.path().join("proc/escape")).unwrap();
+
+        let tree = RootedFs::new(dir.path()).unwrap();
+        assert!(tree.read("/proc/escape").is_none());
+        assert!(tree.read("/proc/version").is_some());
+    }
 }
@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Kernel module resolution and blacklists
+//!
+//! Resolves each device's `modalias` against the running kernel's
+//! `modules.alias` (and `modules.builtin.alias`) to find the drivers that
+//! could claim it, and collects every blacklist that can keep one of them
+//! away: `blacklist` and `install ... /bin/false` lines in modprobe.d, and
+//! `modprobe.blacklist=` / `module_blacklist=` on the kernel command line.
+
+use super::cmdline::KernelCmdline;
+use super::source::SysfsSource;
+use crate::types::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// modprobe.d directories, highest precedence first. A file name in an
+/// earlier directory hides the same name in later ones, as kmod does.
+const MODPROBE_DIRS: &[&str] = &["/etc/modprobe.d", "/run/modprobe.d", "/usr/lib/modprobe.d", "/lib/modprobe.d"];
+
+/// Where kernels keep their module indexes.
+const MODULE_ROOTS: &[&str] = &["/lib/modules", "/usr/lib/modules"];
+
+/// Commands that make an `install` line a hard blacklist.
+const NO_OP_COMMANDS: &[&str] = &["/bin/false", "/bin/true", "/usr/bin/false", "/usr/bin/true"];
+
+/// Module names treat `-` and `_` as the same character.
+pub fn normalize_module(name: &str) -> String {
+    name.trim().replace('-', "_")
+}
+
+/// PCI aliases of the running kernel.
+#[derive(Debug, Default)]
+pub struct ModuleIndex {
+    /// (modalias glob, module) in file order
+    aliases: Vec<(String, String)>,
+    builtin: BTreeSet<String>,
+}
+
+impl ModuleIndex {
+    /// Load the alias files for kernel `release`; empty if none are found.
+    pub fn load(source: &dyn SysfsSource, release: &str) -> Self {
+        let mut index = Self::default();
+        let Some(dir) = MODULE_ROOTS
+            .iter()
+            .map(|root| format!("{}/{}", root, release))
+            .find(|dir| source.exists(&format!("{}/modules.alias", dir)))
+        else {
+            return index;
+        };
+        if let Some(content) = source.read(&format!("{}/modules.alias", dir)) {
+            index.add_aliases(&content, false);
+        }
+        if let Some(content) = source.read(&format!("{}/modules.builtin.alias", dir)) {
+            index.add_aliases(&content, true);
+        }
+        index
+    }
+
+    /// Add `alias pci:... module` lines; other buses are ignored.
+    pub fn add_aliases(&mut self, content: &str, builtin: bool) {
+        for line in content.lines() {
+            let mut parts = line.split_whitespace();
+            let (Some("alias"), Some(pattern), Some(module)) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+            if !pattern.starts_with("pci:") {
+                continue;
+            }
+            let module = normalize_module(module);
+            if builtin {
+                self.builtin.insert(module.clone());
+            }
+            self.aliases.push((pattern.to_string(), module));
+        }
+    }
+
+    /// Modules whose aliases match `modalias`, in alias-file order.
+    pub fn candidates(&self, modalias: &str) -> Vec<String> {
+        let mut modules: Vec<String> = Vec::new();
+        for (pattern, module) in &self.aliases {
+            if !modules.contains(module) && glob_match(pattern, modalias) {
+                modules.push(module.clone());
+            }
+        }
+        modules
+    }
+
+    pub fn is_builtin(&self, module: &str) -> bool {
+        self.builtin.contains(module)
+    }
+}
+
+/// Blacklisted modules and where each was blacklisted.
+#[derive(Debug, Default)]
+pub struct Blacklist {
+    modules: BTreeMap<String, String>,
+}
+
+impl Blacklist {
+    /// Collect the modprobe.d blacklists and the command line's.
+    pub fn load(source: &dyn SysfsSource) -> Self {
+        let mut blacklist = Self::default();
+        let mut seen_files = BTreeSet::new();
+        for dir in MODPROBE_DIRS {
+            for name in source.list(dir).unwrap_or_default() {
+                if !name.ends_with(".conf") || !seen_files.insert(name.clone()) {
+                    continue;
+                }
+                let path = format!("{}/{}", dir, name);
+                if let Some(content) = source.read(&path) {
+                    blacklist.add_conf(&content, &path);
+                }
+            }
+        }
+        if let Some(cmdline) = source.read("/proc/cmdline") {
+            blacklist.add_cmdline(&cmdline);
+        }
+        blacklist
+    }
+
+    /// Add the blacklists in one modprobe.d file.
+    pub fn add_conf(&mut self, content: &str, origin: &str) {
+        for line in join_continuations(content) {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("blacklist"), Some(module)) => self.insert(module, origin),
+                (Some("install"), Some(module)) if words.next().is_some_and(|cmd| NO_OP_COMMANDS.contains(&cmd)) => {
+                    self.insert(module, origin)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Add `modprobe.blacklist=` and `module_blacklist=` from a kernel command line.
+    pub fn add_cmdline(&mut self, cmdline: &str) {
+        let cmdline = KernelCmdline::parse(cmdline);
+        for key in ["modprobe.blacklist", "module_blacklist"] {
+            for module in cmdline.values(key).flat_map(|v| v.split(',')).filter(|m| !m.is_empty()) {
+                self.insert(module, &format!("kernel command line ({})", key));
+            }
+        }
+    }
+
+    fn insert(&mut self, module: &str, origin: &str) {
+        self.modules.entry(normalize_module(module)).or_insert_with(|| origin.to_string());
+    }
+
+    /// Where `module` was blacklisted, if it was.
+    pub fn origin(&self, module: &str) -> Option<&str> {
+        self.modules.get(&normalize_module(module)).map(String::as_str)
+    }
+}
+
+/// Lines of a modprobe.d file with comments dropped and `\` continuations joined.
+fn join_continuations(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if current.is_empty() && line.starts_with('#') {
+            continue;
+        }
+        match line.strip_suffix('\\') {
+            Some(head) => {
+                current.push_str(head);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// The BlacklistedButActive issue for a device, if any: a blacklisted module
+/// is bound anyway, or every loadable candidate is blacklisted and the
+/// powered device has been left without a driver.
+pub fn blacklist_issue(
+    slot: &str,
+    bound: &[&str],
+    candidates: &[String],
+    index: &ModuleIndex,
+    blacklist: &Blacklist,
+    active: bool,
+) -> Option<DeviceIssue> {
+    if let Some((module, origin)) = bound.iter().find_map(|m| Some((*m, blacklist.origin(m)?))) {
+        let how = if index.is_builtin(&normalize_module(module)) {
+            "it is built into the kernel, so the blacklist cannot stop it"
+        } else {
+            "it was loaded anyway (by hand, a dependency or the initramfs)"
+        };
+        return Some(DeviceIssue {
+            severity: IssueSeverity::High,
+            issue_type: IssueType::BlacklistedButActive,
+            description: format!("Device {} is bound to {}, blacklisted in {}, but {}", slot, module, origin, how),
+            remediation: "Rebuild the initramfs, or bind the device to pci-stub/vfio-pci before the driver loads".to_string(),
+        });
+    }
+
+    if !bound.is_empty() || !active {
+        return None;
+    }
+    let loadable: Vec<&String> = candidates.iter().filter(|m| !index.is_builtin(m)).collect();
+    if loadable.is_empty() || loadable.iter().any(|m| blacklist.origin(m).is_none()) {
+        return None;
+    }
+    let reasons: Vec<String> = loadable
+        .iter()
+        .map(|m| format!("{} ({})", m, blacklist.origin(m).unwrap_or_default()))
+        .collect();
+    Some(DeviceIssue {
+        severity: IssueSeverity::Warning,
+        issue_type: IssueType::BlacklistedButActive,
+        description: format!(
+            "Device {} is powered with no driver because every driver for it is blacklisted: {}",
+            slot,
+            reasons.join(", ")
+        ),
+        remediation: "Claim it with pci-stub or vfio-pci so it is held in a known state, or power it off".to_string(),
+    })
+}
+
+/// fnmatch-style match supporting `*`, `?` and `[...]`, as kmod uses for aliases.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Where to resume after the last `*`: (pattern index after it, text index)
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                star = Some((pi + 1, ti));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_class(&p[pi..], t[ti]),
+            Some(&c) if c == t[ti] => Some(1),
+            _ => None,
+        };
+        match step {
+            Some(width) => {
+                pi += width;
+                ti += 1;
+            }
+            None => match star {
+                Some((star_pi, star_ti)) => {
+                    pi = star_pi;
+                    ti = star_ti + 1;
+                    star = Some((star_pi, star_ti + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the bracket expression at the start of `p`; returns the
+/// expression's length on a match.
+fn match_class(p: &[char], c: char) -> Option<usize> {
+    let end = p.iter().skip(1).position(|&x| x == ']')? + 1;
+    let body = &p[1..end];
+    let (negated, body) = match body.first() {
+        Some('!') | Some('^') => (true, &body[1..]),
+        _ => (false, body),
+    };
+    let mut matched = false;
+    let mut i = 0;
+    while i < body.len() {
+        if i + 2 < body.len() && body[i + 1] == '-' {
+            matched |= body[i] <= c && c <= body[i + 2];
+            i += 3;
+        } else {
+            matched |= body[i] == c;
+            i += 1;
+        }
+    }
+    (matched != negated).then_some(end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODALIAS: &str = "pci:v000010DEd000013B0sv000017AAsd00002233bc03sc00i00";
+
+    fn index() -> ModuleIndex {
+        let mut index = ModuleIndex::default();
+        index.add_aliases(
+            "# Aliases extracted from modules themselves.\n\
+             alias pci:v000010DEd*sv*sd*bc03sc*i* nouveau\n\
+             alias pci:v000010DEd000013B0sv*sd*bc*sc*i* nvidia\n\
+             alias pci:v00008086d*sv*sd*bc0Csc03i30* xhci_pci\n\
+             alias usb:v*p*d*dc*dsc*dp*ic09isc*ip*in* usbcore\n",
+            false,
+        );
+        index.add_aliases("alias pci:v*d*sv*sd*bc0[0-9]sc*i* pci-stub\n", true);
+        index
+    }
+
+    #[test]
+    fn test_glob_and_candidates() {
+        assert!(glob_match("pci:v000010DEd*sv*sd*bc03sc*i*", MODALIAS));
+        assert!(!glob_match("pci:v00008086d*", MODALIAS));
+        assert!(glob_match("a[!b]c", "axc"));
+        assert!(!glob_match("a[!b]c", "abc"));
+
+        assert_eq!(index().candidates(MODALIAS), vec!["nouveau", "nvidia", "pci_stub"]);
+        assert!(index().candidates("pci:v00001AF4d00001041sv00001AF4sd00001041bc02sc00i00").contains(&"pci_stub".to_string()));
+    }
+
+    #[test]
+    fn test_blacklist_sources() {
+        let mut blacklist = Blacklist::default();
+        blacklist.add_conf(
+            "# keep the GPU quiet\nblacklist nouveau\ninstall nvidia \\\n    /bin/false\noptions snd-hda-intel model=auto\n",
+            "/etc/modprobe.d/gpu.conf",
+        );
+        blacklist.add_cmdline("BOOT_IMAGE=/vmlinuz root=/dev/sda1 modprobe.blacklist=snd-hda-intel,radeon quiet");
+
+        assert_eq!(blacklist.origin("nouveau"), Some("/etc/modprobe.d/gpu.conf"));
+        assert_eq!(blacklist.origin("nvidia"), Some("/etc/modprobe.d/gpu.conf"));
+        assert_eq!(blacklist.origin("snd_hda_intel"), Some("kernel command line (modprobe.blacklist)"));
+        assert_eq!(blacklist.origin("radeon"), Some("kernel command line (modprobe.blacklist)"));
+        assert_eq!(blacklist.origin("xhci_pci"), None);
+    }
+
+    #[test]
+    fn test_blacklist_issue() {
+        let index = index();
+        let candidates = index.candidates(MODALIAS);
+        let mut blacklist = Blacklist::default();
+        blacklist.add_conf("blacklist nouveau\nblacklist nvidia\n", "/etc/modprobe.d/gpu.conf");
+
+        // Bound to a blacklisted module
+        let issue = blacklist_issue("0000:01:00.0", &["nouveau"], &candidates, &index, &blacklist, true).unwrap();
+        assert_eq!(issue.severity, IssueSeverity::High);
+
+        // Driverless and powered only because both loadable drivers are blacklisted
+        let issue = blacklist_issue("0000:01:00.0", &[], &candidates, &index, &blacklist, true).unwrap();
+        assert_eq!(issue.severity, IssueSeverity::Warning);
+        assert!(issue.description.contains("nouveau") && issue.description.contains("nvidia"));
+
+        // Powered off: nothing active to report
+        assert!(blacklist_issue("0000:01:00.0", &[], &candidates, &index, &blacklist, false).is_none());
+
+        // A loadable driver remains, so the blacklist is not why it is driverless
+        let mut partial = Blacklist::default();
+        partial.add_conf("blacklist nouveau\n", "/etc/modprobe.d/gpu.conf");
+        assert!(blacklist_issue("0000:01:00.0", &[], &candidates, &index, &partial, true).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! PCIe link health
+//!
+//! Reads the AER counters (`aer_dev_correctable`, `aer_dev_nonfatal`,
+//! `aer_dev_fatal`) and the negotiated link (`current_link_*` against
+//! `max_link_*`) sysfs exposes for each PCIe function. Flaky risers and
+//! GPUs trained at x1 show up here long before they show up as crashes.
+
+use super::source::SysfsSource;
+use crate::types::*;
+
+/// Correctable errors are routine in small numbers; this many is a link
+/// replaying enough to matter.
+const CORRECTABLE_WARNING: u64 = 100;
+/// Correctable errors at this level mean the link is on its way out.
+const CORRECTABLE_HIGH: u64 = 10_000;
+
+/// AER totals, `None` if the device has no AER capability (or the kernel
+/// was built without it).
+pub fn read_aer(source: &dyn SysfsSource, device_path: &str) -> Option<AerCounters> {
+    let total = |file: &str| source.read(&format!("{}/{}", device_path, file)).map(|c| parse_aer_total(&c));
+    let correctable = total("aer_dev_correctable");
+    let nonfatal = total("aer_dev_nonfatal");
+    let fatal = total("aer_dev_fatal");
+    if correctable.is_none() && nonfatal.is_none() && fatal.is_none() {
+        return None;
+    }
+    Some(AerCounters {
+        correctable: correctable.unwrap_or(0),
+        nonfatal: nonfatal.unwrap_or(0),
+        fatal: fatal.unwrap_or(0),
+    })
+}
+
+/// Total from an `aer_dev_*` file: the `TOTAL_ERR_*` line, or the sum of the
+/// per-error lines on kernels that don't print one.
+fn parse_aer_total(content: &str) -> u64 {
+    let counts: Vec<(&str, u64)> = content
+        .lines()
+        .filter_map(|line| {
+            let (name, count) = line.split_once(' ')?;
+            Some((name, count.trim().parse().ok()?))
+        })
+        .collect();
+    counts
+        .iter()
+        .find(|(name, _)| name.starts_with("TOTAL_ERR_"))
+        .map_or_else(|| counts.iter().map(|(_, n)| n).sum(), |(_, n)| *n)
+}
+
+/// Link state, `None` for conventional PCI devices and unreadable links.
+pub fn read_link(source: &dyn SysfsSource, device_path: &str) -> Option<PcieLink> {
+    let read = |file: &str| source.read(&format!("{}/{}", device_path, file));
+    Some(PcieLink {
+        current_speed: parse_link_speed(&read("current_link_speed")?),
+        max_speed: parse_link_speed(&read("max_link_speed")?),
+        current_width: read("current_link_width")?.trim().parse().ok()?,
+        max_width: read("max_link_width")?.trim().parse().ok()?,
+    })
+}
+
+/// "8.0 GT/s PCIe" -> 8.0; "Unknown" -> 0.0.
+fn parse_link_speed(content: &str) -> f32 {
+    content
+        .split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Issues for a device's AER counters and link state.
+pub fn issues(slot: &str, class: DeviceClass, aer: Option<&AerCounters>, link: Option<&PcieLink>) -> Vec<DeviceIssue> {
+    let mut issues = Vec::new();
+
+    if let Some(aer) = aer {
+        let severity = if aer.fatal > 0 {
+            Some(IssueSeverity::Critical)
+        } else if aer.nonfatal > 0 || aer.correctable >= CORRECTABLE_HIGH {
+            Some(IssueSeverity::High)
+        } else if aer.correctable >= CORRECTABLE_WARNING {
+            Some(IssueSeverity::Warning)
+        } else {
+            None
+        };
+        if let Some(severity) = severity {
+            issues.push(DeviceIssue {
+                severity,
+                issue_type: IssueType::AerErrors,
+                description: format!(
+                    "Device {} reported PCIe errors since boot: {} fatal, {} non-fatal, {} correctable",
+                    slot, aer.fatal, aer.nonfatal, aer.correctable
+                ),
+                remediation: "Reseat the card, replace the riser or move it to another slot; \
+                              power it off if errors continue"
+                    .to_string(),
+            });
+        }
+    }
+
+    if let Some(link) = link {
+        if link.width_degraded() {
+            // A GPU or accelerator at a fraction of its lanes stalls and times out
+            let severity = match class {
+                DeviceClass::Display | DeviceClass::Accelerator => IssueSeverity::High,
+                _ if u16::from(link.current_width) * 4 <= u16::from(link.max_width) => IssueSeverity::High,
+                _ => IssueSeverity::Warning,
+            };
+            issues.push(DeviceIssue {
+                severity,
+                issue_type: IssueType::LinkDegraded,
+                description: format!(
+                    "Device {} PCIe link trained at x{} of x{} lanes",
+                    slot, link.current_width, link.max_width
+                ),
+                remediation: "Check the riser and slot (or the slot's lane sharing in the BIOS); \
+                              reseat the card"
+                    .to_string(),
+            });
+        } else if link.speed_degraded() && !matches!(class, DeviceClass::Display | DeviceClass::Bridge) {
+            // GPUs and the ports above them drop link speed at idle to save power
+            issues.push(DeviceIssue {
+                severity: IssueSeverity::Warning,
+                issue_type: IssueType::LinkDegraded,
+                description: format!(
+                    "Device {} PCIe link running at {} GT/s of {} GT/s",
+                    slot, link.current_speed, link.max_speed
+                ),
+                remediation: "Check the riser and slot; a link that cannot hold full speed is marginal".to_string(),
+            });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(current_speed: f32, max_speed: f32, current_width: u8, max_width: u8) -> PcieLink {
+        PcieLink { current_speed, max_speed, current_width, max_width }
+    }
+
+    #[test]
+    fn test_parse_aer_and_link_speed() {
+        let correctable = "RxErr 3\nBadTLP 120\nBadDLLP 0\nRollover 0\nTimeout 0\nNonFatalErr 0\nCorrIntErr 0\nHeaderOF 0\nTOTAL_ERR_COR 123\n";
+        assert_eq!(parse_aer_total(correctable), 123);
+        assert_eq!(parse_aer_total("Undefined 0\nDLP 2\nSDES 1\n"), 3);
+        assert_eq!(parse_link_speed("8.0 GT/s PCIe\n"), 8.0);
+        assert_eq!(parse_link_speed("Unknown"), 0.0);
+    }
+
+    #[test]
+    fn test_aer_severity() {
+        let severity = |aer: AerCounters| issues("0000:01:00.0", DeviceClass::Display, Some(&aer), None).first().map(|i| i.severity.clone());
+        assert_eq!(severity(AerCounters { correctable: 5, ..Default::default() }), None);
+        assert_eq!(severity(AerCounters { correctable: 500, ..Default::default() }), Some(IssueSeverity::Warning));
+        assert_eq!(severity(AerCounters { nonfatal: 1, ..Default::default() }), Some(IssueSeverity::High));
+        assert_eq!(severity(AerCounters { fatal: 1, ..Default::default() }), Some(IssueSeverity::Critical));
+    }
+
+    #[test]
+    fn test_link_downtraining() {
+        // GPU at x1 of x16
+        let gpu = issues("0000:01:00.0", DeviceClass::Display, None, Some(&link(8.0, 8.0, 1, 16)));
+        assert_eq!(gpu.len(), 1);
+        assert_eq!(gpu[0].severity, IssueSeverity::High);
+        assert!(matches!(gpu[0].issue_type, IssueType::LinkDegraded));
+
+        // NIC at x2 of x4
+        let nic = issues("0000:02:00.0", DeviceClass::Network, None, Some(&link(8.0, 8.0, 2, 4)));
+        assert_eq!(nic[0].severity, IssueSeverity::Warning);
+
+        // Idle GPU dropping to 2.5 GT/s is power management, an NVMe drive doing it is not
+        assert!(issues("0000:01:00.0", DeviceClass::Display, None, Some(&link(2.5, 16.0, 16, 16))).is_empty());
+        assert_eq!(issues("0000:03:00.0", DeviceClass::Storage, None, Some(&link(2.5, 16.0, 4, 4))).len(), 1);
+
+        // Empty hotplug port: link down, not degraded
+        assert!(issues("0000:00:1c.0", DeviceClass::Bridge, None, Some(&link(0.0, 8.0, 0, 1))).is_empty());
+    }
+}
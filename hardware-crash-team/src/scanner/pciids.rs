@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! pci.ids database
+//!
+//! Resolves vendor, device, subsystem and class names without `lspci`, so
+//! scans work in containers and initramfs images that lack pciutils. The
+//! system database is read through the scan's [`SysfsSource`] (and therefore
+//! lands in snapshots); a trimmed copy is bundled for systems without one.
+
+use super::source::SysfsSource;
+use std::collections::HashMap;
+
+/// Where distributions install pci.ids (hwdata, pciutils).
+const SYSTEM_PATHS: &[&str] = &[
+    "/usr/share/hwdata/pci.ids",
+    "/usr/share/misc/pci.ids",
+    "/usr/share/pci.ids",
+    "/usr/local/share/pci.ids",
+];
+
+const BUNDLED: &str = include_str!("../../data/pci.ids");
+
+#[derive(Debug, Default)]
+struct Vendor {
+    name: String,
+    devices: HashMap<u16, Device>,
+}
+
+#[derive(Debug, Default)]
+struct Device {
+    name: String,
+    subsystems: HashMap<(u16, u16), String>,
+}
+
+#[derive(Debug, Default)]
+struct Class {
+    name: String,
+    subclasses: HashMap<u8, Subclass>,
+}
+
+#[derive(Debug, Default)]
+struct Subclass {
+    name: String,
+    prog_ifs: HashMap<u8, String>,
+}
+
+/// Parsed pci.ids.
+#[derive(Debug, Default)]
+pub struct PciIds {
+    vendors: HashMap<u16, Vendor>,
+    classes: HashMap<u8, Class>,
+}
+
+impl PciIds {
+    /// The first system database `source` has, else the bundled copy.
+    pub fn load(source: &dyn SysfsSource) -> Self {
+        SYSTEM_PATHS
+            .iter()
+            .find_map(|path| source.read(path))
+            .map(|content| Self::parse(&content))
+            .unwrap_or_else(Self::bundled)
+    }
+
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED)
+    }
+
+    /// Parse pci.ids text. Malformed lines are skipped, as `lspci` does.
+    pub fn parse(content: &str) -> Self {
+        let mut ids = Self::default();
+        // Current vendor/device or class/subclass the indented lines belong to
+        let mut vendor: Option<u16> = None;
+        let mut device: Option<u16> = None;
+        let mut class: Option<u8> = None;
+        let mut subclass: Option<u8> = None;
+
+        for line in content.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let depth = line.chars().take_while(|&c| c == '\t').count();
+            let body = &line[depth..];
+
+            match depth {
+                0 if body.starts_with("C ") => {
+                    vendor = None;
+                    let Some((id, name)) = split_id(&body[2..]) else { continue };
+                    class = u8::from_str_radix(id, 16).ok();
+                    subclass = None;
+                    if let Some(c) = class {
+                        ids.classes.entry(c).or_default().name = name.to_string();
+                    }
+                }
+                0 => {
+                    class = None;
+                    let Some((id, name)) = split_id(body) else { continue };
+                    // Other top-level lists (device types, languages) follow the
+                    // classes; their ids are not four hex digits
+                    vendor = u16::from_str_radix(id, 16).ok().filter(|_| id.len() == 4);
+                    device = None;
+                    if let Some(v) = vendor {
+                        ids.vendors.entry(v).or_default().name = name.to_string();
+                    }
+                }
+                1 => {
+                    let Some((id, name)) = split_id(body) else { continue };
+                    if let Some(v) = vendor {
+                        device = u16::from_str_radix(id, 16).ok();
+                        if let Some(d) = device {
+                            let entry = ids.vendors.entry(v).or_default().devices.entry(d).or_default();
+                            entry.name = name.to_string();
+                        }
+                    } else if let Some(c) = class {
+                        subclass = u8::from_str_radix(id, 16).ok();
+                        if let Some(s) = subclass {
+                            let entry = ids.classes.entry(c).or_default().subclasses.entry(s).or_default();
+                            entry.name = name.to_string();
+                        }
+                    }
+                }
+                2 => {
+                    if let (Some(v), Some(d)) = (vendor, device) {
+                        let Some((sub_ids, name)) = split_id(body) else { continue };
+                        let Some((sv, sd)) = sub_ids.split_once(' ') else { continue };
+                        if let (Ok(sv), Ok(sd)) = (u16::from_str_radix(sv, 16), u16::from_str_radix(sd.trim(), 16)) {
+                            if let Some(device) = ids.vendors.get_mut(&v).and_then(|v| v.devices.get_mut(&d)) {
+                                device.subsystems.insert((sv, sd), name.to_string());
+                            }
+                        }
+                    } else if let (Some(c), Some(s)) = (class, subclass) {
+                        let Some((id, name)) = split_id(body) else { continue };
+                        if let (Ok(p), Some(sub)) = (
+                            u8::from_str_radix(id, 16),
+                            ids.classes.get_mut(&c).and_then(|c| c.subclasses.get_mut(&s)),
+                        ) {
+                            sub.prog_ifs.insert(p, name.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        ids
+    }
+
+    pub fn vendor(&self, vendor: u16) -> Option<&str> {
+        self.vendors.get(&vendor).map(|v| v.name.as_str())
+    }
+
+    pub fn device(&self, vendor: u16, device: u16) -> Option<&str> {
+        self.vendors.get(&vendor)?.devices.get(&device).map(|d| d.name.as_str())
+    }
+
+    /// Subsystem name: the specific entry under the device, else the
+    /// subsystem vendor's name.
+    pub fn subsystem(&self, vendor: u16, device: u16, sub_vendor: u16, sub_device: u16) -> Option<String> {
+        if let Some(name) = self
+            .vendors
+            .get(&vendor)
+            .and_then(|v| v.devices.get(&device))
+            .and_then(|d| d.subsystems.get(&(sub_vendor, sub_device)))
+        {
+            return Some(name.clone());
+        }
+        self.vendor(sub_vendor).map(|v| format!("{} Device {:04x}", v, sub_device))
+    }
+
+    /// Most specific name for a 24-bit class code: the subclass name (as
+    /// `lspci` prints it), else the base class name.
+    pub fn class(&self, code: u32) -> Option<&str> {
+        let class = self.classes.get(&((code >> 16) as u8))?;
+        Some(
+            class
+                .subclasses
+                .get(&((code >> 8) as u8))
+                .map_or(class.name.as_str(), |s| s.name.as_str()),
+        )
+    }
+
+    /// Name of the programming interface, e.g. "XHCI" or "NVM Express".
+    pub fn prog_if(&self, code: u32) -> Option<&str> {
+        self.classes
+            .get(&((code >> 16) as u8))?
+            .subclasses
+            .get(&((code >> 8) as u8))?
+            .prog_ifs
+            .get(&(code as u8))
+            .map(String::as_str)
+    }
+
+    /// Class name for display, with the programming interface where it says
+    /// something ("USB controller [XHCI]"), or the hex code if unknown.
+    pub fn class_label(&self, code: u32) -> String {
+        let Some(name) = self.class(code) else {
+            return format!("Class {:06x}", code);
+        };
+        match self.prog_if(code) {
+            Some(prog_if) if code & 0xff != 0 => format!("{} [{}]", name, prog_if),
+            _ => name.to_string(),
+        }
+    }
+
+    /// `lspci`-style description: "NVIDIA Corporation GM206 [GeForce GTX 960]",
+    /// with hex ids where names are unknown.
+    pub fn describe(&self, vendor: u16, device: u16) -> String {
+        let vendor_name = self.vendor(vendor).map_or_else(|| format!("Device {:04x}", vendor), str::to_string);
+        match self.device(vendor, device) {
+            Some(name) => format!("{} {}", vendor_name, name),
+            None => format!("{} Device {:04x}", vendor_name, device),
+        }
+    }
+}
+
+/// Split "1234  Name" into id and name.
+fn split_id(body: &str) -> Option<(&str, &str)> {
+    let (id, name) = body.split_once("  ")?;
+    Some((id.trim(), name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+# comment
+10de  NVIDIA Corporation
+\t13b0  GM107GLM [Quadro M2000M]
+\t\t17aa 2233  ThinkPad P50
+\t0fbc  GM107 High Definition Audio Controller [GeForce 940MX]
+17aa  Lenovo
+
+C 03  Display controller
+\t00  VGA compatible controller
+\t\t00  VGA controller
+\t02  3D controller
+C 0c  Serial bus controller
+\t03  USB controller
+\t\t30  XHCI
+";
+
+    #[test]
+    fn test_parse_vendors_devices_subsystems() {
+        let ids = PciIds::parse(SAMPLE);
+        assert_eq!(ids.vendor(0x10de), Some("NVIDIA Corporation"));
+        assert_eq!(ids.device(0x10de, 0x13b0), Some("GM107GLM [Quadro M2000M]"));
+        assert_eq!(ids.subsystem(0x10de, 0x13b0, 0x17aa, 0x2233).as_deref(), Some("ThinkPad P50"));
+        assert_eq!(ids.subsystem(0x10de, 0x13b0, 0x17aa, 0x1111).as_deref(), Some("Lenovo Device 1111"));
+        assert_eq!(ids.describe(0x10de, 0x0fbc), "NVIDIA Corporation GM107 High Definition Audio Controller [GeForce 940MX]");
+        assert_eq!(ids.describe(0x10de, 0xffff), "NVIDIA Corporation Device ffff");
+        assert_eq!(ids.describe(0xabcd, 0x0001), "Device abcd Device 0001");
+    }
+
+    #[test]
+    fn test_parse_classes() {
+        let ids = PciIds::parse(SAMPLE);
+        assert_eq!(ids.class(0x030000), Some("VGA compatible controller"));
+        assert_eq!(ids.class(0x030200), Some("3D controller"));
+        assert_eq!(ids.class(0x038000), Some("Display controller"));
+        assert_eq!(ids.prog_if(0x0c0330), Some("XHCI"));
+        assert_eq!(ids.class(0x990000), None);
+        assert_eq!(ids.class_label(0x0c0330), "USB controller [XHCI]");
+        assert_eq!(ids.class_label(0x030000), "VGA compatible controller");
+        assert_eq!(ids.class_label(0x990000), "Class 990000");
+        // Class entries must not leak into the vendor table
+        assert_eq!(ids.vendor(0x0003), None);
+    }
+
+    #[test]
+    fn test_bundled_database_parses() {
+        let ids = PciIds::bundled();
+        assert_eq!(ids.vendor(0x8086), Some("Intel Corporation"));
+        assert_eq!(ids.class(0x010802), Some("Non-Volatile memory controller"));
+        assert_eq!(ids.prog_if(0x010802), Some("NVM Express"));
+    }
+}
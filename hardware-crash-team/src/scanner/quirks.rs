@@ -0,0 +1,317 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Quirk database
+//!
+//! Known-problem hardware, as data: each quirk matches devices by ID,
+//! subsystem, class, bound driver and kernel release, and says what issue to
+//! report and which remediation strategy suits them. The database ships in
+//! `data/quirks/`; TOML files in `/etc/hardware-crash-team/quirks.d` (read
+//! in name order through the scan's [`SysfsSource`]) add quirks, replace
+//! shipped ones by `id`, or switch them off with `disable = ["id"]`.
+
+use super::source::SysfsSource;
+use crate::types::*;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// User overrides.
+pub const OVERRIDE_DIR: &str = "/etc/hardware-crash-team/quirks.d";
+
+const BUNDLED: &[(&str, &str)] = &[
+    ("gpu-functions.toml", include_str!("../../data/quirks/gpu-functions.toml")),
+    ("gpu-reset.toml", include_str!("../../data/quirks/gpu-reset.toml")),
+];
+
+/// One quirk file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuirkFile {
+    /// IDs of quirks loaded earlier to drop
+    #[serde(default)]
+    disable: Vec<String>,
+    #[serde(default)]
+    quirk: Vec<Quirk>,
+}
+
+/// A known problem with matching hardware. Unset match fields match anything.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quirk {
+    /// Stable name overrides refer to
+    pub id: String,
+    /// Vendor ID in hex (e.g., "10de")
+    pub vendor: Option<String>,
+    /// Device IDs in hex; empty matches every device of the vendor
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Subsystem as "vendor:device" in hex
+    pub subsystem: Option<String>,
+    /// Leading hex digits of the class code (e.g., "0403" for HD audio)
+    pub class: Option<String>,
+    /// Driver the device is bound to
+    pub driver: Option<String>,
+    /// Kernel releases affected, e.g. ">=5.4, <6.2"
+    pub kernel: Option<String>,
+    pub issue: IssueType,
+    pub severity: IssueSeverity,
+    /// Issue text; `{slot}` is replaced by the device's slot
+    pub description: String,
+    pub remediation: String,
+    /// Preferred strategy, by its CLI name (e.g., "pci-stub")
+    pub strategy: Option<String>,
+}
+
+/// What a quirk is matched against, IDs in lowercase hex without `0x`.
+#[derive(Debug, Clone, Default)]
+pub struct QuirkTarget {
+    pub vendor: String,
+    pub device: String,
+    pub subsystem: Option<String>,
+    pub class: String,
+    pub driver: Option<String>,
+}
+
+impl QuirkTarget {
+    /// Read a device's IDs, class and driver from sysfs.
+    pub fn read(source: &dyn SysfsSource, slot: &str) -> Self {
+        let path = format!("{}/{}", super::PCI_DEVICES, slot);
+        let hex = |file: &str| {
+            source
+                .read(&format!("{}/{}", path, file))
+                .map(|v| v.trim().trim_start_matches("0x").to_lowercase())
+                .unwrap_or_default()
+        };
+        let subsystem = match (hex("subsystem_vendor"), hex("subsystem_device")) {
+            (v, d) if v.is_empty() || d.is_empty() || v == "0000" => None,
+            (v, d) => Some(format!("{}:{}", v, d)),
+        };
+        Self {
+            vendor: hex("vendor"),
+            device: hex("device"),
+            subsystem,
+            class: hex("class"),
+            driver: source
+                .read_link(&format!("{}/driver", path))
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string())),
+        }
+    }
+}
+
+impl Quirk {
+    fn matches(&self, target: &QuirkTarget, kernel: &str) -> bool {
+        let eq = |want: &Option<String>, have: Option<&str>| {
+            want.as_ref().map_or(true, |w| have.is_some_and(|h| w.eq_ignore_ascii_case(h)))
+        };
+        eq(&self.vendor, Some(&target.vendor))
+            && (self.devices.is_empty() || self.devices.iter().any(|d| d.eq_ignore_ascii_case(&target.device)))
+            && eq(&self.subsystem, target.subsystem.as_deref())
+            && self.class.as_ref().map_or(true, |c| target.class.starts_with(&c.to_lowercase()))
+            && eq(&self.driver, target.driver.as_deref())
+            && self.kernel.as_ref().map_or(true, |range| kernel_in_range(range, kernel).unwrap_or(false))
+    }
+
+    /// The issue this quirk reports for `slot`.
+    pub fn issue(&self, slot: &str) -> DeviceIssue {
+        DeviceIssue {
+            severity: self.severity.clone(),
+            issue_type: self.issue.clone(),
+            description: self.description.replace("{slot}", slot),
+            remediation: self.remediation.clone(),
+        }
+    }
+}
+
+/// Shipped quirks with the user's overrides applied.
+#[derive(Debug, Default)]
+pub struct QuirkDb {
+    quirks: Vec<Quirk>,
+}
+
+impl QuirkDb {
+    /// The shipped database plus the overrides `source` has. An override
+    /// file that fails to parse is skipped with a warning.
+    pub fn load(source: &dyn SysfsSource) -> Self {
+        let mut db = Self::bundled();
+        let mut names = source.list(OVERRIDE_DIR).unwrap_or_default();
+        names.retain(|n| n.ends_with(".toml"));
+        names.sort();
+        for name in names {
+            let path = format!("{}/{}", OVERRIDE_DIR, name);
+            let Some(content) = source.read(&path) else {
+                continue;
+            };
+            match parse(&content).with_context(|| format!("Skipping quirk file {}", path)) {
+                Ok(file) => db.apply(file),
+                Err(e) => eprintln!("Warning: {:#}", e),
+            }
+        }
+        db
+    }
+
+    pub fn bundled() -> Self {
+        let mut db = Self::default();
+        for (name, content) in BUNDLED {
+            db.apply(parse(content).unwrap_or_else(|e| panic!("bundled quirks {}: {:#}", name, e)));
+        }
+        db
+    }
+
+    fn apply(&mut self, file: QuirkFile) {
+        self.quirks.retain(|q| !file.disable.contains(&q.id));
+        for quirk in file.quirk {
+            match self.quirks.iter_mut().find(|q| q.id == quirk.id) {
+                Some(existing) => *existing = quirk,
+                None => self.quirks.push(quirk),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn quirks(&self) -> &[Quirk] {
+        &self.quirks
+    }
+
+    /// Quirks for `target` on kernel release `kernel`, in load order.
+    pub fn matching(&self, target: &QuirkTarget, kernel: &str) -> Vec<&Quirk> {
+        self.quirks.iter().filter(|q| q.matches(target, kernel)).collect()
+    }
+}
+
+/// Parse a quirk file, rejecting kernel ranges that don't parse.
+fn parse(content: &str) -> Result<QuirkFile> {
+    let file: QuirkFile = toml::from_str(content)?;
+    for quirk in &file.quirk {
+        if let Some(range) = &quirk.kernel {
+            kernel_in_range(range, "0").with_context(|| format!("quirk {}: bad kernel range {:?}", quirk.id, range))?;
+        }
+    }
+    Ok(file)
+}
+
+/// Whether `release` satisfies every comma-separated bound in `range`
+/// (`>=5.4, <6.2`; a bare version means `=`). Releases compare by their
+/// leading numeric components, so "6.8.0-45-generic" is 6.8.0.
+fn kernel_in_range(range: &str, release: &str) -> Result<bool> {
+    let have = version(release);
+    let mut satisfied = true;
+    for bound in range.split(',').map(str::trim) {
+        let split = bound.find(|c: char| c.is_ascii_digit()).unwrap_or(bound.len());
+        let (op, want) = bound.split_at(split);
+        if want.is_empty() {
+            anyhow::bail!("no version in {:?}", bound);
+        }
+        let ordering = compare(&have, &version(want));
+        satisfied &= match op.trim() {
+            ">=" => ordering.is_ge(),
+            ">" => ordering.is_gt(),
+            "<=" => ordering.is_le(),
+            "<" => ordering.is_lt(),
+            "=" | "==" | "" => ordering.is_eq(),
+            other => anyhow::bail!("unknown operator {:?}", other),
+        };
+    }
+    Ok(satisfied)
+}
+
+fn version(release: &str) -> Vec<u32> {
+    release
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// Compare versions, missing components counting as zero.
+fn compare(a: &[u32], b: &[u32]) -> std::cmp::Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|o| o.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::source::RootedFs;
+
+    fn audio() -> QuirkTarget {
+        QuirkTarget {
+            vendor: "10de".to_string(),
+            device: "0fbc".to_string(),
+            subsystem: Some("17aa:2233".to_string()),
+            class: "040300".to_string(),
+            driver: Some("snd_hda_intel".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_bundled_quirks_match() {
+        let db = QuirkDb::bundled();
+        let ids: Vec<&str> = db.matching(&audio(), "6.8.0-45-generic").iter().map(|q| q.id.as_str()).collect();
+        assert_eq!(ids, vec!["nvidia-hda-partial-binding"]);
+        let issue = db.quirks()[0].issue("0000:01:00.1");
+        assert_eq!(issue.description, "NVIDIA audio codec 0000:01:00.1 bound to snd_hda_intel - partial GPU binding");
+
+        // Unbound, the codec is no partial binding
+        let unbound = QuirkTarget { driver: None, ..audio() };
+        assert_eq!(db.matching(&unbound, "6.8.0").len(), 0);
+        let navi = QuirkTarget { vendor: "1002".to_string(), device: "731f".to_string(), class: "030000".to_string(), ..Default::default() };
+        assert_eq!(db.matching(&navi, "6.8.0").first().map(|q| q.id.as_str()), Some("amd-navi10-reset"));
+    }
+
+    #[test]
+    fn test_kernel_ranges() {
+        assert!(kernel_in_range(">=5.4, <6.2", "5.15.0-91-generic").unwrap());
+        assert!(!kernel_in_range(">=5.4, <6.2", "6.2").unwrap());
+        assert!(kernel_in_range("<6.2", "6.1.77").unwrap());
+        assert!(kernel_in_range("6.8", "6.8.0-test").unwrap());
+        assert!(kernel_in_range("~6.8", "6.8").is_err());
+        assert!(kernel_in_range(">=", "6.8").is_err());
+    }
+
+    #[test]
+    fn test_overrides_add_replace_and_disable() {
+        use std::fs;
+        let dir = tempfile::tempdir().unwrap();
+        let overrides = dir.path().join(OVERRIDE_DIR.trim_start_matches('/'));
+        fs::create_dir_all(&overrides).unwrap();
+        fs::write(
+            overrides.join("10-local.toml"),
+            r#"
+disable = ["amd-navi10-reset"]
+
+[[quirk]]
+id = "nvidia-hda-partial-binding"
+vendor = "10de"
+class = "0403"
+driver = "snd_hda_intel"
+kernel = "<6.0"
+issue = "PartialBinding"
+severity = "High"
+description = "{slot} is half-bound"
+remediation = "Claim it"
+strategy = "vfio-pci"
+
+[[quirk]]
+id = "lenovo-dock"
+subsystem = "17AA:2233"
+issue = "AcpiError"
+severity = "Info"
+description = "{slot} is on a Lenovo board"
+remediation = "None needed"
+"#,
+        )
+        .unwrap();
+        fs::write(overrides.join("20-broken.toml"), "[[quirk]]\nid = 1\n").unwrap();
+
+        let db = QuirkDb::load(&RootedFs::new(dir.path()).unwrap());
+        assert!(db.quirks().iter().all(|q| q.id != "amd-navi10-reset"));
+        let replaced = db.quirks().iter().find(|q| q.id == "nvidia-hda-partial-binding").unwrap();
+        assert_eq!(replaced.strategy.as_deref(), Some("vfio-pci"));
+
+        let ids = |kernel| db.matching(&audio(), kernel).iter().map(|q| q.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids("5.15.0"), vec!["nvidia-hda-partial-binding", "lenovo-dock"]);
+        assert_eq!(ids("6.8.0"), vec!["lenovo-dock"]);
+    }
+}
@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Where the scanner reads sysfs and procfs from.
+//!
+//! The scanner only touches the system through [`SysfsSource`], so the same
+//! code runs against the live machine ([`RootedFs::live`]), a directory laid
+//! out like `/` (test fixtures), or a snapshot written by `capture`
+//! ([`Snapshot`]). [`Recorder`] wraps a source and remembers every path the
+//! scanner touched, which is exactly what a snapshot archives.
+//!
+//! A snapshot is a `.tar.gz` (or an unpacked directory) holding the touched
+//! files under `sys/`, `proc/`, `usr/share/` (pci.ids), the boot's kernel log
+//! as `kernel.log`, plus `capture.json` with the capture time, hostname and
+//! user. Snapshots come from other machines, so reads never follow a symlink
+//! out of the snapshot root.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Metadata file at the root of a snapshot.
+pub const CAPTURE_FILE: &str = "capture.json";
+
+/// Where fixture trees and snapshots keep the kernel log; the live system
+/// asks journald (or `dmesg`) instead.
+pub const KERNEL_LOG: &str = "/kernel.log";
+
+/// Read-only view of the files the scanner needs. Paths are absolute
+/// (`/sys/bus/pci/devices`); implementations map them onto their root.
+pub trait SysfsSource {
+    /// Raw contents of a file, `None` if missing or unreadable.
+    fn read_bytes(&self, path: &str) -> Option<Vec<u8>>;
+    /// Target of a symlink, without following it.
+    fn read_link(&self, path: &str) -> Option<PathBuf>;
+    /// Entry names of a directory, sorted.
+    fn list(&self, path: &str) -> Option<Vec<String>>;
+    fn exists(&self, path: &str) -> bool;
+    /// Kernel messages from the current boot.
+    fn kernel_log(&self) -> Option<String>;
+
+    /// When the data was captured; `None` for the live system.
+    fn captured_at(&self) -> Option<String> {
+        None
+    }
+
+    /// Contents of a text file.
+    fn read(&self, path: &str) -> Option<String> {
+        String::from_utf8(self.read_bytes(path)?).ok()
+    }
+}
+
+/// A filesystem tree mounted at `root`.
+pub struct RootedFs {
+    root: PathBuf,
+    live: bool,
+}
+
+impl RootedFs {
+    /// The running system.
+    pub fn live() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+            live: true,
+        }
+    }
+
+    /// A tree laid out like `/` under `root`. Symlinks out of it are not followed.
+    pub fn new(root: &Path) -> Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Cannot open {}", root.display()))?;
+        Ok(Self { root, live: false })
+    }
+
+    fn host_path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// Host path to read through, refusing anything that resolves outside the root.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let host = self.host_path(path);
+        if self.live {
+            return Some(host);
+        }
+        let resolved = host.canonicalize().ok()?;
+        resolved.starts_with(&self.root).then_some(resolved)
+    }
+}
+
+impl SysfsSource for RootedFs {
+    fn read_bytes(&self, path: &str) -> Option<Vec<u8>> {
+        fs::read(self.resolve(path)?).ok()
+    }
+
+    fn read_link(&self, path: &str) -> Option<PathBuf> {
+        fs::read_link(self.host_path(path)).ok()
+    }
+
+    fn list(&self, path: &str) -> Option<Vec<String>> {
+        let mut names: Vec<String> = fs::read_dir(self.resolve(path)?)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        Some(names)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_some_and(|p| p.exists())
+    }
+
+    fn kernel_log(&self) -> Option<String> {
+        if !self.live {
+            return self.read(KERNEL_LOG);
+        }
+        let journal = Command::new("journalctl")
+            .args(["-b", "0", "-k", "--no-pager", "-q", "-o", "cat"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success() && !o.stdout.is_empty());
+        let output = match journal {
+            Some(output) => output,
+            None => Command::new("dmesg").output().ok().filter(|o| o.status.success())?,
+        };
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Contents of [`CAPTURE_FILE`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureInfo {
+    pub captured_at: String,
+    pub hostname: String,
+    /// User who ran `capture`; older snapshots do not record one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub tool_version: String,
+}
+
+/// A snapshot written by `capture`, opened from its archive or directory.
+pub struct Snapshot {
+    fs: RootedFs,
+    info: CaptureInfo,
+    _unpacked: Option<Unpacked>,
+}
+
+/// Temporary directory an archive was unpacked into; removed on drop.
+struct Unpacked(PathBuf);
+
+impl Drop for Unpacked {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+impl Snapshot {
+    /// Open a snapshot archive (`.tar.gz`) or an unpacked snapshot directory.
+    pub fn open(path: &Path) -> Result<Self> {
+        let (root, unpacked) = if path.is_dir() {
+            (path.to_path_buf(), None)
+        } else {
+            let dir = std::env::temp_dir().join(format!("hct-snapshot-{}", uuid::Uuid::new_v4()));
+            let unpacked = Unpacked(dir.clone());
+            let file = fs::File::open(path).with_context(|| format!("Cannot open snapshot {}", path.display()))?;
+            tar::Archive::new(flate2::read::GzDecoder::new(file))
+                .unpack(&dir)
+                .with_context(|| format!("Cannot unpack snapshot {}", path.display()))?;
+            (dir, Some(unpacked))
+        };
+
+        let info_path = root.join(CAPTURE_FILE);
+        let info = fs::read_to_string(&info_path)
+            .with_context(|| format!("{} is not a snapshot: no {}", path.display(), CAPTURE_FILE))?;
+        Ok(Self {
+            fs: RootedFs::new(&root)?,
+            info: serde_json::from_str(&info)?,
+            _unpacked: unpacked,
+        })
+    }
+
+    pub fn info(&self) -> &CaptureInfo {
+        &self.info
+    }
+}
+
+impl SysfsSource for Snapshot {
+    fn read_bytes(&self, path: &str) -> Option<Vec<u8>> {
+        self.fs.read_bytes(path)
+    }
+
+    fn read_link(&self, path: &str) -> Option<PathBuf> {
+        self.fs.read_link(path)
+    }
+
+    fn list(&self, path: &str) -> Option<Vec<String>> {
+        self.fs.list(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.fs.exists(path)
+    }
+
+    fn kernel_log(&self) -> Option<String> {
+        self.fs.kernel_log()
+    }
+
+    fn captured_at(&self) -> Option<String> {
+        Some(self.info.captured_at.clone())
+    }
+}
+
+/// What was found at a path the scanner touched.
+enum Seen {
+    File(Vec<u8>),
+    Link(PathBuf),
+    Dir(Vec<String>),
+    /// Only checked for existence.
+    Present { dir: bool },
+}
+
+/// Passes reads through to another source and remembers every answer.
+pub struct Recorder<'a> {
+    inner: &'a dyn SysfsSource,
+    seen: RefCell<BTreeMap<String, Seen>>,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(inner: &'a dyn SysfsSource) -> Self {
+        Self {
+            inner,
+            seen: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn note(&self, path: &str, seen: Seen) {
+        let mut map = self.seen.borrow_mut();
+        // A read says more than an existence check; keep the most informative
+        if matches!(seen, Seen::Present { .. }) && map.contains_key(path) {
+            return;
+        }
+        map.insert(path.to_string(), seen);
+    }
+
+    /// Number of paths recorded so far.
+    pub fn recorded(&self) -> usize {
+        self.seen.borrow().len()
+    }
+
+    /// Write everything recorded as a snapshot archive at `out`.
+    pub fn write_snapshot(&self, out: &Path, captured_at: &str, hostname: &str, username: Option<&str>) -> Result<()> {
+        let seen = self.seen.borrow();
+        let file = fs::File::create(out).with_context(|| format!("Cannot create {}", out.display()))?;
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+
+        let is_recorded_below = |path: &str| seen.keys().any(|k| k.len() > path.len() && k.starts_with(path) && k.as_bytes()[path.len()] == b'/');
+        for (path, entry) in seen.iter() {
+            let name = path.trim_start_matches('/');
+            match entry {
+                Seen::File(bytes) => append(&mut tar, name, tar::EntryType::Regular, bytes)?,
+                Seen::Link(target) => {
+                    let mut header = header(tar::EntryType::Symlink, 0);
+                    tar.append_link(&mut header, name, target)?;
+                }
+                Seen::Dir(children) => {
+                    append(&mut tar, name, tar::EntryType::Directory, &[])?;
+                    for child in children {
+                        let child_path = format!("{}/{}", path, child);
+                        if !seen.contains_key(&child_path) {
+                            append(&mut tar, &format!("{}/{}", name, child), tar::EntryType::Directory, &[])?;
+                        }
+                    }
+                }
+                Seen::Present { dir: true } => append(&mut tar, name, tar::EntryType::Directory, &[])?,
+                Seen::Present { dir: false } if !is_recorded_below(path) => {
+                    append(&mut tar, name, tar::EntryType::Regular, &[])?
+                }
+                Seen::Present { .. } => {}
+            }
+        }
+
+        let info = CaptureInfo {
+            captured_at: captured_at.to_string(),
+            hostname: hostname.to_string(),
+            username: username.map(str::to_string),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        append(&mut tar, CAPTURE_FILE, tar::EntryType::Regular, &serde_json::to_vec_pretty(&info)?)?;
+        tar.into_inner()?.finish()?.sync_all()?;
+        Ok(())
+    }
+}
+
+fn header(entry_type: tar::EntryType, size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(if entry_type == tar::EntryType::Directory { 0o755 } else { 0o644 });
+    // Fixed mtime so the same capture produces the same archive
+    header.set_mtime(0);
+    header
+}
+
+fn append<W: std::io::Write>(tar: &mut tar::Builder<W>, name: &str, entry_type: tar::EntryType, bytes: &[u8]) -> Result<()> {
+    let mut header = header(entry_type, bytes.len() as u64);
+    tar.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+impl SysfsSource for Recorder<'_> {
+    fn read_bytes(&self, path: &str) -> Option<Vec<u8>> {
+        let bytes = self.inner.read_bytes(path)?;
+        self.note(path, Seen::File(bytes.clone()));
+        Some(bytes)
+    }
+
+    fn read_link(&self, path: &str) -> Option<PathBuf> {
+        let target = self.inner.read_link(path)?;
+        self.note(path, Seen::Link(target.clone()));
+        Some(target)
+    }
+
+    fn list(&self, path: &str) -> Option<Vec<String>> {
+        let names = self.inner.list(path)?;
+        self.note(path, Seen::Dir(names.clone()));
+        Some(names)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let exists = self.inner.exists(path);
+        if exists {
+            let dir = self.inner.list(path).is_some();
+            self.note(path, Seen::Present { dir });
+        }
+        exists
+    }
+
+    fn kernel_log(&self) -> Option<String> {
+        let log = self.inner.kernel_log()?;
+        self.note(KERNEL_LOG, Seen::File(log.clone().into_bytes()));
+        Some(log)
+    }
+
+    fn captured_at(&self) -> Option<String> {
+        self.inner.captured_at()
+    }
+}
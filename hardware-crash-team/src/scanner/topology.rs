@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! PCI topology
+//!
+//! Each `/sys/bus/pci/devices/<slot>` entry links into `/sys/devices`, whose
+//! path spells out the hierarchy: `pci0000:00/0000:00:01.0/0000:01:00.0` is
+//! a device behind root port 00:01.0 in domain 0000. The functions of one
+//! physical device (a GPU's VGA and HDMI audio) share a node, since they
+//! reset, fail and get claimed together.
+
+use super::iommu::{physical_slot, BRIDGE_CLASSES};
+use super::source::SysfsSource;
+use super::PCI_DEVICES;
+use crate::types::*;
+
+/// One physical device while the tree is assembled.
+struct Entry {
+    physical: String,
+    functions: Vec<String>,
+    bridge: bool,
+    domain: String,
+    /// Physical slot of the bridge above, `None` on the root bus
+    parent: Option<String>,
+}
+
+/// The hierarchy above `devices` (sorted by slot), one tree per domain.
+/// Devices whose link can't be read land on the root bus of the domain
+/// their slot names.
+pub fn build(source: &dyn SysfsSource, devices: &[PciDevice]) -> Vec<TopologyNode> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for device in devices {
+        let slot = &device.slot;
+        let bridge = is_bridge(source, slot);
+        let physical = physical_slot(slot);
+        if let Some(entry) = entries.iter_mut().find(|e| e.physical == physical) {
+            entry.functions.push(slot.clone());
+            entry.bridge |= bridge;
+            continue;
+        }
+        let (domain, parent) = placement(source, slot);
+        entries.push(Entry {
+            physical: physical.to_string(),
+            functions: vec![slot.clone()],
+            bridge,
+            domain,
+            parent: parent.map(|p| physical_slot(&p).to_string()),
+        });
+    }
+    // A parent the scan never saw would strand its subtree
+    let known: Vec<String> = entries.iter().map(|e| e.physical.clone()).collect();
+    for entry in &mut entries {
+        if entry.parent.as_ref().is_some_and(|p| !known.contains(p)) {
+            entry.parent = None;
+        }
+    }
+
+    let mut domains: Vec<&str> = entries.iter().map(|e| e.domain.as_str()).collect();
+    domains.sort_unstable();
+    domains.dedup();
+    domains
+        .into_iter()
+        .map(|domain| TopologyNode {
+            slot: domain.to_string(),
+            kind: TopologyKind::Domain,
+            functions: Vec::new(),
+            children: children(&entries, domain, None),
+        })
+        .collect()
+}
+
+fn children(entries: &[Entry], domain: &str, parent: Option<&str>) -> Vec<TopologyNode> {
+    entries
+        .iter()
+        .filter(|e| e.domain == domain && e.parent.as_deref() == parent)
+        .map(|e| TopologyNode {
+            slot: e.functions[0].clone(),
+            kind: match (e.bridge, parent) {
+                (false, _) => TopologyKind::Endpoint,
+                (true, None) => TopologyKind::RootPort,
+                (true, Some(_)) => TopologyKind::Bridge,
+            },
+            functions: e.functions.clone(),
+            children: children(entries, domain, Some(&e.physical)),
+        })
+        .collect()
+}
+
+/// Domain and upstream bridge from the device's `/sys/devices` path.
+fn placement(source: &dyn SysfsSource, slot: &str) -> (String, Option<String>) {
+    let components: Vec<String> = source
+        .read_link(&format!("{}/{}", PCI_DEVICES, slot))
+        .map(|target| target.iter().map(|c| c.to_string_lossy().to_string()).collect())
+        .unwrap_or_default();
+    match components.iter().position(|c| c.starts_with("pci")) {
+        Some(at) if components.last().is_some_and(|c| c == slot) => {
+            let parent = (at + 2 < components.len()).then(|| components[components.len() - 2].clone());
+            (components[at].clone(), parent)
+        }
+        _ => (format!("pci{}", slot.rsplit_once(':').map_or(slot, |(bus, _)| bus)), None),
+    }
+}
+
+fn is_bridge(source: &dyn SysfsSource, slot: &str) -> bool {
+    source
+        .read(&format!("{}/{}/class", PCI_DEVICES, slot))
+        .and_then(|c| u32::from_str_radix(c.trim().trim_start_matches("0x"), 16).ok())
+        .is_some_and(|c| BRIDGE_CLASSES.contains(&(c >> 8)))
+}
+
+/// Carry issues along the tree: PCIe errors on a bridge implicate every
+/// device below it, and a zombie GPU implicates its other functions.
+pub fn propagate(topology: &[TopologyNode], devices: &mut [PciDevice]) {
+    for root in topology {
+        walk(root, None, devices);
+    }
+}
+
+fn walk<'a>(node: &'a TopologyNode, upstream: Option<(&'a str, IssueSeverity)>, devices: &mut [PciDevice]) {
+    if let Some((bridge, severity)) = &upstream {
+        for slot in &node.functions {
+            if let Some(device) = devices.iter_mut().find(|d| &d.slot == slot) {
+                device.issues.push(DeviceIssue {
+                    severity: severity.clone(),
+                    issue_type: IssueType::UpstreamErrors,
+                    description: format!("Device {} sits behind bridge {}, which reported PCIe errors", slot, bridge),
+                    remediation: format!("Fix the link at {} (riser, slot, cabling) before blaming the device", bridge),
+                });
+            }
+        }
+    }
+    group_zombie_functions(node, devices);
+
+    // The nearest failing bridge is the one worth naming
+    let errors = matches!(node.kind, TopologyKind::RootPort | TopologyKind::Bridge)
+        .then(|| {
+            devices
+                .iter()
+                .filter(|d| node.functions.contains(&d.slot))
+                .flat_map(|d| &d.issues)
+                .filter(|i| matches!(i.issue_type, IssueType::AerErrors))
+                .map(|i| i.severity.clone().min(IssueSeverity::High))
+                .max()
+        })
+        .flatten();
+    let upstream = errors.map(|severity| (node.slot.as_str(), severity)).or(upstream);
+    for child in &node.children {
+        walk(child, upstream.clone(), devices);
+    }
+}
+
+/// A GPU left without a driver while a host driver holds one of its other
+/// functions (usually HDMI audio) is half-claimed: flag those functions so
+/// the device is remediated as a whole.
+fn group_zombie_functions(node: &TopologyNode, devices: &mut [PciDevice]) {
+    let has = |d: &PciDevice, issue: fn(&IssueType) -> bool| d.issues.iter().any(|i| issue(&i.issue_type));
+    let Some(gpu) = devices
+        .iter()
+        .find(|d| {
+            node.functions.contains(&d.slot) && d.device_class == DeviceClass::Display && has(d, |t| matches!(t, IssueType::ZombieDevice))
+        })
+        .map(|d| d.slot.clone())
+    else {
+        return;
+    };
+    for device in devices.iter_mut().filter(|d| node.functions.contains(&d.slot) && d.slot != gpu) {
+        let Some(driver) = device.driver.clone() else {
+            continue;
+        };
+        if has(device, |t| matches!(t, IssueType::PartialBinding)) {
+            continue;
+        }
+        device.issues.push(DeviceIssue {
+            severity: IssueSeverity::Warning,
+            issue_type: IssueType::PartialBinding,
+            description: format!(
+                "Function {} is bound to {} while {} on the same device has no driver - partial binding",
+                device.slot, driver, gpu
+            ),
+            remediation: format!("Claim every function of {} together with the same null driver", gpu),
+        });
+    }
+}
+
+/// Nodes in depth-first order with their depth, domains at 0.
+pub fn flatten(topology: &[TopologyNode]) -> Vec<(usize, &TopologyNode)> {
+    fn visit<'a>(node: &'a TopologyNode, depth: usize, out: &mut Vec<(usize, &'a TopologyNode)>) {
+        out.push((depth, node));
+        for child in &node.children {
+            visit(child, depth + 1, out);
+        }
+    }
+    let mut out = Vec::new();
+    for root in topology {
+        visit(root, 0, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::source::RootedFs;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    /// Root port 00:01.0 -> switch upstream 01:00.0 -> downstream 02:00.0
+    /// -> GPU 03:00.0 and its audio 03:00.1; host bridge 00:00.0 beside it.
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let chain = [
+            ("0000:00:00.0", "pci0000:00/0000:00:00.0", "0x060000"),
+            ("0000:00:01.0", "pci0000:00/0000:00:01.0", "0x060400"),
+            ("0000:01:00.0", "pci0000:00/0000:00:01.0/0000:01:00.0", "0x060400"),
+            ("0000:02:00.0", "pci0000:00/0000:00:01.0/0000:01:00.0/0000:02:00.0", "0x060400"),
+            ("0000:03:00.0", "pci0000:00/0000:00:01.0/0000:01:00.0/0000:02:00.0/0000:03:00.0", "0x030000"),
+            ("0000:03:00.1", "pci0000:00/0000:00:01.0/0000:01:00.0/0000:02:00.0/0000:03:00.1", "0x040300"),
+        ];
+        fs::create_dir_all(root.join("sys/bus/pci/devices")).unwrap();
+        for (slot, path, class) in chain {
+            let real = root.join("sys/devices").join(path);
+            fs::create_dir_all(&real).unwrap();
+            fs::write(real.join("class"), format!("{}\n", class)).unwrap();
+            symlink(format!("../../../devices/{}", path), root.join("sys/bus/pci/devices").join(slot)).unwrap();
+        }
+        dir
+    }
+
+    fn devices() -> Vec<PciDevice> {
+        let port = |slot: &str| PciDevice { device_class: DeviceClass::Bridge, ..PciDevice::test(slot) }.with_driver("pcieport");
+        vec![
+            PciDevice { device_class: DeviceClass::Bridge, ..PciDevice::test("0000:00:00.0") },
+            port("0000:00:01.0").with_issue(IssueType::AerErrors, IssueSeverity::Critical),
+            port("0000:01:00.0"),
+            port("0000:02:00.0").with_issue(IssueType::AerErrors, IssueSeverity::Warning),
+            PciDevice::test("0000:03:00.0").with_issue(IssueType::ZombieDevice, IssueSeverity::High),
+            PciDevice { device_class: DeviceClass::Audio, ..PciDevice::test("0000:03:00.1") }.with_driver("snd_hda_intel"),
+        ]
+    }
+
+    #[test]
+    fn test_build_tree_from_device_paths() {
+        let dir = fixture();
+        let topology = build(&RootedFs::new(dir.path()).unwrap(), &devices());
+
+        assert_eq!(topology.len(), 1);
+        let domain = &topology[0];
+        assert_eq!(domain.slot, "pci0000:00");
+        assert_eq!(domain.kind, TopologyKind::Domain);
+        assert_eq!(domain.children.len(), 2);
+        assert_eq!(domain.children[0].kind, TopologyKind::Endpoint);
+        let port = &domain.children[1];
+        assert_eq!(port.kind, TopologyKind::RootPort);
+        let switch = &port.children[0];
+        assert_eq!(switch.kind, TopologyKind::Bridge);
+        let gpu = &switch.children[0].children[0];
+        assert_eq!(gpu.kind, TopologyKind::Endpoint);
+        assert_eq!(gpu.functions, vec!["0000:03:00.0", "0000:03:00.1"]);
+
+        let depths: Vec<usize> = flatten(&topology).iter().map(|(depth, _)| *depth).collect();
+        assert_eq!(depths, vec![0, 1, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_unlinked_devices_land_on_root_bus() {
+        let dir = tempfile::tempdir().unwrap();
+        let topology = build(&RootedFs::new(dir.path()).unwrap(), &devices()[4..]);
+        assert_eq!(topology[0].slot, "pci0000:03");
+        assert_eq!(topology[0].children.len(), 1);
+        assert_eq!(topology[0].children[0].functions.len(), 2);
+    }
+
+    #[test]
+    fn test_propagate_bridge_errors_and_zombie_functions() {
+        let dir = fixture();
+        let mut devices = devices();
+        let topology = build(&RootedFs::new(dir.path()).unwrap(), &devices);
+        propagate(&topology, &mut devices);
+
+        let upstream = |d: &PciDevice| -> Vec<IssueSeverity> {
+            d.issues
+                .iter()
+                .filter(|i| matches!(i.issue_type, IssueType::UpstreamErrors))
+                .map(|i| i.severity.clone())
+                .collect()
+        };
+        assert!(upstream(&devices[0]).is_empty());
+        assert!(upstream(&devices[1]).is_empty());
+        // Fatal errors on the root port count as High below it
+        assert_eq!(upstream(&devices[2]), vec![IssueSeverity::High]);
+        assert_eq!(upstream(&devices[3]), vec![IssueSeverity::High]);
+        // Below the downstream port, it is the nearer failing bridge
+        assert_eq!(upstream(&devices[4]), vec![IssueSeverity::Warning]);
+        assert!(devices[4].issues[1].description.contains("0000:02:00.0"));
+
+        let audio = &devices[5];
+        let partial = audio.issues.iter().find(|i| matches!(i.issue_type, IssueType::PartialBinding)).unwrap();
+        assert!(partial.description.contains("0000:03:00.0"));
+    }
+}
@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: PMPL-1.0-or-later
+//! Signing and verification of contract documents (envelopes, plans, receipts).
+//!
+//! Keys are raw Ed25519 PKCS#8 files; trust stores are the JSON format from
+//! `ambientops_contracts::signing::TrustStore`.
+
+use anyhow::{Context, Result};
+use ambientops_contracts::signing::{self, SignatureStatus, SigningKey, TrustStore, Verdict};
+use ambientops_contracts::validate::validate_json;
+use ambientops_contracts::{EvidenceEnvelope, ProcedurePlan, Receipt};
+use serde_json::Value;
+use std::path::Path;
+
+/// Which contract a JSON document is, judged by its identifier field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DocumentKind {
+    Envelope,
+    Plan,
+    Receipt,
+}
+
+impl DocumentKind {
+    fn detect(json: &Value) -> Result<Self> {
+        if json.get("envelope_id").is_some() {
+            Ok(Self::Envelope)
+        } else if json.get("receipt_id").is_some() {
+            Ok(Self::Receipt)
+        } else if json.get("plan_id").is_some() {
+            Ok(Self::Plan)
+        } else {
+            anyhow::bail!("Not a contract document (expected envelope_id, plan_id or receipt_id)")
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Envelope => "EvidenceEnvelope",
+            Self::Plan => "ProcedurePlan",
+            Self::Receipt => "Receipt",
+        }
+    }
+}
+
+/// Generate a signing key, optionally adding its public half to a trust store.
+pub fn keygen(output: &Path, signer: &str, trust: Option<&Path>) -> Result<()> {
+    if output.exists() {
+        anyhow::bail!("Refusing to overwrite existing key: {}", output.display());
+    }
+    // A store that exists but cannot be read must not be replaced by one holding only this key
+    let store = trust.map(load_or_new_trust_store).transpose()?;
+
+    let (key, pkcs8) = SigningKey::generate(signer)?;
+    write_private(output, &pkcs8)?;
+    println!("Signing key for '{}' saved to: {}", signer, output.display());
+    println!("Public key: {}", key.public_key_base64());
+
+    if let (Some(trust_path), Some(mut store)) = (trust, store) {
+        store.trust(signer, &key.public_key_base64());
+        std::fs::write(trust_path, store.to_json()?)?;
+        println!("Added '{}' to trust store: {}", signer, trust_path.display());
+    }
+
+    Ok(())
+}
+
+/// Sign a contract document in place (or into `output`).
+pub fn sign_file(document: &Path, key_path: &Path, signer: &str, output: Option<&Path>) -> Result<()> {
+    let pkcs8 = std::fs::read(key_path)
+        .with_context(|| format!("Cannot read signing key {}", key_path.display()))?;
+    let key = SigningKey::from_pkcs8(signer, &pkcs8)?;
+
+    let mut json = read_json(document)?;
+    let kind = DocumentKind::detect(&json)?;
+
+    // Never put a signature on something that does not match the contract
+    match kind {
+        DocumentKind::Envelope => {
+            validate_json::<EvidenceEnvelope>(&json)?;
+            signing::sign_json::<EvidenceEnvelope>(&mut json, &key)?;
+        }
+        DocumentKind::Plan => {
+            validate_json::<ProcedurePlan>(&json)?;
+            signing::sign_json::<ProcedurePlan>(&mut json, &key)?;
+        }
+        DocumentKind::Receipt => {
+            validate_json::<Receipt>(&json)?;
+            signing::sign_json::<Receipt>(&mut json, &key)?;
+        }
+    }
+
+    let target = output.unwrap_or(document);
+    std::fs::write(target, serde_json::to_string_pretty(&json)?)?;
+    println!("Signed {} as '{}': {}", kind.name(), signer, target.display());
+    Ok(())
+}
+
+/// Verify signatures on one or more documents, and the parent links between
+/// any envelopes among them. Fails unless everything is trusted and intact.
+pub fn verify_files(documents: &[std::path::PathBuf], trust_path: &Path) -> Result<()> {
+    let trust = load_trust_store(trust_path)?;
+    let mut envelopes = Vec::new();
+    let mut failures = 0;
+
+    for path in documents {
+        let json = read_json(path)?;
+        let kind = DocumentKind::detect(&json)?;
+        let verification = match kind {
+            DocumentKind::Envelope => signing::verify_json::<EvidenceEnvelope>(&json, &trust)?,
+            DocumentKind::Plan => signing::verify_json::<ProcedurePlan>(&json, &trust)?,
+            DocumentKind::Receipt => signing::verify_json::<Receipt>(&json, &trust)?,
+        };
+
+        let verdict = verification.verdict();
+        println!("{} ({}): {}", path.display(), kind.name(), verdict.to_string().to_uppercase());
+        for check in &verification.signatures {
+            let status = match check.status {
+                SignatureStatus::Valid => "valid",
+                SignatureStatus::UnknownSigner => "unknown signer",
+                SignatureStatus::UnsupportedAlgorithm => "unsupported algorithm",
+                SignatureStatus::Invalid => "INVALID - content modified after signing",
+            };
+            println!("  {} @ {}: {}", check.signer, check.timestamp, status);
+        }
+        if verdict != Verdict::Trusted {
+            failures += 1;
+        }
+
+        if kind == DocumentKind::Envelope {
+            envelopes.push(json);
+        }
+    }
+
+    let chain = signing::verify_chain(&envelopes, &trust)?;
+    for link in chain.links.iter().filter(|l| !l.parent_found) {
+        println!(
+            "Envelope {}: parent {} not supplied, chain cannot be verified",
+            link.envelope_id,
+            link.parent_envelope_id.as_deref().unwrap_or_default()
+        );
+        failures += 1;
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} verification problem(s) found", failures);
+    }
+    println!("\nAll {} document(s) verified.", documents.len());
+    Ok(())
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("{} is not valid JSON", path.display()))
+}
+
+fn load_trust_store(path: &Path) -> Result<TrustStore> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read trust store {}", path.display()))?;
+    Ok(TrustStore::from_json(&content)?)
+}
+
+/// Like [`load_trust_store`], but a store that does not exist yet is empty.
+fn load_or_new_trust_store(path: &Path) -> Result<TrustStore> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => TrustStore::from_json(&content)
+            .with_context(|| format!("Invalid trust store {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TrustStore::default()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read trust store {}", path.display())),
+    }
+}
+
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(bytes)?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope_json() -> Value {
+        let env = ambientops_contracts::conversions::system_report_to_envelope(
+            &serde_json::json!({ "devices": [] }),
+            "test-host",
+        );
+        serde_json::to_value(env).unwrap()
+    }
+
+    #[test]
+    fn test_detect_document_kind() {
+        assert_eq!(DocumentKind::detect(&envelope_json()).unwrap(), DocumentKind::Envelope);
+        assert_eq!(
+            DocumentKind::detect(&serde_json::json!({ "plan_id": "x", "receipt_id": "y" })).unwrap(),
+            DocumentKind::Receipt
+        );
+        assert!(DocumentKind::detect(&serde_json::json!({ "devices": [] })).is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("test.key");
+        let trust = dir.path().join("trust.json");
+        let doc = dir.path().join("envelope.json");
+        std::fs::write(&doc, serde_json::to_string_pretty(&envelope_json()).unwrap()).unwrap();
+
+        keygen(&key, "tester", Some(&trust)).unwrap();
+        assert!(keygen(&key, "tester", None).is_err());
+        verify_files(std::slice::from_ref(&doc), &trust).unwrap_err();
+
+        sign_file(&doc, &key, "tester", None).unwrap();
+        verify_files(std::slice::from_ref(&doc), &trust).unwrap();
+
+        let tampered = std::fs::read_to_string(&doc).unwrap().replace("test-host", "other-host");
+        std::fs::write(&doc, tampered).unwrap();
+        assert!(verify_files(std::slice::from_ref(&doc), &trust).is_err());
+    }
+
+    #[test]
+    fn test_keygen_keeps_unreadable_trust_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("test.key");
+        let trust = dir.path().join("trust.json");
+        std::fs::write(&trust, "{ not json").unwrap();
+
+        assert!(keygen(&key, "tester", Some(&trust)).is_err());
+        assert_eq!(std::fs::read_to_string(&trust).unwrap(), "{ not json");
+        assert!(!key.exists());
+    }
+}
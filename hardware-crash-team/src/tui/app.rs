@@ -12,7 +12,9 @@
 
 use super::Screen;
 use super::ui;
-use crate::scanner;
+use crate::remediation::recommend::{self, Recommendation};
+use crate::scanner::source::RootedFs;
+use crate::scanner::{self, topology};
 use crate::types::*;
 
 /// Application state
@@ -21,6 +23,8 @@ pub struct App {
     pub screen: Screen,
     /// System report from last scan
     pub report: SystemReport,
+    /// Device list rows in topology order
+    pub rows: Vec<TreeRow>,
     /// Selected device index in list
     pub selected_device: usize,
     /// Selected strategy index in plan builder
@@ -29,10 +33,27 @@ pub struct App {
     pub should_quit: bool,
     /// Status message shown in footer
     pub status_message: String,
-    /// Available remediation strategies
+    /// Available remediation strategies, best first for the ranked device
     pub strategies: Vec<&'static str>,
+    /// Ranking behind `strategies`
+    pub ranking: Vec<Recommendation>,
+    /// Device the ranking is for
+    ranked_device: Option<usize>,
 }
 
+/// A line of the device tree: a domain heading or one device function
+pub struct TreeRow {
+    /// Index into `report.devices`, `None` for a domain heading
+    pub device: Option<usize>,
+    /// Slot or domain, indented by depth in the tree
+    pub label: String,
+}
+
+const DEFAULT_STRATEGIES: &[&str] = &[
+    "pci-stub", "vfio-pci", "dual", "power-off", "disable", "unbind",
+    "modprobe", "udev-power", "udev-unbind", "udev-remove",
+];
+
 impl App {
     /// Create app with initial scan
     pub fn new() -> Result<Self> {
@@ -42,17 +63,21 @@ pub fn new() -> Result<Self> {
             .map(|d| d.issues.len())
             .sum();
 
+        let rows = tree_rows(&report);
         Ok(Self {
             screen: Screen::StatusDashboard,
+            selected_device: first_device(&rows),
+            rows,
             report,
-            selected_device: 0,
             selected_strategy: 0,
             should_quit: false,
             status_message: format!(
                 "Scan complete: {} devices, {} issues. Press ? for help.",
                 device_count, issue_count
             ),
-            strategies: vec!["pci-stub", "vfio-pci", "dual", "power-off", "disable", "unbind"],
+            strategies: DEFAULT_STRATEGIES.to_vec(),
+            ranking: Vec::new(),
+            ranked_device: None,
         })
     }
 
@@ -87,6 +112,29 @@ pub fn handle_key(&mut self, key: KeyEvent) {
                 self.handle_screen_key(key);
             }
         }
+        if self.screen == Screen::PlanBuilder && self.ranked_device != Some(self.selected_device) {
+            self.rank_strategies();
+        }
+    }
+
+    /// Rank the strategies for the selected device, best first
+    fn rank_strategies(&mut self) {
+        self.ranked_device = Some(self.selected_device);
+        self.selected_strategy = 0;
+        let Some(dev) = self.report.devices.get(self.selected_device) else {
+            return;
+        };
+        match recommend::recommend(&RootedFs::live(), &self.report, &dev.slot, None) {
+            Ok(ranking) => {
+                self.strategies = ranking.iter().map(|r| r.name).collect();
+                self.ranking = ranking;
+            }
+            Err(e) => {
+                self.strategies = DEFAULT_STRATEGIES.to_vec();
+                self.ranking = Vec::new();
+                self.status_message = format!("Ranking failed: {}", e);
+            }
+        }
     }
 
     fn handle_screen_key(&mut self, key: KeyEvent) {
@@ -103,19 +151,19 @@ fn handle_device_list_key(&mut self, key: KeyEvent) {
         let device_count = self.report.devices.len();
         if device_count == 0 { return; }
 
+        // Navigation follows the tree, skipping domain headings
+        let order: Vec<usize> = self.rows.iter().filter_map(|r| r.device).collect();
+        let position = order.iter().position(|&d| d == self.selected_device).unwrap_or(0);
+
         match key.code {
-            KeyCode::Up | KeyCode::Char('k') => {
-                if self.selected_device > 0 {
-                    self.selected_device -= 1;
-                }
+            KeyCode::Up | KeyCode::Char('k') if position > 0 => {
+                self.selected_device = order[position - 1];
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.selected_device < device_count - 1 {
-                    self.selected_device += 1;
-                }
+            KeyCode::Down | KeyCode::Char('j') if position + 1 < order.len() => {
+                self.selected_device = order[position + 1];
             }
-            KeyCode::Home => self.selected_device = 0,
-            KeyCode::End => self.selected_device = device_count - 1,
+            KeyCode::Home => self.selected_device = order[0],
+            KeyCode::End => self.selected_device = order[order.len() - 1],
             KeyCode::Enter => {
                 self.screen = Screen::DeviceDetail;
                 let dev = &self.report.devices[self.selected_device];
@@ -180,7 +228,9 @@ fn refresh_scan(&mut self) {
                     .map(|d| d.issues.len())
                     .sum();
                 self.report = report;
-                self.selected_device = 0;
+                self.rows = tree_rows(&self.report);
+                self.ranked_device = None;
+                self.selected_device = first_device(&self.rows);
                 self.status_message = format!(
                     "Refreshed: {} devices, {} issues.",
                     device_count, issue_count
@@ -198,6 +248,37 @@ pub fn selected_device(&self) -> Option<&PciDevice> {
     }
 }
 
+/// Device list rows: each domain, then its devices depth-first with the
+/// functions of a device together. Devices the topology misses (older
+/// reports) follow at the top level.
+fn tree_rows(report: &SystemReport) -> Vec<TreeRow> {
+    let mut rows = Vec::new();
+    let mut placed = vec![false; report.devices.len()];
+    for (depth, node) in topology::flatten(&report.topology) {
+        let indent = "  ".repeat(depth);
+        if node.kind == TopologyKind::Domain {
+            rows.push(TreeRow { device: None, label: format!("{}{}", indent, node.slot) });
+            continue;
+        }
+        for (function, slot) in node.functions.iter().enumerate() {
+            let Some(idx) = report.devices.iter().position(|d| &d.slot == slot) else {
+                continue;
+            };
+            placed[idx] = true;
+            let marker = if function == 0 { "" } else { "+ " };
+            rows.push(TreeRow { device: Some(idx), label: format!("{}{}{}", indent, marker, slot) });
+        }
+    }
+    for (idx, dev) in report.devices.iter().enumerate().filter(|(idx, _)| !placed[*idx]) {
+        rows.push(TreeRow { device: Some(idx), label: dev.slot.clone() });
+    }
+    rows
+}
+
+fn first_device(rows: &[TreeRow]) -> usize {
+    rows.iter().find_map(|r| r.device).unwrap_or(0)
+}
+
 /// Run the TUI application
 pub fn run() -> Result<()> {
     // Setup terminal
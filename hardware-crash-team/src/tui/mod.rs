@@ -2,7 +2,7 @@
 //! ATS2 TUI — interactive terminal interface for hardware diagnostics
 //!
 //! Provides 5 screens:
-//! 1. Device List — table of PCI devices with issues color-coded
+//! 1. Device List — PCI topology tree with issues color-coded
 //! 2. Device Detail — full info on selected device
 //! 3. Plan Builder — select strategy and preview plan
 //! 4. Diagnosis View — crash analysis and correlations
@@ -91,7 +91,12 @@ fn render_device_list(frame: &mut Frame, area: Rect, app: &App) {
         .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
     let header = Row::new(header_cells).height(1);
 
-    let rows: Vec<Row> = app.report.devices.iter().enumerate().map(|(idx, dev)| {
+    let rows: Vec<Row> = app.rows.iter().map(|tree_row| {
+        let Some(idx) = tree_row.device else {
+            return Row::new(vec![Cell::from(tree_row.label.clone())])
+                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+        };
+        let dev = &app.report.devices[idx];
         let issue_count = dev.issues.len();
         let max_severity = dev.issues.iter()
             .map(|i| &i.severity)
@@ -118,7 +123,7 @@ fn render_device_list(frame: &mut Frame, area: Rect, app: &App) {
         };
 
         Row::new(vec![
-            Cell::from(dev.slot.clone()),
+            Cell::from(tree_row.label.clone()),
             Cell::from(dev.pci_id.clone()),
             Cell::from(dev.driver.clone().unwrap_or_else(|| "(none)".to_string())),
             Cell::from(format_power_state(&dev.power_state)),
@@ -131,7 +136,7 @@ fn render_device_list(frame: &mut Frame, area: Rect, app: &App) {
     let table = Table::new(
         rows,
         [
-            Constraint::Length(10),
+            Constraint::Length(28),
             Constraint::Length(12),
             Constraint::Length(20),
             Constraint::Length(8),
@@ -285,13 +290,18 @@ fn render_plan_builder(frame: &mut Frame, area: Rect, app: &App) {
 
     // Strategy list
     let items: Vec<ListItem> = app.strategies.iter().enumerate().map(|(idx, s)| {
+        let ranked = app.ranking.get(idx);
         let style = if idx == app.selected_strategy {
             Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        } else if ranked.is_some_and(|r| !r.available) {
+            Style::default().fg(Color::DarkGray)
         } else {
             Style::default()
         };
         let prefix = if idx == app.selected_strategy { "▸ " } else { "  " };
-        ListItem::new(format!("{}{}", prefix, s)).style(style)
+        let score = ranked.map(|r| format!("{:>5}", r.score)).unwrap_or_default();
+        let pick = if idx == 0 && ranked.is_some_and(|r| r.available) { "  (recommended)" } else { "" };
+        ListItem::new(format!("{}{:<12}{}{}", prefix, s, score, pick)).style(style)
     }).collect();
 
     let strategy_list = List::new(items)
@@ -300,8 +310,16 @@ fn render_plan_builder(frame: &mut Frame, area: Rect, app: &App) {
             .title(format!(" Strategy for {} ", dev.slot)));
     frame.render_widget(strategy_list, chunks[0]);
 
-    // Strategy description
-    let desc = strategy_description(app.strategies[app.selected_strategy]);
+    // Strategy description, then why it ranks where it does
+    let mut desc = strategy_description(app.strategies[app.selected_strategy]);
+    if let Some(ranked) = app.ranking.get(app.selected_strategy) {
+        desc.push(Line::raw(""));
+        let verdict = if ranked.available { "Why:" } else { "Unavailable:" };
+        desc.push(Line::styled(verdict, Style::default().fg(Color::Yellow)));
+        for why in &ranked.rationale {
+            desc.push(Line::raw(format!("  {}", why)));
+        }
+    }
     let preview = Paragraph::new(desc)
         .wrap(Wrap { trim: true })
         .block(Block::default()
@@ -414,6 +432,74 @@ fn strategy_description(name: &str) -> Vec<Line<'static>> {
             Line::raw(""),
             Line::raw("Reversible by writing slot to driver bind."),
         ],
+        "modprobe" => vec![
+            Line::styled("modprobe.d Claim", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Line::raw(""),
+            Line::raw("Writes a managed modprobe.d file handing the"),
+            Line::raw("device to vfio-pci; no bootloader change."),
+            Line::raw(""),
+            Line::from(vec![
+                Span::styled("Risk: ", Style::default().fg(Color::Yellow)),
+                Span::styled("Low", Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Reboot: ", Style::default().fg(Color::Yellow)),
+                Span::raw("Required"),
+            ]),
+            Line::raw(""),
+            Line::raw("Undo removes the file and rebuilds the initramfs."),
+        ],
+        "udev-power" => vec![
+            Line::styled("udev Power Control", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Line::raw(""),
+            Line::raw("Managed udev rule sets power/control to auto"),
+            Line::raw("every time the device appears."),
+            Line::raw(""),
+            Line::from(vec![
+                Span::styled("Risk: ", Style::default().fg(Color::Yellow)),
+                Span::styled("Low", Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Reboot: ", Style::default().fg(Color::Yellow)),
+                Span::raw("Not required"),
+            ]),
+            Line::raw(""),
+            Line::raw("Undo removes the rule and powers it back on."),
+        ],
+        "udev-unbind" => vec![
+            Line::styled("udev Unbind", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Line::raw(""),
+            Line::raw("Managed udev rule unbinds any driver that"),
+            Line::raw("binds the device, at every boot."),
+            Line::raw(""),
+            Line::from(vec![
+                Span::styled("Risk: ", Style::default().fg(Color::Yellow)),
+                Span::styled("Low", Style::default().fg(Color::Green)),
+            ]),
+            Line::from(vec![
+                Span::styled("Reboot: ", Style::default().fg(Color::Yellow)),
+                Span::raw("Not required"),
+            ]),
+            Line::raw(""),
+            Line::raw("Undo removes the rule and rebinds the driver."),
+        ],
+        "udev-remove" => vec![
+            Line::styled("udev Remove", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Line::raw(""),
+            Line::raw("Managed udev rule removes the device from"),
+            Line::raw("the bus every time it appears."),
+            Line::raw(""),
+            Line::from(vec![
+                Span::styled("Risk: ", Style::default().fg(Color::Yellow)),
+                Span::styled("Medium", Style::default().fg(Color::Yellow)),
+            ]),
+            Line::from(vec![
+                Span::styled("Reboot: ", Style::default().fg(Color::Yellow)),
+                Span::raw("Not required"),
+            ]),
+            Line::raw(""),
+            Line::raw("Undo removes the rule and rescans the bus."),
+        ],
         _ => vec![Line::raw("Unknown strategy.")],
     }
 }
@@ -14,12 +14,111 @@ pub struct SystemReport {
     pub devices: Vec<PciDevice>,
     /// IOMMU status
     pub iommu: IommuStatus,
+    /// IOMMU groups and their members
+    #[serde(default)]
+    pub iommu_groups: Vec<IommuGroup>,
+    /// PCI hierarchy, one tree per host bridge domain
+    #[serde(default)]
+    pub topology: Vec<TopologyNode>,
     /// ACPI errors detected
     pub acpi_errors: Vec<AcpiError>,
+    /// Null-driver claims on the kernel command line
+    #[serde(default)]
+    pub kernel_claims: KernelClaims,
     /// Overall risk assessment
     pub risk_level: RiskLevel,
 }
 
+#[cfg(test)]
+impl SystemReport {
+    /// A report of `devices` on a machine with the IOMMU on and nothing else found
+    pub fn test(devices: Vec<PciDevice>) -> Self {
+        SystemReport {
+            timestamp: "2026-02-12T00:00:00Z".to_string(),
+            kernel_version: "6.8.0-test".to_string(),
+            devices,
+            iommu: IommuStatus {
+                enabled: true,
+                iommu_type: None,
+                group_count: 0,
+                interrupt_remapping: false,
+                units: 0,
+                dma_mode: None,
+                disabled_reason: None,
+            },
+            iommu_groups: Vec::new(),
+            topology: Vec::new(),
+            acpi_errors: Vec::new(),
+            kernel_claims: KernelClaims::default(),
+            risk_level: RiskLevel::Clean,
+        }
+    }
+}
+
+/// Null-driver claims (`pci-stub.ids=`, `vfio-pci.ids=`) on the running and
+/// next-boot kernel command lines
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KernelClaims {
+    /// Where the next-boot arguments were read from (`None` if unknown;
+    /// the running arguments are assumed to persist)
+    pub next_boot_source: Option<String>,
+    /// The claim arguments (and `rd.driver.pre=vfio-pci`) as they stand for the next boot
+    pub next_boot_params: Vec<String>,
+    /// One entry per stub and ID
+    pub claims: Vec<StubClaim>,
+}
+
+impl KernelClaims {
+    /// Claims that match no present device
+    pub fn stale(&self) -> impl Iterator<Item = &StubClaim> {
+        self.claims.iter().filter(|c| c.devices.is_empty())
+    }
+
+    /// Claims written more than once for the same stub
+    pub fn duplicates(&self) -> impl Iterator<Item = &StubClaim> {
+        self.claims.iter().filter(|c| c.occurrences > 1)
+    }
+
+    /// IDs claimed by both pci-stub and vfio-pci
+    pub fn double_claimed(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .claims
+            .iter()
+            .filter(|c| c.stub == "pci-stub")
+            .filter(|c| self.claims.iter().any(|o| o.stub == "vfio-pci" && o.id == c.id))
+            .map(|c| c.id.as_str())
+            .collect();
+        ids.dedup();
+        ids
+    }
+}
+
+/// One `vendor:device` claimed by a null driver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StubClaim {
+    /// "pci-stub" or "vfio-pci"
+    pub stub: String,
+    /// Claimed ID (e.g., "10de:13b0")
+    pub id: String,
+    /// Present devices the ID matches
+    pub devices: Vec<String>,
+    /// Whether the claim is in effect, pending a reboot, or going away
+    pub state: ClaimState,
+    /// Times the ID is written for this stub in the next-boot arguments
+    pub occurrences: usize,
+}
+
+/// Where a claim stands between the running and next-boot command lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClaimState {
+    /// On the running and next-boot command lines
+    Active,
+    /// Only on the next-boot command line
+    PendingReboot,
+    /// Only on the running command line; gone after a reboot
+    PendingRemoval,
+}
+
 /// A PCI device and its status
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PciDevice {
@@ -33,6 +132,12 @@ pub struct PciDevice {
     pub vendor: String,
     /// Device class (e.g., "VGA compatible controller", "Audio device")
     pub class: String,
+    /// Decoded class, for heuristics
+    #[serde(default)]
+    pub device_class: DeviceClass,
+    /// Subsystem (board/OEM) name, if the subsystem IDs are known
+    #[serde(default)]
+    pub subsystem: Option<String>,
     /// Current driver bound (if any)
     pub driver: Option<String>,
     /// Available kernel modules
@@ -45,10 +150,112 @@ pub struct PciDevice {
     pub iommu_group: Option<u32>,
     /// Memory regions (BAR)
     pub memory_regions: Vec<MemoryRegion>,
+    /// PCIe AER error counters (if the device reports them)
+    #[serde(default)]
+    pub aer: Option<AerCounters>,
+    /// PCIe link state (if the device has a PCIe link)
+    #[serde(default)]
+    pub link: Option<PcieLink>,
     /// Issues detected with this device
     pub issues: Vec<DeviceIssue>,
 }
 
+#[cfg(test)]
+impl PciDevice {
+    /// A driverless, enabled display device (10de:13b0) in D0 at `slot`
+    pub fn test(slot: &str) -> Self {
+        PciDevice {
+            slot: slot.to_string(),
+            pci_id: "10de:13b0".to_string(),
+            description: String::new(),
+            vendor: "10de".to_string(),
+            class: String::new(),
+            device_class: DeviceClass::Display,
+            subsystem: None,
+            driver: None,
+            kernel_modules: Vec::new(),
+            power_state: PowerState::D0,
+            enabled: true,
+            iommu_group: None,
+            memory_regions: Vec::new(),
+            aer: None,
+            link: None,
+            issues: Vec::new(),
+        }
+    }
+
+    /// Bound to `driver`
+    pub fn with_driver(mut self, driver: &str) -> Self {
+        self.driver = Some(driver.to_string());
+        self
+    }
+
+    /// Flagged with `issue_type` at `severity`
+    pub fn with_issue(mut self, issue_type: IssueType, severity: IssueSeverity) -> Self {
+        self.issues.push(DeviceIssue {
+            severity,
+            issue_type,
+            description: String::new(),
+            remediation: String::new(),
+        });
+        self
+    }
+}
+
+/// PCI device class, decoded from the 24-bit class code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceClass {
+    Unclassified,
+    Storage,
+    Network,
+    /// VGA, XGA and 3D controllers
+    Display,
+    /// Audio device or multimedia audio controller (HDA codecs on GPUs)
+    Audio,
+    Multimedia,
+    Memory,
+    Bridge,
+    Communication,
+    SystemPeripheral,
+    Input,
+    Processor,
+    Usb,
+    SerialBus,
+    Wireless,
+    Accelerator,
+    /// A base class with no variant of its own (docking, encryption, ...)
+    Other,
+    #[default]
+    Unknown,
+}
+
+impl DeviceClass {
+    /// Decode a class code such as 0x030000 (base, subclass, prog-if).
+    pub fn from_code(code: u32) -> Self {
+        let subclass = (code >> 8) as u8;
+        match (code >> 16) as u8 {
+            0x00 => Self::Unclassified,
+            0x01 => Self::Storage,
+            0x02 => Self::Network,
+            0x03 => Self::Display,
+            0x04 if subclass == 0x01 || subclass == 0x03 => Self::Audio,
+            0x04 => Self::Multimedia,
+            0x05 => Self::Memory,
+            0x06 => Self::Bridge,
+            0x07 => Self::Communication,
+            0x08 => Self::SystemPeripheral,
+            0x09 => Self::Input,
+            0x0b => Self::Processor,
+            0x0c if subclass == 0x03 => Self::Usb,
+            0x0c => Self::SerialBus,
+            0x0d => Self::Wireless,
+            0x12 => Self::Accelerator,
+            0xff => Self::Unknown,
+            _ => Self::Other,
+        }
+    }
+}
+
 /// PCI device power state
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PowerState {
@@ -75,6 +282,42 @@ pub struct MemoryRegion {
     pub width: u8,
 }
 
+/// PCIe Advanced Error Reporting totals since boot
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AerCounters {
+    /// Corrected by hardware (bad TLPs, replays, receiver errors)
+    pub correctable: u64,
+    /// Uncorrectable, but the link survived
+    pub nonfatal: u64,
+    /// Uncorrectable, link reset required
+    pub fatal: u64,
+}
+
+/// Negotiated versus capable PCIe link
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PcieLink {
+    /// Current link speed in GT/s
+    pub current_speed: f32,
+    /// Maximum link speed in GT/s
+    pub max_speed: f32,
+    /// Current link width (lanes)
+    pub current_width: u8,
+    /// Maximum link width (lanes)
+    pub max_width: u8,
+}
+
+impl PcieLink {
+    /// Trained to fewer lanes than the device supports
+    pub fn width_degraded(&self) -> bool {
+        self.current_width > 0 && self.current_width < self.max_width
+    }
+
+    /// Trained below the device's maximum speed
+    pub fn speed_degraded(&self) -> bool {
+        self.current_speed > 0.0 && self.current_speed < self.max_speed
+    }
+}
+
 /// Issue detected with a PCI device
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceIssue {
@@ -118,6 +361,12 @@ pub enum IssueType {
     UnmanagedMemory,
     /// Power state conflict
     PowerStateConflict,
+    /// PCIe AER errors reported by the device
+    AerErrors,
+    /// PCIe link trained below its capable width or speed
+    LinkDegraded,
+    /// A bridge upstream of the device reported PCIe errors
+    UpstreamErrors,
 }
 
 /// Overall system risk assessment
@@ -146,6 +395,76 @@ pub struct IommuStatus {
     pub group_count: u32,
     /// Interrupt remapping enabled
     pub interrupt_remapping: bool,
+    /// Remapping units (DRHD/IVHD) the firmware describes; 0 if its table is unreadable
+    #[serde(default)]
+    pub units: u32,
+    /// How DMA is translated (None if disabled or unknown)
+    #[serde(default)]
+    pub dma_mode: Option<DmaMode>,
+    /// Why the IOMMU is off, when it is
+    #[serde(default)]
+    pub disabled_reason: Option<String>,
+}
+
+/// IOMMU DMA translation mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DmaMode {
+    /// Every unmap flushes the IOTLB
+    Strict,
+    /// IOTLB flushes are batched (`iommu.strict=0`)
+    Lazy,
+    /// Identity mapping for host drivers (`iommu=pt`); only vfio devices are translated
+    Passthrough,
+}
+
+/// An IOMMU group: the smallest set of devices vfio-pci can hand over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IommuGroup {
+    /// Group number
+    pub id: u32,
+    /// Devices in the group
+    pub members: Vec<GroupMember>,
+    /// Endpoints from more than one physical device share the group
+    /// (typically a bridge upstream without ACS)
+    pub mixed: bool,
+}
+
+/// A device in an IOMMU group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    /// PCI slot (e.g., "0000:01:00.0")
+    pub slot: String,
+    /// Current driver bound (if any)
+    pub driver: Option<String>,
+    /// PCI-to-PCI bridge, which stays with the host when the group is claimed
+    pub bridge: bool,
+}
+
+/// A node in the PCI hierarchy: a host bridge domain, or one physical
+/// device with all its functions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopologyNode {
+    /// Domain ("pci0000:00") or the device's first function ("0000:01:00.0")
+    pub slot: String,
+    /// Where the node sits in the hierarchy
+    pub kind: TopologyKind,
+    /// Functions of the device (e.g., a GPU's VGA and audio); empty for a domain
+    pub functions: Vec<String>,
+    /// Devices on the bus below this one
+    pub children: Vec<TopologyNode>,
+}
+
+/// Role of a node in the PCI hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TopologyKind {
+    /// Host bridge domain and root bus
+    Domain,
+    /// Bridge on the root bus
+    RootPort,
+    /// Bridge further down (switch port, PCI-to-PCI bridge)
+    Bridge,
+    /// Any other device
+    Endpoint,
 }
 
 /// ACPI error from system logs
@@ -217,7 +536,7 @@ pub struct RemediationPlan {
     pub strategy: RemediationStrategy,
     /// Steps to execute
     pub steps: Vec<RemediationStep>,
-    /// Undo steps (reverse order)
+    /// One undo step per step, at the same index; undone last step first
     pub undo_steps: Vec<RemediationStep>,
     /// Requires reboot
     pub requires_reboot: bool,
@@ -240,18 +559,31 @@ pub enum RemediationStrategy {
     SysfsDisable,
     /// Unbind current driver
     DriverUnbind,
+    /// Remove stale and duplicate null-driver claims from the kernel command line
+    ClaimCleanup,
+    /// Claim with vfio-pci through a managed modprobe.d snippet
+    ModprobeConf,
+    /// Managed udev rule setting `power/control` to auto on add
+    UdevPowerAuto,
+    /// Managed udev rule unbinding the driver whenever one binds
+    UdevUnbind,
+    /// Managed udev rule removing the device on add
+    UdevRemove,
 }
 
 impl RemediationStrategy {
     /// Whether this strategy requires a reboot to take effect
     pub fn requires_reboot(&self) -> bool {
-        matches!(self, Self::PciStub | Self::VfioPci | Self::DualNullDriver)
+        matches!(
+            self,
+            Self::PciStub | Self::VfioPci | Self::DualNullDriver | Self::ClaimCleanup | Self::ModprobeConf
+        )
     }
 
     /// Risk level for this strategy
     pub fn risk_level(&self) -> RiskLevel {
         match self {
-            Self::AcpiPowerOff => RiskLevel::Medium,
+            Self::AcpiPowerOff | Self::UdevRemove => RiskLevel::Medium,
             _ => RiskLevel::Low,
         }
     }
@@ -295,5 +627,145 @@ pub struct RemediationReceipt {
     /// Whether reboot is pending
     pub reboot_pending: bool,
     /// Pre-apply device state (for undo verification)
-    pub pre_state: String,
+    #[serde(default, deserialize_with = "pre_state_or_legacy")]
+    pub pre_state: PreState,
+    /// How the run ended
+    #[serde(default)]
+    pub status: ApplyStatus,
+    /// One entry per plan step that was run (or skipped)
+    #[serde(default)]
+    pub results: Vec<StepOutcome>,
+    /// Undo steps run after a failure, in the order they ran
+    #[serde(default)]
+    pub rollback: Vec<StepOutcome>,
+    /// Undo steps run by `undo`, in the order they ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undone: Vec<StepOutcome>,
+    /// Outcome check from `verify-fix`, once it has run; `status` stays the apply's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+}
+
+/// How an apply run ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApplyStatus {
+    /// Every step succeeded
+    Completed,
+    /// A step failed and rollback did not (fully) restore the system
+    Failed,
+    /// A step failed and the completed steps were undone
+    RolledBack,
+    /// Nothing was run (`--dry-run`, and every receipt written before real execution)
+    #[default]
+    DryRun,
+}
+
+/// Receipts written before real execution carry `pre_state` as a string
+/// (always empty); read those as an empty state.
+fn pre_state_or_legacy<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<PreState, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        State(PreState),
+        Legacy(#[allow(dead_code)] String),
+    }
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::State(state) => state,
+        Stored::Legacy(_) => PreState::default(),
+    })
+}
+
+/// State captured before a plan runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreState {
+    /// When it was captured
+    pub captured_at: String,
+    /// Running kernel command line
+    pub cmdline: String,
+    /// The plan's target devices
+    pub devices: Vec<DeviceState>,
+}
+
+/// One device as sysfs showed it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub slot: String,
+    /// Bound driver, if any
+    pub driver: Option<String>,
+    /// `power_state` (D0, D3hot, ...)
+    pub power_state: String,
+    /// `power/control` (on, auto)
+    pub power_control: Option<String>,
+    /// `enable`
+    pub enabled: Option<bool>,
+}
+
+/// Live state of a receipt's targets, checked after apply (and reboot)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub verified_at: String,
+    /// Kernel the check ran under
+    pub kernel_version: String,
+    /// One per target device (or the kernel command line for claim cleanup)
+    pub checks: Vec<TargetCheck>,
+    /// How many of the checks passed
+    pub outcome: VerifyOutcome,
+}
+
+/// How many of a receipt's targets ended up as planned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyOutcome {
+    /// All of them
+    Verified,
+    /// Some of them
+    Partial,
+    /// None of them
+    Failed,
+}
+
+/// Expected versus observed state of one target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetCheck {
+    /// PCI slot, or "kernel-cmdline"
+    pub target: String,
+    /// What the strategy should have produced (e.g., "bound to pci-stub")
+    pub expected: String,
+    /// What the scan found
+    pub observed: String,
+    /// Whether observed matches expected
+    pub outcome_met: bool,
+    /// ZombieDevice/UnmanagedMemory issues the scan still reports
+    pub remaining_issues: Vec<String>,
+}
+
+impl TargetCheck {
+    pub fn passed(&self) -> bool {
+        self.outcome_met && self.remaining_issues.is_empty()
+    }
+}
+
+/// Result of running one plan or undo step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepOutcome {
+    /// 1-based position in the plan's steps (or undo steps)
+    pub step: usize,
+    pub command: String,
+    pub status: StepRunStatus,
+    /// Exit status, `None` if the command never ran or was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Tail of stdout and stderr
+    pub output: String,
+    pub started_at: String,
+    pub completed_at: String,
+}
+
+/// What happened to a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StepRunStatus {
+    Success,
+    Failed,
+    /// Not run: dry run, or an earlier step failed
+    Skipped,
+    /// Succeeded, then undone by the rollback
+    RolledBack,
 }